@@ -0,0 +1,13 @@
+// swap in statuses behind content warnings without reloading the page (links fall back to
+// reloading with the status revealed). Delegated, so statuses swapped in by htmx work too
+document.addEventListener("click", async (event) => {
+    const link = event.target.closest("a.reveal");
+    if (!link) return;
+    event.preventDefault();
+    const response = await fetch("/reveal?uri=" + encodeURIComponent(link.dataset.uri));
+    if (!response.ok) {
+        window.location = link.href;
+        return;
+    }
+    link.closest(".content-warning").outerHTML = await response.text();
+});
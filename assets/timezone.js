@@ -0,0 +1,10 @@
+// report the browser's timezone once per visit so dates are shown in local time
+if (!sessionStorage.getItem("timezone-sent")) {
+    const timezone = Intl.DateTimeFormat().resolvedOptions().timeZone;
+    fetch("/preferences/timezone", {
+        method: "POST",
+        body: new URLSearchParams({ timezone }),
+    }).then((response) => {
+        if (response.ok) sessionStorage.setItem("timezone-sent", "1");
+    });
+}
@@ -0,0 +1,362 @@
+//! Generates the Rust types for the lexicons in `lexicons/` (the `crate::lexicons` module), so that
+//! they can't drift from the schema: adding a field or a collection is a matter of editing or adding
+//! a lexicon document.
+//!
+//! Only what the app's own lexicons use is supported: records whose properties are strings,
+//! integers, booleans, blobs, or arrays of those. Anything else fails the build, rather than being
+//! silently skipped.
+
+use std::{collections::BTreeMap, env, fmt::Write, fs, path::Path};
+
+use serde_json::Value;
+
+const LEXICON_DIR: &str = "lexicons";
+
+/// A record lexicon, e.g. `xyz.statusphere.status`.
+struct RecordLexicon {
+    nsid: String,
+    description: Option<String>,
+    fields: Vec<Field>,
+}
+
+struct Field {
+    json_name: String,
+    rust_name: String,
+    rust_type: String,
+    required: bool,
+    description: Option<String>,
+}
+
+fn main() {
+    println!("cargo::rerun-if-changed={LEXICON_DIR}");
+
+    let mut lexicons = Vec::new();
+    let mut entries = fs::read_dir(LEXICON_DIR)
+        .expect("lexicon directory exists")
+        .map(|entry| entry.expect("lexicon directory is readable").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<_>>();
+    entries.sort();
+    for path in entries {
+        println!("cargo::rerun-if-changed={}", path.display());
+        let document = fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("couldn't read {}: {e}", path.display()));
+        let document: Value = serde_json::from_str(&document)
+            .unwrap_or_else(|e| panic!("{} isn't valid JSON: {e}", path.display()));
+        lexicons.push(parse_lexicon(&path, &document));
+    }
+    lexicons.sort_by(|a, b| a.nsid.cmp(&b.nsid));
+
+    let out_dir = env::var("OUT_DIR").expect("cargo sets OUT_DIR");
+    fs::write(Path::new(&out_dir).join("lexicons.rs"), generate(&lexicons))
+        .expect("generated lexicons are writable");
+}
+
+fn fail(path: &Path, problem: &str) -> ! {
+    panic!("{}: {problem}", path.display())
+}
+
+fn parse_lexicon(path: &Path, document: &Value) -> RecordLexicon {
+    if document["lexicon"] != 1 {
+        fail(path, "only lexicon version 1 is supported");
+    }
+    let nsid = document["id"]
+        .as_str()
+        .unwrap_or_else(|| fail(path, "missing `id`"))
+        .to_owned();
+    let main = &document["defs"]["main"];
+    if main["type"] != "record" {
+        fail(path, "only record lexicons are supported");
+    }
+    let record = &main["record"];
+    if record["type"] != "object" {
+        fail(path, "the record must be an object");
+    }
+
+    let required = record["required"]
+        .as_array()
+        .map(|names| names.iter().filter_map(Value::as_str).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let properties = record["properties"]
+        .as_object()
+        .unwrap_or_else(|| fail(path, "the record has no `properties`"));
+    // sorted by name, for a stable field order
+    let properties = properties.iter().collect::<BTreeMap<_, _>>();
+    let fields = properties
+        .into_iter()
+        .map(|(name, property)| Field {
+            json_name: name.clone(),
+            rust_name: field_name(name),
+            rust_type: rust_type(property)
+                .unwrap_or_else(|problem| fail(path, &format!("property `{name}`: {problem}"))),
+            required: required.contains(&name.as_str()),
+            description: property["description"].as_str().map(str::to_owned),
+        })
+        .collect();
+
+    RecordLexicon {
+        nsid,
+        description: main["description"].as_str().map(str::to_owned),
+        fields,
+    }
+}
+
+fn rust_type(property: &Value) -> Result<String, String> {
+    Ok(match property["type"].as_str() {
+        Some("string") => match property["format"].as_str() {
+            Some("datetime") => "atrium_api::types::string::Datetime".to_owned(),
+            // at-uris, handles, DIDs etc. are kept as plain strings, as the rest of the app does
+            _ => "String".to_owned(),
+        },
+        Some("integer") => "i64".to_owned(),
+        Some("boolean") => "bool".to_owned(),
+        Some("blob") => "atrium_api::types::BlobRef".to_owned(),
+        Some("array") => format!("Vec<{}>", rust_type(&property["items"])?),
+        Some(other) => return Err(format!("type `{other}` isn't supported")),
+        None => return Err("missing `type`".to_owned()),
+    })
+}
+
+fn generate(lexicons: &[RecordLexicon]) -> String {
+    // namespace (e.g. `xyz.statusphere`) -> its records' names (e.g. `status`) and lexicons
+    let mut namespaces = BTreeMap::<Vec<&str>, Vec<(&str, &RecordLexicon)>>::new();
+    for lexicon in lexicons {
+        let mut segments = lexicon.nsid.split('.').collect::<Vec<_>>();
+        let name = segments.pop().expect("NSIDs have segments");
+        namespaces
+            .entry(segments)
+            .or_default()
+            .push((name, lexicon));
+    }
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// @generated by build.rs from the documents in `lexicons/`. DO NOT EDIT."
+    )
+    .unwrap();
+    generate_known_records(&mut out, lexicons);
+    // every namespace here has a common root (e.g. `xyz`), and is nested in the one before it
+    let mut open: Vec<&str> = Vec::new();
+    for (namespace, records) in &namespaces {
+        let common = open
+            .iter()
+            .zip(namespace)
+            .take_while(|(a, b)| a == b)
+            .count();
+        for _ in common..open.len() {
+            writeln!(out, "}}").unwrap();
+        }
+        open.truncate(common);
+        for segment in &namespace[common..] {
+            open.push(*segment);
+            writeln!(out, "pub mod {segment} {{").unwrap();
+            writeln!(
+                out,
+                "//!Definitions for the `{}` namespace.",
+                open.join(".")
+            )
+            .unwrap();
+        }
+        for (name, lexicon) in records {
+            generate_collection(&mut out, name, lexicon);
+        }
+    }
+    for _ in &open {
+        writeln!(out, "}}").unwrap();
+    }
+    out
+}
+
+fn generate_known_records(out: &mut String, lexicons: &[RecordLexicon]) {
+    writeln!(out, "pub mod record {{").unwrap();
+    writeln!(out, "//!A collection of known record types.").unwrap();
+    writeln!(
+        out,
+        "#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]"
+    )
+    .unwrap();
+    writeln!(out, "#[serde(tag = \"$type\")]").unwrap();
+    writeln!(out, "pub enum KnownRecord {{").unwrap();
+    for lexicon in lexicons {
+        writeln!(out, "#[serde(rename = \"{}\")]", lexicon.nsid).unwrap();
+        writeln!(
+            out,
+            "{}(Box<{}::Record>),",
+            variant_name(lexicon),
+            module_path(lexicon)
+        )
+        .unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    for lexicon in lexicons {
+        let (variant, module) = (variant_name(lexicon), module_path(lexicon));
+        writeln!(
+            out,
+            "impl From<{module}::Record> for KnownRecord {{
+    fn from(record: {module}::Record) -> Self {{
+        KnownRecord::{variant}(Box::new(record))
+    }}
+}}
+impl From<{module}::RecordData> for KnownRecord {{
+    fn from(record_data: {module}::RecordData) -> Self {{
+        KnownRecord::{variant}(Box::new(record_data.into()))
+    }}
+}}"
+        )
+        .unwrap();
+    }
+    writeln!(
+        out,
+        "impl From<KnownRecord> for atrium_api::types::Unknown {{
+    fn from(record: KnownRecord) -> Self {{
+        atrium_api::types::TryIntoUnknown::try_into_unknown(&record).unwrap()
+    }}
+}}"
+    )
+    .unwrap();
+    writeln!(out, "}}").unwrap();
+}
+
+fn generate_collection(out: &mut String, name: &str, lexicon: &RecordLexicon) {
+    let nsid = &lexicon.nsid;
+    let collection = pascal_case(name);
+    writeln!(
+        out,
+        "#[derive(Debug)]
+pub struct {collection};
+impl atrium_api::types::Collection for {collection} {{
+    const NSID: &'static str = \"{nsid}\";
+    type Record = {name}::Record;
+}}"
+    )
+    .unwrap();
+
+    writeln!(out, "pub mod {name} {{").unwrap();
+    writeln!(out, "//!Definitions for the `{nsid}` namespace.").unwrap();
+    writeln!(out, "use atrium_api::types::TryFromUnknown;").unwrap();
+    if let Some(description) = &lexicon.description {
+        write_doc(out, description);
+    }
+    writeln!(
+        out,
+        "#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]"
+    )
+    .unwrap();
+    writeln!(out, "#[serde(rename_all = \"camelCase\")]").unwrap();
+    writeln!(out, "pub struct RecordData {{").unwrap();
+    for field in &lexicon.fields {
+        if let Some(description) = &field.description {
+            write_doc(out, description);
+        }
+        // fields whose name doesn't survive the round trip through snake case keep their own
+        if camel_case(field.rust_name.trim_start_matches("r#")) != field.json_name {
+            writeln!(out, "#[serde(rename = \"{}\")]", field.json_name).unwrap();
+        }
+        if field.required {
+            writeln!(out, "pub {}: {},", field.rust_name, field.rust_type).unwrap();
+        } else {
+            writeln!(
+                out,
+                "#[serde(skip_serializing_if = \"core::option::Option::is_none\")]"
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "pub {}: core::option::Option<{}>,",
+                field.rust_name, field.rust_type
+            )
+            .unwrap();
+        }
+    }
+    writeln!(out, "}}").unwrap();
+    writeln!(
+        out,
+        "pub type Record = atrium_api::types::Object<RecordData>;
+impl From<atrium_api::types::Unknown> for RecordData {{
+    fn from(value: atrium_api::types::Unknown) -> Self {{
+        Self::try_from_unknown(value).unwrap()
+    }}
+}}"
+    )
+    .unwrap();
+    writeln!(out, "}}").unwrap();
+}
+
+fn write_doc(out: &mut String, description: &str) {
+    for line in description.lines() {
+        writeln!(out, "///{line}").unwrap();
+    }
+}
+
+/// E.g. `crate::lexicons::xyz::statusphere::status`.
+fn module_path(lexicon: &RecordLexicon) -> String {
+    format!("crate::lexicons::{}", lexicon.nsid.replace('.', "::"))
+}
+
+/// E.g. `LexiconsXyzStatusphereStatus`.
+fn variant_name(lexicon: &RecordLexicon) -> String {
+    std::iter::once("lexicons")
+        .chain(lexicon.nsid.split('.'))
+        .map(pascal_case)
+        .collect()
+}
+
+/// `createdAt` -> `created_at`, and `type` -> `r#type`.
+fn field_name(name: &str) -> String {
+    let name = snake_case(name);
+    if KEYWORDS.contains(&name.as_str()) {
+        format!("r#{name}")
+    } else {
+        name
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+    "pub", "ref", "return", "static", "struct", "trait", "true", "try", "type", "unsafe", "use",
+    "where", "while", "yield",
+];
+
+/// `createdAt` -> `created_at`.
+fn snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// `created_at` -> `createdAt`, as serde's `rename_all = "camelCase"` does.
+fn camel_case(name: &str) -> String {
+    let pascal = pascal_case(name);
+    let mut chars = pascal.chars();
+    chars
+        .next()
+        .map(|first| first.to_ascii_lowercase())
+        .into_iter()
+        .chain(chars)
+        .collect()
+}
+
+/// `created_at` or `createdAt` -> `CreatedAt`.
+fn pascal_case(name: &str) -> String {
+    name.split('_')
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase())
+                .into_iter()
+                .chain(chars)
+        })
+        .collect()
+}
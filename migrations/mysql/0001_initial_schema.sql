@@ -0,0 +1,105 @@
+-- The schema of migrations/sqlite/0001_initial_schema.sql, for MySQL and MariaDB. URIs, DIDs and
+-- timestamps are ASCII, which keeps their indices small; all text compares byte for byte, as in
+-- SQLite, so keys are case-sensitive and distinct emoji never collate as equal.
+
+create table if not exists status
+(
+    uri varchar(512) character set ascii collate ascii_bin primary key,
+    -- numbers statuses in insertion order, as SQLite's rowid does
+    seq bigint not null auto_increment unique,
+    author_did varchar(256) character set ascii collate ascii_bin not null,
+    status varchar(64) not null,
+    created_at varchar(64) character set ascii collate ascii_bin not null,
+    indexed_at varchar(64) character set ascii collate ascii_bin not null,
+    raw_created_at varchar(64) character set ascii collate ascii_bin,
+    visibility varchar(16) character set ascii collate ascii_bin not null default 'public',
+    content_warning text,
+    cid varchar(128) character set ascii collate ascii_bin,
+    -- author filters (e.g. the logged-in user's latest status)
+    index status_author_did_idx (author_did, indexed_at desc, uri desc),
+    -- date range filters
+    index status_indexed_at_idx (indexed_at),
+    -- covers the home feed query, so it never has to touch the table itself
+    index status_feed_idx
+        (indexed_at desc, uri desc, author_did, status, created_at, raw_created_at)
+) default character set utf8mb4 collate utf8mb4_bin;
+
+-- at most one pinned status per author
+create table if not exists status_pin
+(
+    author_did varchar(256) character set ascii collate ascii_bin primary key,
+    subject varchar(512) character set ascii collate ascii_bin not null,
+    created_at varchar(64) character set ascii collate ascii_bin not null
+) default character set utf8mb4 collate utf8mb4_bin;
+
+-- reactions to statuses, keyed by the reaction record's URI
+create table if not exists status_reaction
+(
+    uri varchar(512) character set ascii collate ascii_bin primary key,
+    author_did varchar(256) character set ascii collate ascii_bin not null,
+    subject varchar(512) character set ascii collate ascii_bin not null,
+    emoji varchar(64) not null,
+    created_at varchar(64) character set ascii collate ascii_bin not null,
+    index status_reaction_subject_idx (subject)
+) default character set utf8mb4 collate utf8mb4_bin;
+
+-- Bluesky posts announcing a status, created alongside it when the user opts in
+create table if not exists status_crosspost
+(
+    subject varchar(512) character set ascii collate ascii_bin primary key,
+    post_uri varchar(512) character set ascii collate ascii_bin not null
+) default character set utf8mb4 collate utf8mb4_bin;
+
+-- hourly rollups, maintained incrementally by `rollup`
+create table if not exists status_hourly_author
+(
+    hour varchar(16) character set ascii collate ascii_bin not null,
+    author_did varchar(256) character set ascii collate ascii_bin not null,
+    posts bigint not null,
+    primary key (hour, author_did)
+) default character set utf8mb4 collate utf8mb4_bin;
+
+create table if not exists status_hourly_emoji
+(
+    hour varchar(16) character set ascii collate ascii_bin not null,
+    status varchar(64) not null,
+    posts bigint not null,
+    primary key (hour, status)
+) default character set utf8mb4 collate utf8mb4_bin;
+
+-- single-row table holding the last status seq included in the rollups
+create table if not exists status_rollup_state
+(
+    id int primary key check (id = 0),
+    last_rowid bigint not null
+) default character set utf8mb4 collate utf8mb4_bin;
+
+create table if not exists dead_letter
+(
+    id bigint primary key auto_increment,
+    payload mediumtext not null,
+    error text not null,
+    failed_at varchar(64) character set ascii collate ascii_bin not null,
+    attempts bigint not null default 1
+) default character set utf8mb4 collate utf8mb4_bin;
+
+create table if not exists handle_cache
+(
+    `key` varchar(256) character set ascii collate ascii_bin primary key,
+    handle varchar(256) character set ascii collate ascii_bin,
+    resolved_at varchar(64) character set ascii collate ascii_bin not null
+) default character set utf8mb4 collate utf8mb4_bin;
+
+create table if not exists oauth_session
+(
+    `key` varchar(256) character set ascii collate ascii_bin primary key,
+    session mediumtext not null,
+    created_at varchar(64) character set ascii collate ascii_bin
+) default character set utf8mb4 collate utf8mb4_bin;
+
+create table if not exists oauth_state
+(
+    `key` varchar(256) character set ascii collate ascii_bin primary key,
+    state mediumtext not null,
+    created_at varchar(64) character set ascii collate ascii_bin
+) default character set utf8mb4 collate utf8mb4_bin;
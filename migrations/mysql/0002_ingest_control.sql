@@ -0,0 +1,7 @@
+-- single-row table holding the ingester's pause switch, and where in the event stream it paused
+create table if not exists ingest_control
+(
+    id int primary key check (id = 0),
+    paused boolean not null default false,
+    resume_cursor bigint
+) default character set utf8mb4 collate utf8mb4_bin;
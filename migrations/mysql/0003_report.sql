@@ -0,0 +1,18 @@
+-- statuses reported by users, for moderators to review on the admin dashboard
+create table if not exists report
+(
+    id bigint primary key auto_increment,
+    subject varchar(512) character set ascii collate ascii_bin not null,
+    reporter_did varchar(256) character set ascii collate ascii_bin not null,
+    reason_type varchar(128) character set ascii collate ascii_bin not null,
+    reason text,
+    created_at varchar(64) character set ascii collate ascii_bin not null,
+    -- the ID the moderation service gave the report, if it was filed with one
+    filed_id bigint,
+    resolved_at varchar(64) character set ascii collate ascii_bin,
+    resolved_by varchar(256) character set ascii collate ascii_bin,
+    -- each user reports a status at most once
+    unique (subject, reporter_did),
+    -- the open reports, oldest first
+    index report_open_idx (resolved_at, id)
+) default character set utf8mb4 collate utf8mb4_bin;
@@ -0,0 +1,3 @@
+-- image blob attached to a status, if any, as served by its author's PDS
+alter table status add column image_cid text;
+alter table status add column image_mime_type text;
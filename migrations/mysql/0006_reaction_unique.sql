@@ -0,0 +1,11 @@
+-- each user reacts to a status with each emoji at most once; of any duplicates already stored,
+-- the reaction record with the lowest URI is kept
+delete r from status_reaction r
+join status_reaction kept
+    on kept.author_did = r.author_did
+    and kept.subject = r.subject
+    and kept.emoji = r.emoji
+    and kept.uri < r.uri;
+
+create unique index status_reaction_author_idx
+on status_reaction (author_did, subject, emoji);
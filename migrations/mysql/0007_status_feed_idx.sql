@@ -0,0 +1,6 @@
+-- the feed reads columns added since status_feed_idx was made to cover it, so it no longer does;
+-- it's only kept for the order the feed is read in, without the columns that were along for the ride
+drop index status_feed_idx on status;
+
+create index status_feed_idx
+on status (indexed_at desc, uri desc);
@@ -0,0 +1,111 @@
+-- The schema of migrations/sqlite/0001_initial_schema.sql, for Postgres. Text sorts and compares
+-- byte for byte ("C" collation), as in SQLite, whatever the database's locale.
+
+create table if not exists status
+(
+    uri text collate "C" primary key,
+    -- numbers statuses in insertion order, as SQLite's rowid does
+    seq bigint generated always as identity unique,
+    author_did text collate "C" not null,
+    status text collate "C" not null,
+    created_at text collate "C" not null,
+    indexed_at text collate "C" not null,
+    raw_created_at text collate "C",
+    visibility text collate "C" not null default 'public',
+    content_warning text collate "C",
+    cid text collate "C"
+);
+
+-- author filters (e.g. the logged-in user's latest status)
+create index if not exists status_author_did_idx
+on status (author_did, indexed_at desc, uri desc);
+
+-- date range filters
+create index if not exists status_indexed_at_idx
+on status (indexed_at);
+
+-- covers the home feed query, so it never has to touch the table itself
+create index if not exists status_feed_idx
+on status (indexed_at desc, uri desc) include (author_did, status, created_at, raw_created_at);
+
+-- at most one pinned status per author
+create table if not exists status_pin
+(
+    author_did text collate "C" primary key,
+    subject text collate "C" not null,
+    created_at text collate "C" not null
+);
+
+-- reactions to statuses, keyed by the reaction record's URI
+create table if not exists status_reaction
+(
+    uri text collate "C" primary key,
+    author_did text collate "C" not null,
+    subject text collate "C" not null,
+    emoji text collate "C" not null,
+    created_at text collate "C" not null
+);
+
+create index if not exists status_reaction_subject_idx
+on status_reaction (subject);
+
+-- Bluesky posts announcing a status, created alongside it when the user opts in
+create table if not exists status_crosspost
+(
+    subject text collate "C" primary key,
+    post_uri text collate "C" not null
+);
+
+-- hourly rollups, maintained incrementally by `rollup`
+create table if not exists status_hourly_author
+(
+    hour text collate "C" not null,
+    author_did text collate "C" not null,
+    posts bigint not null,
+    primary key (hour, author_did)
+);
+
+create table if not exists status_hourly_emoji
+(
+    hour text collate "C" not null,
+    status text collate "C" not null,
+    posts bigint not null,
+    primary key (hour, status)
+);
+
+-- single-row table holding the last status seq included in the rollups
+create table if not exists status_rollup_state
+(
+    id integer primary key check (id = 0),
+    last_rowid bigint not null
+);
+
+create table if not exists dead_letter
+(
+    id bigint generated always as identity primary key,
+    payload text collate "C" not null,
+    error text collate "C" not null,
+    failed_at text collate "C" not null,
+    attempts bigint not null default 1
+);
+
+create table if not exists handle_cache
+(
+    key text collate "C" primary key,
+    handle text collate "C",
+    resolved_at text collate "C" not null
+);
+
+create table if not exists oauth_session
+(
+    key text collate "C" primary key,
+    session text collate "C" not null,
+    created_at text collate "C"
+);
+
+create table if not exists oauth_state
+(
+    key text collate "C" primary key,
+    state text collate "C" not null,
+    created_at text collate "C"
+);
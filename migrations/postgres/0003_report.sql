@@ -0,0 +1,20 @@
+-- statuses reported by users, for moderators to review on the admin dashboard
+create table if not exists report
+(
+    id bigint generated always as identity primary key,
+    subject text collate "C" not null,
+    reporter_did text collate "C" not null,
+    reason_type text collate "C" not null,
+    reason text collate "C",
+    created_at text collate "C" not null,
+    -- the ID the moderation service gave the report, if it was filed with one
+    filed_id bigint,
+    resolved_at text collate "C",
+    resolved_by text collate "C",
+    -- each user reports a status at most once
+    unique (subject, reporter_did)
+);
+
+-- the open reports, oldest first
+create index if not exists report_open_idx
+on report (resolved_at, id);
@@ -0,0 +1,9 @@
+-- each user reacts to a status with each emoji at most once; of any duplicates already stored,
+-- the reaction record with the lowest URI is kept
+delete from status_reaction
+where uri not in (
+    select min(uri) from status_reaction group by author_did, subject, emoji
+);
+
+create unique index if not exists status_reaction_author_idx
+on status_reaction (author_did, subject, emoji);
@@ -0,0 +1,109 @@
+-- The schema as it stood when migrations started being versioned. Tables and indices are created
+-- only if missing, so databases set up before then adopt it as they are.
+
+create table if not exists status
+(
+    uri text primary key,
+    author_did text not null,
+    status text not null,
+    created_at text not null,
+    indexed_at text not null,
+    raw_created_at text,
+    visibility text not null default 'public',
+    content_warning text,
+    cid text
+);
+
+-- author filters (e.g. the logged-in user's latest status)
+create index if not exists status_author_did_idx
+on status (author_did, indexed_at desc, uri desc);
+
+-- date range filters and rollups
+create index if not exists status_indexed_at_idx
+on status (indexed_at);
+
+-- covers the home feed query, so it never has to touch the table itself
+create index if not exists status_feed_idx
+on status (indexed_at desc, uri desc, author_did, status, created_at, raw_created_at);
+
+-- at most one pinned status per author
+create table if not exists status_pin
+(
+    author_did text primary key,
+    subject text not null,
+    created_at text not null
+);
+
+-- reactions to statuses, keyed by the reaction record's URI
+create table if not exists status_reaction
+(
+    uri text primary key,
+    author_did text not null,
+    subject text not null,
+    emoji text not null,
+    created_at text not null
+);
+
+create index if not exists status_reaction_subject_idx
+on status_reaction (subject);
+
+-- Bluesky posts announcing a status, created alongside it when the user opts in
+create table if not exists status_crosspost
+(
+    subject text primary key,
+    post_uri text not null
+);
+
+-- hourly rollups, maintained incrementally by `rollup`
+create table if not exists status_hourly_author
+(
+    hour text not null,
+    author_did text not null,
+    posts integer not null,
+    primary key (hour, author_did)
+);
+
+create table if not exists status_hourly_emoji
+(
+    hour text not null,
+    status text not null,
+    posts integer not null,
+    primary key (hour, status)
+);
+
+-- single-row table holding the last status rowid included in the rollups
+create table if not exists status_rollup_state
+(
+    id integer primary key check (id = 0),
+    last_rowid integer not null
+);
+
+create table if not exists dead_letter
+(
+    id integer primary key autoincrement,
+    payload text not null,
+    error text not null,
+    failed_at text not null,
+    attempts integer not null default 1
+);
+
+create table if not exists handle_cache
+(
+    key text primary key,
+    handle text,
+    resolved_at text not null
+);
+
+create table if not exists oauth_session
+(
+    key text primary key,
+    session text not null,
+    created_at text
+);
+
+create table if not exists oauth_state
+(
+    key text primary key,
+    state text not null,
+    created_at text
+);
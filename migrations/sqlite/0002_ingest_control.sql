@@ -0,0 +1,7 @@
+-- single-row table holding the ingester's pause switch, and where in the event stream it paused
+create table if not exists ingest_control
+(
+    id integer primary key check (id = 0),
+    paused boolean not null default 0,
+    resume_cursor integer
+);
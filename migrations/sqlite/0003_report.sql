@@ -0,0 +1,20 @@
+-- statuses reported by users, for moderators to review on the admin dashboard
+create table if not exists report
+(
+    id integer primary key autoincrement,
+    subject text not null,
+    reporter_did text not null,
+    reason_type text not null,
+    reason text,
+    created_at text not null,
+    -- the ID the moderation service gave the report, if it was filed with one
+    filed_id integer,
+    resolved_at text,
+    resolved_by text,
+    -- each user reports a status at most once
+    unique (subject, reporter_did)
+);
+
+-- the open reports, oldest first
+create index if not exists report_open_idx
+on report (resolved_at, id);
@@ -0,0 +1,3 @@
+-- highest status sequence number seen by the last rollup, only rolled up by the next one: on
+-- MySQL and Postgres a lower number can still be committed after a higher one is visible
+alter table status_rollup_state add column pending_rowid integer not null default 0;
@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use atrium_api::{
+    com::atproto::repo::{delete_record, list_records},
+    types::{
+        Collection,
+        string::{Did, Nsid, RecordKey},
+    },
+};
+use atrium_common::store::Store;
+use axum::{
+    Form,
+    extract::State,
+    response::{Html, IntoResponse, Response},
+};
+use minijinja::context;
+use serde::Deserialize;
+use tower_sessions::Session;
+use tracing::{info, warn};
+
+use crate::{
+    AppState,
+    auth::RequireAuth,
+    error::Error,
+    i18n::Locale,
+    lexicons::xyz::statusphere::{Pin, Reaction, Status},
+    oauth::{ATProtoAgent, agent_did},
+    open_template, preferences,
+    upstream::with_timeout,
+};
+
+// the collections this app writes to users' repos
+const REPO_COLLECTIONS: [&str; 3] = [Status::NSID, Reaction::NSID, Pin::NSID];
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteInput {
+    /// Set (by a checkbox) to also delete the user's records from their repo.
+    delete_records: Option<String>,
+}
+
+/// Asks the user to confirm deleting their data.
+pub async fn delete_form(
+    State(state): State<Arc<AppState>>,
+    locale: Locale,
+    RequireAuth(_agent): RequireAuth,
+    session: Session,
+) -> Result<Response, Error> {
+    let template = open_template!(state, "delete_account");
+    let rendered = template.render(context! {
+        locale => locale,
+        theme => preferences::theme(&session).await?,
+        deleted => false,
+    })?;
+    Ok(Html(rendered).into_response())
+}
+
+/// Deletes everything stored about the user: their statuses, reactions and pin, and their OAuth
+/// session (revoking its tokens), and logs them out. Their records in their repo are deleted too
+/// if asked for; otherwise they stay, and are imported again should they log in again.
+pub async fn delete_account(
+    State(state): State<Arc<AppState>>,
+    locale: Locale,
+    RequireAuth(agent): RequireAuth,
+    session: Session,
+    Form(input): Form<DeleteInput>,
+) -> Result<Response, Error> {
+    let did = agent_did(&agent).await;
+
+    // this needs their tokens, so it goes first; if it fails nothing else is deleted, so they can
+    // try again
+    let records_deleted = match input.delete_records {
+        Some(_) => Some(delete_repo_records(state.as_ref(), &agent, &did).await?),
+        None => None,
+    };
+
+    let statuses_deleted = state.status_store.delete_author(&did).await?;
+    info!(
+        "Deleted the data of {}: {statuses_deleted} statuses, {} repo records",
+        did.as_str(),
+        records_deleted.unwrap_or_default()
+    );
+
+    // development mode sessions never had any tokens
+    if !state.config.dev_fake_auth {
+        if let Err(e) = state.oauth_client.revoke(&did).await {
+            warn!("failed to revoke the OAuth tokens of {}: {e}", did.as_str());
+        }
+        // whether or not revoking got as far as removing it
+        state.oauth_session_store.del(&did).await?;
+    }
+
+    // the theme lives in the session, so it's read before the session goes
+    let theme = preferences::theme(&session).await?;
+    session.delete().await?;
+
+    let template = open_template!(state, "delete_account");
+    let rendered = template.render(context! {
+        locale => locale,
+        theme => theme,
+        deleted => true,
+        records_deleted => records_deleted,
+    })?;
+    Ok(Html(rendered).into_response())
+}
+
+// deletes the records in `did`'s repo from this app's collections, returning how many there were
+async fn delete_repo_records(
+    state: &AppState,
+    agent: &ATProtoAgent,
+    did: &Did,
+) -> Result<usize, Error> {
+    let mut deleted = 0;
+    for collection in REPO_COLLECTIONS {
+        let collection: Nsid = collection
+            .parse()
+            .expect("NSID is generated, should never fail to parse");
+        // cursors are record keys, so deleting the records already listed doesn't disturb them
+        let mut cursor = None;
+        loop {
+            let output = with_timeout(
+                state.config.upstream_timeout,
+                "record listing",
+                agent.api.com.atproto.repo.list_records(
+                    list_records::ParametersData {
+                        collection: collection.clone(),
+                        cursor,
+                        limit: None,
+                        repo: did.clone().into(),
+                        reverse: None,
+                    }
+                    .into(),
+                ),
+            )
+            .await??;
+            for record in &output.data.records {
+                let Some(rkey) = record
+                    .uri
+                    .rsplit('/')
+                    .next()
+                    .and_then(|rkey| RecordKey::new(rkey.to_owned()).ok())
+                else {
+                    warn!("not deleting record with unexpected URI {}", record.uri);
+                    continue;
+                };
+                with_timeout(
+                    state.config.upstream_timeout,
+                    "record deletion",
+                    agent.api.com.atproto.repo.delete_record(
+                        delete_record::InputData {
+                            collection: collection.clone(),
+                            repo: did.clone().into(),
+                            rkey,
+                            swap_commit: None,
+                            swap_record: None,
+                        }
+                        .into(),
+                    ),
+                )
+                .await??;
+                deleted += 1;
+            }
+            cursor = output.data.cursor.clone();
+            if cursor.is_none() {
+                break;
+            }
+        }
+    }
+    Ok(deleted)
+}
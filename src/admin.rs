@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use axum::{
+    Form, Router,
+    extract::State,
+    middleware,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::post,
+};
+use minijinja::context;
+use serde::{Deserialize, Serialize};
+use tower_sessions::Session;
+use tracing::info;
+
+use crate::{
+    AppState, dead_letter,
+    error::Error,
+    open_template, preferences,
+    roles::{Authorized, Moderator, admin_only},
+};
+
+// open reports listed on the dashboard, oldest first
+const OPEN_REPORTS_SHOWN: usize = 50;
+
+pub async fn admin_dashboard(
+    State(state): State<Arc<AppState>>,
+    user: Authorized<Moderator>,
+    session: Session,
+) -> Result<Response, Error> {
+    let template = open_template!(state, "admin");
+
+    let dead_letter_count = state.dead_letters.count().await?;
+    let open_reports = state.reports.fetch_open(OPEN_REPORTS_SHOWN).await?;
+    let report_count = state.reports.count_open().await?;
+
+    #[derive(Serialize)]
+    struct CollectionView {
+        collection: &'static str,
+        enabled: bool,
+    }
+    let collections = state
+        .collection_toggles
+        .states()
+        .into_iter()
+        .map(|(collection, enabled)| CollectionView {
+            collection,
+            enabled,
+        })
+        .collect::<Vec<_>>();
+
+    let ingest_control = state.ingest_control.get().await?;
+
+    let rendered = template.render(context! {
+        theme => preferences::theme(&session).await?,
+        did => user.did.as_str(),
+        role => user.role,
+        dead_letter_count => dead_letter_count,
+        ingestion_paused => ingest_control.paused,
+        ingester => state.ingester_status.report(),
+        collections => collections,
+        open_reports => open_reports,
+        report_count => report_count,
+    })?;
+
+    Ok(Html(rendered).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveReportInput {
+    id: i64,
+}
+
+/// Marks a report as dealt with, taking it off the dashboard. Open to moderators, unlike the
+/// maintenance actions.
+pub async fn resolve_report(
+    State(state): State<Arc<AppState>>,
+    user: Authorized<Moderator>,
+    Form(input): Form<ResolveReportInput>,
+) -> Result<Response, Error> {
+    state.reports.resolve(input.id, &user.did).await?;
+    info!("Report {} resolved by {}", input.id, user.did.as_str());
+    Ok(Redirect::to("/admin").into_response())
+}
+
+/// Maintenance actions, all restricted to owners.
+pub fn maintenance_routes(state: &Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route(
+            "/admin/dead-letters/reprocess",
+            post(reprocess_dead_letters),
+        )
+        .route("/admin/collections/toggle", post(toggle_collection))
+        .route("/admin/ingester/pause", post(pause_ingester))
+        .route("/admin/ingester/resume", post(resume_ingester))
+        // a route layer, so only requests for these routes are checked
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(state),
+            admin_only,
+        ))
+}
+
+async fn reprocess_dead_letters(State(state): State<Arc<AppState>>) -> Result<Response, Error> {
+    dead_letter::reprocess(&state.dead_letters, &state.config, &state.status_store).await?;
+    Ok(Redirect::to("/admin").into_response())
+}
+
+// the ingester notices within a few seconds, and holds its cursor while paused
+async fn pause_ingester(State(state): State<Arc<AppState>>) -> Result<Response, Error> {
+    state.ingest_control.set_paused(true).await?;
+    info!("Ingestion paused from the admin dashboard");
+    Ok(Redirect::to("/admin").into_response())
+}
+
+async fn resume_ingester(State(state): State<Arc<AppState>>) -> Result<Response, Error> {
+    state.ingest_control.set_paused(false).await?;
+    info!("Ingestion resumed from the admin dashboard");
+    Ok(Redirect::to("/admin").into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct ToggleCollectionInput {
+    collection: String,
+    enabled: bool,
+}
+
+async fn toggle_collection(
+    State(state): State<Arc<AppState>>,
+    Form(input): Form<ToggleCollectionInput>,
+) -> Result<Response, Error> {
+    if !state
+        .collection_toggles
+        .set(&input.collection, input.enabled)
+    {
+        return Err(Error::InvalidQuery(format!(
+            "unknown collection '{}'",
+            input.collection
+        )));
+    }
+    info!(
+        "Ingestion of {} {}",
+        input.collection,
+        if input.enabled { "resumed" } else { "paused" }
+    );
+    Ok(Redirect::to("/admin").into_response())
+}
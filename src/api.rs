@@ -0,0 +1,287 @@
+//! Versioned JSON API. Each version has its own serializers for the view models in its
+//! submodule, so response shapes can change in a new version without breaking integrations
+//! built against an older one.
+
+mod v1;
+mod v2;
+
+use std::sync::Arc;
+
+use atrium_api::types::string::Datetime;
+use axum::{
+    Json,
+    extract::{FromRequestParts, Query, State},
+    http::{HeaderValue, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{AppState, cursor::FeedCursor, error::Error};
+
+const DEFAULT_HOURS_PER_PAGE: usize = 24;
+const MAX_HOURS_PER_PAGE: usize = 168;
+const DEFAULT_STATS_DAYS: usize = 30;
+const MAX_STATS_DAYS: usize = 365;
+const DEFAULT_STATUSES_PER_PAGE: usize = 50;
+const MAX_STATUSES_PER_PAGE: usize = 100;
+
+// media type clients can request a specific version with on unversioned paths, e.g.
+// `Accept: application/vnd.statusphere.v2+json`
+const VERSIONED_MEDIA_TYPE_PREFIX: &str = "application/vnd.statusphere.";
+
+/// Version of the JSON API a request is served with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    pub const LATEST: ApiVersion = ApiVersion::V2;
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "v1" => Some(Self::V1),
+            "v2" => Some(Self::V2),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::V1 => "v1",
+            Self::V2 => "v2",
+        }
+    }
+
+    /// Whether clients should move off this version.
+    pub fn is_deprecated(&self) -> bool {
+        *self < Self::LATEST
+    }
+}
+
+/// Extractor negotiating the API version of a request. Versioned paths (`/api/v2/...`) pick the
+/// version explicitly; unversioned paths (`/api/...`) use the version requested in the `Accept`
+/// header, falling back to v1, which is what they served before versioning was introduced.
+#[derive(Debug, Clone)]
+pub struct ApiRequest {
+    pub version: ApiVersion,
+    // path of the requested resource below the version, e.g. `stats/hourly`
+    resource: String,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ApiRequest {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let path = parts.uri.path().trim_start_matches("/api/");
+        let (first, rest) = path.split_once('/').unwrap_or((path, ""));
+        if first.starts_with('v') && first[1..].chars().all(|c| c.is_ascii_digit()) {
+            let version = ApiVersion::parse(first)
+                .ok_or_else(|| Error::UnsupportedApiVersion(first.to_owned()))?;
+            return Ok(Self {
+                version,
+                resource: rest.to_owned(),
+            });
+        }
+
+        let accept = parts
+            .headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or_default();
+        let requested = accept.split(',').find_map(|media_type| {
+            media_type
+                .trim()
+                .strip_prefix(VERSIONED_MEDIA_TYPE_PREFIX)?
+                .strip_suffix("+json")
+        });
+        let version = match requested {
+            Some(requested) => ApiVersion::parse(requested)
+                .ok_or_else(|| Error::UnsupportedApiVersion(requested.to_owned()))?,
+            None => ApiVersion::V1,
+        };
+        Ok(Self {
+            version,
+            resource: path.to_owned(),
+        })
+    }
+}
+
+impl ApiRequest {
+    /// Serializes `body` as JSON, labelled with the negotiated version (and with `Deprecation`
+    /// and successor `Link` headers if it's deprecated).
+    pub fn respond<T: Serialize>(&self, body: T) -> Response {
+        let mut response = Json(body).into_response();
+        let headers = response.headers_mut();
+        headers.insert(
+            "api-version",
+            HeaderValue::from_static(self.version.as_str()),
+        );
+        if self.version.is_deprecated() {
+            headers.insert("deprecation", HeaderValue::from_static("true"));
+            let successor = format!(
+                "</api/{}/{}>; rel=\"successor-version\"",
+                ApiVersion::LATEST.as_str(),
+                self.resource
+            );
+            if let Ok(link) = HeaderValue::from_str(&successor) {
+                headers.insert(header::LINK, link);
+            }
+        }
+        response
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    /// How many of the most recent days to report counts for.
+    days: Option<usize>,
+}
+
+/// All-time activity: totals, per-day counts, and per-emoji counts of public statuses, as of the
+/// last rollup.
+pub async fn stats(
+    State(state): State<Arc<AppState>>,
+    request: ApiRequest,
+    Query(query): Query<StatsQuery>,
+) -> Result<Response, Error> {
+    let days = query
+        .days
+        .unwrap_or(DEFAULT_STATS_DAYS)
+        .clamp(1, MAX_STATS_DAYS);
+
+    let totals = state.status_store.totals().await?;
+    let daily = state.status_store.daily_counts(days).await?;
+    let emojis = state.status_store.emoji_counts().await?;
+
+    Ok(match request.version {
+        ApiVersion::V1 => request.respond(v1::Stats::new(totals, &daily, &emojis)),
+        ApiVersion::V2 => request.respond(v2::Stats::new(totals, &daily, &emojis)),
+    })
+}
+
+/// The emoji users can pick as statuses and reactions.
+pub async fn options(State(state): State<Arc<AppState>>, request: ApiRequest) -> Response {
+    let config = &state.config;
+    match request.version {
+        ApiVersion::V1 => request.respond(v1::Options::new(
+            &config.status_options,
+            &config.reaction_options,
+        )),
+        ApiVersion::V2 => request.respond(v2::Options::new(
+            &config.status_options,
+            &config.reaction_options,
+        )),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HourlyStatsQuery {
+    /// Start of the range (RFC 3339), defaulting to 24 hours before `to`.
+    from: Option<String>,
+    /// End of the range (RFC 3339), defaulting to now.
+    to: Option<String>,
+    limit: Option<usize>,
+    /// Last hour of the previous page.
+    cursor: Option<String>,
+}
+
+// rollup tables are keyed by the first 13 characters of RFC 3339 timestamps
+fn hour_of(datetime: DateTime<Utc>) -> String {
+    datetime.format("%Y-%m-%dT%H").to_string()
+}
+
+fn parse_datetime(param: &'static str, value: &str) -> Result<DateTime<Utc>, Error> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|datetime| datetime.with_timezone(&Utc))
+        .map_err(|e| Error::InvalidQuery(format!("{param} '{value}': {e}")))
+}
+
+/// Hourly public activity (posts, unique authors, top emojis) from the rollup tables, for
+/// external dashboards.
+pub async fn hourly_stats(
+    State(state): State<Arc<AppState>>,
+    request: ApiRequest,
+    Query(query): Query<HourlyStatsQuery>,
+) -> Result<Response, Error> {
+    let to = match &query.to {
+        Some(to) => parse_datetime("to", to)?,
+        None => Utc::now(),
+    };
+    let from = match &query.from {
+        Some(from) => parse_datetime("from", from)?,
+        None => to - chrono::Duration::hours(24),
+    };
+    if from > to {
+        return Err(Error::InvalidQuery("from is after to".to_owned()));
+    }
+    if let Some(cursor) = &query.cursor {
+        NaiveDateTime::parse_from_str(&format!("{cursor}:00"), "%Y-%m-%dT%H:%M")
+            .map_err(|e| Error::InvalidQuery(format!("cursor '{cursor}': {e}")))?;
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_HOURS_PER_PAGE)
+        .clamp(1, MAX_HOURS_PER_PAGE);
+
+    let hours = state
+        .status_store
+        .hourly_stats(&hour_of(from), &hour_of(to), query.cursor.as_deref(), limit)
+        .await?;
+    // a full page might be followed by more hours
+    let cursor = (hours.len() == limit)
+        .then(|| hours.last().map(|stats| stats.hour.clone()))
+        .flatten();
+
+    Ok(match request.version {
+        ApiVersion::V1 => request.respond(v1::HourlyStatsPage::new(&hours, cursor)),
+        ApiVersion::V2 => request.respond(v2::HourlyStatsPage::new(&hours, cursor, limit)),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatusesQuery {
+    /// The previous page's `since` to continue from, or, to start, a time (RFC 3339) to list the
+    /// statuses indexed from.
+    since: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Public statuses indexed since a timestamp, oldest first, for clients polling for new ones.
+pub async fn statuses(
+    State(state): State<Arc<AppState>>,
+    request: ApiRequest,
+    Query(query): Query<StatusesQuery>,
+) -> Result<Response, Error> {
+    let since = query
+        .since
+        .as_deref()
+        .ok_or_else(|| Error::InvalidQuery("since is required".to_owned()))?;
+    let after = match parse_datetime("since", since) {
+        // sorts before any URI, so statuses indexed at that very time are included
+        Ok(since) => FeedCursor {
+            indexed_at: Datetime::new(since.fixed_offset()),
+            uri: String::new(),
+        },
+        Err(_) => state.config.cursor_codec.decode(since)?,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_STATUSES_PER_PAGE)
+        .clamp(1, MAX_STATUSES_PER_PAGE);
+
+    let statuses = state.status_store.fetch_since(&after, limit).await?;
+    // nothing new means the next poll is from the same point
+    let next_since = state.config.cursor_codec.encode(
+        &statuses
+            .last()
+            .map_or_else(|| after.clone(), |status| status.cursor()),
+    );
+
+    Ok(match request.version {
+        ApiVersion::V1 => request.respond(v1::StatusesPage::new(&statuses, next_since)),
+        ApiVersion::V2 => request.respond(v2::StatusesPage::new(&statuses, next_since, limit)),
+    })
+}
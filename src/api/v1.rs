@@ -0,0 +1,141 @@
+//! v1 serializers (deprecated): snake_case fields, cursor at the top level.
+
+use serde::Serialize;
+
+use crate::store::{EmojiCount, HourlyStats, Status, Totals};
+
+#[derive(Debug, Serialize)]
+pub struct Options<'a> {
+    status_options: &'a [String],
+    reaction_options: &'a [String],
+}
+
+impl<'a> Options<'a> {
+    pub fn new(status_options: &'a [String], reaction_options: &'a [String]) -> Self {
+        Self {
+            status_options,
+            reaction_options,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stats<'a> {
+    total_statuses: i64,
+    unique_authors: i64,
+    /// Newest day first.
+    daily: Vec<DayCountView<'a>>,
+    emojis: Vec<EmojiCountView<'a>>,
+}
+
+impl<'a> Stats<'a> {
+    pub fn new(totals: Totals, daily: &'a [(String, i64)], emojis: &'a [EmojiCount]) -> Self {
+        Self {
+            total_statuses: totals.statuses,
+            unique_authors: totals.authors,
+            daily: daily
+                .iter()
+                .map(|(day, posts)| DayCountView { day, posts: *posts })
+                .collect(),
+            emojis: emojis.iter().map(EmojiCountView::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DayCountView<'a> {
+    day: &'a str,
+    posts: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HourlyStatsPage<'a> {
+    hours: Vec<HourlyStatsView<'a>>,
+    /// Pass as `cursor` to fetch the next page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
+}
+
+impl<'a> HourlyStatsPage<'a> {
+    pub fn new(hours: &'a [HourlyStats], cursor: Option<String>) -> Self {
+        Self {
+            hours: hours.iter().map(HourlyStatsView::from).collect(),
+            cursor,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct HourlyStatsView<'a> {
+    hour: &'a str,
+    posts: i64,
+    unique_authors: i64,
+    top_emojis: Vec<EmojiCountView<'a>>,
+}
+
+impl<'a> From<&'a HourlyStats> for HourlyStatsView<'a> {
+    fn from(stats: &'a HourlyStats) -> Self {
+        Self {
+            hour: &stats.hour,
+            posts: stats.posts,
+            unique_authors: stats.unique_authors,
+            top_emojis: stats.top_emojis.iter().map(EmojiCountView::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusesPage<'a> {
+    /// Oldest first.
+    statuses: Vec<StatusView<'a>>,
+    /// Pass as `since` to poll for newer statuses.
+    since: String,
+}
+
+impl<'a> StatusesPage<'a> {
+    pub fn new(statuses: &'a [Status], since: String) -> Self {
+        Self {
+            statuses: statuses.iter().map(StatusView::from).collect(),
+            since,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct StatusView<'a> {
+    uri: &'a str,
+    author_did: &'a str,
+    status: &'a str,
+    created_at: &'a str,
+    indexed_at: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_warning: Option<&'a str>,
+}
+
+impl<'a> From<&'a Status> for StatusView<'a> {
+    fn from(status: &'a Status) -> Self {
+        Self {
+            uri: &status.uri,
+            author_did: status.author_did.as_str(),
+            status: &status.status,
+            created_at: status.created_at.as_str(),
+            indexed_at: status.indexed_at.as_str(),
+            content_warning: status.content_warning.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EmojiCountView<'a> {
+    status: &'a str,
+    posts: i64,
+}
+
+impl<'a> From<&'a EmojiCount> for EmojiCountView<'a> {
+    fn from(count: &'a EmojiCount) -> Self {
+        Self {
+            status: &count.status,
+            posts: count.posts,
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! v2 serializers: camelCase fields, full RFC 3339 hour timestamps, and pagination details
+//! grouped under `page`.
+
+use serde::Serialize;
+
+use crate::store::{EmojiCount, HourlyStats, Status, Totals};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Options<'a> {
+    status_options: &'a [String],
+    reaction_options: &'a [String],
+}
+
+impl<'a> Options<'a> {
+    pub fn new(status_options: &'a [String], reaction_options: &'a [String]) -> Self {
+        Self {
+            status_options,
+            reaction_options,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats<'a> {
+    total_statuses: i64,
+    unique_users: i64,
+    /// Newest day first.
+    daily: Vec<DayCountView<'a>>,
+    emojis: Vec<EmojiCountView<'a>>,
+}
+
+impl<'a> Stats<'a> {
+    pub fn new(totals: Totals, daily: &'a [(String, i64)], emojis: &'a [EmojiCount]) -> Self {
+        Self {
+            total_statuses: totals.statuses,
+            unique_users: totals.authors,
+            daily: daily
+                .iter()
+                .map(|(date, posts)| DayCountView {
+                    date,
+                    posts: *posts,
+                })
+                .collect(),
+            emojis: emojis.iter().map(EmojiCountView::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DayCountView<'a> {
+    date: &'a str,
+    posts: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HourlyStatsPage<'a> {
+    hours: Vec<HourlyStatsView<'a>>,
+    page: PageInfo,
+}
+
+impl<'a> HourlyStatsPage<'a> {
+    pub fn new(hours: &'a [HourlyStats], cursor: Option<String>, limit: usize) -> Self {
+        Self {
+            hours: hours.iter().map(HourlyStatsView::from).collect(),
+            page: PageInfo {
+                limit,
+                next_cursor: cursor,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    limit: usize,
+    /// Pass as `cursor` to fetch the next page; `null` on the last page.
+    next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HourlyStatsView<'a> {
+    /// Start of the hour, e.g. `2025-01-01T13:00:00Z`.
+    hour: String,
+    posts: i64,
+    unique_users: i64,
+    top_emojis: Vec<EmojiCountView<'a>>,
+}
+
+impl<'a> From<&'a HourlyStats> for HourlyStatsView<'a> {
+    fn from(stats: &'a HourlyStats) -> Self {
+        Self {
+            hour: format!("{}:00:00Z", stats.hour),
+            posts: stats.posts,
+            unique_users: stats.unique_authors,
+            top_emojis: stats.top_emojis.iter().map(EmojiCountView::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusesPage<'a> {
+    /// Oldest first.
+    statuses: Vec<StatusView<'a>>,
+    page: SincePageInfo,
+}
+
+impl<'a> StatusesPage<'a> {
+    pub fn new(statuses: &'a [Status], next_since: String, limit: usize) -> Self {
+        Self {
+            statuses: statuses.iter().map(StatusView::from).collect(),
+            page: SincePageInfo { limit, next_since },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SincePageInfo {
+    limit: usize,
+    /// Pass as `since` to poll for newer statuses; a full page means there are more already.
+    next_since: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatusView<'a> {
+    uri: &'a str,
+    author_did: &'a str,
+    status: &'a str,
+    created_at: &'a str,
+    indexed_at: &'a str,
+    content_warning: Option<&'a str>,
+}
+
+impl<'a> From<&'a Status> for StatusView<'a> {
+    fn from(status: &'a Status) -> Self {
+        Self {
+            uri: &status.uri,
+            author_did: status.author_did.as_str(),
+            status: &status.status,
+            created_at: status.created_at.as_str(),
+            indexed_at: status.indexed_at.as_str(),
+            content_warning: status.content_warning.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EmojiCountView<'a> {
+    emoji: &'a str,
+    posts: i64,
+}
+
+impl<'a> From<&'a EmojiCount> for EmojiCountView<'a> {
+    fn from(count: &'a EmojiCount) -> Self {
+        Self {
+            emoji: &count.status,
+            posts: count.posts,
+        }
+    }
+}
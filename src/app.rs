@@ -0,0 +1,652 @@
+//! Putting the app together: its stores, its state, and the routes serving it.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{
+    Router,
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{get, post},
+};
+use log::LevelFilter;
+use minijinja::Environment;
+use tower_http::{
+    catch_panic::CatchPanicLayer,
+    compression::{
+        CompressionLayer,
+        predicate::{NotForContentType, Predicate, SizeAbove},
+    },
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
+use tower_sessions::{
+    Expiry, SessionManagerLayer, SessionStore,
+    cookie::{SameSite, time::Duration},
+    session::{Id, Record},
+    session_store::{self, ExpiredDeletion},
+};
+#[cfg(feature = "mysql")]
+use tower_sessions_sqlx_store::{
+    MySqlStore,
+    sqlx::{
+        MySql, MySqlPool,
+        mysql::{MySqlConnectOptions, MySqlPoolOptions},
+    },
+};
+#[cfg(feature = "postgres")]
+use tower_sessions_sqlx_store::{
+    PostgresStore,
+    sqlx::{
+        PgPool, Postgres,
+        postgres::{PgConnectOptions, PgPoolOptions},
+    },
+};
+use tower_sessions_sqlx_store::{
+    SqliteStore,
+    sqlx::{
+        self, ConnectOptions, Sqlite, SqlitePool,
+        migrate::MigrateDatabase,
+        sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    },
+};
+use tracing::{error, info, warn};
+
+#[cfg(feature = "admin")]
+use crate::admin;
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::{
+    AppState, account, api,
+    assets::{self, Assets},
+    auth,
+    avatar::{self, GeneratedAvatars, Identicon, ProfileAvatars},
+    config::{AppConfig, DbConfig, OAuthStoreConfig, oauth_cipher_from_env},
+    error, feed_generator,
+    follows::FollowCache,
+    handles::HandleResolver,
+    health, home,
+    ingester_status::IngesterStatus,
+    initialize_templates,
+    lexicons::xyz::statusphere::{Pin, Reaction, Status},
+    login,
+    lookups::LookupCache,
+    metrics::{Metrics, Scope},
+    oauth, permalink, preferences, profile, report, request_id, security_headers, status,
+    store::{
+        self, Db, DeadLetterStore, HandleCache, IngestControlStore, OAuthSessionStore,
+        OAuthStateStore, ReportStore, StatusStore,
+    },
+    throttle::{LoginThrottle, PostGuard},
+    toggles::CollectionToggles,
+    upstream::CircuitBreaker,
+    validate,
+};
+
+// longest wait between attempts to connect to the database at startup
+const MAX_CONNECT_BACKOFF: std::time::Duration = std::time::Duration::from_secs(30);
+
+// connect to DB at configured URL, retrying with backoff while it can't be reached yet (e.g. a
+// database container that's still starting up)
+async fn db_connect(config: &DbConfig) -> Result<Db, sqlx::error::Error> {
+    let attempts = config.connect_retries.saturating_add(1);
+    let mut backoff = config.connect_backoff;
+    let mut attempt = 1;
+    loop {
+        match db_connect_once(config).await {
+            Ok(db) => return Ok(db),
+            Err(e) if attempt < attempts && is_transient(&e) => {
+                warn!(
+                    "Database connection attempt {attempt} of {attempts} failed: {e}; retrying in \
+                     {backoff:?}"
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_CONNECT_BACKOFF);
+                attempt += 1;
+            }
+            Err(e) => {
+                error!(
+                    "Database connection attempt {attempt} of {attempts} failed, giving up: {e}"
+                );
+                return Err(e);
+            }
+        }
+    }
+}
+
+// whether connecting failed in a way waiting could fix (the server isn't accepting connections,
+// or is still starting up), unlike e.g. a malformed URL
+fn is_transient(e: &sqlx::error::Error) -> bool {
+    matches!(
+        e,
+        sqlx::error::Error::Io(_)
+            | sqlx::error::Error::PoolTimedOut
+            | sqlx::error::Error::Database(_)
+    )
+}
+
+// logs every statement at DEBUG (under the `sqlx::query` target, in the span of the store method
+// running it) with its duration and how many rows it returned or affected, and slow ones at WARN
+fn with_query_logging<O: ConnectOptions>(options: O, config: &DbConfig) -> O {
+    options
+        .log_statements(LevelFilter::Debug)
+        .log_slow_statements(LevelFilter::Warn, config.slow_query_threshold)
+}
+
+// connect to DB at configured URL (creating if not existing), whichever database its scheme is
+async fn db_connect_once(config: &DbConfig) -> Result<Db, sqlx::error::Error> {
+    let url = config.url.as_str();
+    if url.starts_with("mysql:") || url.starts_with("mariadb:") {
+        #[cfg(feature = "mysql")]
+        return Ok(Db::MySql(mysql_connect(config).await?));
+        #[cfg(not(feature = "mysql"))]
+        return Err(not_compiled_in("MySQL", "mysql"));
+    }
+    if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+        #[cfg(feature = "postgres")]
+        return Ok(Db::Postgres(postgres_connect(config).await?));
+        #[cfg(not(feature = "postgres"))]
+        return Err(not_compiled_in("Postgres", "postgres"));
+    }
+    Ok(Db::Sqlite(sqlite_connect(config).await?))
+}
+
+// the error connecting to a database whose support is behind a feature this build doesn't have
+#[cfg(not(all(feature = "mysql", feature = "postgres")))]
+fn not_compiled_in(database: &str, feature: &str) -> sqlx::error::Error {
+    sqlx::error::Error::Configuration(
+        format!("{database} support isn't compiled in; build with the `{feature}` feature").into(),
+    )
+}
+
+async fn sqlite_connect(config: &DbConfig) -> Result<SqlitePool, sqlx::error::Error> {
+    let url = config.url.as_str();
+    if !Sqlite::database_exists(url).await? {
+        Sqlite::create_database(url).await?;
+        info!("Database created at {url}");
+    }
+    // pragmas are per-connection, so set them on the connect options rather than running them
+    // once against the pool
+    let connect_options = url
+        .parse::<SqliteConnectOptions>()?
+        .journal_mode(config.journal_mode)
+        .busy_timeout(config.busy_timeout)
+        .synchronous(config.synchronous);
+    let connect_options = with_query_logging(connect_options, config);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(config.max_connections)
+        .acquire_timeout(config.acquire_timeout)
+        .connect_with(connect_options)
+        .await?;
+    info!(
+        "Sqlite DB connected: {url} (journal_mode={:?}, synchronous={:?}, pool={})",
+        config.journal_mode, config.synchronous, config.max_connections
+    );
+    Ok(pool)
+}
+
+#[cfg(feature = "mysql")]
+async fn mysql_connect(config: &DbConfig) -> Result<MySqlPool, sqlx::error::Error> {
+    // MariaDB speaks MySQL's protocol, but sqlx only knows it by MySQL's scheme
+    let url = match config.url.strip_prefix("mariadb:") {
+        Some(rest) => format!("mysql:{rest}"),
+        None => config.url.clone(),
+    };
+    if !MySql::database_exists(&url).await? {
+        MySql::create_database(&url).await?;
+        info!("MySQL database created");
+    }
+    let connect_options = with_query_logging(url.parse::<MySqlConnectOptions>()?, config);
+    let pool = MySqlPoolOptions::new()
+        .max_connections(config.max_connections)
+        .acquire_timeout(config.acquire_timeout)
+        .connect_with(connect_options)
+        .await?;
+    // not the URL, which has the password in it
+    info!("MySQL DB connected (pool={})", config.max_connections);
+    Ok(pool)
+}
+
+#[cfg(feature = "postgres")]
+async fn postgres_connect(config: &DbConfig) -> Result<PgPool, sqlx::error::Error> {
+    let url = config.url.as_str();
+    if !Postgres::database_exists(url).await? {
+        Postgres::create_database(url).await?;
+        info!("Postgres database created");
+    }
+    let connect_options = with_query_logging(url.parse::<PgConnectOptions>()?, config);
+    let pool = PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .acquire_timeout(config.acquire_timeout)
+        .connect_with(connect_options)
+        .await?;
+    // not the URL, which has the password in it
+    info!("Postgres DB connected (pool={})", config.max_connections);
+    Ok(pool)
+}
+
+/// Web sessions, kept alongside everything else in the session store crate's table for them.
+#[derive(Debug, Clone)]
+pub enum WebSessionStore {
+    Sqlite(SqliteStore),
+    #[cfg(feature = "mysql")]
+    MySql(MySqlStore),
+    #[cfg(feature = "postgres")]
+    Postgres(PostgresStore),
+}
+
+impl WebSessionStore {
+    // the store in `db`, with its table created (the crate's, so it creates it itself)
+    async fn open(db: &Db) -> anyhow::Result<Self> {
+        Ok(match db {
+            Db::Sqlite(pool) => {
+                let store = SqliteStore::new(pool.clone());
+                store.migrate().await?;
+                Self::Sqlite(store)
+            }
+            #[cfg(feature = "mysql")]
+            Db::MySql(pool) => {
+                // in the app's database rather than one of its own, which hosts offering MySQL
+                // don't always allow creating
+                let (database,): (Option<String>,) =
+                    sqlx::query_as("select database()").fetch_one(pool).await?;
+                let database = database
+                    .ok_or_else(|| anyhow::anyhow!("DATABASE_URL doesn't name a database"))?;
+                let store = MySqlStore::new(pool.clone())
+                    .with_schema_name(database)
+                    .map_err(|e| anyhow::anyhow!("invalid MySQL database name: {e}"))?;
+                store.migrate().await?;
+                Self::MySql(store)
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => {
+                let store = PostgresStore::new(pool.clone());
+                store.migrate().await?;
+                Self::Postgres(store)
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl SessionStore for WebSessionStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        match self {
+            Self::Sqlite(store) => store.create(record).await,
+            #[cfg(feature = "mysql")]
+            Self::MySql(store) => store.create(record).await,
+            #[cfg(feature = "postgres")]
+            Self::Postgres(store) => store.create(record).await,
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        match self {
+            Self::Sqlite(store) => store.save(record).await,
+            #[cfg(feature = "mysql")]
+            Self::MySql(store) => store.save(record).await,
+            #[cfg(feature = "postgres")]
+            Self::Postgres(store) => store.save(record).await,
+        }
+    }
+
+    async fn load(&self, id: &Id) -> session_store::Result<Option<Record>> {
+        match self {
+            Self::Sqlite(store) => store.load(id).await,
+            #[cfg(feature = "mysql")]
+            Self::MySql(store) => store.load(id).await,
+            #[cfg(feature = "postgres")]
+            Self::Postgres(store) => store.load(id).await,
+        }
+    }
+
+    async fn delete(&self, id: &Id) -> session_store::Result<()> {
+        match self {
+            Self::Sqlite(store) => store.delete(id).await,
+            #[cfg(feature = "mysql")]
+            Self::MySql(store) => store.delete(id).await,
+            #[cfg(feature = "postgres")]
+            Self::Postgres(store) => store.delete(id).await,
+        }
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for WebSessionStore {
+    async fn delete_expired(&self) -> session_store::Result<()> {
+        match self {
+            Self::Sqlite(store) => store.delete_expired().await,
+            #[cfg(feature = "mysql")]
+            Self::MySql(store) => store.delete_expired().await,
+            #[cfg(feature = "postgres")]
+            Self::Postgres(store) => store.delete_expired().await,
+        }
+    }
+}
+
+/// Everything the app keeps, and where it's kept.
+pub struct Stores {
+    pub status_store: StatusStore,
+    pub dead_letters: DeadLetterStore,
+    pub ingest_control: IngestControlStore,
+    pub reports: ReportStore,
+    pub handle_cache: HandleCache,
+    pub session_store: WebSessionStore,
+    pub oauth_session_store: OAuthSessionStore,
+    pub oauth_state_store: OAuthStateStore,
+}
+
+impl Stores {
+    /// The stores in the database at `DATABASE_URL` (created if missing), with OAuth sessions and
+    /// states kept and encrypted as configured.
+    pub async fn from_env() -> anyhow::Result<Self> {
+        // set up DB connection pool
+        let db = db_connect(&DbConfig::from_env()?).await?;
+
+        let (oauth_session_store, oauth_state_store) = match OAuthStoreConfig::from_env()? {
+            OAuthStoreConfig::Database => (
+                OAuthSessionStore::new(db.clone()),
+                OAuthStateStore::new(db.clone()),
+            ),
+            #[cfg(feature = "redis")]
+            OAuthStoreConfig::Redis(url) => {
+                let backend = crate::store::redis::RedisBackend::connect(&url).await?;
+                info!("Redis connected for OAuth stores");
+                (
+                    OAuthSessionStore::redis(backend.clone()),
+                    OAuthStateStore::redis(backend),
+                )
+            }
+        };
+        let (oauth_session_store, oauth_state_store) = match oauth_cipher_from_env()? {
+            Some(cipher) => (
+                oauth_session_store.with_encryption(cipher.clone()),
+                oauth_state_store.with_encryption(cipher),
+            ),
+            None => {
+                warn!("No OAuth encryption key configured, storing OAuth tokens as plaintext");
+                (oauth_session_store, oauth_state_store)
+            }
+        };
+        Self::open(db, oauth_session_store, oauth_state_store).await
+    }
+
+    /// Every store in `db_pool`, OAuth tokens included (as plaintext).
+    pub async fn sqlite(db_pool: SqlitePool) -> anyhow::Result<Self> {
+        let db = Db::from(db_pool);
+        let oauth_session_store = OAuthSessionStore::new(db.clone());
+        let oauth_state_store = OAuthStateStore::new(db.clone());
+        Self::open(db, oauth_session_store, oauth_state_store).await
+    }
+
+    // the stores in `db` alongside the given OAuth stores, with their tables created
+    async fn open(
+        db: Db,
+        oauth_session_store: OAuthSessionStore,
+        oauth_state_store: OAuthStateStore,
+    ) -> anyhow::Result<Self> {
+        store::migrate(&db).await?;
+        let status_store = StatusStore::new(db.clone());
+        let dead_letters = DeadLetterStore::new(db.clone());
+        let ingest_control = IngestControlStore::new(db.clone());
+        let reports = ReportStore::new(db.clone());
+        let handle_cache = HandleCache::new(db.clone());
+        let session_store = WebSessionStore::open(&db).await?;
+
+        Ok(Self {
+            status_store,
+            dead_letters,
+            ingest_control,
+            reports,
+            handle_cache,
+            session_store,
+            oauth_session_store,
+            oauth_state_store,
+        })
+    }
+}
+
+/// The app, put together and ready to serve.
+pub struct App {
+    pub state: Arc<AppState>,
+    pub router: Router,
+}
+
+/// Puts the app together from its configuration. Its stores, templates and OAuth client are
+/// made from the configuration too, unless they're given, e.g. to run tests against an
+/// in-memory database.
+pub struct AppBuilder {
+    config: AppConfig,
+    stores: Option<Stores>,
+    template_env: Option<Environment<'static>>,
+    oauth_client: Option<oauth::Client>,
+}
+
+impl AppBuilder {
+    pub fn new(config: AppConfig) -> Self {
+        Self {
+            config,
+            stores: None,
+            template_env: None,
+            oauth_client: None,
+        }
+    }
+
+    /// Uses `stores` instead of opening the configured database.
+    pub fn stores(mut self, stores: Stores) -> Self {
+        self.stores = Some(stores);
+        self
+    }
+
+    /// Renders pages with `template_env` instead of the compiled-in (and configured) templates.
+    pub fn template_env(mut self, template_env: Environment<'static>) -> Self {
+        self.template_env = Some(template_env);
+        self
+    }
+
+    /// Authorizes logins with `oauth_client` instead of one keeping its sessions in the stores.
+    pub fn oauth_client(mut self, oauth_client: oauth::Client) -> Self {
+        self.oauth_client = Some(oauth_client);
+        self
+    }
+
+    /// Builds the app's state and routes. Background workers the state relies on (e.g. handle
+    /// resolution) are spawned, but not the jobs `main` runs alongside the server.
+    pub async fn build(self) -> anyhow::Result<App> {
+        let config = self.config;
+        let Stores {
+            status_store,
+            dead_letters,
+            ingest_control,
+            reports,
+            handle_cache,
+            session_store,
+            oauth_session_store,
+            oauth_state_store,
+        } = match self.stores {
+            Some(stores) => stores,
+            None => Stores::from_env().await?,
+        };
+        let oauth_state_store = oauth_state_store.with_ttl(config.oauth_state_ttl);
+
+        let assets = Arc::new(Assets::new(config.assets_dir.clone()));
+        let template_env = match self.template_env {
+            Some(template_env) => template_env,
+            None => initialize_templates(config.templates_dir.as_deref(), Arc::clone(&assets))?,
+        };
+
+        // HTTP client used by oauth client and DID resolver
+        let http_client = Arc::new(oauth::http_client());
+
+        let oauth_client = match self.oauth_client {
+            Some(oauth_client) => oauth_client,
+            None => oauth::client(
+                Arc::clone(&http_client),
+                oauth_session_store.clone(),
+                oauth_state_store.clone(),
+                config.bind_addr.port(),
+                &config.plc_directory_url,
+            )?,
+        };
+        let circuit_breaker = CircuitBreaker::new(
+            config.circuit_breaker_threshold,
+            config.circuit_breaker_cooldown,
+        );
+        let handle_resolver = HandleResolver::spawn(
+            handle_cache,
+            oauth::did_resolver(Arc::clone(&http_client), &config.plc_directory_url),
+            oauth::handle_resolver(Arc::clone(&http_client))?,
+            circuit_breaker.clone(),
+            config.handle_cache_ttl,
+        );
+
+        // common app state
+        let state = Arc::new(AppState {
+            template_env,
+            oauth_client,
+            oauth_session_store,
+            oauth_state_store,
+            status_store,
+            dead_letters,
+            ingest_control,
+            reports,
+            did_resolver: oauth::did_resolver(Arc::clone(&http_client), &config.plc_directory_url),
+            http_client: Arc::clone(&http_client),
+            handle_resolver,
+            circuit_breaker,
+            follow_cache: FollowCache::new(config.follows_cache_ttl),
+            lookup_cache: LookupCache::new(config.lookup_cache_ttl),
+            login_throttle: LoginThrottle::new(
+                config.login_max_attempts,
+                config.login_attempt_window,
+                config.login_lockout,
+            ),
+            post_guard: PostGuard::new(config.post_cooldown, config.post_dedupe_window),
+            generated_avatars: GeneratedAvatars::new(Identicon),
+            profile_avatars: config.avatar_appview_url.as_deref().map(|url| {
+                ProfileAvatars::spawn(Arc::clone(&http_client), url, config.avatar_cache_ttl)
+            }),
+            metrics: Arc::new(Metrics::new(Scope::web_server(&config))?),
+            collection_toggles: CollectionToggles::new([Status::NSID, Pin::NSID, Reaction::NSID]),
+            ingester_status: IngesterStatus::new(),
+            config,
+        });
+
+        Ok(App {
+            router: router(Arc::clone(&state), session_store, assets),
+            state,
+        })
+    }
+}
+
+/// The app's routes and middleware, serving `app_state`, with web sessions kept in `session_store`
+/// and assets served from `assets`.
+pub fn router(
+    app_state: Arc<AppState>,
+    session_store: WebSessionStore,
+    assets: Arc<Assets>,
+) -> Router {
+    // user session management layer
+    let sesssion_layer = SessionManagerLayer::new(session_store)
+        // behind a proxy, cookies are still sent to us over plain HTTP
+        .with_secure(app_state.config.tls.is_some())
+        .with_expiry(Expiry::OnInactivity(Duration::weeks(1)))
+        // the `/oauth/callback` redirect doesn't set a session cookie unless this is set to Lax
+        .with_same_site(SameSite::Lax);
+
+    // small responses aren't worth the overhead, and images other than SVG (like generated
+    // avatars) are already compressed
+    let compression_layer = CompressionLayer::new().compress_when(
+        SizeAbove::new(app_state.config.compression_min_size)
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::GRPC)
+            .and(NotForContentType::SSE),
+    );
+
+    let routes = Router::new()
+        .route(
+            "/login",
+            get(login::login_form).post(login::accept_login_form),
+        )
+        .route("/oauth/callback", get(login::oauth_callback))
+        .route("/logout", post(login::logout))
+        .route(
+            "/account/delete",
+            get(account::delete_form).post(account::delete_account),
+        )
+        .route(
+            "/status",
+            // room for an image on top of the rest of the form
+            post(status::post_status).layer(DefaultBodyLimit::max(
+                validate::IMAGE_MAX_SIZE + app_state.config.max_body_size,
+            )),
+        )
+        .route("/pin", post(status::pin_status))
+        .route("/unpin", post(status::unpin_status))
+        .route("/react", post(status::react))
+        .route("/report", post(report::report_status))
+        .route("/reveal", get(home::reveal))
+        .route("/preferences/timezone", post(preferences::set_timezone))
+        .route("/preferences/theme", post(preferences::set_theme))
+        .route("/avatar/{did}", get(avatar::avatar))
+        .route("/status/{did}/{rkey}", get(permalink::show_status))
+        .route("/status/{did}/{rkey}/image", get(permalink::status_image))
+        .route("/profile", get(profile::lookup))
+        .route("/profile/{handle}/history", get(profile::history))
+        .route("/api/options", get(api::options))
+        .route("/api/{version}/options", get(api::options))
+        .route("/api/stats", get(api::stats))
+        .route("/api/{version}/stats", get(api::stats))
+        .route("/api/stats/hourly", get(api::hourly_stats))
+        .route("/api/{version}/stats/hourly", get(api::hourly_stats))
+        .route("/api/statuses", get(api::statuses))
+        .route("/api/{version}/statuses", get(api::statuses))
+        .route("/healthz", get(health::healthz))
+        .route("/", get(home::home));
+    #[cfg(feature = "metrics")]
+    let routes = routes.route("/metrics", get(metrics::metrics));
+    #[cfg(feature = "admin")]
+    let routes = routes
+        .route("/admin", get(admin::admin_dashboard))
+        .route("/admin/reports/resolve", post(admin::resolve_report))
+        .merge(admin::maintenance_routes(&app_state));
+    let routes = if app_state.config.feed_generator.is_some() {
+        routes.merge(feed_generator::routes())
+    } else {
+        routes
+    };
+
+    routes
+        .fallback(error::not_found)
+        // our forms are tiny, so anything bigger is rejected before it's read
+        .layer(DefaultBodyLimit::max(app_state.config.max_body_size))
+        // inside the error middleware, so panics render the error page
+        .layer(CatchPanicLayer::custom(error::handle_panic))
+        // a layer rather than a route layer, so unknown routes get an error page too
+        .layer(middleware::from_fn_with_state(
+            Arc::clone(&app_state),
+            error::error_middleware,
+        ))
+        // only needs to be outside the handlers, as the agent is restored by their extractors
+        .layer(middleware::from_fn(auth::cache_agent))
+        // outside the error middleware, so error pages can read the viewer's preferences
+        .layer(sesssion_layer)
+        .nest_service("/assets", assets::service(assets))
+        // outside the asset service, so assets get the headers too
+        .layer(middleware::from_fn_with_state(
+            Arc::clone(&app_state),
+            security_headers::security_headers,
+        ))
+        .layer(compression_layer)
+        // layers run outermost-last: the ID is assigned (unless the client sent one), then the
+        // request is traced under it, and it's echoed back in the response
+        .layer(PropagateRequestIdLayer::new(
+            request_id::REQUEST_ID_HEADER.clone(),
+        ))
+        .layer(TraceLayer::new_for_http().make_span_with(request_id::request_span))
+        .layer(SetRequestIdLayer::new(
+            request_id::REQUEST_ID_HEADER.clone(),
+            MakeRequestUuid,
+        ))
+        .with_state(app_state)
+}
@@ -0,0 +1,140 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use axum::{
+    Router,
+    extract::{Request, State},
+    handler::HandlerWithoutStateExt,
+    http::{HeaderValue, StatusCode, Uri, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+use sha2::{Digest, Sha256};
+use tower_http::services::ServeDir;
+
+/// The `assets/` directory, compiled in so the binary can be deployed on its own.
+#[derive(RustEmbed)]
+#[folder = "assets/"]
+struct EmbeddedAssets;
+
+// fingerprinted URLs change whenever the file does, so they can be cached forever
+const FINGERPRINTED_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// Static assets: the compiled-in ones, overridden by files in `dir` if given, so operators can
+/// customize (or add) assets without rebuilding.
+pub struct Assets {
+    dir: Option<PathBuf>,
+    // content hash of each asset, by path; `None` for assets that don't exist
+    fingerprints: RwLock<HashMap<String, Option<String>>>,
+}
+
+impl Assets {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            fingerprints: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// URL of the asset at `path` (relative to `/assets`), fingerprinted with a hash of its
+    /// contents. Hashes are computed on first use, so overrides changed while running need a
+    /// restart to be picked up.
+    pub fn url(&self, path: &str) -> String {
+        match self.fingerprint(path) {
+            Some(fingerprint) => format!("/assets/{path}?v={fingerprint}"),
+            None => format!("/assets/{path}"),
+        }
+    }
+
+    fn fingerprint(&self, path: &str) -> Option<String> {
+        if let Some(fingerprint) = self
+            .fingerprints
+            .read()
+            .expect("poisoned asset fingerprints")
+            .get(path)
+        {
+            return fingerprint.clone();
+        }
+        let fingerprint = self.contents(path).map(|contents| {
+            Sha256::digest(&contents)[..8]
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>()
+        });
+        self.fingerprints
+            .write()
+            .expect("poisoned asset fingerprints")
+            .insert(path.to_owned(), fingerprint.clone());
+        fingerprint
+    }
+
+    // the contents of the asset that's served at `path`
+    fn contents(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        let overridden = self
+            .dir
+            .as_ref()
+            .and_then(|dir| std::fs::read(dir.join(path)).ok());
+        match overridden {
+            Some(contents) => Some(Cow::Owned(contents)),
+            None => EmbeddedAssets::get(path).map(|file| file.data),
+        }
+    }
+}
+
+/// Service for `/assets`.
+pub fn service(assets: Arc<Assets>) -> Router {
+    let router = match &assets.dir {
+        Some(dir) => {
+            Router::new().fallback_service(ServeDir::new(dir).fallback(embedded.into_service()))
+        }
+        None => Router::new().fallback(embedded),
+    };
+    router.layer(middleware::from_fn_with_state(assets, cache_control))
+}
+
+// the request's path is relative to `/assets`, which is nested away
+async fn embedded(uri: Uri) -> Response {
+    match EmbeddedAssets::get(uri.path().trim_start_matches('/')) {
+        Some(file) => (
+            [(header::CONTENT_TYPE, file.metadata.mimetype().to_owned())],
+            file.data,
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+// assets requested by their current fingerprinted URL are cached for good; anything else (e.g. a
+// stale fingerprint, or a bare URL from an overridden template) has to be revalidated
+async fn cache_control(
+    State(assets): State<Arc<Assets>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().trim_start_matches('/').to_owned();
+    let requested = request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|param| param.strip_prefix("v="))
+            .map(str::to_owned)
+    });
+
+    let mut response = next.run(request).await;
+    if response.status().is_success() {
+        let current = requested.is_some() && requested == assets.fingerprint(&path);
+        response.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(if current {
+                FINGERPRINTED_CACHE_CONTROL
+            } else {
+                "no-cache"
+            }),
+        );
+    }
+    response
+}
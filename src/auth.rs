@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::request::Parts,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::OnceCell;
+use tower_sessions::Session;
+use tracing::warn;
+
+use crate::{
+    AppState,
+    error::Error,
+    htmx::HxRequest,
+    oauth::{ATProtoAgent, session_agent},
+};
+
+// the request's agent, once someone's restored it
+#[derive(Clone, Default)]
+struct CachedAgent(Arc<OnceCell<Option<Arc<ATProtoAgent>>>>);
+
+/// Middleware letting everything handling a request share the user's agent: their session is
+/// restored (which may refresh their tokens with their PDS) the first time [`RequireAuth`] or
+/// [`OptionalAuth`] asks for it, and at most once per request.
+pub async fn cache_agent(mut request: Request, next: Next) -> Response {
+    request.extensions_mut().insert(CachedAgent::default());
+    next.run(request).await
+}
+
+// the user's agent, from the request's cache if it has one
+async fn request_agent(
+    parts: &Parts,
+    state: &AppState,
+    session: &Session,
+) -> Result<Option<Arc<ATProtoAgent>>, Error> {
+    let restore = || async { Ok::<_, Error>(session_agent(state, session).await?.map(Arc::new)) };
+    match parts.extensions.get::<CachedAgent>() {
+        Some(CachedAgent(agent)) => agent.get_or_try_init(restore).await.cloned(),
+        None => restore().await,
+    }
+}
+
+/// The logged-in user's agent, for handlers that act on their behalf. Anyone not logged in is
+/// redirected to log in instead.
+pub struct RequireAuth(pub Arc<ATProtoAgent>);
+
+impl FromRequestParts<Arc<AppState>> for RequireAuth {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        match request_agent(parts, state, &session).await {
+            Ok(Some(agent)) => Ok(Self(agent)),
+            Ok(None) => {
+                let Ok(hx_request) = HxRequest::from_request_parts(parts, state).await;
+                Err(hx_request.redirect("/login"))
+            }
+            Err(e) => Err(e.into_response()),
+        }
+    }
+}
+
+/// The logged-in user's agent, if anyone's logged in. Users whose PDS is unreachable are treated
+/// as logged out, so pages that work either way still render.
+pub struct OptionalAuth(pub Option<Arc<ATProtoAgent>>);
+
+impl FromRequestParts<Arc<AppState>> for OptionalAuth {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        match request_agent(parts, state, &session).await {
+            Ok(agent) => Ok(Self(agent)),
+            Err(e) if e.is_upstream_outage() => {
+                warn!("treating the user as logged out: {e}");
+                Ok(Self(None))
+            }
+            Err(e) => Err(e.into_response()),
+        }
+    }
+}
@@ -0,0 +1,444 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use atrium_api::{
+    app::bsky::actor::profile,
+    client::AtpServiceClient,
+    com::atproto::repo::get_record,
+    types::{
+        BlobRef, TryFromUnknown, TypedBlobRef,
+        string::{Did, Nsid, RecordKey},
+    },
+    xrpc::{
+        self,
+        error::{XrpcError, XrpcErrorKind},
+    },
+};
+use atrium_oauth::DefaultHttpClient;
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::{IntoResponse, Redirect, Response},
+};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::{AppState, backfill::ServiceClient, error::Error, store::StatusFilter};
+
+// capacity of the profile lookup queue; when it's full, lookups are skipped until the next view
+const QUEUE_CAPACITY: usize = 1024;
+// most profile avatars kept; past it, the longest-cached ones make way for new lookups
+const MAX_CACHED_AVATARS: usize = 10_000;
+
+/// Generates a placeholder avatar image deterministically from a seed (typically a DID).
+pub trait AvatarGenerator: Send + Sync {
+    fn content_type(&self) -> &'static str;
+    fn generate(&self, seed: &str) -> String;
+}
+
+// FNV-1a; we need a hash that's stable across builds and platforms, which std's hashers don't
+// guarantee
+fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Classic 5x5 horizontally-mirrored identicon rendered as SVG.
+#[derive(Debug, Default)]
+pub struct Identicon;
+
+impl Identicon {
+    const GRID: usize = 5;
+    const CELL: usize = 10;
+}
+
+impl AvatarGenerator for Identicon {
+    fn content_type(&self) -> &'static str {
+        "image/svg+xml"
+    }
+
+    fn generate(&self, seed: &str) -> String {
+        let hash = fnv1a(seed.as_bytes());
+        let hue = hash % 360;
+        let size = Self::GRID * Self::CELL;
+
+        let mut cells = String::new();
+        // only need to decide the left half (plus middle column), the rest is mirrored
+        let half = Self::GRID.div_ceil(2);
+        for row in 0..Self::GRID {
+            for col in 0..half {
+                let bit = 8 + row * half + col;
+                if (hash >> bit) & 1 == 0 {
+                    continue;
+                }
+                let mirror = Self::GRID - 1 - col;
+                let columns = if mirror == col {
+                    vec![col]
+                } else {
+                    vec![col, mirror]
+                };
+                for x in columns {
+                    cells.push_str(&format!(
+                        r#"<rect x="{}" y="{}" width="{cell}" height="{cell}"/>"#,
+                        x * Self::CELL,
+                        row * Self::CELL,
+                        cell = Self::CELL,
+                    ));
+                }
+            }
+        }
+
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {size} {size}" width="{size}" height="{size}"><rect width="{size}" height="{size}" fill="hsl({hue}, 20%, 92%)"/><g fill="hsl({hue}, 65%, 50%)">{cells}</g></svg>"#
+        )
+    }
+}
+
+/// Avatars generated for DIDs. They're cheap to generate, and any DID can be asked for, so
+/// they're generated afresh each time rather than kept around.
+pub struct GeneratedAvatars {
+    generator: Box<dyn AvatarGenerator>,
+}
+
+impl GeneratedAvatars {
+    pub fn new(generator: impl AvatarGenerator + 'static) -> Self {
+        Self {
+            generator: Box::new(generator),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        self.generator.content_type()
+    }
+
+    pub fn get(&self, did: &Did) -> String {
+        self.generator.generate(did.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CachedAvatar {
+    // CDN URL of the profile's avatar image, if it has one
+    url: Option<String>,
+    fetched_at: Instant,
+}
+
+/// Looks up users' Bluesky profile avatars from an AppView, without authentication. Lookups
+/// happen in the background, so callers fall back to a generated avatar until one completes. At
+/// most [`MAX_CACHED_AVATARS`] are kept.
+#[derive(Clone)]
+pub struct ProfileAvatars {
+    cache: Arc<RwLock<HashMap<Did, CachedAvatar>>>,
+    queue: mpsc::Sender<Did>,
+    // DIDs currently queued or being looked up
+    pending: Arc<Mutex<HashSet<Did>>>,
+    ttl: Duration,
+}
+
+// CDN URL of a profile's avatar, or `None` if the profile doesn't exist or has no avatar
+async fn fetch_avatar(
+    client: &AtpServiceClient<ServiceClient>,
+    did: &Did,
+) -> Result<Option<String>, Error> {
+    let output = match client
+        .service
+        .com
+        .atproto
+        .repo
+        .get_record(
+            get_record::ParametersData {
+                cid: None,
+                collection: Nsid::new("app.bsky.actor.profile".to_owned())
+                    .expect("unexpected Nsid failure"),
+                repo: did.clone().into(),
+                rkey: RecordKey::new("self".to_owned()).expect("unexpected record key failure"),
+            }
+            .into(),
+        )
+        .await
+    {
+        Ok(output) => output,
+        Err(xrpc::Error::XrpcResponse(XrpcError {
+            error: Some(XrpcErrorKind::Custom(get_record::Error::RecordNotFound(_))),
+            ..
+        })) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let profile = profile::RecordData::try_from_unknown(output.data.value.clone())
+        .map_err(Error::ProfileParse)?;
+    let cid = match profile.avatar {
+        Some(BlobRef::Typed(TypedBlobRef::Blob(blob))) => blob.r#ref.0.to_string(),
+        Some(BlobRef::Untyped(blob)) => blob.cid,
+        None => return Ok(None),
+    };
+    Ok(Some(format!(
+        "https://cdn.bsky.app/img/avatar/plain/{}/{cid}@jpeg",
+        did.as_str()
+    )))
+}
+
+// makes room in a full cache by dropping its longest-cached avatar
+fn evict_oldest(cache: &mut HashMap<Did, CachedAvatar>) {
+    let oldest = cache
+        .iter()
+        .min_by_key(|(_, cached)| cached.fetched_at)
+        .map(|(did, _)| did.clone());
+    if let Some(did) = oldest {
+        cache.remove(&did);
+    }
+}
+
+impl ProfileAvatars {
+    /// Creates the lookup cache and spawns its background worker, which queries the AppView at
+    /// `appview_url`. Cached avatars older than `ttl` are looked up again.
+    pub fn spawn(http_client: Arc<DefaultHttpClient>, appview_url: &str, ttl: Duration) -> Self {
+        let (queue, mut rx) = mpsc::channel::<Did>(QUEUE_CAPACITY);
+        let cache = Arc::new(RwLock::new(HashMap::new()));
+        let pending = Arc::new(Mutex::new(HashSet::new()));
+
+        let client = AtpServiceClient::new(ServiceClient::new(http_client, appview_url));
+        let worker_cache = Arc::clone(&cache);
+        let worker_pending = Arc::clone(&pending);
+        tokio::spawn(async move {
+            while let Some(did) = rx.recv().await {
+                match fetch_avatar(&client, &did).await {
+                    Ok(url) => {
+                        debug!("Fetched avatar for {}: {url:?}", did.as_str());
+                        let mut cache = worker_cache.write().expect("poisoned lock");
+                        if cache.len() >= MAX_CACHED_AVATARS && !cache.contains_key(&did) {
+                            evict_oldest(&mut cache);
+                        }
+                        cache.insert(
+                            did.clone(),
+                            CachedAvatar {
+                                url,
+                                fetched_at: Instant::now(),
+                            },
+                        );
+                    }
+                    Err(e) => warn!("failed to fetch avatar for {}: {e}", did.as_str()),
+                }
+                worker_pending.lock().expect("poisoned lock").remove(&did);
+            }
+        });
+
+        Self {
+            cache,
+            queue,
+            pending,
+            ttl,
+        }
+    }
+
+    fn enqueue(&self, did: &Did) {
+        let mut pending = self.pending.lock().expect("poisoned lock");
+        if pending.contains(did) {
+            return;
+        }
+        if self.queue.try_send(did.clone()).is_ok() {
+            pending.insert(did.clone());
+        }
+    }
+
+    /// The user's profile avatar URL, if known, queueing a lookup if it isn't cached (or is
+    /// stale).
+    pub fn lookup(&self, did: &Did) -> Option<String> {
+        let cached = self.cache.read().expect("poisoned lock").get(did).cloned();
+        match cached {
+            Some(cached) => {
+                if cached.fetched_at.elapsed() > self.ttl {
+                    self.enqueue(did);
+                }
+                cached.url
+            }
+            None => {
+                self.enqueue(did);
+                None
+            }
+        }
+    }
+}
+
+/// URL of the avatar for a DID: the user's profile avatar if known, otherwise a generated one.
+pub fn avatar_url(did: &Did) -> String {
+    format!("/avatar/{}", did.as_str())
+}
+
+pub async fn avatar(
+    State(state): State<Arc<AppState>>,
+    Path(did): Path<String>,
+) -> Result<Response, Error> {
+    let did = Did::new(did).map_err(Error::InvalidDid)?;
+    // only authors of statuses we've stored are looked up, so requests for made-up DIDs can't
+    // send us to the AppView or fill up the cache
+    let profile_avatar = match &state.profile_avatars {
+        Some(profile_avatars)
+            if state
+                .status_store
+                .fetch_one(&StatusFilter::new().author(did.clone()))
+                .await?
+                .is_some() =>
+        {
+            profile_avatars.lookup(&did)
+        }
+        _ => None,
+    };
+    if let Some(url) = profile_avatar {
+        return Ok((
+            [(header::CACHE_CONTROL, "public, max-age=3600")],
+            Redirect::temporary(&url),
+        )
+            .into_response());
+    }
+
+    let avatar = state.generated_avatars.get(&did);
+    // generated avatars are a pure function of the DID, but may be replaced by a profile avatar
+    // once it's been looked up
+    let cache_control = match state.profile_avatars {
+        Some(_) => "public, max-age=300",
+        None => "public, max-age=31536000, immutable",
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, state.generated_avatars.content_type()),
+            (header::CACHE_CONTROL, cache_control),
+        ],
+        avatar,
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{method, path, query_param},
+    };
+
+    use super::*;
+    use crate::{
+        oauth,
+        test_support::{did, xrpc_error},
+    };
+
+    const ALICE: &str = "did:plc:alice0000000000000000000";
+    const AVATAR_CID: &str = "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku";
+
+    fn client(appview: &MockServer) -> AtpServiceClient<ServiceClient> {
+        AtpServiceClient::new(ServiceClient::new(
+            Arc::new(oauth::http_client()),
+            appview.uri(),
+        ))
+    }
+
+    // has `appview` answer a fetch of Alice's profile record with `response`
+    async fn mock_profile(appview: &MockServer, response: ResponseTemplate) {
+        Mock::given(method("GET"))
+            .and(path("/xrpc/com.atproto.repo.getRecord"))
+            .and(query_param("repo", ALICE))
+            .and(query_param("collection", "app.bsky.actor.profile"))
+            .respond_with(response)
+            .mount(appview)
+            .await;
+    }
+
+    fn profile(value: serde_json::Value) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({
+            "uri": format!("at://{ALICE}/app.bsky.actor.profile/self"),
+            "cid": "bafyreie5737gdxlw5i64vzichcalba3z2v5n6icifvx5xytvske7mr3hpm",
+            "value": value,
+        }))
+    }
+
+    #[tokio::test]
+    async fn avatar_is_on_the_cdn() {
+        let appview = MockServer::start().await;
+        mock_profile(
+            &appview,
+            profile(json!({
+                "$type": "app.bsky.actor.profile",
+                "displayName": "Alice",
+                "avatar": {
+                    "$type": "blob",
+                    "ref": { "$link": AVATAR_CID },
+                    "mimeType": "image/jpeg",
+                    "size": 12345,
+                },
+            })),
+        )
+        .await;
+
+        let url = fetch_avatar(&client(&appview), &did(ALICE)).await;
+        assert_eq!(
+            url.expect("profile is fetched"),
+            Some(format!(
+                "https://cdn.bsky.app/img/avatar/plain/{ALICE}/{AVATAR_CID}@jpeg"
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn profile_without_avatar_has_none() {
+        let appview = MockServer::start().await;
+        mock_profile(
+            &appview,
+            profile(json!({ "$type": "app.bsky.actor.profile", "displayName": "Alice" })),
+        )
+        .await;
+
+        let url = fetch_avatar(&client(&appview), &did(ALICE)).await;
+        assert_eq!(url.expect("profile is fetched"), None);
+    }
+
+    #[tokio::test]
+    async fn missing_profile_has_no_avatar() {
+        let appview = MockServer::start().await;
+        mock_profile(&appview, xrpc_error(400, "RecordNotFound")).await;
+
+        let url = fetch_avatar(&client(&appview), &did(ALICE)).await;
+        assert_eq!(url.expect("missing profile isn't an error"), None);
+    }
+
+    #[test]
+    fn full_cache_drops_its_oldest_avatar() {
+        let now = Instant::now();
+        let mut cache = HashMap::from([
+            (
+                did(ALICE),
+                CachedAvatar {
+                    url: None,
+                    fetched_at: now - Duration::from_secs(60),
+                },
+            ),
+            (
+                did("did:plc:bob00000000000000000000000"),
+                CachedAvatar {
+                    url: None,
+                    fetched_at: now,
+                },
+            ),
+        ]);
+
+        evict_oldest(&mut cache);
+
+        assert!(!cache.contains_key(&did(ALICE)));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn unreachable_appview_fails() {
+        let appview = MockServer::start().await;
+        mock_profile(&appview, ResponseTemplate::new(404)).await;
+
+        assert!(fetch_avatar(&client(&appview), &did(ALICE)).await.is_err());
+    }
+}
@@ -0,0 +1,408 @@
+use std::{pin::pin, sync::Arc, time::Duration};
+
+use atrium_api::{
+    client::AtpServiceClient,
+    com::atproto::{
+        repo::{get_record, list_records},
+        sync::{get_blob, list_repos},
+    },
+    types::{
+        Collection, TryFromUnknown,
+        string::{Cid, Datetime, Did, RecordKey},
+    },
+    xrpc::{
+        self, HttpClient, XrpcClient,
+        error::{XrpcError, XrpcErrorKind},
+        http::{Request, Response},
+    },
+};
+use atrium_common::resolver::Resolver;
+use atrium_oauth::DefaultHttpClient;
+use futures::{Stream, TryStreamExt, stream};
+use tracing::{error, info, warn};
+
+use crate::{
+    AppState,
+    config::{DidFilter, is_allowed_status},
+    error::Error,
+    lexicons::xyz::statusphere::{Status, status::RecordData},
+    oauth::{self, DidResolver},
+    store::{
+        Status as StoreStatus, StatusImage, StatusStore, Visibility, sanitize_content_warning,
+    },
+};
+
+/// Unauthenticated XRPC client pointed at a specific service (PDS or relay).
+pub struct ServiceClient {
+    http_client: Arc<DefaultHttpClient>,
+    base_uri: String,
+}
+
+impl ServiceClient {
+    pub fn new(http_client: Arc<DefaultHttpClient>, base_uri: impl Into<String>) -> Self {
+        Self {
+            http_client,
+            base_uri: base_uri.into(),
+        }
+    }
+}
+
+impl HttpClient for ServiceClient {
+    async fn send_http(
+        &self,
+        request: Request<Vec<u8>>,
+    ) -> core::result::Result<Response<Vec<u8>>, Box<dyn std::error::Error + Send + Sync + 'static>>
+    {
+        self.http_client.send_http(request).await
+    }
+}
+
+impl XrpcClient for ServiceClient {
+    fn base_uri(&self) -> String {
+        self.base_uri.clone()
+    }
+}
+
+/// Resolves the PDS endpoint for a DID.
+pub async fn resolve_pds(resolver: &DidResolver, did: &Did) -> Result<String, Error> {
+    resolver
+        .resolve(did)
+        .await?
+        .get_pds_endpoint()
+        .ok_or_else(|| Error::MissingPds(did.as_str().to_owned()))
+}
+
+/// Fetches all `xyz.statusphere.status` records in a user's repo, directly from their PDS, with
+/// creation times more than `max_clock_skew` in the future clamped as the ingester clamps them.
+/// They're dated as indexed when they were created, not now, so old statuses don't jump to the
+/// top of the feed when stored.
+pub async fn fetch_repo_statuses(
+    http_client: Arc<DefaultHttpClient>,
+    resolver: &DidResolver,
+    did: &Did,
+    max_clock_skew: Duration,
+) -> Result<Vec<StoreStatus>, Error> {
+    let pds = resolve_pds(resolver, did).await?;
+    let client = AtpServiceClient::new(ServiceClient::new(http_client, pds));
+
+    let mut statuses = vec![];
+    let mut cursor = None;
+    loop {
+        let (page, next) = fetch_status_page(&client, did, cursor, None, max_clock_skew).await?;
+        statuses.extend(page);
+        match next {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    Ok(statuses)
+}
+
+/// Fetches up to `limit` of the most recent `xyz.statusphere.status` records in a user's repo,
+/// in a single request, clamped and dated as in [`fetch_repo_statuses`].
+pub async fn fetch_recent_repo_statuses(
+    http_client: Arc<DefaultHttpClient>,
+    resolver: &DidResolver,
+    did: &Did,
+    limit: u8,
+    max_clock_skew: Duration,
+) -> Result<Vec<StoreStatus>, Error> {
+    let pds = resolve_pds(resolver, did).await?;
+    let client = AtpServiceClient::new(ServiceClient::new(http_client, pds));
+    let (statuses, _) = fetch_status_page(&client, did, None, Some(limit), max_clock_skew).await?;
+    Ok(statuses)
+}
+
+// one page of `listRecords` over a user's statuses, newest first, and the cursor of the next
+// page, if there may be one
+async fn fetch_status_page(
+    client: &AtpServiceClient<ServiceClient>,
+    did: &Did,
+    cursor: Option<String>,
+    limit: Option<u8>,
+    max_clock_skew: Duration,
+) -> Result<(Vec<StoreStatus>, Option<String>), Error> {
+    let output = client
+        .service
+        .com
+        .atproto
+        .repo
+        .list_records(
+            list_records::ParametersData {
+                collection: Status::NSID
+                    .parse()
+                    .expect("NSID is generated, should never fail to parse"),
+                cursor,
+                limit: limit.and_then(|limit| limit.try_into().ok()),
+                repo: did.clone().into(),
+                reverse: None,
+            }
+            .into(),
+        )
+        .await?;
+    if output.data.records.is_empty() {
+        return Ok((vec![], None));
+    }
+    let mut statuses = vec![];
+    for record in &output.data.records {
+        match RecordData::try_from_unknown(record.value.clone()) {
+            Ok(RecordData {
+                status,
+                created_at,
+                content_warning,
+                image,
+            }) => statuses.push(
+                StoreStatus {
+                    uri: record.uri.clone(),
+                    author_did: did.clone(),
+                    status,
+                    created_at,
+                    indexed_at: Datetime::now(),
+                    raw_created_at: None,
+                    visibility: Visibility::Public,
+                    content_warning: sanitize_content_warning(content_warning),
+                    cid: Some(record.cid.as_ref().to_string()),
+                    image: image.as_ref().and_then(StatusImage::from_blob),
+                }
+                .into_historical(max_clock_skew),
+            ),
+            Err(e) => warn!("skipping malformed status record {}: {e}", record.uri),
+        }
+    }
+    Ok((statuses, output.data.cursor.clone()))
+}
+
+/// Fetches a single `xyz.statusphere.status` record directly from its author's PDS, or `None` if
+/// the record doesn't exist. It's clamped and dated as in [`fetch_repo_statuses`].
+pub async fn fetch_repo_status(
+    http_client: Arc<DefaultHttpClient>,
+    resolver: &DidResolver,
+    did: &Did,
+    rkey: RecordKey,
+    max_clock_skew: Duration,
+) -> Result<Option<StoreStatus>, Error> {
+    let pds = resolve_pds(resolver, did).await?;
+    let client = AtpServiceClient::new(ServiceClient::new(http_client, pds));
+
+    let output = match client
+        .service
+        .com
+        .atproto
+        .repo
+        .get_record(
+            get_record::ParametersData {
+                cid: None,
+                collection: Status::NSID
+                    .parse()
+                    .expect("NSID is generated, should never fail to parse"),
+                repo: did.clone().into(),
+                rkey,
+            }
+            .into(),
+        )
+        .await
+    {
+        Ok(output) => output,
+        Err(xrpc::Error::XrpcResponse(XrpcError {
+            error: Some(XrpcErrorKind::Custom(get_record::Error::RecordNotFound(_))),
+            ..
+        })) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let RecordData {
+        status,
+        created_at,
+        content_warning,
+        image,
+    } = match RecordData::try_from_unknown(output.data.value.clone()) {
+        Ok(record) => record,
+        Err(e) => {
+            warn!("ignoring malformed status record {}: {e}", output.data.uri);
+            return Ok(None);
+        }
+    };
+    Ok(Some(
+        StoreStatus {
+            uri: output.data.uri.clone(),
+            author_did: did.clone(),
+            status,
+            created_at,
+            indexed_at: Datetime::now(),
+            raw_created_at: None,
+            visibility: Visibility::Public,
+            content_warning: sanitize_content_warning(content_warning),
+            cid: output.data.cid.as_ref().map(|cid| cid.as_ref().to_string()),
+            image: image.as_ref().and_then(StatusImage::from_blob),
+        }
+        .into_historical(max_clock_skew),
+    ))
+}
+
+/// Fetches a blob from `did`'s repo directly from their PDS, or `None` if the PDS doesn't have
+/// it.
+pub async fn fetch_blob(
+    http_client: Arc<DefaultHttpClient>,
+    resolver: &DidResolver,
+    did: &Did,
+    cid: Cid,
+) -> Result<Option<Vec<u8>>, Error> {
+    let pds = resolve_pds(resolver, did).await?;
+    let client = AtpServiceClient::new(ServiceClient::new(http_client, pds));
+
+    match client
+        .service
+        .com
+        .atproto
+        .sync
+        .get_blob(
+            get_blob::ParametersData {
+                cid,
+                did: did.clone(),
+            }
+            .into(),
+        )
+        .await
+    {
+        Ok(blob) => Ok(Some(blob)),
+        Err(xrpc::Error::XrpcResponse(XrpcError {
+            error: Some(XrpcErrorKind::Custom(get_blob::Error::BlobNotFound(_))),
+            ..
+        })) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Enumerates all repos hosted by a relay (or PDS) via `com.atproto.sync.listRepos`, a page at a
+/// time.
+///
+/// Note that on a full-network relay this is a *lot* of repos.
+pub fn list_all_repos(
+    http_client: Arc<DefaultHttpClient>,
+    relay_url: &str,
+) -> impl Stream<Item = Result<Vec<Did>, Error>> {
+    let client = AtpServiceClient::new(ServiceClient::new(http_client, relay_url));
+    // the cursor of the next page, or `None` once the last page has been listed
+    stream::try_unfold((client, Some(None)), |(client, cursor)| async move {
+        let Some(cursor) = cursor else {
+            return Ok::<_, Error>(None);
+        };
+        let output = client
+            .service
+            .com
+            .atproto
+            .sync
+            .list_repos(
+                list_repos::ParametersData {
+                    cursor,
+                    limit: None,
+                }
+                .into(),
+            )
+            .await?;
+        let dids = output
+            .data
+            .repos
+            .iter()
+            .map(|repo| repo.did.clone())
+            .collect::<Vec<_>>();
+        let next = match output.data.cursor.clone() {
+            Some(next) if !dids.is_empty() => Some(Some(next)),
+            _ => None,
+        };
+        Ok(Some((dids, (client, next))))
+    })
+}
+
+/// Where to find the repos to backfill.
+#[derive(Debug, Clone)]
+pub enum BackfillSource {
+    /// An explicit list of DIDs.
+    Dids(Vec<Did>),
+    /// Every repo known to a relay.
+    Relay(String),
+}
+
+pub struct Backfill {
+    pub http_client: Arc<DefaultHttpClient>,
+    pub did_resolver: DidResolver,
+    pub status_store: StatusStore,
+    pub status_options: Vec<String>,
+    pub did_filter: DidFilter,
+    pub max_clock_skew: Duration,
+}
+
+impl Backfill {
+    /// Backfill into the app's status store, of the statuses its config accepts.
+    pub fn new(state: &AppState) -> Self {
+        Self {
+            http_client: Arc::clone(&state.http_client),
+            did_resolver: oauth::did_resolver(
+                Arc::clone(&state.http_client),
+                &state.config.plc_directory_url,
+            ),
+            status_store: state.status_store.clone(),
+            status_options: state.config.status_options.clone(),
+            did_filter: state.config.did_filter.clone(),
+            max_clock_skew: state.config.max_clock_skew,
+        }
+    }
+
+    /// Backfills the statuses of a single repo, returning the number of statuses stored.
+    pub async fn backfill_repo(&self, did: &Did) -> Result<usize, Error> {
+        if !self.did_filter.allows(did.as_str()) {
+            return Ok(0);
+        }
+        let statuses = fetch_repo_statuses(
+            Arc::clone(&self.http_client),
+            &self.did_resolver,
+            did,
+            self.max_clock_skew,
+        )
+        .await?
+        .into_iter()
+        .filter(|status| is_allowed_status(&self.status_options, &status.status))
+        .collect::<Vec<_>>();
+        let count = statuses.len();
+        self.status_store.insert_many(statuses).await?;
+        Ok(count)
+    }
+
+    // backfills a repo, logging rather than returning failures: one bad repo shouldn't stop the
+    // whole backfill
+    async fn backfill_or_log(&self, did: &Did) -> usize {
+        match self.backfill_repo(did).await {
+            Ok(count) => count,
+            Err(e) => {
+                error!("backfill of {} failed: {e}", did.as_str());
+                0
+            }
+        }
+    }
+
+    pub async fn run(&self, source: BackfillSource) -> Result<(), Error> {
+        let mut total = 0;
+        let mut repos = 0;
+        match source {
+            BackfillSource::Dids(dids) => {
+                info!("Backfilling statuses from {} repos", dids.len());
+                for did in &dids {
+                    total += self.backfill_or_log(did).await;
+                }
+                repos = dids.len();
+            }
+            // listed a page at a time, as a relay can have far too many repos to hold at once
+            BackfillSource::Relay(relay_url) => {
+                info!("Backfilling statuses from the repos on {relay_url}");
+                let mut pages = pin!(list_all_repos(Arc::clone(&self.http_client), &relay_url));
+                while let Some(dids) = pages.try_next().await? {
+                    for did in &dids {
+                        total += self.backfill_or_log(did).await;
+                    }
+                    repos += dids.len();
+                }
+            }
+        }
+        info!("Backfill complete: {total} statuses stored from {repos} repos");
+        Ok(())
+    }
+}
@@ -0,0 +1,7 @@
+use statusphere_example_rs::ConsumerRegistry;
+
+// runs only the ingester, against the same database as the web server
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    statusphere_example_rs::run_ingester(ConsumerRegistry::new()).await
+}
@@ -0,0 +1,648 @@
+#[cfg(test)]
+use std::cell::Cell;
+use std::{
+    collections::HashSet,
+    env,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+#[cfg(feature = "ingester")]
+use atproto_jetstream::connection::bluesky_instances::US_EAST_1;
+use atrium_api::types::string::Did;
+#[cfg(feature = "ingester")]
+use atrium_api::types::{Collection, string::Nsid};
+use atrium_identity::did::DEFAULT_PLC_DIRECTORY_URL;
+use axum::http::Uri;
+use tower_sessions_sqlx_store::sqlx::sqlite::{SqliteJournalMode, SqliteSynchronous};
+
+#[cfg(feature = "admin")]
+use crate::roles::RoleMap;
+use crate::{
+    backfill::BackfillSource, cursor::CursorCodec, envelope::EnvelopeCipher,
+    feed_generator::FeedGeneratorConfig, security_headers::SecurityHeaders, tls::TlsConfig,
+    views::DatePolicy,
+};
+#[cfg(feature = "ingester")]
+use crate::{
+    ingester::{BatchConfig, IngestSource},
+    lexicons::xyz::statusphere::{Pin, Reaction, Status},
+};
+
+pub const DEFAULT_STATUS_OPTIONS: [&str; 27] = [
+    "👍",
+    "👎",
+    "💙",
+    "🥹",
+    "😧",
+    "😤",
+    "🙃",
+    "😉",
+    "😎",
+    "🤓",
+    "🤨",
+    "🥳",
+    "😭",
+    "🤯",
+    "🫡",
+    "💀",
+    "✊",
+    "🤘",
+    "👀",
+    "🧠",
+    "👩‍💻",
+    "🧑‍💻",
+    "🥷",
+    "🧌",
+    "🦋",
+    "🚀",
+    "🦀",
+];
+
+/// Emoji offered as reactions under statuses.
+pub const DEFAULT_REACTION_OPTIONS: [&str; 5] = ["👍", "💙", "😂", "😮", "😭"];
+
+pub struct AppConfig {
+    /// Address the web server listens on.
+    pub bind_addr: SocketAddr,
+    pub show_error_messages: bool,
+    /// Statuses (emoji) users are allowed to post, and which the ingester accepts.
+    pub status_options: Vec<String>,
+    /// Emoji users can react to statuses with, and which the ingester accepts as reactions.
+    pub reaction_options: Vec<String>,
+    /// Which authors' statuses the ingester accepts.
+    pub did_filter: DidFilter,
+    /// Repos to backfill historical statuses from at startup, if any.
+    pub backfill: Option<BackfillSource>,
+    /// Whether statuses fetched from the PDS when looking up an unknown user are stored.
+    pub lookup_backfill: bool,
+    /// Where the ingester reads repo events from.
+    #[cfg(feature = "ingester")]
+    pub ingest_source: IngestSource,
+    /// Collections (NSIDs) the ingester subscribes to, besides those of registered consumers.
+    #[cfg(feature = "ingester")]
+    pub ingest_collections: Vec<String>,
+    /// Whether the web server runs the ingester itself; false when it's deployed on its own, as
+    /// the `ingester` binary.
+    #[cfg(feature = "ingester")]
+    pub embedded_ingester: bool,
+    /// Where the `ingester` binary serves its `/metrics`, if anywhere.
+    #[cfg(all(feature = "ingester", feature = "metrics"))]
+    pub ingester_metrics_addr: Option<SocketAddr>,
+    /// How far in the future an ingested status's `created_at` may be before it's clamped.
+    pub max_clock_skew: Duration,
+    /// How the ingester batches inserts.
+    #[cfg(feature = "ingester")]
+    pub ingest_batch: BatchConfig,
+    /// Worker tasks processing ingested events in parallel. Events about the same repo are still
+    /// processed in order.
+    #[cfg(feature = "ingester")]
+    pub ingest_workers: usize,
+    /// Signs and verifies pagination cursors handed out to clients.
+    pub cursor_codec: CursorCodec,
+    /// How often new statuses are folded into the aggregate rollup tables.
+    pub rollup_interval: Duration,
+    /// How often logged-in users' repos are reconciled with the store; `None` disables it.
+    pub reconcile_interval: Option<Duration>,
+    /// How often expired web sessions and OAuth states are deleted.
+    pub session_cleanup_interval: Duration,
+    /// How long a user has to complete an OAuth login flow.
+    pub oauth_state_ttl: Duration,
+    /// How long resolved handles are used before being re-resolved.
+    pub handle_cache_ttl: Duration,
+    /// How long who a viewer follows is used before being fetched from their PDS again.
+    pub follows_cache_ttl: Duration,
+    /// How long statuses looked up on an unknown user's PDS are shown before looking again.
+    pub lookup_cache_ttl: Duration,
+    /// PLC directory `did:plc` DIDs are resolved with.
+    pub plc_directory_url: String,
+    /// How long requests to PDSes and identity services may take while serving a page.
+    pub upstream_timeout: Duration,
+    /// Consecutive failures after which calls to an upstream are failed fast.
+    pub circuit_breaker_threshold: u32,
+    /// How long calls to a failing upstream are failed fast before it's tried again.
+    pub circuit_breaker_cooldown: Duration,
+    /// AppView to look up profile avatars from; `None` shows only generated avatars.
+    pub avatar_appview_url: Option<String>,
+    /// How long looked-up profile avatars are used before being looked up again.
+    pub avatar_cache_ttl: Duration,
+    /// Which status timestamps to display.
+    pub date_policy: DatePolicy,
+    /// How much earlier than its indexing time a status can claim to be created before it's
+    /// considered backdated.
+    pub backdate_threshold: Duration,
+    /// Elevated roles (owner, moderator) of specific users.
+    #[cfg(feature = "admin")]
+    pub roles: RoleMap,
+    /// Security headers (CSP, HSTS, ...) added to responses.
+    pub security_headers: SecurityHeaders,
+    /// Whether to take clients' addresses from `X-Forwarded-For` and their scheme from
+    /// `X-Forwarded-Proto`, when behind a reverse proxy.
+    pub trust_proxy: bool,
+    /// How many reverse proxies in front of the server append to `X-Forwarded-For`.
+    pub trusted_proxy_hops: usize,
+    /// Login attempts a client may make per `login_attempt_window` before being locked out.
+    pub login_max_attempts: u32,
+    pub login_attempt_window: Duration,
+    /// How long a client who made too many login attempts is locked out for.
+    pub login_lockout: Duration,
+    /// How soon after posting a status another post by the same user replaces it rather than
+    /// adding another.
+    pub post_cooldown: Duration,
+    /// How long resubmitting the same status form (same token and status) replaces the status it
+    /// posted rather than adding another.
+    pub post_dedupe_window: Duration,
+    /// Largest request body (e.g. a submitted form) accepted, in bytes.
+    pub max_body_size: usize,
+    /// Responses smaller than this many bytes aren't compressed.
+    pub compression_min_size: u16,
+    /// Directory of templates replacing the compiled-in ones of the same name, if any.
+    pub templates_dir: Option<PathBuf>,
+    /// Directory of static assets served in preference to the compiled-in ones, if any.
+    pub assets_dir: Option<PathBuf>,
+    /// Certificate and key to serve HTTPS with; `None` serves plain HTTP.
+    pub tls: Option<TlsConfig>,
+    /// The Bluesky feed generator to serve, if any.
+    pub feed_generator: Option<FeedGeneratorConfig>,
+    /// Development mode without OAuth: anyone can log in as any DID, and their record writes are
+    /// logged instead of sent to a PDS. Only allowed when listening on a loopback address.
+    pub dev_fake_auth: bool,
+}
+
+impl AppConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let bind_addr = bind_addr_from_env()?;
+        Ok(Self {
+            bind_addr,
+            show_error_messages: env_var_or_default("SHOW_ERRORS", "false")?.parse()?,
+            status_options: emoji_options_from_env(
+                "STATUS_OPTIONS",
+                "STATUS_OPTIONS_FILE",
+                &DEFAULT_STATUS_OPTIONS,
+            )?,
+            reaction_options: emoji_options_from_env(
+                "REACTION_OPTIONS",
+                "REACTION_OPTIONS_FILE",
+                &DEFAULT_REACTION_OPTIONS,
+            )?,
+            did_filter: DidFilter::from_env()?,
+            backfill: backfill_source_from_env()?,
+            lookup_backfill: env_var_or_default("LOOKUP_BACKFILL", "false")?.parse()?,
+            #[cfg(feature = "ingester")]
+            ingest_source: ingest_source_from_env()?,
+            #[cfg(feature = "ingester")]
+            ingest_collections: ingest_collections_from_env()?,
+            #[cfg(feature = "ingester")]
+            embedded_ingester: env_var_or_default("EMBEDDED_INGESTER", "true")?.parse()?,
+            #[cfg(all(feature = "ingester", feature = "metrics"))]
+            ingester_metrics_addr: match env_var_or_default("INGESTER_METRICS_ADDR", "")?.as_str() {
+                "" => None,
+                addr => {
+                    Some(addr.parse().map_err(|e| {
+                        anyhow::anyhow!("invalid INGESTER_METRICS_ADDR '{addr}': {e}")
+                    })?)
+                }
+            },
+            max_clock_skew: Duration::from_secs(
+                env_var_or_default("MAX_CLOCK_SKEW_SECS", "300")?.parse()?,
+            ),
+            #[cfg(feature = "ingester")]
+            ingest_batch: BatchConfig {
+                max_size: nonzero_env_var("INGEST_BATCH_SIZE", "100")?,
+                max_delay: Duration::from_millis(nonzero_env_var(
+                    "INGEST_BATCH_INTERVAL_MS",
+                    "1000",
+                )?),
+            },
+            #[cfg(feature = "ingester")]
+            ingest_workers: nonzero_env_var("INGEST_WORKERS", "4")?,
+            // without a configured secret, cursors are only valid until restart
+            cursor_codec: match env_var_or_default("CURSOR_SECRET", "")?.as_str() {
+                "" => CursorCodec::random(),
+                secret => CursorCodec::new(secret.as_bytes()),
+            },
+            rollup_interval: Duration::from_secs(nonzero_env_var("ROLLUP_INTERVAL_SECS", "60")?),
+            reconcile_interval: match env_var_or_default("RECONCILE_INTERVAL_SECS", "3600")?
+                .parse()?
+            {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            session_cleanup_interval: Duration::from_secs(nonzero_env_var(
+                "SESSION_CLEANUP_INTERVAL_SECS",
+                "60",
+            )?),
+            oauth_state_ttl: Duration::from_secs(
+                env_var_or_default("OAUTH_STATE_TTL_SECS", "600")?.parse()?,
+            ),
+            handle_cache_ttl: Duration::from_secs(
+                env_var_or_default("HANDLE_CACHE_TTL_SECS", "86400")?.parse()?,
+            ),
+            follows_cache_ttl: Duration::from_secs(
+                env_var_or_default("FOLLOWS_CACHE_TTL_SECS", "60")?.parse()?,
+            ),
+            lookup_cache_ttl: Duration::from_secs(
+                env_var_or_default("LOOKUP_CACHE_TTL_SECS", "300")?.parse()?,
+            ),
+            plc_directory_url: env_var_or_default("PLC_DIRECTORY_URL", DEFAULT_PLC_DIRECTORY_URL)?,
+            upstream_timeout: Duration::from_secs(nonzero_env_var("UPSTREAM_TIMEOUT_SECS", "10")?),
+            circuit_breaker_threshold: nonzero_env_var("CIRCUIT_BREAKER_THRESHOLD", "5")?,
+            circuit_breaker_cooldown: Duration::from_secs(
+                env_var_or_default("CIRCUIT_BREAKER_COOLDOWN_SECS", "30")?.parse()?,
+            ),
+            avatar_appview_url: match env_var_or_default(
+                "AVATAR_APPVIEW_URL",
+                "https://public.api.bsky.app",
+            )?
+            .as_str()
+            {
+                "" => None,
+                url => Some(url.to_owned()),
+            },
+            avatar_cache_ttl: Duration::from_secs(
+                env_var_or_default("AVATAR_CACHE_TTL_SECS", "3600")?.parse()?,
+            ),
+            date_policy: env_var_or_default("DATE_POLICY", "earliest")?.parse()?,
+            backdate_threshold: Duration::from_secs(
+                env_var_or_default("BACKDATE_THRESHOLD_SECS", "3600")?.parse()?,
+            ),
+            #[cfg(feature = "admin")]
+            roles: RoleMap::from_env()?,
+            security_headers: SecurityHeaders::from_env()?,
+            trust_proxy: env_var_or_default("TRUST_PROXY", "false")?.parse()?,
+            trusted_proxy_hops: env_var_or_default("TRUSTED_PROXY_HOPS", "1")?.parse()?,
+            login_max_attempts: nonzero_env_var("LOGIN_MAX_ATTEMPTS", "10")?,
+            login_attempt_window: Duration::from_secs(
+                env_var_or_default("LOGIN_ATTEMPT_WINDOW_SECS", "300")?.parse()?,
+            ),
+            login_lockout: Duration::from_secs(
+                env_var_or_default("LOGIN_LOCKOUT_SECS", "900")?.parse()?,
+            ),
+            post_cooldown: Duration::from_millis(
+                env_var_or_default("POST_COOLDOWN_MS", "2000")?.parse()?,
+            ),
+            post_dedupe_window: Duration::from_secs(
+                env_var_or_default("POST_DEDUPE_WINDOW_SECS", "60")?.parse()?,
+            ),
+            max_body_size: env_var_or_default("MAX_BODY_BYTES", "16384")?.parse()?,
+            compression_min_size: env_var_or_default("COMPRESSION_MIN_SIZE", "1024")?.parse()?,
+            templates_dir: dir_from_env("TEMPLATES_DIR")?,
+            assets_dir: dir_from_env("ASSETS_DIR")?,
+            tls: TlsConfig::from_env()?,
+            feed_generator: FeedGeneratorConfig::from_env()?,
+            dev_fake_auth: dev_fake_auth_from_env(bind_addr)?,
+        })
+    }
+
+    /// The configuration with every setting at its default, whatever's set in the environment, so
+    /// tests behave the same in every shell.
+    #[cfg(test)]
+    pub fn for_tests() -> Self {
+        DEFAULTS_ONLY.set(true);
+        let config = Self::from_env();
+        DEFAULTS_ONLY.set(false);
+        config.expect("the defaults are a valid configuration")
+    }
+
+    pub fn is_allowed_status(&self, status: &str) -> bool {
+        is_allowed_status(&self.status_options, status)
+    }
+
+    pub fn is_allowed_reaction(&self, emoji: &str) -> bool {
+        is_allowed_status(&self.reaction_options, emoji)
+    }
+}
+
+pub fn is_allowed_status(status_options: &[String], status: &str) -> bool {
+    status_options.iter().any(|option| option == status)
+}
+
+// lexicon limit on a status (or reaction emoji), in bytes
+const MAX_EMOJI_OPTION_LEN: usize = 32;
+
+/// Loads a set of selectable emoji from `var` (comma- or whitespace-separated) or from the file
+/// named by `file_var` (one per line, lines starting with `#` are ignored), falling back to
+/// `defaults`.
+fn emoji_options_from_env(
+    var: &'static str,
+    file_var: &'static str,
+    defaults: &[&str],
+) -> anyhow::Result<Vec<String>> {
+    let list = env_var_or_default(var, "")?;
+    let path = env_var_or_default(file_var, "")?;
+    let options = match (list.as_str(), path.as_str()) {
+        ("", "") => defaults.iter().map(|s| s.to_string()).collect(),
+        (list, "") => list
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|option| !option.is_empty())
+            .map(|option| option.to_owned())
+            .collect(),
+        ("", path) => std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("{file_var} '{path}': {e}"))?
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.to_owned())
+            .collect(),
+        _ => anyhow::bail!("only one of {var} and {file_var} may be set"),
+    };
+    validate_emoji_options(var, options)
+}
+
+fn validate_emoji_options(var: &'static str, options: Vec<String>) -> anyhow::Result<Vec<String>> {
+    if options.is_empty() {
+        anyhow::bail!("{var}: at least one option is required");
+    }
+    let mut seen = HashSet::new();
+    for option in &options {
+        if option.len() > MAX_EMOJI_OPTION_LEN {
+            anyhow::bail!("{var}: '{option}' is longer than {MAX_EMOJI_OPTION_LEN} bytes");
+        }
+        // not a full grapheme check, but catches words and other obvious typos
+        if option.is_ascii() {
+            anyhow::bail!("{var}: '{option}' isn't an emoji");
+        }
+        if !seen.insert(option) {
+            anyhow::bail!("{var}: '{option}' is listed more than once");
+        }
+    }
+    Ok(options)
+}
+
+/// Allow/deny lists of DIDs applied to ingested records.
+///
+/// If the allowlist is non-empty, only DIDs in it are accepted (useful for running a private
+/// instance restricted to a community). DIDs in the denylist are always rejected.
+#[derive(Debug, Clone, Default)]
+pub struct DidFilter {
+    allow: HashSet<String>,
+    deny: HashSet<String>,
+}
+
+impl DidFilter {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            allow: parse_did_list(&env_var_or_default("INGEST_ALLOW_DIDS", "")?),
+            deny: parse_did_list(&env_var_or_default("INGEST_DENY_DIDS", "")?),
+        })
+    }
+
+    pub fn allows(&self, did: &str) -> bool {
+        !self.deny.contains(did) && (self.allow.is_empty() || self.allow.contains(did))
+    }
+}
+
+/// Database connection and pool settings. The SQLite defaults (WAL journaling, a busy timeout)
+/// let the ingester and web handlers write concurrently without `database is locked` errors.
+#[derive(Debug, Clone)]
+pub struct DbConfig {
+    /// `sqlite:`, `mysql://` or `mariadb://` (the `mysql` feature) or `postgres://` (the
+    /// `postgres` feature) URL, picking the database; the journal mode, busy timeout and
+    /// synchronous settings only apply to SQLite.
+    pub url: String,
+    pub journal_mode: SqliteJournalMode,
+    /// How long a connection waits on a locked database before giving up.
+    pub busy_timeout: Duration,
+    pub synchronous: SqliteSynchronous,
+    pub max_connections: u32,
+    /// How long to wait for a free pooled connection.
+    pub acquire_timeout: Duration,
+    /// How many more times to try connecting at startup if the database isn't up yet.
+    pub connect_retries: u32,
+    /// Wait before the first retry, doubled for each one after.
+    pub connect_backoff: Duration,
+    /// Queries taking longer than this are logged at WARN.
+    pub slow_query_threshold: Duration,
+}
+
+impl DbConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let journal_mode = env_var_or_default("DB_JOURNAL_MODE", "wal")?;
+        let synchronous = env_var_or_default("DB_SYNCHRONOUS", "normal")?;
+        Ok(Self {
+            url: env_var_required("DATABASE_URL")?,
+            journal_mode: journal_mode
+                .parse()
+                .map_err(|e| anyhow::anyhow!("invalid DB_JOURNAL_MODE '{journal_mode}': {e}"))?,
+            busy_timeout: Duration::from_millis(
+                env_var_or_default("DB_BUSY_TIMEOUT_MS", "5000")?.parse()?,
+            ),
+            synchronous: synchronous
+                .parse()
+                .map_err(|e| anyhow::anyhow!("invalid DB_SYNCHRONOUS '{synchronous}': {e}"))?,
+            max_connections: env_var_or_default("DB_MAX_CONNECTIONS", "10")?.parse()?,
+            acquire_timeout: Duration::from_secs(
+                env_var_or_default("DB_ACQUIRE_TIMEOUT_SECS", "30")?.parse()?,
+            ),
+            connect_retries: env_var_or_default("DB_CONNECT_RETRIES", "5")?.parse()?,
+            connect_backoff: Duration::from_millis(
+                env_var_or_default("DB_CONNECT_BACKOFF_MS", "1000")?.parse()?,
+            ),
+            slow_query_threshold: Duration::from_millis(
+                env_var_or_default("DB_SLOW_QUERY_MS", "500")?.parse()?,
+            ),
+        })
+    }
+}
+
+/// Where OAuth sessions and login states are stored.
+#[derive(Debug, Clone)]
+pub enum OAuthStoreConfig {
+    /// Alongside everything else, in the database at `DATABASE_URL`.
+    Database,
+    /// In Redis at the given URL, so multiple instances can share them.
+    #[cfg(feature = "redis")]
+    Redis(String),
+}
+
+impl OAuthStoreConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        match env_var_or_default("OAUTH_STORE", "database")?.as_str() {
+            // from when the database could only be SQLite
+            "database" | "sqlite" => Ok(Self::Database),
+            #[cfg(feature = "redis")]
+            "redis" => Ok(Self::Redis(env_var_required("REDIS_URL")?)),
+            #[cfg(not(feature = "redis"))]
+            "redis" => Err(anyhow::anyhow!(
+                "OAUTH_STORE is 'redis', but this build doesn't include the `redis` feature"
+            )),
+            other => Err(anyhow::anyhow!(
+                "invalid OAUTH_STORE '{other}': expected 'database' or 'redis'"
+            )),
+        }
+    }
+}
+
+/// Key-encryption key for OAuth sessions and states at rest, from `OAUTH_ENCRYPTION_KEY` (32
+/// bytes, base64-encoded) or a file at `OAUTH_ENCRYPTION_KEY_FILE` (e.g. a secret mounted from a
+/// KMS). Without either, they're stored as plaintext.
+pub fn oauth_cipher_from_env() -> anyhow::Result<Option<EnvelopeCipher>> {
+    let key = match env_var_or_default("OAUTH_ENCRYPTION_KEY_FILE", "")?.as_str() {
+        "" => env_var_or_default("OAUTH_ENCRYPTION_KEY", "")?,
+        path => std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("reading OAUTH_ENCRYPTION_KEY_FILE '{path}': {e}"))?,
+    };
+    if key.trim().is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(EnvelopeCipher::from_base64(&key).map_err(|e| {
+        anyhow::anyhow!("invalid OAuth encryption key: {e}")
+    })?))
+}
+
+#[cfg(feature = "ingester")]
+fn ingest_source_from_env() -> anyhow::Result<IngestSource> {
+    match env_var_or_default("INGEST_SOURCE", "jetstream")?.as_str() {
+        "jetstream" => Ok(IngestSource::Jetstream(websocket_url_from_env(
+            "JETSTREAM_URL",
+            US_EAST_1,
+        )?)),
+        "firehose" => Ok(IngestSource::Firehose(websocket_url_from_env(
+            "FIREHOSE_URL",
+            "wss://bsky.network",
+        )?)),
+        other => Err(anyhow::anyhow!(
+            "invalid INGEST_SOURCE '{other}': expected 'jetstream' or 'firehose'"
+        )),
+    }
+}
+
+// comma- or whitespace-separated NSIDs, by default the app's own collections
+#[cfg(feature = "ingester")]
+fn ingest_collections_from_env() -> anyhow::Result<Vec<String>> {
+    let collections = env_var_or_default("INGEST_COLLECTIONS", "")?;
+    if collections.trim().is_empty() {
+        return Ok(vec![
+            Status::NSID.to_owned(),
+            Pin::NSID.to_owned(),
+            Reaction::NSID.to_owned(),
+        ]);
+    }
+    collections
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|collection| !collection.is_empty())
+        .map(|collection| {
+            Nsid::new(collection.to_owned())
+                .map(|_| collection.to_owned())
+                .map_err(|e| {
+                    anyhow::anyhow!("invalid INGEST_COLLECTIONS entry '{collection}': {e}")
+                })
+        })
+        .collect()
+}
+
+// websocket URLs must be ws:// or wss:// with a host
+#[cfg(feature = "ingester")]
+fn websocket_url_from_env(key: &'static str, default: &str) -> anyhow::Result<String> {
+    let url = env_var_or_default(key, default)?;
+    let uri = url
+        .parse::<Uri>()
+        .map_err(|e| anyhow::anyhow!("invalid {key} '{url}': {e}"))?;
+    if !matches!(uri.scheme_str(), Some("ws" | "wss")) || uri.host().is_none() {
+        anyhow::bail!("invalid {key} '{url}': expected a ws:// or wss:// URL");
+    }
+    Ok(url)
+}
+
+// an explicit DID list takes precedence over enumerating a relay
+fn backfill_source_from_env() -> anyhow::Result<Option<BackfillSource>> {
+    let dids = parse_did_list(&env_var_or_default("BACKFILL_DIDS", "")?);
+    if !dids.is_empty() {
+        let dids = dids
+            .into_iter()
+            .map(|did| Did::new(did.clone()).map_err(|e| anyhow::anyhow!("{e}: {did}")))
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(Some(BackfillSource::Dids(dids)));
+    }
+    let relay_url = env_var_or_default("BACKFILL_RELAY_URL", "")?;
+    Ok((!relay_url.is_empty()).then_some(BackfillSource::Relay(relay_url)))
+}
+
+fn bind_addr_from_env() -> anyhow::Result<SocketAddr> {
+    let host = env_var_or_default("BIND_HOST", "0.0.0.0")?;
+    let port = env_var_or_default("PORT", "8081")?;
+    Ok(SocketAddr::new(
+        host.parse::<IpAddr>()
+            .map_err(|e| anyhow::anyhow!("invalid BIND_HOST '{host}': {e}"))?,
+        port.parse()
+            .map_err(|e| anyhow::anyhow!("invalid PORT '{port}': {e}"))?,
+    ))
+}
+
+// anyone who can reach the server can log in as anyone in development mode, so it's refused unless
+// only this machine can
+fn dev_fake_auth_from_env(bind_addr: SocketAddr) -> anyhow::Result<bool> {
+    let dev_fake_auth = matches!(
+        env_var_or_default("DEV_FAKE_AUTH", "0")?.as_str(),
+        "1" | "true"
+    );
+    if dev_fake_auth && !bind_addr.ip().is_loopback() {
+        anyhow::bail!(
+            "DEV_FAKE_AUTH requires BIND_HOST to be a loopback address (e.g. 127.0.0.1), not {}",
+            bind_addr.ip()
+        );
+    }
+    Ok(dev_fake_auth)
+}
+
+// an optional directory, which must exist if given
+fn dir_from_env(key: &'static str) -> anyhow::Result<Option<PathBuf>> {
+    match env_var_or_default(key, "")?.as_str() {
+        "" => Ok(None),
+        dir => {
+            let path = PathBuf::from(dir);
+            if !path.is_dir() {
+                anyhow::bail!("{key} '{dir}' isn't a directory");
+            }
+            Ok(Some(path))
+        }
+    }
+}
+
+// comma- or whitespace-separated list of DIDs
+fn parse_did_list(value: &str) -> HashSet<String> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|did| !did.is_empty())
+        .map(|did| did.to_owned())
+        .collect()
+}
+
+#[cfg(test)]
+thread_local! {
+    // while building `AppConfig::for_tests`, every variable is taken to be unset
+    static DEFAULTS_ONLY: Cell<bool> = const { Cell::new(false) };
+}
+
+// improve std::env::var error reporting
+pub fn env_var_or_default(key: &'static str, default: impl AsRef<str>) -> anyhow::Result<String> {
+    #[cfg(test)]
+    if DEFAULTS_ONLY.get() {
+        return Ok(default.as_ref().to_string());
+    }
+    Ok(match env::var(key) {
+        Ok(v) => v,
+        Err(env::VarError::NotPresent) => default.as_ref().to_string(),
+        Err(e) => Err(e)?,
+    })
+}
+
+// a count or interval that must be positive: channels of zero capacity and zero-length intervals
+// panic, and zero of a limit shuts everyone out
+fn nonzero_env_var<T>(key: &'static str, default: &str) -> anyhow::Result<T>
+where
+    T: FromStr + Default + PartialEq,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let value: T = env_var_or_default(key, default)?.parse()?;
+    if value == T::default() {
+        anyhow::bail!("{key} must be greater than zero");
+    }
+    Ok(value)
+}
+
+pub fn env_var_required(key: &'static str) -> anyhow::Result<String> {
+    env::var(key).map_err(|e| anyhow::anyhow!("{e}: {key}"))
+}
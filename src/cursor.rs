@@ -0,0 +1,200 @@
+use std::str::FromStr;
+
+use atrium_api::types::string::Datetime;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use thiserror::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// bump when the payload layout changes; old cursors then fail to decode instead of being
+// misinterpreted
+const CURSOR_VERSION: &str = "1";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("malformed cursor")]
+    Malformed,
+    #[error("cursor signature mismatch")]
+    BadSignature,
+    #[error("unsupported cursor version '{0}'")]
+    UnsupportedVersion(String),
+}
+
+/// Position in a feed ordered by `(indexed_at, uri)`: descending for the feed, ascending for the
+/// statuses delta. Pages continue with rows strictly after this position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedCursor {
+    pub indexed_at: Datetime,
+    pub uri: String,
+}
+
+/// Encodes [`FeedCursor`]s into opaque, URL-safe, signed tokens and back.
+#[derive(Clone)]
+pub struct CursorCodec {
+    key: Vec<u8>,
+}
+
+impl CursorCodec {
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self { key: key.into() }
+    }
+
+    /// Codec with a random key; cursors won't survive a restart.
+    pub fn random() -> Self {
+        Self::new(rand::random::<[u8; 32]>().to_vec())
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length")
+    }
+
+    pub fn encode(&self, cursor: &FeedCursor) -> String {
+        let payload = format!(
+            "{CURSOR_VERSION}|{}|{}",
+            cursor.indexed_at.as_str(),
+            cursor.uri
+        );
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        let signature = mac.finalize().into_bytes();
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(payload),
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    pub fn decode(&self, token: &str) -> Result<FeedCursor, Error> {
+        let (payload, signature) = token.split_once('.').ok_or(Error::Malformed)?;
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload)
+            .map_err(|_| Error::Malformed)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| Error::Malformed)?;
+
+        let mut mac = self.mac();
+        mac.update(&payload);
+        mac.verify_slice(&signature)
+            .map_err(|_| Error::BadSignature)?;
+
+        let payload = String::from_utf8(payload).map_err(|_| Error::Malformed)?;
+        let mut parts = payload.splitn(3, '|');
+        let version = parts.next().ok_or(Error::Malformed)?;
+        if version != CURSOR_VERSION {
+            return Err(Error::UnsupportedVersion(version.to_owned()));
+        }
+        let indexed_at = parts
+            .next()
+            .and_then(|dt| Datetime::from_str(dt).ok())
+            .ok_or(Error::Malformed)?;
+        let uri = parts.next().ok_or(Error::Malformed)?.to_owned();
+        Ok(FeedCursor { indexed_at, uri })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor() -> FeedCursor {
+        FeedCursor {
+            indexed_at: Datetime::from_str("2024-05-01T12:00:00.000Z").expect("valid datetime"),
+            uri: "at://did:plc:alice0000000000000000000/xyz.statusphere.status/3kaaaaaaaaaa2"
+                .to_owned(),
+        }
+    }
+
+    // a token carrying `payload` as is, signed with `codec`'s key
+    fn signed(codec: &CursorCodec, payload: &str) -> String {
+        let mut mac = codec.mac();
+        mac.update(payload.as_bytes());
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(payload),
+            URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+        )
+    }
+
+    // `token` with the first byte of its payload or signature flipped
+    fn tampered(token: &str, in_signature: bool) -> String {
+        let (payload, signature) = token.split_once('.').expect("token has two parts");
+        let flip = |part: &str| {
+            let mut bytes = URL_SAFE_NO_PAD.decode(part).expect("part is base64");
+            bytes[0] ^= 0x01;
+            URL_SAFE_NO_PAD.encode(bytes)
+        };
+        if in_signature {
+            format!("{payload}.{}", flip(signature))
+        } else {
+            format!("{}.{signature}", flip(payload))
+        }
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let codec = CursorCodec::new("secret");
+
+        let token = codec.encode(&cursor());
+        assert_eq!(codec.decode(&token).expect("cursor decodes"), cursor());
+    }
+
+    #[test]
+    fn tampered_cursors_are_rejected() {
+        let codec = CursorCodec::new("secret");
+        let token = codec.encode(&cursor());
+
+        assert!(matches!(
+            codec.decode(&tampered(&token, false)),
+            Err(Error::BadSignature)
+        ));
+        assert!(matches!(
+            codec.decode(&tampered(&token, true)),
+            Err(Error::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn cursors_signed_with_another_key_are_rejected() {
+        let token = CursorCodec::new("secret").encode(&cursor());
+
+        assert!(matches!(
+            CursorCodec::new("other secret").decode(&token),
+            Err(Error::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn cursors_of_other_versions_are_rejected() {
+        let codec = CursorCodec::new("secret");
+        let token = signed(&codec, "0|2024-05-01T12:00:00.000Z|at://did:plc:alice/a/b");
+
+        assert!(matches!(
+            codec.decode(&token),
+            Err(Error::UnsupportedVersion(version)) if version == "0"
+        ));
+    }
+
+    #[test]
+    fn garbage_cursors_are_malformed() {
+        let codec = CursorCodec::new("secret");
+
+        for token in ["", "no-separator", "not base64!.not base64!"] {
+            assert!(
+                matches!(codec.decode(token), Err(Error::Malformed)),
+                "{token:?} is malformed"
+            );
+        }
+        // signed, but not a cursor
+        assert!(matches!(
+            codec.decode(&signed(&codec, "1|not a datetime|at://did:plc:alice/a/b")),
+            Err(Error::Malformed)
+        ));
+        assert!(matches!(
+            codec.decode(&signed(&codec, "1")),
+            Err(Error::Malformed)
+        ));
+    }
+}
@@ -0,0 +1,200 @@
+use atrium_api::types::{
+    Collection,
+    string::{Datetime, Did},
+};
+#[cfg(feature = "ingester")]
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::{
+    config::AppConfig,
+    error::Error,
+    lexicons::xyz::statusphere::{
+        Pin, Reaction, Status, pin::RecordData as PinRecordData,
+        reaction::RecordData as ReactionRecordData, status::RecordData as StatusRecordData,
+    },
+    store::{
+        DeadLetterStore, Reaction as StoreReaction, Status as StoreStatus, StatusImage,
+        StatusStore, Visibility, sanitize_content_warning,
+    },
+};
+
+// the parts of a Jetstream commit event we need to reprocess it
+#[derive(Debug, Deserialize)]
+struct JetstreamEvent {
+    did: String,
+    commit: Option<JetstreamCommit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JetstreamCommit {
+    collection: String,
+    rkey: String,
+    record: Option<serde_json::Value>,
+    cid: Option<String>,
+}
+
+/// Jetstream-formatted event payload for a status, for dead-lettering statuses that failed
+/// after they were decoded.
+#[cfg(feature = "ingester")]
+pub fn status_payload(status: &StoreStatus) -> String {
+    // at://{did}/{collection}/{rkey}
+    let mut parts = status
+        .uri
+        .trim_start_matches("at://")
+        .splitn(3, '/')
+        .skip(1);
+    let collection = parts.next().unwrap_or_default();
+    let rkey = parts.next().unwrap_or_default();
+    serde_json::json!({
+        "did": status.author_did.as_str(),
+        "kind": "commit",
+        "commit": {
+            "operation": "create",
+            "collection": collection,
+            "rkey": rkey,
+            "cid": status.cid,
+            "record": {
+                "$type": Status::NSID,
+                "status": status.status,
+                "createdAt": status.raw_created_at.as_ref().unwrap_or(&status.created_at).as_str(),
+                "contentWarning": status.content_warning,
+                // a legacy blob ref, as the blob's size isn't kept
+                "image": status.image.as_ref().map(|image| serde_json::json!({
+                    "cid": image.cid,
+                    "mimeType": image.mime_type,
+                })),
+            },
+        },
+    })
+    .to_string()
+}
+
+/// Payload for a firehose frame that failed to decode: the frame itself, base64-encoded. It's kept
+/// for inspection; reprocessing it fails again, as it isn't a Jetstream event.
+#[cfg(feature = "ingester")]
+pub fn firehose_frame_payload(frame: &[u8]) -> String {
+    serde_json::json!({ "firehoseFrame": STANDARD.encode(frame) }).to_string()
+}
+
+/// Whether `uri` points at an `xyz.statusphere.status` record.
+pub fn is_status_uri(uri: &str) -> bool {
+    uri.strip_prefix("at://")
+        .and_then(|path| path.split_once('/'))
+        .is_some_and(|(_, path)| path.starts_with(&format!("{}/", Status::NSID)))
+}
+
+#[derive(Debug, Default)]
+pub struct ReprocessSummary {
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+async fn reprocess_one(
+    payload: &str,
+    config: &AppConfig,
+    status_store: &StatusStore,
+) -> Result<(), Error> {
+    let event: JetstreamEvent = serde_json::from_str(payload).map_err(Error::DeadLetterPayload)?;
+    // non-commit events have nothing to re-insert
+    let Some(JetstreamCommit {
+        collection,
+        rkey,
+        record,
+        cid,
+    }) = event.commit
+    else {
+        return Ok(());
+    };
+    let author_did = Did::new(event.did.clone()).map_err(Error::InvalidDid)?;
+    if !config.did_filter.allows(author_did.as_str()) {
+        return Ok(());
+    }
+    // nor do deletes, except that of a pin
+    let Some(record) = record else {
+        if collection == Pin::NSID {
+            status_store.unpin(&author_did).await?;
+        }
+        return Ok(());
+    };
+
+    if collection == Status::NSID {
+        let StatusRecordData {
+            status,
+            created_at,
+            content_warning,
+            image,
+        } = serde_json::from_value(record).map_err(Error::DeadLetterPayload)?;
+        if !config.is_allowed_status(&status) {
+            return Ok(());
+        }
+        let status = StoreStatus {
+            uri: format!("at://{}/{collection}/{rkey}", event.did),
+            author_did,
+            status,
+            created_at,
+            indexed_at: Datetime::now(),
+            raw_created_at: None,
+            visibility: Visibility::Public,
+            content_warning: sanitize_content_warning(content_warning),
+            cid,
+            image: image.as_ref().and_then(StatusImage::from_blob),
+        }
+        .clamp_created_at(config.max_clock_skew);
+        status_store.insert(status).await?;
+    } else if collection == Pin::NSID {
+        let pin: PinRecordData =
+            serde_json::from_value(record).map_err(Error::DeadLetterPayload)?;
+        status_store
+            .pin(&author_did, pin.subject, &pin.created_at)
+            .await?;
+    } else if collection == Reaction::NSID {
+        let ReactionRecordData {
+            subject,
+            emoji,
+            created_at,
+        } = serde_json::from_value(record).map_err(Error::DeadLetterPayload)?;
+        if !config.is_allowed_reaction(&emoji) || !is_status_uri(&subject) {
+            return Ok(());
+        }
+        status_store
+            .react(StoreReaction {
+                uri: format!("at://{}/{collection}/{rkey}", event.did),
+                author_did,
+                subject,
+                emoji,
+                created_at,
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+/// Retries every dead-lettered message, removing the ones that succeed.
+pub async fn reprocess(
+    dead_letters: &DeadLetterStore,
+    config: &AppConfig,
+    status_store: &StatusStore,
+) -> Result<ReprocessSummary, Error> {
+    let count = dead_letters.count().await?;
+    let mut summary = ReprocessSummary::default();
+    for letter in dead_letters.fetch_n(count as usize).await? {
+        match reprocess_one(&letter.payload, config, status_store).await {
+            Ok(()) => {
+                dead_letters.delete(letter.id).await?;
+                summary.succeeded += 1;
+            }
+            Err(e) => {
+                warn!("dead letter {} failed again: {e}", letter.id);
+                dead_letters.record_failure(letter.id, e).await?;
+                summary.failed += 1;
+            }
+        }
+    }
+    info!(
+        "Reprocessed dead letters: {} succeeded, {} failed",
+        summary.succeeded, summary.failed
+    );
+    Ok(summary)
+}
@@ -0,0 +1,187 @@
+use atrium_api::{
+    types::string::{Datetime, Did, Tid},
+    xrpc::{
+        HttpClient, XrpcClient,
+        http::{Request, Response, StatusCode, header::CONTENT_TYPE},
+    },
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::info;
+
+// a well-formed CID for the records we pretend to write and the commits they pretend to be on;
+// nothing ever looks it up
+const FAKE_CID: &str = "bafyreie5737gdxlw5i64vzichcalba3z2v5n6icifvx5xytvske7mr3hpm";
+// likewise for the blobs we pretend to upload, which have the raw codec rather than DAG-CBOR
+const FAKE_BLOB_CID: &str = "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku";
+
+// the MIME type of an uploaded image, going by its magic bytes as a PDS would
+fn sniff_mime_type(body: &[u8]) -> &'static str {
+    if body.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if body.starts_with(b"\xff\xd8\xff") {
+        "image/jpeg"
+    } else if body.starts_with(b"GIF87a") || body.starts_with(b"GIF89a") {
+        "image/gif"
+    } else if body.starts_with(b"RIFF") && body.get(8..12) == Some(&b"WEBP"[..]) {
+        "image/webp"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+// a TID for the current time, as record keys and repo revisions are
+fn now_tid() -> String {
+    Tid::now(
+        0.try_into()
+            .expect("unexpected clock ID conversion failure"),
+    )
+    .to_string()
+}
+
+/// Stand-in for a user's OAuth session in development mode (`DEV_FAKE_AUTH`), so the UI can be
+/// worked on offline: XRPC calls are answered in-process, with record writes logged rather than
+/// sent anywhere and reads finding nothing.
+#[derive(Debug, Clone)]
+pub struct FakeSession {
+    did: Did,
+}
+
+// the parts of a createRecord or putRecord call needed to make up its result
+#[derive(Debug, Deserialize)]
+struct WriteInput {
+    collection: String,
+    rkey: Option<String>,
+    record: serde_json::Value,
+}
+
+// the parts of a createReport call needed to make up its result
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReportInput {
+    reason_type: String,
+    reason: Option<String>,
+    subject: serde_json::Value,
+}
+
+impl FakeSession {
+    pub fn new(did: Did) -> Self {
+        Self { did }
+    }
+
+    pub fn did(&self) -> &Did {
+        &self.did
+    }
+
+    // (status, JSON body) of the response to an XRPC call to `nsid`
+    fn respond(&self, nsid: &str, body: &[u8]) -> (StatusCode, serde_json::Value) {
+        match nsid {
+            "com.atproto.repo.createRecord" | "com.atproto.repo.putRecord" => {
+                let input = match serde_json::from_slice::<WriteInput>(body) {
+                    Ok(input) => input,
+                    Err(e) => {
+                        return (
+                            StatusCode::BAD_REQUEST,
+                            json!({ "error": "InvalidRequest", "message": e.to_string() }),
+                        );
+                    }
+                };
+                let rkey = input.rkey.unwrap_or_else(now_tid);
+                let uri = format!("at://{}/{}/{rkey}", self.did.as_str(), input.collection);
+                info!(
+                    "DEV_FAKE_AUTH: not sending {nsid} of {uri}: {}",
+                    input.record
+                );
+                (StatusCode::OK, json!({ "uri": uri, "cid": FAKE_CID }))
+            }
+            "com.atproto.repo.deleteRecord" => {
+                info!(
+                    "DEV_FAKE_AUTH: not sending {nsid}: {}",
+                    String::from_utf8_lossy(body)
+                );
+                (StatusCode::OK, json!({}))
+            }
+            "com.atproto.repo.uploadBlob" => {
+                let mime_type = sniff_mime_type(body);
+                info!(
+                    "DEV_FAKE_AUTH: not sending {nsid} of {} bytes of {mime_type}",
+                    body.len()
+                );
+                (
+                    StatusCode::OK,
+                    json!({
+                        "blob": {
+                            "$type": "blob",
+                            "ref": { "$link": FAKE_BLOB_CID },
+                            "mimeType": mime_type,
+                            "size": body.len(),
+                        }
+                    }),
+                )
+            }
+            "com.atproto.moderation.createReport" => {
+                let input = match serde_json::from_slice::<ReportInput>(body) {
+                    Ok(input) => input,
+                    Err(e) => {
+                        return (
+                            StatusCode::BAD_REQUEST,
+                            json!({ "error": "InvalidRequest", "message": e.to_string() }),
+                        );
+                    }
+                };
+                info!("DEV_FAKE_AUTH: not sending {nsid} of {}", input.subject);
+                (
+                    StatusCode::OK,
+                    json!({
+                        "id": 1,
+                        "reasonType": input.reason_type,
+                        "reason": input.reason,
+                        "subject": input.subject,
+                        "reportedBy": self.did.as_str(),
+                        "createdAt": Datetime::now(),
+                    }),
+                )
+            }
+            "com.atproto.sync.getLatestCommit" => {
+                (StatusCode::OK, json!({ "cid": FAKE_CID, "rev": now_tid() }))
+            }
+            "com.atproto.repo.listRecords" => (StatusCode::OK, json!({ "records": [] })),
+            "com.atproto.repo.getRecord" => (
+                StatusCode::BAD_REQUEST,
+                json!({ "error": "RecordNotFound", "message": "development mode has no records" }),
+            ),
+            "app.bsky.graph.getRelationships" => (StatusCode::OK, json!({ "relationships": [] })),
+            _ => (
+                StatusCode::NOT_IMPLEMENTED,
+                json!({ "error": "MethodNotImplemented", "message": format!("{nsid} isn't faked") }),
+            ),
+        }
+    }
+}
+
+impl HttpClient for FakeSession {
+    async fn send_http(
+        &self,
+        request: Request<Vec<u8>>,
+    ) -> core::result::Result<Response<Vec<u8>>, Box<dyn std::error::Error + Send + Sync + 'static>>
+    {
+        let nsid = request
+            .uri()
+            .path()
+            .strip_prefix("/xrpc/")
+            .unwrap_or_default()
+            .to_owned();
+        let (status, body) = self.respond(&nsid, request.body());
+        Ok(Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&body)?)?)
+    }
+}
+
+impl XrpcClient for FakeSession {
+    fn base_uri(&self) -> String {
+        // never connected to, as requests don't leave the process
+        "http://dev-fake-auth.invalid".to_owned()
+    }
+}
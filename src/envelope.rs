@@ -0,0 +1,157 @@
+use aes_gcm::{
+    Aes256Gcm, Key, Nonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use thiserror::Error;
+
+// prefix of sealed values; bump the version when the layout changes
+const SEALED_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid key: expected 32 bytes, base64-encoded")]
+    InvalidKey,
+    #[error("malformed sealed value")]
+    Malformed,
+    #[error("encryption failed")]
+    Encrypt,
+    #[error("decryption failed (wrong key, or value tampered with)")]
+    Decrypt,
+}
+
+/// Envelope encryption of secrets at rest. Each value is encrypted with a fresh data key, which
+/// is itself encrypted ("wrapped") by a long-lived key-encryption key, so only the small wrapped
+/// keys would need re-encrypting to rotate the key-encryption key.
+#[derive(Clone)]
+pub struct EnvelopeCipher {
+    kek: Aes256Gcm,
+}
+
+impl EnvelopeCipher {
+    pub fn new(kek: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            kek: Aes256Gcm::new_from_slice(kek).map_err(|_| Error::InvalidKey)?,
+        })
+    }
+
+    pub fn from_base64(kek: &str) -> Result<Self, Error> {
+        Self::new(&STANDARD.decode(kek.trim()).map_err(|_| Error::InvalidKey)?)
+    }
+
+    /// Whether `value` was produced by [`EnvelopeCipher::seal`] (rather than being a plaintext
+    /// value written before encryption was enabled).
+    pub fn is_sealed(value: &str) -> bool {
+        value.starts_with(SEALED_PREFIX)
+    }
+
+    /// Encrypts `plaintext`, bound to `context` (which must be passed again to open it).
+    pub fn seal(&self, plaintext: &str, context: &str) -> Result<String, Error> {
+        let dek = Aes256Gcm::generate_key(OsRng);
+        let wrapped_dek = encrypt(&self.kek, &dek, context)?;
+        let ciphertext = encrypt(&Aes256Gcm::new(&dek), plaintext.as_bytes(), context)?;
+        Ok(format!(
+            "{SEALED_PREFIX}{}:{}",
+            STANDARD.encode(wrapped_dek),
+            STANDARD.encode(ciphertext)
+        ))
+    }
+
+    /// Decrypts a value sealed with the same key-encryption key and `context`.
+    pub fn open(&self, sealed: &str, context: &str) -> Result<String, Error> {
+        let (wrapped_dek, ciphertext) = sealed
+            .strip_prefix(SEALED_PREFIX)
+            .and_then(|sealed| sealed.split_once(':'))
+            .ok_or(Error::Malformed)?;
+        let wrapped_dek = STANDARD.decode(wrapped_dek).map_err(|_| Error::Malformed)?;
+        let ciphertext = STANDARD.decode(ciphertext).map_err(|_| Error::Malformed)?;
+
+        let dek = decrypt(&self.kek, &wrapped_dek, context)?;
+        let dek = Key::<Aes256Gcm>::from_exact_iter(dek).ok_or(Error::Malformed)?;
+        let plaintext = decrypt(&Aes256Gcm::new(&dek), &ciphertext, context)?;
+        String::from_utf8(plaintext).map_err(|_| Error::Malformed)
+    }
+}
+
+// nonce || ciphertext
+fn encrypt(cipher: &Aes256Gcm, plaintext: &[u8], context: &str) -> Result<Vec<u8>, Error> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad: context.as_bytes(),
+            },
+        )
+        .map_err(|_| Error::Encrypt)?;
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+fn decrypt(cipher: &Aes256Gcm, sealed: &[u8], context: &str) -> Result<Vec<u8>, Error> {
+    if sealed.len() < NONCE_LEN {
+        return Err(Error::Malformed);
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: context.as_bytes(),
+            },
+        )
+        .map_err(|_| Error::Decrypt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    #[test]
+    fn seal_round_trips() {
+        let cipher = EnvelopeCipher::new(&KEY).expect("valid key");
+        let sealed = cipher
+            .seal(r#"{"token":"secret"}"#, "oauth_session:did:plc:abc")
+            .unwrap();
+        assert!(EnvelopeCipher::is_sealed(&sealed));
+        assert!(!sealed.contains("secret"));
+        assert_eq!(
+            cipher.open(&sealed, "oauth_session:did:plc:abc").unwrap(),
+            r#"{"token":"secret"}"#
+        );
+    }
+
+    #[test]
+    fn open_rejects_other_context_or_key() {
+        let cipher = EnvelopeCipher::new(&KEY).expect("valid key");
+        let sealed = cipher.seal("secret", "oauth_state:a").unwrap();
+        assert!(matches!(
+            cipher.open(&sealed, "oauth_state:b"),
+            Err(Error::Decrypt)
+        ));
+
+        let other = EnvelopeCipher::new(&[8; 32]).expect("valid key");
+        assert!(matches!(
+            other.open(&sealed, "oauth_state:a"),
+            Err(Error::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_keys_and_values() {
+        assert!(matches!(
+            EnvelopeCipher::new(&[0; 16]),
+            Err(Error::InvalidKey)
+        ));
+        let cipher = EnvelopeCipher::new(&KEY).expect("valid key");
+        assert!(matches!(
+            cipher.open("enc:v1:nope", "ctx"),
+            Err(Error::Malformed)
+        ));
+        assert!(!EnvelopeCipher::is_sealed(r#"{"plain":"json"}"#));
+    }
+}
@@ -1,17 +1,29 @@
-use std::sync::Arc;
+use std::{any::Any, backtrace::Backtrace, sync::Arc};
 
 use axum::{
     extract::{Request, State},
-    http::StatusCode,
+    http::{
+        StatusCode,
+        header::{ACCEPT, CONTENT_TYPE},
+    },
     middleware::Next,
     response::{Html, IntoResponse, Response},
 };
 use hickory_resolver::ResolveError;
 use minijinja::context;
+use serde::Serialize;
 use thiserror::Error;
+use tower_sessions::Session;
 use tracing::error;
 
-use crate::{AppState, open_template};
+use crate::{
+    AppState,
+    i18n::Locale,
+    open_template,
+    preferences::{self, Theme},
+    request_id::request_id,
+    upstream::is_transient,
+};
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -20,6 +32,8 @@ pub enum Error {
     OAuthClientCreation(atrium_oauth::Error),
     #[error("oauth authorize: {0}")]
     Authorize(atrium_oauth::Error),
+    #[error("oauth callback: {0}")]
+    Callback(atrium_oauth::Error),
     #[error("oauth restore: {0}")]
     Restore(atrium_oauth::Error),
     #[error("DNS resolver: {0}")]
@@ -30,31 +44,300 @@ pub enum Error {
     Session(#[from] tower_sessions::session::Error),
     #[error("session already exists")]
     SessionAlreadyExists,
+    // only the admin routes require a role
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    #[error("not logged in")]
+    Unauthorized,
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    #[error("insufficient permissions")]
+    Forbidden,
     #[error("missing did")]
     MissingDid,
+    #[error("invalid did: {0}")]
+    InvalidDid(&'static str),
+    #[error("status '{0}' is not one of the allowed status options")]
+    InvalidStatus(String),
+    #[error("reaction '{0}' is not one of the allowed reaction options")]
+    InvalidReaction(String),
+    #[error("cannot pin '{0}': only your own statuses can be pinned")]
+    InvalidPin(String),
+    #[error("unsupported API version '{0}'")]
+    UnsupportedApiVersion(String),
+    #[error("invalid query: {0}")]
+    InvalidQuery(String),
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+    #[error("status '{0}' not found")]
+    StatusNotFound(String),
+    #[error("unknown handle '{0}'")]
+    UnknownHandle(String),
     #[error("atproto record create: {0}")]
     RecordCreate(
         #[from] atrium_api::xrpc::Error<atrium_api::com::atproto::repo::create_record::Error>,
     ),
+    #[error("atproto record put: {0}")]
+    RecordPut(#[from] atrium_api::xrpc::Error<atrium_api::com::atproto::repo::put_record::Error>),
     #[error("atproto record get: {0}")]
     RecordGet(#[from] atrium_api::xrpc::Error<atrium_api::com::atproto::repo::get_record::Error>),
+    #[error("atproto record delete: {0}")]
+    RecordDelete(
+        #[from] atrium_api::xrpc::Error<atrium_api::com::atproto::repo::delete_record::Error>,
+    ),
+    #[error("atproto list records: {0}")]
+    ListRecords(
+        #[from] atrium_api::xrpc::Error<atrium_api::com::atproto::repo::list_records::Error>,
+    ),
+    #[error("atproto get relationships: {0}")]
+    GetRelationships(
+        #[from] atrium_api::xrpc::Error<atrium_api::app::bsky::graph::get_relationships::Error>,
+    ),
+    #[error("atproto list repos: {0}")]
+    ListRepos(#[from] atrium_api::xrpc::Error<atrium_api::com::atproto::sync::list_repos::Error>),
+    #[error("atproto get latest commit: {0}")]
+    GetLatestCommit(
+        #[from] atrium_api::xrpc::Error<atrium_api::com::atproto::sync::get_latest_commit::Error>,
+    ),
+    #[error("atproto create report: {0}")]
+    CreateReport(
+        #[from] atrium_api::xrpc::Error<atrium_api::com::atproto::moderation::create_report::Error>,
+    ),
+    #[error("atproto upload blob: {0}")]
+    UploadBlob(#[from] atrium_api::xrpc::Error<atrium_api::com::atproto::repo::upload_blob::Error>),
+    #[error("atproto get blob: {0}")]
+    GetBlob(#[from] atrium_api::xrpc::Error<atrium_api::com::atproto::sync::get_blob::Error>),
+    #[error("{0} timed out")]
+    UpstreamTimeout(&'static str),
+    #[error("{0} is unavailable")]
+    UpstreamUnavailable(String),
+    #[error("no PDS endpoint found for {0}")]
+    MissingPds(String),
+    #[error("cursor: {0}")]
+    Cursor(#[from] crate::cursor::Error),
+    #[error("invalid record: {0}")]
+    InvalidRecord(#[from] crate::validate::Error),
+    #[error("dead letter payload: {0}")]
+    DeadLetterPayload(serde_json::Error),
     #[error("storage: {0}")]
     Storage(#[from] crate::store::Error),
     #[error("did resolution: {0}")]
     DidResolver(#[from] atrium_identity::Error),
     #[error("profile parsing: {0}")]
     ProfileParse(atrium_api::error::Error),
+    #[cfg(feature = "metrics")]
+    #[error("metrics: {0}")]
+    Metrics(#[from] prometheus::Error),
+    #[cfg(feature = "ingester")]
     #[error("jetstream connection: {0}")]
     JetstreamConnection(#[from] atproto_jetstream::connection::Error),
 }
 
+impl Error {
+    /// Whether the error means another service (PLC directory, PDS, ...) is struggling, as
+    /// opposed to e.g. the request being invalid.
+    pub fn is_upstream_outage(&self) -> bool {
+        match self {
+            Error::UpstreamTimeout(_) | Error::UpstreamUnavailable(_) | Error::Restore(_) => true,
+            Error::DidResolver(e) => !matches!(e, atrium_identity::Error::NotFound),
+            Error::RecordCreate(e) => is_transient(e),
+            Error::RecordPut(e) => is_transient(e),
+            Error::RecordGet(e) => is_transient(e),
+            Error::RecordDelete(e) => is_transient(e),
+            Error::GetRelationships(e) => is_transient(e),
+            Error::GetLatestCommit(e) => is_transient(e),
+            Error::CreateReport(e) => is_transient(e),
+            Error::UploadBlob(e) => is_transient(e),
+            Error::GetBlob(e) => is_transient(e),
+            _ => false,
+        }
+    }
+
+    /// Machine-readable name of the error, used as the `type` of API problem details.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::OAuthClientCreation(_) => "oauth-client-creation",
+            Error::Authorize(_) => "authorize",
+            Error::Callback(_) => "callback",
+            Error::Restore(_) => "restore",
+            Error::Resolver(_) => "resolver",
+            Error::Template(_) => "template",
+            Error::Session(_) => "session",
+            Error::SessionAlreadyExists => "session-already-exists",
+            Error::Unauthorized => "unauthorized",
+            Error::Forbidden => "forbidden",
+            Error::MissingDid => "missing-did",
+            Error::InvalidDid(_) => "invalid-did",
+            Error::InvalidStatus(_) => "invalid-status",
+            Error::InvalidReaction(_) => "invalid-reaction",
+            Error::InvalidPin(_) => "invalid-pin",
+            Error::UnsupportedApiVersion(_) => "unsupported-api-version",
+            Error::InvalidQuery(_) => "invalid-query",
+            Error::InvalidInput(_) => "invalid-input",
+            Error::StatusNotFound(_) => "status-not-found",
+            Error::UnknownHandle(_) => "unknown-handle",
+            Error::RecordCreate(_) => "record-create",
+            Error::RecordPut(_) => "record-put",
+            Error::RecordGet(_) => "record-get",
+            Error::RecordDelete(_) => "record-delete",
+            Error::ListRecords(_) => "list-records",
+            Error::GetRelationships(_) => "get-relationships",
+            Error::ListRepos(_) => "list-repos",
+            Error::GetLatestCommit(_) => "get-latest-commit",
+            Error::CreateReport(_) => "create-report",
+            Error::UploadBlob(_) => "upload-blob",
+            Error::GetBlob(_) => "get-blob",
+            Error::UpstreamTimeout(_) => "upstream-timeout",
+            Error::UpstreamUnavailable(_) => "upstream-unavailable",
+            Error::MissingPds(_) => "missing-pds",
+            Error::Cursor(_) => "cursor",
+            Error::InvalidRecord(_) => "invalid-record",
+            Error::DeadLetterPayload(_) => "dead-letter-payload",
+            Error::Storage(_) => "storage",
+            Error::DidResolver(_) => "did-resolver",
+            Error::ProfileParse(_) => "profile-parse",
+            #[cfg(feature = "metrics")]
+            Error::Metrics(_) => "metrics",
+            #[cfg(feature = "ingester")]
+            Error::JetstreamConnection(_) => "jetstream-connection",
+        }
+    }
+}
+
+// kind of the `Error` a response was built from, for `error_middleware` to report
+#[derive(Debug, Clone, Copy)]
+struct ErrorKind(&'static str);
+
+/// RFC 7807 problem details, returned instead of error pages to API clients.
+#[derive(Debug, Serialize)]
+struct Problem {
+    #[serde(rename = "type")]
+    problem_type: String,
+    title: &'static str,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    #[serde(rename = "requestId", skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl Problem {
+    fn new(
+        status: StatusCode,
+        kind: Option<ErrorKind>,
+        detail: Option<String>,
+        request_id: Option<String>,
+    ) -> Self {
+        Self {
+            // responses that didn't come from an `Error` (e.g. unknown routes) have no more
+            // specific type than their status code
+            problem_type: kind.map_or_else(
+                || "about:blank".to_owned(),
+                |ErrorKind(kind)| format!("urn:statusphere:error:{kind}"),
+            ),
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            detail,
+            request_id,
+        }
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        match serde_json::to_vec(&self) {
+            Ok(body) => {
+                (status, [(CONTENT_TYPE, "application/problem+json")], body).into_response()
+            }
+            Err(_) => status.into_response(),
+        }
+    }
+}
+
+/// Logs panics with a backtrace. The hook runs on the panicking thread, so the log line lands in
+/// the span of the request that panicked.
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        error!(
+            "panic: {info}\n{backtrace}",
+            backtrace = Backtrace::force_capture()
+        );
+    }));
+}
+
+/// Turns a handler panic (already logged by the panic hook) into a 500 response, for
+/// [`error_middleware`] to render like any other error.
+pub fn handle_panic(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("unknown panic");
+    let mut response = (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("handler panicked: {message}"),
+    )
+        .into_response();
+    response.extensions_mut().insert(ErrorKind("panic"));
+    response
+}
+
+// whether errors for this request should be problem details rather than an HTML page
+fn wants_problem_json(request: &Request) -> bool {
+    request.uri().path().starts_with("/api/")
+        || request.uri().path().starts_with("/xrpc/")
+        || request
+            .headers()
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|accept| accept.to_str().ok())
+            .any(|accept| {
+                accept.contains("application/json") || accept.contains("application/problem+json")
+            })
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         error!(%self);
-        // kinda a lazy catch-all, but mostly correct
-        let (status_code, message) = (StatusCode::SERVICE_UNAVAILABLE, self.to_string());
+        let status_code = match self {
+            Error::InvalidDid(_)
+            | Error::InvalidPin(_)
+            | Error::InvalidQuery(_)
+            | Error::Cursor(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidStatus(_)
+            | Error::InvalidReaction(_)
+            | Error::InvalidInput(_)
+            | Error::InvalidRecord(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::StatusNotFound(_)
+            | Error::UnknownHandle(_)
+            | Error::UnsupportedApiVersion(_) => StatusCode::NOT_FOUND,
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+            Error::Forbidden => StatusCode::FORBIDDEN,
+            Error::UpstreamTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            // kinda a lazy catch-all, but mostly correct
+            _ => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        let message = self.to_string();
 
-        (status_code, message).into_response()
+        let mut response = (status_code, message).into_response();
+        response.extensions_mut().insert(ErrorKind(self.kind()));
+        response
+    }
+}
+
+/// Fallback for routes that don't exist, rendered as a 404 page by [`error_middleware`].
+pub async fn not_found() -> StatusCode {
+    StatusCode::NOT_FOUND
+}
+
+// the error page for responses with `status`
+fn error_template(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::NOT_FOUND => "error_404",
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => "error_401",
+        StatusCode::UNPROCESSABLE_ENTITY => "error_422",
+        StatusCode::PAYLOAD_TOO_LARGE => "error_413",
+        status if status.is_server_error() => "error_5xx",
+        _ => "error",
     }
 }
 
@@ -63,10 +346,28 @@ pub async fn error_middleware(
     request: Request,
     next: Next,
 ) -> Response {
+    let locale = Locale::from_headers(request.headers());
+    let session = request.extensions().get::<Session>().cloned();
+    let problem_json = wants_problem_json(&request);
+    let request_id = request_id(request.headers()).map(str::to_owned);
     let response = next.run(request).await;
     let status = response.status();
-    if status.is_client_error() || status.is_server_error() {
-        let template = open_template!(state, "error");
+    let kind = response.extensions().get::<ErrorKind>().copied();
+    if problem_json && (status.is_client_error() || status.is_server_error()) {
+        // client errors describe the request, so they're safe to explain
+        let detail = if status.is_client_error() || state.config.show_error_messages {
+            let (_, body) = response.into_parts();
+            axum::body::to_bytes(body, usize::MAX)
+                .await
+                .ok()
+                .and_then(|body| String::from_utf8(body.to_vec()).ok())
+                .filter(|detail| !detail.is_empty())
+        } else {
+            None
+        };
+        Problem::new(status, kind, detail, request_id).into_response()
+    } else if status.is_client_error() || status.is_server_error() {
+        let template = open_template!(state, error_template(status));
 
         let error_details = if state.config.show_error_messages {
             let (_, body) = response.into_parts();
@@ -80,8 +381,19 @@ pub async fn error_middleware(
             None
         };
 
+        // a broken session shouldn't stop the error page from rendering
+        let theme = match &session {
+            Some(session) => preferences::theme(session).await.unwrap_or_default(),
+            None => Theme::default(),
+        };
+
         match template.render(context! {
-            error_details => error_details
+            locale => locale,
+            theme => theme,
+            status_code => status.as_u16(),
+            error_kind => kind.map(|ErrorKind(kind)| kind),
+            error_details => error_details,
+            request_id => request_id,
         }) {
             Ok(rendered) => (status, Html(rendered)).into_response(),
             Err(_) => (status, "Something went wrong!").into_response(),
@@ -0,0 +1,209 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    str::FromStr,
+};
+
+use atrium_api::types::string::{Datetime, Did};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::store::{Status, StatusImage, StatusStore, Visibility};
+
+// statuses inserted per transaction when importing
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+/// File format of a status dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One JSON object per line.
+    Ndjson,
+    /// Comma-separated values with a header row.
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ndjson" => Ok(Self::Ndjson),
+            "csv" => Ok(Self::Csv),
+            other => Err(anyhow::anyhow!(
+                "invalid format '{other}': expected 'ndjson' or 'csv'"
+            )),
+        }
+    }
+}
+
+/// Where to dump statuses to or load them from, parsed from `[--format ndjson|csv] [PATH]`.
+/// Without a path, stdin/stdout are used. The format defaults to the path's extension, and to
+/// NDJSON otherwise.
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub format: Format,
+    pub path: Option<String>,
+}
+
+impl Args {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut format = None;
+        let mut path = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("--format requires a value"))?;
+                    format = Some(value.parse()?);
+                }
+                "-" => path = None,
+                _ if path.is_none() => path = Some(arg),
+                _ => anyhow::bail!("unexpected argument '{arg}'"),
+            }
+        }
+        let format = format.unwrap_or(match &path {
+            Some(path) if path.ends_with(".csv") => Format::Csv,
+            _ => Format::Ndjson,
+        });
+        Ok(Self { format, path })
+    }
+}
+
+// a status as it appears in dumps; kept separate from `Status` so the dump format only changes
+// deliberately
+#[derive(Debug, Serialize, Deserialize)]
+struct StatusRecord {
+    uri: String,
+    author_did: String,
+    status: String,
+    created_at: String,
+    indexed_at: String,
+    raw_created_at: Option<String>,
+    visibility: String,
+    content_warning: Option<String>,
+    // absent from dumps made before it was kept
+    #[serde(default)]
+    cid: Option<String>,
+    #[serde(default)]
+    image_cid: Option<String>,
+    #[serde(default)]
+    image_mime_type: Option<String>,
+}
+
+impl From<Status> for StatusRecord {
+    fn from(status: Status) -> Self {
+        Self {
+            uri: status.uri,
+            author_did: status.author_did.as_str().to_owned(),
+            status: status.status,
+            created_at: status.created_at.as_str().to_owned(),
+            indexed_at: status.indexed_at.as_str().to_owned(),
+            raw_created_at: status.raw_created_at.map(|dt| dt.as_str().to_owned()),
+            visibility: status.visibility.as_str().to_owned(),
+            content_warning: status.content_warning,
+            cid: status.cid,
+            image_cid: status.image.as_ref().map(|image| image.cid.clone()),
+            image_mime_type: status.image.map(|image| image.mime_type),
+        }
+    }
+}
+
+impl TryFrom<StatusRecord> for Status {
+    type Error = anyhow::Error;
+
+    fn try_from(record: StatusRecord) -> Result<Self, Self::Error> {
+        let parse_datetime = |dt: &str| {
+            Datetime::from_str(dt).map_err(|e| anyhow::anyhow!("invalid datetime '{dt}': {e}"))
+        };
+        Ok(Self {
+            author_did: Did::new(record.author_did)
+                .map_err(|e| anyhow::anyhow!("invalid did: {e}"))?,
+            status: record.status,
+            created_at: parse_datetime(&record.created_at)?,
+            indexed_at: parse_datetime(&record.indexed_at)?,
+            raw_created_at: record
+                .raw_created_at
+                .as_deref()
+                .map(parse_datetime)
+                .transpose()?,
+            visibility: Visibility::from_str(&record.visibility)?,
+            content_warning: record.content_warning,
+            cid: record.cid,
+            image: record
+                .image_cid
+                .zip(record.image_mime_type)
+                .map(|(cid, mime_type)| StatusImage { cid, mime_type }),
+            uri: record.uri,
+        })
+    }
+}
+
+/// Writes every status to `args`' destination, returning how many were written.
+pub async fn export(status_store: &StatusStore, args: &Args) -> anyhow::Result<usize> {
+    let output: Box<dyn Write> = match &args.path {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let mut count = 0;
+    let mut statuses = std::pin::pin!(status_store.stream_all());
+    match args.format {
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(output);
+            while let Some(status) = statuses.try_next().await? {
+                writer.serialize(StatusRecord::from(status))?;
+                count += 1;
+            }
+            writer.flush()?;
+        }
+        Format::Ndjson => {
+            let mut output = BufWriter::new(output);
+            while let Some(status) = statuses.try_next().await? {
+                serde_json::to_writer(&mut output, &StatusRecord::from(status))?;
+                output.write_all(b"\n")?;
+                count += 1;
+            }
+            output.flush()?;
+        }
+    }
+    Ok(count)
+}
+
+/// Loads statuses from `args`' source, replacing existing statuses with the same URI. Returns
+/// how many were imported.
+pub async fn import(status_store: &StatusStore, args: &Args) -> anyhow::Result<usize> {
+    let input: Box<dyn BufRead> = match &args.path {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(io::stdin().lock()),
+    };
+    let records: Box<dyn Iterator<Item = anyhow::Result<StatusRecord>>> = match args.format {
+        Format::Csv => Box::new(
+            csv::Reader::from_reader(input)
+                .into_deserialize()
+                .map(|record| record.map_err(anyhow::Error::from)),
+        ),
+        Format::Ndjson => Box::new(
+            input
+                .lines()
+                .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+                .map(|line| Ok(serde_json::from_str(&line?)?)),
+        ),
+    };
+
+    let mut count = 0;
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    for (i, record) in records.enumerate() {
+        let status = record
+            .and_then(Status::try_from)
+            .map_err(|e| anyhow::anyhow!("record {}: {e}", i + 1))?;
+        batch.push(status);
+        if batch.len() == IMPORT_BATCH_SIZE {
+            count += batch.len();
+            status_store.insert_many(std::mem::take(&mut batch)).await?;
+        }
+    }
+    count += batch.len();
+    status_store.insert_many(batch).await?;
+    Ok(count)
+}
@@ -0,0 +1,308 @@
+//! A Bluesky feed generator serving the posts that crosspost statuses, newest status first, so
+//! the statusphere can be followed as a custom feed in Bluesky clients.
+//!
+//! The service is `did:web:<FEED_GENERATOR_HOSTNAME>`, whose DID document is served here too. The
+//! feed is published by creating an `app.bsky.feed.generator` record with rkey
+//! `FEED_GENERATOR_RKEY` in the repo of `FEED_GENERATOR_PUBLISHER_DID`, with that service DID as
+//! its `did`.
+
+use std::sync::Arc;
+
+use atrium_api::types::string::{Did, RecordKey};
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::uri::Authority,
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{AppState, config::env_var_or_default, error::Error};
+
+const DEFAULT_SKELETON_LIMIT: usize = 50;
+const MAX_SKELETON_LIMIT: usize = 100;
+
+/// Where the feed generator is served and who publishes the feed.
+#[derive(Debug, Clone)]
+pub struct FeedGeneratorConfig {
+    /// Public hostname of this server, which the service DID is derived from.
+    pub hostname: String,
+    /// Account whose repo holds the feed's `app.bsky.feed.generator` record.
+    pub publisher_did: Did,
+    /// Record key of the feed's `app.bsky.feed.generator` record.
+    pub rkey: String,
+}
+
+impl FeedGeneratorConfig {
+    /// Feed generator settings, if `FEED_GENERATOR_HOSTNAME` is set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let hostname = env_var_or_default("FEED_GENERATOR_HOSTNAME", "")?;
+        if hostname.is_empty() {
+            return Ok(None);
+        }
+        // just a host (and maybe port), not a URL
+        if hostname.contains('/')
+            || hostname.contains('@')
+            || hostname.parse::<Authority>().is_err()
+        {
+            anyhow::bail!("invalid FEED_GENERATOR_HOSTNAME '{hostname}': expected a hostname");
+        }
+        let publisher_did = env_var_or_default("FEED_GENERATOR_PUBLISHER_DID", "")?;
+        if publisher_did.is_empty() {
+            anyhow::bail!("FEED_GENERATOR_PUBLISHER_DID must be set with FEED_GENERATOR_HOSTNAME");
+        }
+        let publisher_did = Did::new(publisher_did.clone()).map_err(|e| {
+            anyhow::anyhow!("invalid FEED_GENERATOR_PUBLISHER_DID '{publisher_did}': {e}")
+        })?;
+        let rkey = env_var_or_default("FEED_GENERATOR_RKEY", "statusphere")?;
+        RecordKey::new(rkey.clone())
+            .map_err(|e| anyhow::anyhow!("invalid FEED_GENERATOR_RKEY '{rkey}': {e}"))?;
+        Ok(Some(Self {
+            hostname,
+            publisher_did,
+            rkey,
+        }))
+    }
+
+    /// The feed generator service's DID, e.g. `did:web:statusphere.example.com`.
+    pub fn service_did(&self) -> String {
+        // did:web encodes a port's colon
+        format!("did:web:{}", self.hostname.replace(':', "%3A"))
+    }
+
+    /// AT URI of the feed's `app.bsky.feed.generator` record.
+    pub fn feed_uri(&self) -> String {
+        format!(
+            "at://{}/app.bsky.feed.generator/{}",
+            self.publisher_did.as_str(),
+            self.rkey
+        )
+    }
+}
+
+/// The DID document and XRPC methods of the feed generator. Only served when it's configured.
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/.well-known/did.json", get(did_document))
+        .route(
+            "/xrpc/app.bsky.feed.describeFeedGenerator",
+            get(describe_feed_generator),
+        )
+        .route(
+            "/xrpc/app.bsky.feed.getFeedSkeleton",
+            get(get_feed_skeleton),
+        )
+}
+
+fn config(state: &AppState) -> &FeedGeneratorConfig {
+    state
+        .config
+        .feed_generator
+        .as_ref()
+        .expect("feed generator routes are only served when it's configured")
+}
+
+async fn did_document(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let config = config(&state);
+    Json(json!({
+        "@context": ["https://www.w3.org/ns/did/v1"],
+        "id": config.service_did(),
+        "service": [{
+            "id": "#bsky_fg",
+            "type": "BskyFeedGenerator",
+            "serviceEndpoint": format!("https://{}", config.hostname),
+        }],
+    }))
+}
+
+async fn describe_feed_generator(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let config = config(&state);
+    Json(json!({
+        "did": config.service_did(),
+        "feeds": [{ "uri": config.feed_uri() }],
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct SkeletonQuery {
+    feed: String,
+    limit: Option<usize>,
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SkeletonPost {
+    post: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Skeleton {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
+    feed: Vec<SkeletonPost>,
+}
+
+async fn get_feed_skeleton(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SkeletonQuery>,
+) -> Result<Json<Skeleton>, Error> {
+    if query.feed != config(&state).feed_uri() {
+        return Err(Error::InvalidQuery(format!(
+            "unknown feed '{}'",
+            query.feed
+        )));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SKELETON_LIMIT)
+        .clamp(1, MAX_SKELETON_LIMIT);
+    let after = query
+        .cursor
+        .as_deref()
+        .map(|cursor| state.config.cursor_codec.decode(cursor))
+        .transpose()?;
+
+    // plus one to find out whether there's another page
+    let mut crossposts = state
+        .status_store
+        .fetch_crossposts(after.as_ref(), limit + 1)
+        .await?;
+    let cursor = if crossposts.len() > limit {
+        crossposts.truncate(limit);
+        crossposts
+            .last()
+            .map(|crosspost| state.config.cursor_codec.encode(&crosspost.cursor))
+    } else {
+        None
+    };
+
+    Ok(Json(Skeleton {
+        cursor,
+        feed: crossposts
+            .into_iter()
+            .map(|crosspost| SkeletonPost {
+                post: crosspost.post_uri,
+            })
+            .collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::{
+        config::AppConfig,
+        test_support::{TestApp, did},
+    };
+
+    const ALICE: &str = "did:plc:alice0000000000000000000";
+    const PUBLISHER: &str = "did:plc:publisher00000000000000";
+    const FEED: &str = "at://did:plc:publisher00000000000000/app.bsky.feed.generator/statusphere";
+
+    async fn feed_generator_app() -> TestApp {
+        let mut config = AppConfig::for_tests();
+        config.feed_generator = Some(FeedGeneratorConfig {
+            hostname: "statusphere.example.com".to_owned(),
+            publisher_did: did(PUBLISHER),
+            rkey: "statusphere".to_owned(),
+        });
+        TestApp::with_config(config).await
+    }
+
+    fn json(body: &str) -> serde_json::Value {
+        serde_json::from_str(body).expect("body is JSON")
+    }
+
+    #[tokio::test]
+    async fn serves_crossposts_newest_first_a_page_at_a_time() {
+        let app = feed_generator_app().await;
+        for rkey in ["3kaaaaaaaaaa2", "3kaaaaaaaaab2", "3kaaaaaaaaac2"] {
+            let uri = app.seed_status(&did(ALICE), rkey, "🦋").await;
+            app.state
+                .status_store
+                .set_crosspost(&uri, format!("at://{ALICE}/app.bsky.feed.post/{rkey}"))
+                .await
+                .expect("crosspost is stored");
+        }
+        // statuses that weren't crossposted aren't in the feed
+        app.seed_status(&did(ALICE), "3kaaaaaaaaad2", "🦀").await;
+
+        let first = app
+            .get(
+                &format!("/xrpc/app.bsky.feed.getFeedSkeleton?feed={FEED}&limit=2"),
+                None,
+            )
+            .await;
+        assert_eq!(first.status, StatusCode::OK);
+        let first = json(&first.body);
+        assert_eq!(
+            first["feed"],
+            serde_json::json!([
+                { "post": format!("at://{ALICE}/app.bsky.feed.post/3kaaaaaaaaac2") },
+                { "post": format!("at://{ALICE}/app.bsky.feed.post/3kaaaaaaaaab2") },
+            ])
+        );
+        let cursor = first["cursor"].as_str().expect("a full page has a cursor");
+
+        let second = app
+            .get(
+                &format!("/xrpc/app.bsky.feed.getFeedSkeleton?feed={FEED}&limit=2&cursor={cursor}"),
+                None,
+            )
+            .await;
+        let second = json(&second.body);
+        assert_eq!(
+            second["feed"],
+            serde_json::json!([
+                { "post": format!("at://{ALICE}/app.bsky.feed.post/3kaaaaaaaaaa2") },
+            ])
+        );
+        assert!(second["cursor"].is_null());
+    }
+
+    #[tokio::test]
+    async fn only_serves_its_own_feed() {
+        let app = feed_generator_app().await;
+
+        let response = app
+            .get(
+                &format!("/xrpc/app.bsky.feed.getFeedSkeleton?feed=at://{PUBLISHER}/app.bsky.feed.generator/other"),
+                None,
+            )
+            .await;
+
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn describes_itself_and_its_did() {
+        let app = feed_generator_app().await;
+
+        let description = json(
+            &app.get("/xrpc/app.bsky.feed.describeFeedGenerator", None)
+                .await
+                .body,
+        );
+        assert_eq!(description["did"], "did:web:statusphere.example.com");
+        assert_eq!(description["feeds"][0]["uri"], FEED);
+
+        let document = json(&app.get("/.well-known/did.json", None).await.body);
+        assert_eq!(document["id"], "did:web:statusphere.example.com");
+        assert_eq!(
+            document["service"][0]["serviceEndpoint"],
+            "https://statusphere.example.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn isnt_served_unless_configured() {
+        let app = TestApp::new().await;
+
+        let response = app.get("/.well-known/did.json", None).await;
+
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,465 @@
+use std::{collections::HashMap, io, time::Duration};
+
+use atrium_api::types::{
+    Collection, Unknown,
+    string::{Datetime, Did},
+};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use ipld_core::cid::Cid;
+use serde::Deserialize;
+use serde_ipld_dagcbor::de::Deserializer;
+use thiserror::Error;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{error, info, warn};
+
+use crate::{
+    dead_letter,
+    ingester::{Consumers, PauseSwitch},
+    ingester_status::{ConnectionState, IngesterStatus},
+    lexicons::xyz::statusphere::{
+        Pin, Reaction, Status, pin::RecordData as PinRecordData,
+        reaction::RecordData as ReactionRecordData, status::RecordData as StatusRecordData,
+    },
+    record_consumer::{CommitOperation, RecordCommit},
+    store::{
+        DeadLetterStore, Reaction as StoreReaction, Status as StoreStatus, StatusImage, Visibility,
+        sanitize_content_warning,
+    },
+};
+
+// reconnect backoff bounds
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("websocket: {0}")]
+    WebSocket(#[from] tokio_tungstenite::tungstenite::Error),
+    #[error("cbor decoding: {0}")]
+    Cbor(String),
+    #[error("car decoding: {0}")]
+    Car(String),
+    #[error("firehose error frame: {0}")]
+    ErrorFrame(String),
+}
+
+// every firehose frame is a DAG-CBOR header followed by a DAG-CBOR body
+#[derive(Debug, Deserialize)]
+struct FrameHeader {
+    op: i64,
+    t: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    error: String,
+    message: Option<String>,
+}
+
+// the subset of `com.atproto.sync.subscribeRepos#commit` we need
+#[derive(Debug, Deserialize)]
+struct CommitBody {
+    seq: i64,
+    repo: String,
+    // when the commit was made, RFC 3339
+    time: Option<String>,
+    ops: Vec<RepoOp>,
+    blocks: serde_bytes::ByteBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct RepoOp {
+    action: String,
+    path: String,
+    cid: Option<Cid>,
+}
+
+fn read_varint(reader: &mut impl io::Read) -> Result<Option<u64>, Error> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    let mut byte = [0u8];
+    loop {
+        match reader.read(&mut byte) {
+            Ok(0) if shift == 0 => return Ok(None),
+            Ok(0) => return Err(Error::Car("truncated varint".to_owned())),
+            Ok(_) => {}
+            Err(e) => return Err(Error::Car(e.to_string())),
+        }
+        let bits = (byte[0] & 0x7f) as u64;
+        // only the lowest bit of a tenth byte still fits
+        if shift == 63 && bits > 1 {
+            return Err(Error::Car("varint overflow".to_owned()));
+        }
+        value |= bits << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+        shift += 7;
+        if shift >= 64 {
+            return Err(Error::Car("varint overflow".to_owned()));
+        }
+    }
+}
+
+// parse a CARv1 file into its blocks; we don't care about the roots in the header
+fn read_car_blocks(car: &[u8]) -> Result<HashMap<Cid, Vec<u8>>, Error> {
+    let mut reader = io::Cursor::new(car);
+    let header_len =
+        read_varint(&mut reader)?.ok_or_else(|| Error::Car("missing header".to_owned()))?;
+    let header_end = reader
+        .position()
+        .checked_add(header_len)
+        .filter(|&end| end <= car.len() as u64)
+        .ok_or_else(|| Error::Car("truncated header".to_owned()))?;
+    reader.set_position(header_end);
+
+    let mut blocks = HashMap::new();
+    while let Some(section_len) = read_varint(&mut reader)? {
+        let start = reader.position() as usize;
+        let end = usize::try_from(section_len)
+            .ok()
+            .and_then(|len| start.checked_add(len))
+            .filter(|&end| end <= car.len())
+            .ok_or_else(|| Error::Car("truncated block".to_owned()))?;
+        let mut section = io::Cursor::new(&car[start..end]);
+        let cid = Cid::read_bytes(&mut section).map_err(|e| Error::Car(e.to_string()))?;
+        let data_start = start + section.position() as usize;
+        blocks.insert(cid, car[data_start..end].to_vec());
+        reader.set_position(end as u64);
+    }
+    Ok(blocks)
+}
+
+// the collection and record key of a repo path
+fn split_path(path: &str) -> Option<(&str, &str)> {
+    path.split_once('/')
+}
+
+async fn process_commit(commit: CommitBody, consumers: &Consumers) -> Result<(), Error> {
+    // skip decoding the CAR entirely unless the commit touches a collection we care about
+    let wanted = |op: &RepoOp| {
+        split_path(&op.path)
+            .is_some_and(|(collection, _)| consumers.collections.iter().any(|c| c == collection))
+    };
+    if !commit.ops.iter().any(wanted) {
+        return Ok(());
+    }
+
+    let Ok(author_did) = Did::new(commit.repo.clone()) else {
+        warn!("ignoring commit with invalid repo DID: {}", commit.repo);
+        return Ok(());
+    };
+    let blocks = read_car_blocks(&commit.blocks)?;
+
+    for op in commit.ops.iter().filter(|op| wanted(op)) {
+        let Some((collection, rkey)) = split_path(&op.path) else {
+            continue;
+        };
+        let block = op.cid.as_ref().and_then(|cid| blocks.get(cid));
+
+        if let Some(consumer) = consumers.registered.get(collection) {
+            let Some(operation) = CommitOperation::parse(&op.action) else {
+                continue;
+            };
+            let record = match (operation, block) {
+                (CommitOperation::Delete, _) => None,
+                (_, Some(block)) => Some(
+                    serde_ipld_dagcbor::from_slice::<Unknown>(block)
+                        .map_err(|e| Error::Cbor(e.to_string()))?,
+                ),
+                (_, None) => {
+                    warn!("missing block for {}/{}", commit.repo, op.path);
+                    continue;
+                }
+            };
+            let record_commit = RecordCommit {
+                did: author_did.clone(),
+                collection: collection.to_owned(),
+                rkey: rkey.to_owned(),
+                operation,
+                record,
+                cid: op.cid.as_ref().map(Cid::to_string),
+            };
+            if let Err(e) = consumer.consume(record_commit).await {
+                error!("error consuming {}/{}: {e}", commit.repo, op.path);
+            }
+            continue;
+        }
+
+        // deletes don't carry a record; of the app's own, only a deleted pin changes anything
+        if op.action == "delete" && collection == Pin::NSID {
+            if let Err(e) = consumers.pin.unpin(author_did.clone()).await {
+                error!("error storing {}/{}: {e}", commit.repo, op.path);
+            }
+            continue;
+        }
+        if op.action != "create" && op.action != "update" {
+            continue;
+        }
+        let Some(block) = block else {
+            warn!("missing block for {}/{}", commit.repo, op.path);
+            continue;
+        };
+
+        let result = if collection == Status::NSID {
+            let StatusRecordData {
+                status,
+                created_at,
+                content_warning,
+                image,
+            } = serde_ipld_dagcbor::from_slice(block).map_err(|e| Error::Cbor(e.to_string()))?;
+            consumers
+                .status
+                .ingest(StoreStatus {
+                    uri: format!("at://{}/{}", commit.repo, op.path),
+                    author_did: author_did.clone(),
+                    status,
+                    created_at,
+                    indexed_at: Datetime::now(),
+                    raw_created_at: None,
+                    visibility: Visibility::Public,
+                    content_warning: sanitize_content_warning(content_warning),
+                    cid: op.cid.as_ref().map(Cid::to_string),
+                    image: image.as_ref().and_then(StatusImage::from_blob),
+                })
+                .await
+        } else if collection == Reaction::NSID {
+            let ReactionRecordData {
+                subject,
+                emoji,
+                created_at,
+            } = serde_ipld_dagcbor::from_slice(block).map_err(|e| Error::Cbor(e.to_string()))?;
+            consumers
+                .reaction
+                .ingest(StoreReaction {
+                    uri: format!("at://{}/{}", commit.repo, op.path),
+                    author_did: author_did.clone(),
+                    subject,
+                    emoji,
+                    created_at,
+                })
+                .await
+        } else if collection == Pin::NSID {
+            let pin: PinRecordData =
+                serde_ipld_dagcbor::from_slice(block).map_err(|e| Error::Cbor(e.to_string()))?;
+            consumers.pin.ingest(author_did.clone(), pin).await
+        } else {
+            // subscribed to, but consumed by nothing
+            continue;
+        };
+        if let Err(e) = result {
+            error!("error storing {}/{}: {e}", commit.repo, op.path);
+        }
+    }
+    Ok(())
+}
+
+// where a commit frame sits in the stream
+#[derive(Debug)]
+struct Position {
+    seq: i64,
+    time: Option<DateTime<Utc>>,
+}
+
+// process a single binary frame, returning its position if it was a commit
+async fn process_frame(frame: &[u8], consumers: &Consumers) -> Result<Option<Position>, Error> {
+    let mut de = Deserializer::from_slice(frame);
+    let header = FrameHeader::deserialize(&mut de).map_err(|e| Error::Cbor(e.to_string()))?;
+    match (header.op, header.t.as_deref()) {
+        (1, Some("#commit")) => {
+            let commit =
+                CommitBody::deserialize(&mut de).map_err(|e| Error::Cbor(e.to_string()))?;
+            let position = Position {
+                seq: commit.seq,
+                time: commit
+                    .time
+                    .as_deref()
+                    .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                    .map(|time| time.with_timezone(&Utc)),
+            };
+            process_commit(commit, consumers).await?;
+            Ok(Some(position))
+        }
+        (-1, _) => {
+            let body = ErrorBody::deserialize(&mut de).map_err(|e| Error::Cbor(e.to_string()))?;
+            Err(Error::ErrorFrame(format!(
+                "{}{}",
+                body.error,
+                body.message.map(|m| format!(": {m}")).unwrap_or_default()
+            )))
+        }
+        // identity, account, sync, info frames aren't relevant to us
+        _ => Ok(None),
+    }
+}
+
+async fn subscribe(
+    url: &str,
+    cursor: &mut Option<i64>,
+    consumers: &Consumers,
+    dead_letters: &DeadLetterStore,
+    status: &IngesterStatus,
+) -> Result<(), Error> {
+    let endpoint = match cursor {
+        Some(seq) => format!("{url}/xrpc/com.atproto.sync.subscribeRepos?cursor={seq}"),
+        None => format!("{url}/xrpc/com.atproto.sync.subscribeRepos"),
+    };
+    status.set_connection(ConnectionState::Connecting);
+    let (mut stream, _) = connect_async(endpoint.as_str()).await?;
+    info!("Firehose connected: {endpoint}");
+
+    while let Some(message) = stream.next().await {
+        match message? {
+            Message::Binary(frame) => match process_frame(&frame, consumers).await {
+                Ok(Some(Position { seq, time })) => {
+                    *cursor = Some(seq);
+                    status.event_received(time);
+                }
+                Ok(None) => status.event_received(None),
+                Err(e @ Error::ErrorFrame(_)) => return Err(e),
+                Err(e) => {
+                    error!("error during firehose frame processing: {e}");
+                    let payload = dead_letter::firehose_frame_payload(&frame);
+                    if let Err(e) = dead_letters.insert(payload, &e).await {
+                        error!("failed to dead-letter firehose frame: {e}");
+                    }
+                }
+            },
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Consumes the `com.atproto.sync.subscribeRepos` firehose at `url` (e.g. `wss://bsky.network`),
+/// reconnecting from the last seen sequence number when the connection drops, and disconnecting
+/// while `pause` is on. Frames that fail to decode are dead-lettered. Only returns if the pause
+/// switch can't be read or written.
+pub async fn firehose(
+    url: String,
+    consumers: Consumers,
+    dead_letters: DeadLetterStore,
+    mut pause: PauseSwitch,
+    status: IngesterStatus,
+) -> Result<(), crate::error::Error> {
+    let url = url.trim_end_matches('/');
+    // where the ingester paused, if it did
+    let mut cursor = pause.resume_cursor;
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        pause.resumed().await?;
+        let subscribed = tokio::select! {
+            subscribed = subscribe(
+                url,
+                &mut cursor,
+                &consumers,
+                &dead_letters,
+                &status,
+            ) => Some(subscribed),
+            () = pause.paused() => None,
+        };
+        match subscribed {
+            // the cursor is only moved past frames once they're processed, so the one being
+            // processed when the subscription stopped is replayed on resuming
+            None => {
+                if let Some(cursor) = cursor {
+                    pause.hold(cursor).await?;
+                }
+                status.set_connection(ConnectionState::Paused);
+                info!("Ingestion paused at sequence number {cursor:?}");
+                continue;
+            }
+            Some(Ok(())) => {
+                status.set_connection(ConnectionState::Disconnected);
+                warn!("Firehose connection closed, reconnecting");
+                backoff = MIN_BACKOFF;
+            }
+            Some(Err(e)) => {
+                status.set_connection(ConnectionState::Disconnected);
+                error!("Firehose connection failed: {e}");
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+        tokio::time::sleep(backoff).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CID: &str = "bafyreie5737gdxlw5i64vzichcalba3z2v5n6icifvx5xytvske7mr3hpm";
+
+    fn varint(bytes: &[u8]) -> Result<Option<u64>, Error> {
+        read_varint(&mut io::Cursor::new(bytes))
+    }
+
+    // a CAR with an empty header and `sections`, each prefixed with its length
+    fn car(sections: &[Vec<u8>]) -> Vec<u8> {
+        let mut car = vec![1, 0xa0];
+        for section in sections {
+            car.push(section.len() as u8);
+            car.extend(section);
+        }
+        car
+    }
+
+    #[test]
+    fn varints_are_read() {
+        assert_eq!(varint(&[]).expect("empty input reads"), None);
+        assert_eq!(varint(&[0x01]).expect("varint reads"), Some(1));
+        assert_eq!(varint(&[0xac, 0x02]).expect("varint reads"), Some(300));
+        let mut max = vec![0xff; 9];
+        max.push(0x01);
+        assert_eq!(varint(&max).expect("varint reads"), Some(u64::MAX));
+    }
+
+    #[test]
+    fn truncated_varints_are_rejected() {
+        assert!(matches!(varint(&[0x80]), Err(Error::Car(_))));
+        assert!(matches!(varint(&[0xff, 0xff]), Err(Error::Car(_))));
+    }
+
+    #[test]
+    fn oversized_varints_are_rejected() {
+        let mut too_big = vec![0xff; 9];
+        too_big.push(0x02);
+        assert!(matches!(varint(&too_big), Err(Error::Car(_))));
+        assert!(matches!(varint(&[0xff; 11]), Err(Error::Car(_))));
+    }
+
+    #[test]
+    fn car_blocks_are_read() {
+        let cid: Cid = CID.parse().expect("CID parses");
+        let mut section = cid.to_bytes();
+        section.extend(b"block");
+
+        let blocks = read_car_blocks(&car(&[section])).expect("CAR reads");
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks.get(&cid).map(Vec::as_slice), Some(&b"block"[..]));
+    }
+
+    #[test]
+    fn malformed_cars_are_rejected() {
+        // no header
+        assert!(matches!(read_car_blocks(&[]), Err(Error::Car(_))));
+        // header longer than the CAR
+        assert!(matches!(read_car_blocks(&[5, 0xa0]), Err(Error::Car(_))));
+        // block longer than the CAR
+        let mut truncated = car(&[]);
+        truncated.extend([10, 1, 2]);
+        assert!(matches!(read_car_blocks(&truncated), Err(Error::Car(_))));
+        // block length beyond any CAR
+        let mut oversized = car(&[]);
+        oversized.extend([0xff; 9]);
+        oversized.push(0x01);
+        assert!(matches!(read_car_blocks(&oversized), Err(Error::Car(_))));
+        // block without a valid CID
+        assert!(matches!(
+            read_car_blocks(&car(&[vec![0xff, 0xff, 0xff]])),
+            Err(Error::Car(_))
+        ));
+    }
+}
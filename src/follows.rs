@@ -0,0 +1,91 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use atrium_api::types::string::Did;
+
+// most viewers whose follows are kept; past it, the longest-cached ones make way
+const MAX_CACHED_VIEWERS: usize = 4096;
+
+#[derive(Debug)]
+struct CachedFollows {
+    followed: Vec<Did>,
+    fetched_at: Instant,
+}
+
+/// Which authors of followers-only statuses each viewer follows, as last fetched from their PDS,
+/// so pages don't ask the PDS again on every view. Kept for `ttl`, so a new follow shows up
+/// within it.
+#[derive(Debug)]
+pub struct FollowCache {
+    ttl: Duration,
+    viewers: Mutex<HashMap<Did, CachedFollows>>,
+}
+
+impl FollowCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            viewers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The authors `viewer` follows, unless they're unknown or stale.
+    pub fn get(&self, viewer: &Did) -> Option<Vec<Did>> {
+        self.viewers
+            .lock()
+            .expect("poisoned lock")
+            .get(viewer)
+            .filter(|cached| cached.fetched_at.elapsed() <= self.ttl)
+            .map(|cached| cached.followed.clone())
+    }
+
+    pub fn insert(&self, viewer: Did, followed: Vec<Did>) {
+        let now = Instant::now();
+        let mut viewers = self.viewers.lock().expect("poisoned lock");
+        if viewers.len() >= MAX_CACHED_VIEWERS && !viewers.contains_key(&viewer) {
+            viewers.retain(|_, cached| now.duration_since(cached.fetched_at) <= self.ttl);
+            if viewers.len() >= MAX_CACHED_VIEWERS {
+                let oldest = viewers
+                    .iter()
+                    .min_by_key(|(_, cached)| cached.fetched_at)
+                    .map(|(did, _)| did.clone());
+                if let Some(did) = oldest {
+                    viewers.remove(&did);
+                }
+            }
+        }
+        viewers.insert(
+            viewer,
+            CachedFollows {
+                followed,
+                fetched_at: now,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::did;
+
+    const ALICE: &str = "did:plc:alice0000000000000000000";
+    const BOB: &str = "did:plc:bob00000000000000000000000";
+
+    #[test]
+    fn follows_are_kept_until_stale() {
+        let cache = FollowCache::new(Duration::from_secs(60));
+        assert_eq!(cache.get(&did(ALICE)), None);
+
+        cache.insert(did(ALICE), vec![did(BOB)]);
+        assert_eq!(cache.get(&did(ALICE)), Some(vec![did(BOB)]));
+
+        let stale = FollowCache::new(Duration::ZERO);
+        stale.insert(did(ALICE), vec![did(BOB)]);
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(stale.get(&did(ALICE)), None);
+    }
+}
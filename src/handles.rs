@@ -0,0 +1,263 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use atrium_api::types::string::{Datetime, Did, Handle};
+use atrium_common::resolver::Resolver;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::{
+    error::Error,
+    oauth::{DidResolver, HandleDidResolver},
+    store::{CachedHandle, HandleCache},
+    upstream::{CircuitBreaker, IDENTITY_CIRCUIT},
+};
+
+// capacity of the resolution queue; when it's full, lookups just skip enqueueing and try again on
+// the next view
+const QUEUE_CAPACITY: usize = 1024;
+
+/// Looks up handles from the cache, resolving cache misses in the background so that callers
+/// never block on DID resolution.
+#[derive(Clone)]
+pub struct HandleResolver {
+    cache: HandleCache,
+    handle_did_resolver: Arc<HandleDidResolver>,
+    queue: mpsc::Sender<Did>,
+    // DIDs currently queued or being resolved, so we don't queue the same DID repeatedly
+    pending: Arc<Mutex<HashSet<Did>>>,
+    ttl: Duration,
+}
+
+async fn resolve_handle(resolver: &DidResolver, did: &Did) -> Result<Option<String>, Error> {
+    let akas = resolver.resolve(did).await?.also_known_as;
+    Ok(akas
+        .and_then(|akas| akas.into_iter().next())
+        .map(|aka| aka.replace("at://", "")))
+}
+
+/// How a DID is displayed: `@handle` if known, otherwise the DID itself.
+pub fn display_handle(did: &Did, handle: Option<&str>) -> String {
+    match handle {
+        Some(handle) => format!("@{handle}"),
+        None => did.as_str().to_owned(),
+    }
+}
+
+impl HandleResolver {
+    /// Creates the resolver and spawns the background resolution worker. Cached handles older
+    /// than `ttl` are still used, but are re-resolved in the background.
+    pub fn spawn(
+        cache: HandleCache,
+        did_resolver: DidResolver,
+        handle_did_resolver: HandleDidResolver,
+        circuit_breaker: CircuitBreaker,
+        ttl: Duration,
+    ) -> Self {
+        let (queue, mut rx) = mpsc::channel::<Did>(QUEUE_CAPACITY);
+        let pending = Arc::new(Mutex::new(HashSet::new()));
+
+        let worker_cache = cache.clone();
+        let worker_pending = Arc::clone(&pending);
+        tokio::spawn(async move {
+            while let Some(did) = rx.recv().await {
+                // while the PLC directory is down, DIDs are shown as-is and retried on a later view
+                let resolved = circuit_breaker
+                    .call(IDENTITY_CIRCUIT, resolve_handle(&did_resolver, &did))
+                    .await;
+                match resolved {
+                    Ok(handle) => {
+                        debug!("Resolved {} to {handle:?}", did.as_str());
+                        if let Err(e) = worker_cache.set(&did, handle.as_deref()).await {
+                            warn!("failed to cache handle for {}: {e}", did.as_str());
+                        }
+                    }
+                    Err(e) => warn!("failed to resolve handle for {}: {e}", did.as_str()),
+                }
+                worker_pending.lock().expect("poisoned lock").remove(&did);
+            }
+        });
+
+        Self {
+            cache,
+            handle_did_resolver: Arc::new(handle_did_resolver),
+            queue,
+            pending,
+            ttl,
+        }
+    }
+
+    fn enqueue(&self, did: &Did) {
+        let mut pending = self.pending.lock().expect("poisoned lock");
+        if pending.contains(did) {
+            return;
+        }
+        if self.queue.try_send(did.clone()).is_ok() {
+            pending.insert(did.clone());
+        }
+    }
+
+    fn is_stale(&self, cached: &CachedHandle) -> bool {
+        let age = Datetime::now()
+            .as_ref()
+            .signed_duration_since(cached.resolved_at.as_ref());
+        age.to_std().map(|age| age > self.ttl).unwrap_or(false)
+    }
+
+    /// Handle of a DID if it's cached, queueing a background resolution if it isn't (or is
+    /// stale).
+    pub async fn handle(&self, did: &Did) -> Result<Option<String>, Error> {
+        match self.cache.get(did).await? {
+            Some(cached) => {
+                if self.is_stale(&cached) {
+                    self.enqueue(did);
+                }
+                Ok(cached.handle)
+            }
+            None => {
+                self.enqueue(did);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Display string for a DID (see [`display_handle`]), queueing a background resolution if
+    /// the handle isn't cached (or is stale).
+    pub async fn lookup(&self, did: &Did) -> Result<String, Error> {
+        Ok(display_handle(did, self.handle(did).await?.as_deref()))
+    }
+
+    /// Display strings for `dids`, in order, as [`lookup`](Self::lookup) would give them but
+    /// with the cache read in one query. Uncached (or stale) handles are queued for resolution.
+    pub async fn lookup_many(&self, dids: &[Did]) -> Result<Vec<String>, Error> {
+        // a feed repeats its authors, and each only needs looking up once
+        let unique: Vec<Did> = dids
+            .iter()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .cloned()
+            .collect();
+        let cached = self.cache.get_many(&unique).await?;
+        for did in &unique {
+            match cached.get(did) {
+                Some(cached) if !self.is_stale(cached) => {}
+                _ => self.enqueue(did),
+            }
+        }
+        Ok(dids
+            .iter()
+            .map(|did| {
+                let handle = cached.get(did).and_then(|cached| cached.handle.as_deref());
+                display_handle(did, handle)
+            })
+            .collect())
+    }
+
+    /// DID for a handle given in a URL (with or without a leading `@`; DIDs are passed through).
+    /// Uses the cache while fresh, and resolves the handle otherwise.
+    pub async fn resolve_did(&self, handle: &str) -> Result<Did, Error> {
+        let handle = handle.trim_start_matches('@');
+        if let Ok(did) = Did::new(handle.to_owned()) {
+            return Ok(did);
+        }
+        let handle = Handle::new(handle.to_lowercase())
+            .map_err(|_| Error::UnknownHandle(handle.to_owned()))?;
+
+        if let Some((did, cached)) = self.cache.get_did(handle.as_str()).await? {
+            if !self.is_stale(&cached) {
+                return Ok(did);
+            }
+        }
+        // not cached: the cache maps DIDs to the handle their DID document claims, and a
+        // handle pointing at a DID doesn't prove the reverse
+        match self.handle_did_resolver.resolve(&handle).await {
+            Ok(did) => Ok(did),
+            Err(atrium_identity::Error::NotFound) => {
+                Err(Error::UnknownHandle(handle.as_str().to_owned()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{method, path},
+    };
+
+    use super::*;
+    use crate::{
+        oauth,
+        store::Db,
+        test_support::{did, did_document, memory_pool, mock_did_document},
+    };
+
+    const ALICE: &str = "did:plc:alice0000000000000000000";
+    const BOB: &str = "did:plc:bob00000000000000000000000";
+    const CAROL: &str = "did:plc:carol0000000000000000000";
+
+    fn resolver(plc: &MockServer) -> DidResolver {
+        oauth::did_resolver(Arc::new(oauth::http_client()), &plc.uri())
+    }
+
+    #[tokio::test]
+    async fn handle_comes_from_also_known_as() {
+        let plc = MockServer::start().await;
+        mock_did_document(
+            &plc,
+            ALICE,
+            did_document(ALICE, Some("alice.test"), "https://pds.test"),
+        )
+        .await;
+
+        let handle = resolve_handle(&resolver(&plc), &did(ALICE)).await;
+        assert_eq!(handle.expect("DID resolves"), Some("alice.test".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn missing_also_known_as_has_no_handle() {
+        let plc = MockServer::start().await;
+        mock_did_document(&plc, ALICE, did_document(ALICE, None, "https://pds.test")).await;
+
+        let handle = resolve_handle(&resolver(&plc), &did(ALICE)).await;
+        assert_eq!(handle.expect("DID resolves"), None);
+    }
+
+    #[tokio::test]
+    async fn unknown_did_fails() {
+        let plc = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/{ALICE}")))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&plc)
+            .await;
+
+        assert!(resolve_handle(&resolver(&plc), &did(ALICE)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn get_many_returns_cached_only() {
+        let db = Db::from(memory_pool().await);
+        crate::store::migrate(&db).await.expect("database migrates");
+        let cache = HandleCache::new(db);
+        cache
+            .set(&did(ALICE), Some("alice.test"))
+            .await
+            .expect("handle is cached");
+        cache.set(&did(BOB), None).await.expect("handle is cached");
+
+        let cached = cache
+            .get_many(&[did(ALICE), did(BOB), did(CAROL)])
+            .await
+            .expect("handles are fetched");
+        assert_eq!(cached.len(), 2);
+        assert_eq!(cached[&did(ALICE)].handle.as_deref(), Some("alice.test"));
+        assert_eq!(cached[&did(BOB)].handle, None);
+        assert!(!cached.contains_key(&did(CAROL)));
+    }
+}
@@ -0,0 +1,45 @@
+//! Health report for load balancers and monitoring, served at `/healthz`.
+
+use std::sync::Arc;
+
+use axum::{Json, extract::State};
+use serde::Serialize;
+
+use crate::{AppState, ingester_status::IngesterReport};
+
+/// The web server is up if it can answer at all; the ingester's state is reported alongside, but
+/// a struggling ingester doesn't make the web server unhealthy.
+#[derive(Debug, Serialize)]
+pub struct Health {
+    status: &'static str,
+    ingester: IngesterReport,
+}
+
+pub async fn healthz(State(state): State<Arc<AppState>>) -> Json<Health> {
+    Json(Health {
+        status: "ok",
+        ingester: state.ingester_status.report(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use crate::test_support::TestApp;
+
+    #[tokio::test]
+    async fn reports_ingester_not_run_by_the_app() {
+        let app = TestApp::new().await;
+
+        let response = app.get("/healthz", None).await;
+
+        assert_eq!(response.status, StatusCode::OK);
+        let health: serde_json::Value = serde_json::from_str(&response.body).expect("body is JSON");
+        assert_eq!(health["status"], "ok");
+        assert_eq!(health["ingester"]["state"], "disabled");
+        assert_eq!(health["ingester"]["restarts"], 0);
+        assert_eq!(health["ingester"]["connection"], "disconnected");
+        assert!(health["ingester"]["last_event_at"].is_null());
+    }
+}
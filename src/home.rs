@@ -1,88 +1,69 @@
-use std::sync::Arc;
+use std::{
+    fmt::Debug,
+    sync::{Arc, LazyLock},
+};
 
 use atrium_api::{
-    com::atproto::repo,
+    app::bsky::graph::get_relationships,
     types::{
-        TryFromUnknown,
-        string::{Datetime, Did, Nsid, RecordKey},
+        Union,
+        string::{AtIdentifier, Did},
     },
 };
-use atrium_common::resolver::Resolver;
 use axum::{
     extract::{Query, State},
+    http::{
+        HeaderMap, HeaderName, HeaderValue, StatusCode,
+        header::{CACHE_CONTROL, ETAG, IF_NONE_MATCH, VARY},
+    },
     response::{Html, IntoResponse, Response},
 };
-use chrono::Local;
+use chrono::Utc;
+use chrono_tz::Tz;
 use minijinja::context;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tower_sessions::Session;
+use tracing::warn;
 
 use crate::{
     AppState,
+    auth::OptionalAuth,
+    avatar::avatar_url,
+    cursor::FeedCursor,
     error::Error,
-    oauth::{DidResolver, agent_did, session_agent},
-    open_template,
+    htmx::HxRequest,
+    i18n::Locale,
+    oauth::{ATProtoAgent, agent_did},
+    open_template, preferences,
+    store::{StatusFilter, Visibility},
+    throttle,
+    upstream::{pds_circuit, with_timeout},
+    viewer,
+    views::{DisplayDates, display_dates, image_url, permalink},
 };
 
-const STATUS_OPTIONS: [&'static str; 28] = [
-    "👍",
-    "👎",
-    "💙",
-    "🥹",
-    "😧",
-    "😤",
-    "🙃",
-    "😉",
-    "😎",
-    "🤓",
-    "🤨",
-    "🥳",
-    "😭",
-    "😤",
-    "🤯",
-    "🫡",
-    "💀",
-    "✊",
-    "🤘",
-    "👀",
-    "🧠",
-    "👩‍💻",
-    "🧑‍💻",
-    "🥷",
-    "🧌",
-    "🦋",
-    "🚀",
-    "🦀",
-];
-
-//TODO: memoize calls to this so we don't have to use resolver each time. either in-memory hashmap
-// or another sqlite store would be helpful
-async fn resolve_into_handle(resolver: &DidResolver, author_did: &Did) -> Result<String, Error> {
-    let akas = resolver.resolve(author_did).await?.also_known_as;
-    Ok(match akas {
-        None => author_did.as_str().to_owned(),
-        Some(akas) if akas.is_empty() => author_did.as_str().to_owned(),
-        Some(akas) => format!("@{}", akas[0].replace("at://", "")),
-    })
-}
-
-fn choose_date<'a>(created_at: &'a Datetime, indexed_at: &'a Datetime) -> &'a Datetime {
-    if created_at < indexed_at {
-        created_at
-    } else {
-        indexed_at
-    }
-}
-
-fn display_date(dt: &Datetime) -> String {
-    chrono::DateTime::<Local>::from(dt.as_ref().clone())
-        .date_naive()
-        .to_string()
-}
-
 #[derive(Debug, Deserialize)]
 pub struct HomeQuery {
     error: Option<HomeError>,
+    /// URI of a status whose content warning the viewer chose to look past (the no-JS fallback
+    /// for `/reveal`).
+    reveal: Option<String>,
+    #[serde(default)]
+    feed: FeedMode,
+    /// Opaque cursor from the previous page's "older statuses" link.
+    cursor: Option<String>,
+}
+
+/// Which statuses the home feed shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedMode {
+    /// Every recent status.
+    #[default]
+    All,
+    /// Only each user's most recent status, so one active user can't fill the feed.
+    Current,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -91,89 +72,455 @@ pub enum HomeError {
     LoggedOut,
 }
 
-pub async fn home(
-    State(state): State<Arc<AppState>>,
-    Query(home_query): Query<HomeQuery>,
-    session: Session,
-) -> Result<Response, Error> {
-    let maybe_agent = session_agent(state.as_ref(), &session).await?;
+// statuses per page of the home feed
+const FEED_PAGE_SIZE: usize = 10;
 
-    // fetch statuses from any user from DB
-    let mut statuses = state.status_store.fetch_n(None, 10).await?;
-    let user_status = match &maybe_agent {
-        Some(agent) => state
-            .status_store
-            .fetch_one(Some(agent_did(agent).await))
-            .await?
-            .map(|s| s.status),
-        None => None,
-    };
+// identifies this run of the server in feed ETags, so pages rendered by a previous run (maybe with
+// other templates or config) are never reused
+static RUN_ID: LazyLock<u64> = LazyLock::new(rand::random);
+
+// getRelationships accepts at most this many other actors per call
+const RELATIONSHIPS_BATCH_SIZE: usize = 30;
 
-    // fetch profile
-    #[derive(Debug, Deserialize, Serialize)]
-    #[serde(rename_all(deserialize = "camelCase"))]
-    struct Profile {
-        display_name: String,
+// authors of followers-only statuses that `viewer` follows, fetched from their PDS unless
+// recently cached
+async fn followed_private_authors(
+    state: &AppState,
+    agent: &ATProtoAgent,
+    viewer: &Did,
+) -> Result<Vec<Did>, Error> {
+    if let Some(followed) = state.follow_cache.get(viewer) {
+        return Ok(followed);
     }
-    let profile = match &maybe_agent {
-        Some(agent) => {
-            let object_data = agent
+    let candidates = state
+        .status_store
+        .followers_only_authors()
+        .await?
+        .into_iter()
+        .filter(|did| did != viewer)
+        .collect::<Vec<_>>();
+
+    let mut followed = vec![];
+    for batch in candidates.chunks(RELATIONSHIPS_BATCH_SIZE) {
+        let parameters = get_relationships::ParametersData {
+            actor: AtIdentifier::Did(viewer.clone()),
+            others: Some(batch.iter().cloned().map(AtIdentifier::Did).collect()),
+        };
+        let output = with_timeout(
+            state.config.upstream_timeout,
+            "relationships fetch",
+            agent
                 .api
-                .com
-                .atproto
-                .repo
-                .get_record(
-                    repo::get_record::ParametersData {
-                        cid: None,
-                        collection: Nsid::new("app.bsky.actor.profile".to_owned())
-                            .expect("unexpected Nsid failure"),
-                        repo: atrium_api::types::string::AtIdentifier::Did(agent_did(agent).await),
-                        rkey: RecordKey::new("self".to_owned())
-                            .expect("unexpected record key failure"),
-                    }
-                    .into(),
-                )
-                .await?
+                .app
+                .bsky
+                .graph
+                .get_relationships(parameters.into()),
+        )
+        .await??;
+        followed.extend(
+            output
                 .data
-                .value;
-            Some(Profile::try_from_unknown(object_data).map_err(Error::ProfileParse)?)
+                .relationships
+                .into_iter()
+                .filter_map(|relationship| match relationship {
+                    Union::Refs(
+                        get_relationships::OutputRelationshipsItem::AppBskyGraphDefsRelationship(
+                            relationship,
+                        ),
+                    ) if relationship.following.is_some() => Some(relationship.did.clone()),
+                    _ => None,
+                }),
+        );
+    }
+    state.follow_cache.insert(viewer.clone(), followed.clone());
+    Ok(followed)
+}
+
+// statuses `viewer` may see: public ones, plus followers-only statuses of the viewer and the
+// authors they follow
+pub(crate) async fn visibility_filter(
+    state: &AppState,
+    maybe_agent: Option<&ATProtoAgent>,
+    viewer: Option<&Did>,
+) -> Result<StatusFilter, Error> {
+    Ok(match (maybe_agent, viewer) {
+        (Some(agent), Some(did)) => {
+            let followed = state
+                .circuit_breaker
+                .call(
+                    &pds_circuit(did),
+                    followed_private_authors(state, agent, did),
+                )
+                .await;
+            let followed = match followed {
+                Ok(followed) => followed,
+                // without the follow graph, only the viewer's own followers-only statuses show
+                Err(e) if e.is_upstream_outage() => {
+                    warn!(
+                        "can't fetch who {} follows, showing public statuses: {e}",
+                        did.as_str()
+                    );
+                    vec![]
+                }
+                Err(e) => return Err(e),
+            };
+            StatusFilter::new().visible_to(did, followed)
         }
+        _ => StatusFilter::new(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevealQuery {
+    uri: String,
+}
+
+/// Renders just the status behind a content warning, for the feed's reveal control to swap in
+/// without a full page load.
+pub async fn reveal(
+    State(state): State<Arc<AppState>>,
+    Query(RevealQuery { uri }): Query<RevealQuery>,
+    OptionalAuth(maybe_agent): OptionalAuth,
+) -> Result<Response, Error> {
+    let user_did = match &maybe_agent {
+        Some(agent) => Some(agent_did(agent).await),
         None => None,
     };
 
-    // map DIDs into handles
-    let mut handles = vec![];
-    for status in &statuses {
-        handles.push(resolve_into_handle(&state.did_resolver, &status.author_did).await?);
-    }
+    let filter =
+        visibility_filter(state.as_ref(), maybe_agent.as_deref(), user_did.as_ref()).await?;
+    let Some(status) = state
+        .status_store
+        .fetch_by_uri(&uri)
+        .await?
+        .filter(|status| filter.is_visible(status))
+    else {
+        return Err(Error::StatusNotFound(uri));
+    };
+
+    let template = open_template!(state, "reveal");
+    let rendered = template.render(context! {
+        status => status.status,
+    })?;
+
+    Ok(Html(rendered).into_response())
+}
+
+#[derive(Serialize)]
+struct ReactionView {
+    emoji: String,
+    count: i64,
+}
+
+#[derive(Serialize)]
+struct StatusView {
+    uri: String,
+    permalink: Option<String>,
+    mine: bool,
+    // whether the viewer can report the status: signed in, and not its author
+    reportable: bool,
+    status: String,
+    handle: String,
+    avatar: String,
+    followers_only: bool,
+    // one entry per reaction option; followers-only statuses can't be reacted to
+    reactions: Vec<ReactionView>,
+    content_warning: Option<String>,
+    // URL of the status's image, if it has one
+    image: Option<String>,
+    // whether to show the status despite its content warning
+    revealed: bool,
+    #[serde(flatten)]
+    dates: DisplayDates,
+}
 
-    #[derive(Serialize)]
-    struct StatusView {
-        status: String,
-        handle: String,
-        date: String,
+// a page of the home feed, as the `feed` template expects it, and the cursor of the next page
+async fn feed_views(
+    state: &AppState,
+    mut feed_filter: StatusFilter,
+    user_did: Option<&Did>,
+    feed: FeedMode,
+    after: Option<&FeedCursor>,
+    reveal: Option<&str>,
+    timezone: Option<Tz>,
+) -> Result<(Vec<StatusView>, Option<String>), Error> {
+    if feed == FeedMode::Current {
+        feed_filter = feed_filter.latest_per_author();
     }
 
+    // fetch statuses from any user from DB, plus one to find out whether there's another page
+    let mut statuses = state
+        .status_store
+        .fetch_page(&feed_filter, after, FEED_PAGE_SIZE + 1)
+        .await?;
+    let next_cursor = if statuses.len() > FEED_PAGE_SIZE {
+        statuses.truncate(FEED_PAGE_SIZE);
+        statuses
+            .last()
+            .map(|status| state.config.cursor_codec.encode(&status.cursor()))
+    } else {
+        None
+    };
+
+    // map DIDs into handles (unknown handles are resolved in the background, so these may be
+    // DIDs until the next view)
+    let mut handles = state
+        .handle_resolver
+        .lookup_many(
+            &statuses
+                .iter()
+                .map(|status| status.author_did.clone())
+                .collect::<Vec<_>>(),
+        )
+        .await?;
+
+    let reaction_counts = state
+        .status_store
+        .reaction_counts(
+            &statuses
+                .iter()
+                .map(|status| status.uri.clone())
+                .collect::<Vec<_>>(),
+        )
+        .await?;
+
     let status_views = statuses
         .drain(..)
         .zip(handles.drain(..))
         .map(|(status, handle)| StatusView {
+            mine: user_did == Some(&status.author_did),
+            reportable: user_did.is_some_and(|did| did != &status.author_did),
+            permalink: permalink(&status.uri),
+            avatar: avatar_url(&status.author_did),
+            followers_only: status.visibility == Visibility::Followers,
+            reactions: match status.visibility {
+                Visibility::Public => {
+                    let counts = reaction_counts.get(&status.uri);
+                    state
+                        .config
+                        .reaction_options
+                        .iter()
+                        .map(|emoji| ReactionView {
+                            emoji: emoji.clone(),
+                            count: counts
+                                .and_then(|counts| counts.iter().find(|c| &c.emoji == emoji))
+                                .map_or(0, |c| c.count),
+                        })
+                        .collect()
+                }
+                Visibility::Followers => vec![],
+            },
+            revealed: reveal == Some(status.uri.as_str()),
+            image: image_url(&status.uri, status.image.as_ref()),
+            uri: status.uri,
+            content_warning: status.content_warning,
             status: status.status,
             handle,
-            date: display_date(choose_date(&status.created_at, &status.indexed_at)),
+            dates: display_dates(
+                state.config.date_policy,
+                state.config.backdate_threshold,
+                &status.created_at,
+                &status.indexed_at,
+                timezone,
+            ),
         })
-        .collect::<Vec<_>>();
+        .collect();
+    Ok((status_views, next_cursor))
+}
+
+// weak validator for a render of the home feed, from the store's feed version and `inputs`,
+// everything else the render depends on. Relative times ("3 minutes ago") are rendered here, so
+// renders also go stale every minute.
+async fn feed_etag(
+    state: &AppState,
+    filter: &StatusFilter,
+    inputs: impl Debug,
+) -> Result<HeaderValue, Error> {
+    let version = state.status_store.feed_version(filter).await?;
+    let minute = Utc::now().timestamp() / 60;
+    let digest = Sha256::digest(format!("{}|{minute}|{version:?}|{inputs:?}", *RUN_ID));
+    let hex = digest[..16]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    Ok(HeaderValue::from_str(&format!("W/\"{hex}\"")).expect("hex digest is a valid ETag"))
+}
+
+// whether the client's `If-None-Match` lists `etag`, compared weakly as RFC 9110 requires
+fn if_none_match(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let etag = etag.to_str().unwrap_or_default().trim_start_matches("W/");
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+// the feed differs per viewer, so only their browser may cache it, and must check it's current
+fn feed_cache_headers(etag: HeaderValue) -> [(HeaderName, HeaderValue); 3] {
+    [
+        (ETAG, etag),
+        (CACHE_CONTROL, HeaderValue::from_static("private, no-cache")),
+        (
+            VARY,
+            HeaderValue::from_static("Cookie, Accept-Language, HX-Request"),
+        ),
+    ]
+}
+
+// a page of the home feed rendered on its own, for htmx to swap in
+async fn feed_fragment(
+    state: &AppState,
+    filter: StatusFilter,
+    user_did: Option<&Did>,
+    locale: Locale,
+    feed: FeedMode,
+    after: Option<&FeedCursor>,
+    timezone: Option<Tz>,
+) -> Result<String, Error> {
+    let (status_views, next_cursor) =
+        feed_views(state, filter, user_did, feed, after, None, timezone).await?;
+
+    let template = open_template!(state, "feed");
+    Ok(template.render(context! {
+        locale => locale,
+        statuses => status_views,
+        feed => feed,
+        next_cursor => next_cursor,
+        appended => after.is_some(),
+    })?)
+}
+
+/// Renders just a page of the home feed, for htmx to swap into the page (or append, for pages
+/// after `after`).
+pub(crate) async fn render_feed(
+    state: &AppState,
+    agent: Option<&ATProtoAgent>,
+    session: &Session,
+    locale: Locale,
+    feed: FeedMode,
+    after: Option<&FeedCursor>,
+) -> Result<Response, Error> {
+    let user_did = match agent {
+        Some(agent) => Some(agent_did(agent).await),
+        None => None,
+    };
+    let filter = visibility_filter(state, agent, user_did.as_ref()).await?;
+    let rendered = feed_fragment(
+        state,
+        filter,
+        user_did.as_ref(),
+        locale,
+        feed,
+        after,
+        preferences::timezone(session).await?,
+    )
+    .await?;
+
+    Ok(Html(rendered).into_response())
+}
+
+pub async fn home(
+    State(state): State<Arc<AppState>>,
+    Query(home_query): Query<HomeQuery>,
+    locale: Locale,
+    hx_request: HxRequest,
+    headers: HeaderMap,
+    OptionalAuth(maybe_agent): OptionalAuth,
+    session: Session,
+) -> Result<Response, Error> {
+    let after = home_query
+        .cursor
+        .as_deref()
+        .map(|cursor| state.config.cursor_codec.decode(cursor))
+        .transpose()?;
+
+    let timezone = preferences::timezone(&session).await?;
+    let theme = preferences::theme(&session).await?;
+
+    let user_did = match &maybe_agent {
+        Some(agent) => Some(agent_did(agent).await),
+        None => None,
+    };
+    // followers-only statuses are visible to their author and the author's followers
+    let filter =
+        visibility_filter(state.as_ref(), maybe_agent.as_deref(), user_did.as_ref()).await?;
+    let viewer = viewer::current(state.as_ref(), &session, maybe_agent.as_deref()).await?;
+
+    // polling clients mostly find nothing's changed, so skip rendering
+    let etag = feed_etag(
+        state.as_ref(),
+        &filter,
+        (
+            user_did.as_ref().map(Did::as_str),
+            &viewer,
+            locale,
+            theme,
+            timezone,
+            hx_request,
+            &home_query,
+        ),
+    )
+    .await?;
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, feed_cache_headers(etag)).into_response());
+    }
+
+    // htmx refreshes and "older statuses" requests only swap in the feed
+    if hx_request.0 {
+        let rendered = feed_fragment(
+            state.as_ref(),
+            filter,
+            user_did.as_ref(),
+            locale,
+            home_query.feed,
+            after.as_ref(),
+            timezone,
+        )
+        .await?;
+        return Ok((feed_cache_headers(etag), Html(rendered)).into_response());
+    }
+
+    let user_status = match &user_did {
+        Some(did) => state
+            .status_store
+            .fetch_one(&StatusFilter::new().author(did.clone()).visible_to(did, []))
+            .await?
+            .map(|s| s.status),
+        None => None,
+    };
+    let totals = state.status_store.totals().await?;
+
+    let (status_views, next_cursor) = feed_views(
+        state.as_ref(),
+        filter,
+        user_did.as_ref(),
+        home_query.feed,
+        after.as_ref(),
+        home_query.reveal.as_deref(),
+        timezone,
+    )
+    .await?;
 
     let template = open_template!(state, "home");
 
     let rendered = template.render(context! {
+        locale => locale,
+        theme => theme,
         statuses => status_views,
-        profile => profile,
+        viewer => viewer,
         error => home_query.error,
+        feed => home_query.feed,
+        next_cursor => next_cursor,
+        appended => false,
         user_status => user_status,
-        status_options => STATUS_OPTIONS,
-        today => display_date(&Datetime::now())
+        total_statuses => totals.statuses,
+        total_authors => totals.authors,
+        status_options => state.config.status_options,
+        post_token => throttle::post_token(),
     })?;
 
-    Ok(Html(rendered).into_response())
+    Ok((feed_cache_headers(etag), Html(rendered)).into_response())
 }
@@ -0,0 +1,40 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderValue, request::Parts},
+    response::{IntoResponse, Redirect, Response},
+};
+
+/// Whether the request was made by htmx (it sets `HX-Request: true`), in which case handlers
+/// respond with just the fragment being swapped in rather than a full page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HxRequest(pub bool);
+
+impl<S: Send + Sync> FromRequestParts<S> for HxRequest {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(
+            parts
+                .headers
+                .get("HX-Request")
+                .is_some_and(|value| value == "true"),
+        ))
+    }
+}
+
+impl HxRequest {
+    /// Redirects to `to`. htmx follows plain redirects itself and would swap the target page into
+    /// the fragment, so it's asked to navigate the whole page instead.
+    pub fn redirect(self, to: &str) -> Response {
+        if self.0 {
+            match HeaderValue::from_str(to) {
+                Ok(location) => [("HX-Redirect", location)].into_response(),
+                Err(_) => Redirect::to(to).into_response(),
+            }
+        } else {
+            Redirect::to(to).into_response()
+        }
+    }
+}
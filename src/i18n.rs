@@ -0,0 +1,195 @@
+use std::{collections::HashMap, convert::Infallible, sync::LazyLock};
+
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderMap, header::ACCEPT_LANGUAGE, request::Parts},
+};
+use minijinja::{State, Value, value::Kwargs};
+use serde::Serialize;
+
+/// Locale templates are written in, used when nothing better can be negotiated.
+pub const DEFAULT_LOCALE: &str = "en";
+
+// gettext catalogs shipped with the binary, keyed by language subtag
+const CATALOG_SOURCES: &[(&str, &str)] = &[
+    ("es", include_str!("../locales/es.po")),
+    ("fr", include_str!("../locales/fr.po")),
+];
+
+static CATALOGS: LazyLock<HashMap<&'static str, HashMap<String, String>>> = LazyLock::new(|| {
+    CATALOG_SOURCES
+        .iter()
+        .map(|(locale, source)| {
+            let catalog = parse_po(source)
+                // panic, the catalogs are compiled in so this can only be a packaging mistake
+                .unwrap_or_else(|e| panic!("invalid {locale} catalog: {e}"));
+            (*locale, catalog)
+        })
+        .collect()
+});
+
+/// Translation of `msgid` into `locale`, falling back to `msgid` itself (the English text) for
+/// untranslated messages and unsupported locales.
+pub fn gettext<'a>(locale: &str, msgid: &'a str) -> &'a str {
+    CATALOGS
+        .get(locale)
+        .and_then(|catalog| catalog.get(msgid))
+        .map_or(msgid, String::as_str)
+}
+
+/// The locale a template is being rendered in, from its `locale` context variable.
+pub fn template_locale(state: &State) -> String {
+    state
+        .lookup("locale")
+        .and_then(|locale| locale.as_str().map(str::to_owned))
+        .unwrap_or_else(|| DEFAULT_LOCALE.to_owned())
+}
+
+/// Minijinja function translating a message into the template's locale, e.g.
+/// `{{ t("Hi, {name}.", name=viewer.name) }}`. Keyword arguments replace the matching
+/// `{placeholders}` after translation.
+pub fn translate(state: &State, msgid: String, kwargs: Kwargs) -> Result<String, minijinja::Error> {
+    let mut translated = gettext(&template_locale(state), &msgid).to_owned();
+    for name in kwargs.args() {
+        let value = kwargs.get::<Value>(name)?;
+        translated = translated.replace(&format!("{{{name}}}"), &value.to_string());
+    }
+    Ok(translated)
+}
+
+/// The request's preferred locale among those with a catalog, negotiated from
+/// `Accept-Language`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct Locale(pub &'static str);
+
+impl Locale {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .map_or(Self(DEFAULT_LOCALE), negotiate)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Locale {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_headers(&parts.headers))
+    }
+}
+
+// picks the highest-weighted language range we have a catalog for, matching on the primary
+// subtag so e.g. `fr-CA` gets the `fr` catalog
+fn negotiate(accept_language: &str) -> Locale {
+    let mut ranges = accept_language
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim().to_ascii_lowercase();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+        })
+        .collect::<Vec<_>>();
+    // stable, so equally-weighted ranges keep the client's order
+    ranges.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+    for (tag, _) in ranges {
+        let language = tag.split('-').next().unwrap_or_default();
+        if language == DEFAULT_LOCALE || language == "*" {
+            break;
+        }
+        if let Some((locale, _)) = CATALOG_SOURCES
+            .iter()
+            .find(|(locale, _)| *locale == language)
+        {
+            return Locale(locale);
+        }
+    }
+    Locale(DEFAULT_LOCALE)
+}
+
+// parses the subset of the PO format we use: `msgid`/`msgstr` pairs of (possibly continued)
+// quoted strings, with comments. Untranslated entries and the header are left out.
+fn parse_po(source: &str) -> Result<HashMap<String, String>, String> {
+    #[derive(PartialEq)]
+    enum Field {
+        None,
+        Id,
+        Str,
+    }
+
+    let mut catalog = HashMap::new();
+    let mut msgid = String::new();
+    let mut msgstr = String::new();
+    let mut field = Field::None;
+    let mut finish_entry = |msgid: &mut String, msgstr: &mut String| {
+        if !msgid.is_empty() && !msgstr.is_empty() {
+            catalog.insert(std::mem::take(msgid), std::mem::take(msgstr));
+        }
+        msgid.clear();
+        msgstr.clear();
+    };
+
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        let error = |e: &str| format!("line {}: {e}", i + 1);
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("msgid ") {
+            if field == Field::Id {
+                return Err(error("msgid without msgstr"));
+            }
+            finish_entry(&mut msgid, &mut msgstr);
+            msgid = unquote(rest).map_err(|e| error(&e))?;
+            field = Field::Id;
+        } else if let Some(rest) = line.strip_prefix("msgstr ") {
+            if field != Field::Id {
+                return Err(error("msgstr without msgid"));
+            }
+            msgstr = unquote(rest).map_err(|e| error(&e))?;
+            field = Field::Str;
+        } else if line.starts_with('"') {
+            let continued = unquote(line).map_err(|e| error(&e))?;
+            match field {
+                Field::Id => msgid.push_str(&continued),
+                Field::Str => msgstr.push_str(&continued),
+                Field::None => return Err(error("string outside of an entry")),
+            }
+        } else {
+            return Err(error("unsupported syntax"));
+        }
+    }
+    if field == Field::Id {
+        return Err("trailing msgid without msgstr".to_owned());
+    }
+    finish_entry(&mut msgid, &mut msgstr);
+    Ok(catalog)
+}
+
+fn unquote(quoted: &str) -> Result<String, String> {
+    let inner = quoted
+        .trim()
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a quoted string, got {quoted}"))?;
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unquoted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unquoted.push('\n'),
+            Some('t') => unquoted.push('\t'),
+            Some(c @ ('"' | '\\')) => unquoted.push(c),
+            other => return Err(format!("unsupported escape \\{}", other.unwrap_or(' '))),
+        }
+    }
+    Ok(unquoted)
+}
@@ -1,22 +1,49 @@
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    num::NonZeroUsize,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use atproto_jetstream::{
-    connection::{Connection, Cursor, Options, bluesky_instances::US_EAST_1},
+    connection::{Connection, Cursor, Options},
     consumer::{Consumer, FlattenedCommitEvent, ProcessEffect, process_message},
     multi_consumer,
 };
 use atrium_api::types::{
-    Collection,
+    Collection, Unknown,
     string::{Datetime, Did},
 };
-use tracing::error;
+use chrono::DateTime;
+use lru::LruCache;
+use serde::Deserialize;
+use tokio::sync::{mpsc, watch};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, error, info, warn};
 
 use crate::{
-    lexicons::xyz::statusphere::{Status, status::RecordData},
-    store::{Error as StoreError, Status as StoreStatus, StatusStore},
+    config::{AppConfig, DidFilter, is_allowed_status},
+    dead_letter::{self, is_status_uri},
+    firehose,
+    ingester_status::{ConnectionState, IngesterStatus},
+    lexicons::xyz::statusphere::{
+        Pin, Reaction, Status, pin::RecordData as PinRecordData,
+        reaction::RecordData as ReactionRecordData, status::RecordData as StatusRecordData,
+    },
+    metrics::Metrics,
+    record_consumer::{CommitOperation, ConsumerRegistry, RecordCommit},
+    store::{
+        DeadLetterStore, Error as StoreError, IngestControlStore, Reaction as StoreReaction,
+        Status as StoreStatus, StatusImage, StatusStore, Visibility, sanitize_content_warning,
+    },
+    supervisor,
+    toggles::CollectionToggles,
 };
 
-impl TryFrom<FlattenedCommitEvent<RecordData>> for StoreStatus {
+impl TryFrom<FlattenedCommitEvent<StatusRecordData>> for StoreStatus {
     type Error = StoreError;
 
     fn try_from(
@@ -24,9 +51,16 @@ impl TryFrom<FlattenedCommitEvent<RecordData>> for StoreStatus {
             did,
             collection,
             rkey,
-            record: RecordData { status, created_at },
+            cid,
+            record:
+                StatusRecordData {
+                    status,
+                    created_at,
+                    content_warning,
+                    image,
+                },
             ..
-        }: FlattenedCommitEvent<RecordData>,
+        }: FlattenedCommitEvent<StatusRecordData>,
     ) -> Result<Self, Self::Error> {
         Ok(Self {
             uri: format!("at://{did}/{collection}/{rkey}"),
@@ -34,84 +68,1452 @@ impl TryFrom<FlattenedCommitEvent<RecordData>> for StoreStatus {
             status,
             created_at,
             indexed_at: Datetime::now(),
+            raw_created_at: None,
+            visibility: Visibility::Public,
+            content_warning: sanitize_content_warning(content_warning),
+            cid: cid.into(),
+            image: image.as_ref().and_then(StatusImage::from_blob),
         })
     }
 }
 
-#[derive(Debug)]
-struct StatusConsumer {
+/// Where the ingester reads repo events from.
+#[derive(Debug, Clone)]
+pub enum IngestSource {
+    /// A Jetstream instance at the given websocket URL (JSON-encoded, pre-filtered by
+    /// collection).
+    Jetstream(String),
+    /// The full `com.atproto.sync.subscribeRepos` firehose of a relay or PDS.
+    Firehose(String),
+}
+
+/// Controls how the ingester groups status inserts into transactions.
+#[derive(Debug, Clone)]
+pub struct BatchConfig {
+    /// Flush once this many statuses are buffered.
+    pub max_size: usize,
+    /// Flush buffered statuses at least this often.
+    pub max_delay: Duration,
+}
+
+// buffers statuses and flushes them in multi-row transactions from a background task
+#[derive(Debug, Clone)]
+struct StatusBatcher {
+    tx: mpsc::Sender<StoreStatus>,
+}
+
+impl StatusBatcher {
+    fn spawn(store: StatusStore, dead_letters: DeadLetterStore, config: BatchConfig) -> Self {
+        let (tx, mut rx) = mpsc::channel(config.max_size * 4);
+        tokio::spawn(async move {
+            let mut buffer = Vec::with_capacity(config.max_size);
+            let mut ticker = tokio::time::interval(config.max_delay);
+            loop {
+                let closed = tokio::select! {
+                    received = rx.recv() => match received {
+                        Some(status) => {
+                            buffer.push(status);
+                            if buffer.len() < config.max_size {
+                                continue;
+                            }
+                            false
+                        }
+                        None => true,
+                    },
+                    _ = ticker.tick() => false,
+                };
+                if !buffer.is_empty() {
+                    let batch = std::mem::replace(&mut buffer, Vec::with_capacity(config.max_size));
+                    let payloads = batch
+                        .iter()
+                        .map(dead_letter::status_payload)
+                        .collect::<Vec<_>>();
+                    if let Err(e) = store.insert_many(batch).await {
+                        error!("failed to insert batch of {} statuses: {e}", payloads.len());
+                        for payload in payloads {
+                            if let Err(e) = dead_letters.insert(payload, &e).await {
+                                error!("failed to dead-letter status: {e}");
+                            }
+                        }
+                    }
+                }
+                if closed {
+                    break;
+                }
+            }
+        });
+        Self { tx }
+    }
+
+    async fn push(&self, status: StoreStatus) -> Result<(), StoreError> {
+        self.tx
+            .send(status)
+            .await
+            .map_err(|_| StoreError::BatcherClosed)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StatusConsumer {
+    enabled: watch::Receiver<bool>,
+    batcher: StatusBatcher,
+    status_options: Vec<String>,
+    did_filter: DidFilter,
+    max_clock_skew: Duration,
+    metrics: Arc<Metrics>,
+}
+
+impl StatusConsumer {
+    pub async fn ingest(&self, status: StoreStatus) -> Result<(), StoreError> {
+        if !*self.enabled.borrow() {
+            debug!("ignoring status {}: ingestion paused", status.uri);
+            return Ok(());
+        }
+        if !self.did_filter.allows(status.author_did.as_str()) {
+            debug!(
+                "ignoring status from {}: filtered by DID allow/deny list",
+                status.author_did.as_str()
+            );
+            return Ok(());
+        }
+        if !is_allowed_status(&self.status_options, &status.status) {
+            debug!(
+                "ignoring status '{}' from {}: not an allowed option",
+                status.status,
+                status.author_did.as_str()
+            );
+            return Ok(());
+        }
+        self.metrics.record_jetstream_receipt(&status.uri);
+        self.batcher
+            .push(status.clamp_created_at(self.max_clock_skew))
+            .await?;
+        Ok(())
+    }
+}
+
+impl Consumer<StatusRecordData, StoreError> for StatusConsumer {
+    async fn consume(
+        &self,
+        message: FlattenedCommitEvent<StatusRecordData>,
+    ) -> Result<(), StoreError> {
+        self.ingest(StoreStatus::try_from(message)?).await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PinConsumer {
+    enabled: watch::Receiver<bool>,
     store: StatusStore,
+    did_filter: DidFilter,
 }
 
-impl Consumer<RecordData, StoreError> for StatusConsumer {
-    async fn consume(&self, message: FlattenedCommitEvent<RecordData>) -> Result<(), StoreError> {
-        let store_status = StoreStatus::try_from(message)?;
-        self.store.insert(store_status).await?;
+impl PinConsumer {
+    pub async fn ingest(&self, author_did: Did, pin: PinRecordData) -> Result<(), StoreError> {
+        if !self.accepts(&author_did, "pin") {
+            return Ok(());
+        }
+        self.store
+            .pin(&author_did, pin.subject, &pin.created_at)
+            .await?;
         Ok(())
     }
+
+    /// Handles the deletion of `author_did`'s pin record.
+    pub async fn unpin(&self, author_did: Did) -> Result<(), StoreError> {
+        if !self.accepts(&author_did, "unpin") {
+            return Ok(());
+        }
+        self.store.unpin(&author_did).await
+    }
+
+    // whether changes to `author_did`'s pin are to be stored, logging why if not
+    fn accepts(&self, author_did: &Did, what: &str) -> bool {
+        if !*self.enabled.borrow() {
+            debug!(
+                "ignoring {what} from {}: ingestion paused",
+                author_did.as_str()
+            );
+            return false;
+        }
+        if !self.did_filter.allows(author_did.as_str()) {
+            debug!(
+                "ignoring {what} from {}: filtered by DID allow/deny list",
+                author_did.as_str()
+            );
+            return false;
+        }
+        true
+    }
+}
+
+impl Consumer<PinRecordData, StoreError> for PinConsumer {
+    async fn consume(
+        &self,
+        message: FlattenedCommitEvent<PinRecordData>,
+    ) -> Result<(), StoreError> {
+        let author_did = Did::new(message.did).map_err(StoreError::InvalidDid)?;
+        self.ingest(author_did, message.record).await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReactionConsumer {
+    enabled: watch::Receiver<bool>,
+    store: StatusStore,
+    reaction_options: Vec<String>,
+    did_filter: DidFilter,
+}
+
+impl ReactionConsumer {
+    pub async fn ingest(&self, reaction: StoreReaction) -> Result<(), StoreError> {
+        if !*self.enabled.borrow() {
+            debug!("ignoring reaction {}: ingestion paused", reaction.uri);
+            return Ok(());
+        }
+        if !self.did_filter.allows(reaction.author_did.as_str()) {
+            debug!(
+                "ignoring reaction from {}: filtered by DID allow/deny list",
+                reaction.author_did.as_str()
+            );
+            return Ok(());
+        }
+        if !is_allowed_status(&self.reaction_options, &reaction.emoji) {
+            debug!(
+                "ignoring reaction '{}' from {}: not an allowed option",
+                reaction.emoji,
+                reaction.author_did.as_str()
+            );
+            return Ok(());
+        }
+        if !is_status_uri(&reaction.subject) {
+            debug!(
+                "ignoring reaction {}: subject {} isn't a status",
+                reaction.uri, reaction.subject
+            );
+            return Ok(());
+        }
+        self.store.react(reaction).await
+    }
+}
+
+impl TryFrom<FlattenedCommitEvent<ReactionRecordData>> for StoreReaction {
+    type Error = StoreError;
+
+    fn try_from(
+        FlattenedCommitEvent {
+            did,
+            collection,
+            rkey,
+            record:
+                ReactionRecordData {
+                    subject,
+                    emoji,
+                    created_at,
+                },
+            ..
+        }: FlattenedCommitEvent<ReactionRecordData>,
+    ) -> Result<Self, Self::Error> {
+        Ok(Self {
+            uri: format!("at://{did}/{collection}/{rkey}"),
+            author_did: Did::new(did).map_err(StoreError::InvalidDid)?,
+            subject,
+            emoji,
+            created_at,
+        })
+    }
+}
+
+impl Consumer<ReactionRecordData, StoreError> for ReactionConsumer {
+    async fn consume(
+        &self,
+        message: FlattenedCommitEvent<ReactionRecordData>,
+    ) -> Result<(), StoreError> {
+        self.ingest(StoreReaction::try_from(message)?).await
+    }
+}
+
+/// The consumers of each collection's records, sharing `status_store`, and the record of which
+/// Jetstream events they've already been handed.
+#[derive(Debug, Clone)]
+pub struct Consumers {
+    pub status: StatusConsumer,
+    pub pin: PinConsumer,
+    pub reaction: ReactionConsumer,
+    /// Consumers of collections beyond the app's own.
+    pub registered: ConsumerRegistry,
+    /// The collections subscribed to: the configured ones and those of registered consumers.
+    pub collections: Vec<String>,
+    dedup: Deduplicator,
+}
+
+impl Consumers {
+    pub fn new(
+        config: &AppConfig,
+        status_store: StatusStore,
+        dead_letters: DeadLetterStore,
+        metrics: Arc<Metrics>,
+        toggles: &CollectionToggles,
+        registered: ConsumerRegistry,
+    ) -> Self {
+        let mut collections = config.ingest_collections.clone();
+        for collection in registered.collections() {
+            if !collections.iter().any(|c| c == collection) {
+                collections.push(collection.to_owned());
+            }
+        }
+        for collection in &collections {
+            let consumed = [Status::NSID, Pin::NSID, Reaction::NSID].contains(&collection.as_str())
+                || registered.get(collection).is_some();
+            if !consumed {
+                warn!("ingesting collection {collection}, but nothing consumes it");
+            }
+        }
+
+        Self {
+            status: StatusConsumer {
+                enabled: toggles.subscribe(Status::NSID),
+                batcher: StatusBatcher::spawn(
+                    status_store.clone(),
+                    dead_letters,
+                    config.ingest_batch.clone(),
+                ),
+                status_options: config.status_options.clone(),
+                did_filter: config.did_filter.clone(),
+                max_clock_skew: config.max_clock_skew,
+                metrics: Arc::clone(&metrics),
+            },
+            pin: PinConsumer {
+                enabled: toggles.subscribe(Pin::NSID),
+                store: status_store.clone(),
+                did_filter: config.did_filter.clone(),
+            },
+            reaction: ReactionConsumer {
+                enabled: toggles.subscribe(Reaction::NSID),
+                store: status_store,
+                reaction_options: config.reaction_options.clone(),
+                did_filter: config.did_filter.clone(),
+            },
+            registered,
+            collections,
+            dedup: Deduplicator::new(metrics),
+        }
+    }
+}
+
+// records whose latest events are remembered to spot re-deliveries
+const DEDUP_CAPACITY: NonZeroUsize = NonZeroUsize::new(50_000).expect("capacity is positive");
+
+// the parts of a Jetstream event identifying a commit, parsed once per event to route it
+#[derive(Debug, Deserialize)]
+struct CommitEventKey<'a> {
+    did: &'a str,
+    time_us: u64,
+    commit: Option<CommitKey<'a>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitKey<'a> {
+    operation: &'a str,
+    collection: &'a str,
+    rkey: &'a str,
+}
+
+impl<'a> CommitEventKey<'a> {
+    // the key of `message`, if it's a commit event
+    fn parse(message: &'a Message) -> Option<Self> {
+        message
+            .to_text()
+            .ok()
+            .and_then(|text| serde_json::from_str::<Self>(text).ok())
+            .filter(|event| event.commit.is_some())
+    }
+}
+
+/// Spots Jetstream commit events delivered again, e.g. after reconnecting with an older cursor, by
+/// remembering when the latest event about each record happened. Only the records most recently
+/// seen are remembered.
+#[derive(Debug, Clone)]
+struct Deduplicator {
+    // `time_us` of the latest event about each record, by URI
+    seen: Arc<Mutex<LruCache<String, u64>>>,
+    metrics: Arc<Metrics>,
 }
 
-pub async fn ingester(status_store: StatusStore) -> Result<(), crate::error::Error> {
-    // needed for tungstenite
-    rustls::crypto::aws_lc_rs::default_provider()
-        .install_default()
-        .expect("failed to install default crypto provider");
+impl Deduplicator {
+    fn new(metrics: Arc<Metrics>) -> Self {
+        Self::with_capacity(metrics, DEDUP_CAPACITY)
+    }
+
+    fn with_capacity(metrics: Arc<Metrics>, capacity: NonZeroUsize) -> Self {
+        Self {
+            seen: Arc::new(Mutex::new(LruCache::new(capacity))),
+            metrics,
+        }
+    }
+
+    // whether `event` is a commit event no newer than one already seen about the same record, in
+    // which case it's counted; other commit events are remembered
+    fn is_duplicate(&self, event: &CommitEventKey<'_>) -> bool {
+        let Some(commit) = &event.commit else {
+            return false;
+        };
+        let uri = format!("at://{}/{}/{}", event.did, commit.collection, commit.rkey);
+
+        let mut seen = self.seen.lock().expect("poisoned lock");
+        match seen.get_mut(&uri) {
+            Some(latest) if *latest >= event.time_us => {
+                drop(seen);
+                debug!("skipping re-delivered event about {uri}");
+                self.metrics.record_ingest_duplicate();
+                true
+            }
+            Some(latest) => {
+                *latest = event.time_us;
+                false
+            }
+            None => {
+                seen.push(uri, event.time_us);
+                false
+            }
+        }
+    }
+}
 
-    let mut connection = Connection::new(
-        Options::new(US_EAST_1)
-            .wanted_collections([Status::NSID.to_owned()])
-            .compress(true),
+/// Starts the ingester in the background, under a supervisor restarting it whenever it stops and
+/// recording what it's up to in `status`. It's paused and resumed with `ingest_control`. Commits to
+/// the collections of `registered` consumers are handed to them.
+#[allow(clippy::too_many_arguments)]
+pub fn ingester(
+    config: &AppConfig,
+    status_store: StatusStore,
+    dead_letters: DeadLetterStore,
+    ingest_control: IngestControlStore,
+    metrics: Arc<Metrics>,
+    toggles: &CollectionToggles,
+    registered: ConsumerRegistry,
+    status: IngesterStatus,
+) {
+    let consumers = Consumers::new(
+        config,
+        status_store,
+        dead_letters.clone(),
+        metrics,
+        toggles,
+        registered,
     );
+    let source = config.ingest_source.clone();
+    let workers = config.ingest_workers;
+    supervisor::spawn_supervisor(status.clone(), move || {
+        run(
+            source.clone(),
+            consumers.clone(),
+            dead_letters.clone(),
+            ingest_control.clone(),
+            status.clone(),
+            workers,
+        )
+    });
+}
+
+// runs until the ingester stops, which it only does when something goes wrong
+async fn run(
+    source: IngestSource,
+    consumers: Consumers,
+    dead_letters: DeadLetterStore,
+    ingest_control: IngestControlStore,
+    status: IngesterStatus,
+    workers: usize,
+) -> Result<(), crate::error::Error> {
+    let pause = PauseSwitch::spawn(ingest_control).await?;
+    match source {
+        IngestSource::Jetstream(url) => {
+            jetstream(url, consumers, dead_letters, pause, status, workers).await
+        }
+        IngestSource::Firehose(url) => {
+            firehose::firehose(url, consumers, dead_letters, pause, status).await
+        }
+    }
+}
+
+// how often the ingester checks whether it's been paused or resumed
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The ingester's pause switch, polled from the [`IngestControlStore`] in the background.
+#[derive(Debug)]
+pub struct PauseSwitch {
+    store: IngestControlStore,
+    paused: watch::Receiver<bool>,
+    /// Where the ingester paused, until it's resumed and picks up from there.
+    pub resume_cursor: Option<i64>,
+}
+
+impl PauseSwitch {
+    async fn spawn(store: IngestControlStore) -> Result<Self, StoreError> {
+        let control = store.get().await?;
+        let (tx, paused) = watch::channel(control.paused);
+        let polled = store.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(PAUSE_POLL_INTERVAL);
+            loop {
+                // until the ingester run watching the switch is over
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    () = tx.closed() => break,
+                }
+                match polled.get().await {
+                    Ok(control) => {
+                        if tx.send_if_modified(|paused| {
+                            std::mem::replace(paused, control.paused) != control.paused
+                        }) {
+                            info!(
+                                "Ingestion {}",
+                                if control.paused { "paused" } else { "resumed" }
+                            );
+                        }
+                    }
+                    Err(e) => error!("failed to read the ingester's pause switch: {e}"),
+                }
+            }
+        });
+        Ok(Self {
+            store,
+            paused,
+            resume_cursor: control.resume_cursor,
+        })
+    }
+
+    /// Waits until ingestion isn't paused. The cursor held while it was paused is then let go of,
+    /// as the caller has it in hand.
+    pub async fn resumed(&mut self) -> Result<(), StoreError> {
+        self.until(false).await;
+        if self.resume_cursor.take().is_some() {
+            self.store.set_resume_cursor(None).await?;
+        }
+        Ok(())
+    }
+
+    /// Waits until ingestion is paused.
+    pub async fn paused(&mut self) {
+        self.until(true).await
+    }
+
+    /// Holds `cursor` while paused, so a restarted ingester picks up from there too.
+    pub async fn hold(&mut self, cursor: i64) -> Result<(), StoreError> {
+        self.store.set_resume_cursor(Some(cursor)).await?;
+        self.resume_cursor = Some(cursor);
+        Ok(())
+    }
+
+    async fn until(&mut self, paused: bool) {
+        // the poller only goes away with the switch, but if it did, nothing would flip it again
+        if self.paused.wait_for(|p| *p == paused).await.is_err() {
+            std::future::pending().await
+        }
+    }
+}
+
+/// Where Jetstream messages come from: the live connection, or in tests, messages made up for the
+/// occasion.
+pub trait EventSource: Send + 'static {
+    /// The next message, or `None` once there are no more.
+    fn next_message(&mut self) -> impl Future<Output = Option<Message>> + Send;
+}
+
+impl EventSource for mpsc::Receiver<Message> {
+    fn next_message(&mut self) -> impl Future<Output = Option<Message>> + Send {
+        self.recv()
+    }
+}
+
+impl EventSource for mpsc::UnboundedReceiver<Message> {
+    fn next_message(&mut self) -> impl Future<Output = Option<Message>> + Send {
+        self.recv()
+    }
+}
+
+// the part of a Jetstream event that's also its cursor
+#[derive(Debug, Deserialize)]
+struct EventTime {
+    time_us: u64,
+}
 
+/// Passes on the messages of `source`, keeping track of the cursor of the latest one in `latest`,
+/// and recording their receipt in `status`.
+pub struct CursorTracking<S> {
+    pub source: S,
+    pub latest: Arc<AtomicU64>,
+    pub status: IngesterStatus,
+}
+
+impl<S: EventSource> EventSource for CursorTracking<S> {
+    async fn next_message(&mut self) -> Option<Message> {
+        let message = self.source.next_message().await?;
+        let time_us = message
+            .to_text()
+            .ok()
+            .and_then(|text| serde_json::from_str::<EventTime>(text).ok())
+            .map(|event| event.time_us);
+        if let Some(time_us) = time_us {
+            self.latest.store(time_us, Ordering::Relaxed);
+        }
+        self.status.event_received(
+            time_us.and_then(|time_us| DateTime::from_timestamp_micros(time_us as i64)),
+        );
+        Some(message)
+    }
+}
+
+async fn jetstream(
+    url: String,
+    consumers: Consumers,
+    dead_letters: DeadLetterStore,
+    mut pause: PauseSwitch,
+    status: IngesterStatus,
+    workers: usize,
+) -> Result<(), crate::error::Error> {
+    // cursor into the stream: where the ingester paused, or else 30 minutes back
+    let mut cursor = match pause.resume_cursor {
+        Some(cursor) => cursor as u64,
+        None => {
+            let thirty_minutes_ago = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("system time error")
+                - Duration::from_secs(30 * 60);
+            thirty_minutes_ago.as_micros() as u64
+        }
+    };
+
+    loop {
+        pause.resumed().await?;
+        status.set_connection(ConnectionState::Connecting);
+
+        let mut connection = Connection::new(
+            Options::new(url.as_str())
+                .wanted_collections(consumers.collections.clone())
+                .compress(true),
+        );
+        let message_rx = connection
+            .take_message_rx()
+            .expect("message_rx already taken");
+        let latest = Arc::new(AtomicU64::new(cursor));
+        let source = CursorTracking {
+            source: message_rx,
+            latest: Arc::clone(&latest),
+            status: status.clone(),
+        };
+
+        // run the message loop alongside the Jetstream connection, until either stops (the next
+        // run rewinds the cursor again, so messages still in flight aren't lost) or ingestion is
+        // paused
+        tokio::select! {
+            () = consume_events(source, consumers.clone(), dead_letters.clone(), workers) => {
+                return Ok(());
+            }
+            connected = connection.connect(Cursor::from(cursor)) => return Ok(connected?),
+            () = pause.paused() => {}
+        }
+
+        // the workers finish what they were handed, and Jetstream replays from the cursor on, so
+        // nothing received before pausing is skipped on resuming
+        cursor = latest.load(Ordering::Relaxed);
+        pause.hold(cursor as i64).await?;
+        status.set_connection(ConnectionState::Paused);
+        info!("Ingestion paused at cursor {cursor}");
+    }
+}
+
+// messages each worker can have queued before the dispatcher waits for it to catch up
+const WORKER_QUEUE_SIZE: usize = 64;
+
+// the repo a Jetstream event is about
+#[derive(Debug, Deserialize)]
+struct EventRepo<'a> {
+    did: &'a str,
+}
+
+/// Hands each message from `source` to one of `workers` (at least one) worker tasks, until the
+/// source runs dry or Jetstream closes the connection. Messages about the same repo all go to the
+/// same worker, so they're processed in order. Returns once the workers are done with what they
+/// were handed.
+pub async fn consume_events(
+    mut source: impl EventSource,
+    consumers: Consumers,
+    dead_letters: DeadLetterStore,
+    workers: usize,
+) {
+    let workers = (0..workers)
+        .map(|_| {
+            let (tx, rx) = mpsc::channel(WORKER_QUEUE_SIZE);
+            let worker = tokio::spawn(process_events(rx, consumers.clone(), dead_letters.clone()));
+            (tx, worker)
+        })
+        .collect::<Vec<_>>();
+
+    while let Some(message) = source.next_message().await {
+        let closing = message.is_close();
+        let repo = message
+            .to_text()
+            .ok()
+            .and_then(|text| serde_json::from_str::<EventRepo>(text).ok())
+            .map(|event| event.did);
+        let (tx, _) = &workers[worker_for(repo, workers.len())];
+        if tx.send(message).await.is_err() {
+            error!("ingest worker stopped, no longer dispatching messages");
+            break;
+        }
+        if closing {
+            break;
+        }
+    }
+
+    for (tx, worker) in workers {
+        drop(tx);
+        if let Err(e) = worker.await {
+            error!("ingest worker crashed: {e}");
+        }
+    }
+}
+
+// which of `workers` processes the events of `repo`
+fn worker_for(repo: Option<&str>, workers: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    repo.hash(&mut hasher);
+    (hasher.finish() % workers as u64) as usize
+}
+
+// hands each message to the consumer of its collection, dead-lettering those that fail to process
+async fn process_events(
+    mut messages: mpsc::Receiver<Message>,
+    consumers: Consumers,
+    dead_letters: DeadLetterStore,
+) {
     let status_multi_consumer = multi_consumer!(
         StatusMultiConsumer<StoreError> {
-            Status::NSID => RecordData => StatusConsumer = StatusConsumer { store: status_store.clone() }
+            Status::NSID => StatusRecordData => StatusConsumer = consumers.status,
+            Pin::NSID => PinRecordData => PinConsumer = consumers.pin,
+            Reaction::NSID => ReactionRecordData => ReactionConsumer = consumers.reaction
         }
     );
 
-    // cursor into the stream
-    let thirty_minutes_ago = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("system time error")
-        - Duration::from_secs(30 * 60);
-    let cursor = Cursor::from(thirty_minutes_ago.as_micros() as u64);
-
-    let mut message_rx = connection
-        .take_message_rx()
-        .expect("message_rx already taken");
-
-    // spawn the message loop
-    tokio::spawn(async move {
-        while let Some(message) = message_rx.recv().await {
-            match process_message(&status_multi_consumer, message).await {
-                Err(e) => {
-                    error!("error during message processing: {e}");
-                }
-                Ok(ProcessEffect::Closed(err_message)) => {
-                    error!(
-                        "Jetstream connection closed{}",
-                        err_message
-                            .map(|em| format!(": {}", em.to_string()))
-                            .unwrap_or("".to_owned())
-                    );
-                    break;
+    while let Some(message) = messages.recv().await {
+        if let Some(key) = CommitEventKey::parse(&message) {
+            if consumers.dedup.is_duplicate(&key) {
+                continue;
+            }
+            if consume_registered(&message, &key, &consumers.registered, &dead_letters).await {
+                continue;
+            }
+            if consume_pin_delete(&message, &key, &consumers.pin, &dead_letters).await {
+                continue;
+            }
+        }
+        // keep the raw message around in case it needs to be dead-lettered
+        let raw = message.to_text().map(|text| text.to_owned()).ok();
+        match process_message(&status_multi_consumer, message).await {
+            Err(e) => {
+                error!("error during message processing: {e}");
+                if let Some(raw) = raw {
+                    if let Err(e) = dead_letters.insert(raw, &e).await {
+                        error!("failed to dead-letter message: {e}");
+                    }
                 }
-                Ok(
-                    ProcessEffect::Ignored
-                    | ProcessEffect::ProcessedAccount
-                    | ProcessEffect::ProcessedIdentity
-                    | ProcessEffect::ProcessedCommit,
-                ) => {}
             }
+            Ok(ProcessEffect::Closed(err_message)) => {
+                error!(
+                    "Jetstream connection closed{}",
+                    err_message
+                        .map(|em| format!(": {}", em.to_string()))
+                        .unwrap_or("".to_owned())
+                );
+                break;
+            }
+            Ok(
+                ProcessEffect::Ignored
+                | ProcessEffect::ProcessedAccount
+                | ProcessEffect::ProcessedIdentity
+                | ProcessEffect::ProcessedCommit,
+            ) => {}
         }
-    });
+    }
+}
+
+// a Jetstream commit event, as handed to registered consumers
+#[derive(Debug, Deserialize)]
+struct JetstreamCommitEvent {
+    did: String,
+    commit: JetstreamCommit,
+}
+
+#[derive(Debug, Deserialize)]
+struct JetstreamCommit {
+    operation: String,
+    collection: String,
+    rkey: String,
+    record: Option<Unknown>,
+    cid: Option<String>,
+}
+
+impl TryFrom<JetstreamCommitEvent> for RecordCommit {
+    type Error = anyhow::Error;
+
+    fn try_from(
+        JetstreamCommitEvent { did, commit }: JetstreamCommitEvent,
+    ) -> Result<Self, Self::Error> {
+        let operation = CommitOperation::parse(&commit.operation)
+            .ok_or_else(|| anyhow::anyhow!("unknown commit operation '{}'", commit.operation))?;
+        Ok(Self {
+            did: Did::new(did.clone()).map_err(|e| anyhow::anyhow!("{e}: {did}"))?,
+            collection: commit.collection,
+            rkey: commit.rkey,
+            operation,
+            record: commit.record,
+            cid: commit.cid,
+        })
+    }
+}
+
+// hands `message` to the registered consumer of its collection, dead-lettering it if that fails;
+// returns whether it was a commit to a registered collection
+async fn consume_registered(
+    message: &Message,
+    key: &CommitEventKey<'_>,
+    registered: &ConsumerRegistry,
+    dead_letters: &DeadLetterStore,
+) -> bool {
+    let Some(consumer) = key
+        .commit
+        .as_ref()
+        .and_then(|commit| registered.get(commit.collection))
+    else {
+        return false;
+    };
+    let Ok(text) = message.to_text() else {
+        return false;
+    };
 
-    // spin up the Jetstream connection
-    tokio::spawn(async move {
-        if let Err(e) = connection.connect(cursor).await {
-            error!("Jetstream connection failed: {e}");
+    let consumed = match serde_json::from_str::<JetstreamCommitEvent>(text) {
+        Ok(event) => match RecordCommit::try_from(event) {
+            Ok(commit) => consumer.consume(commit).await,
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = consumed {
+        error!("error during message processing: {e}");
+        if let Err(e) = dead_letters.insert(text, &e).await {
+            error!("failed to dead-letter message: {e}");
         }
-    });
+    }
+    true
+}
+
+// unpins the author of `message` if it's the deletion of their pin record, dead-lettering it if
+// that fails; returns whether it was. Deletes carry no record, so they never reach the consumers of
+// the app's own collections
+async fn consume_pin_delete(
+    message: &Message,
+    key: &CommitEventKey<'_>,
+    pin: &PinConsumer,
+    dead_letters: &DeadLetterStore,
+) -> bool {
+    let is_pin_delete = key
+        .commit
+        .as_ref()
+        .is_some_and(|commit| commit.collection == Pin::NSID && commit.operation == "delete");
+    if !is_pin_delete {
+        return false;
+    }
+    let Ok(text) = message.to_text() else {
+        return false;
+    };
+
+    let unpinned = match Did::new(key.did.to_owned()) {
+        Ok(did) => pin.unpin(did).await,
+        Err(e) => Err(StoreError::InvalidDid(e)),
+    };
+    if let Err(e) = unpinned {
+        error!("error during message processing: {e}");
+        if let Err(e) = dead_letters.insert(text, &e).await {
+            error!("failed to dead-letter message: {e}");
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        metrics::Scope,
+        record_consumer::RecordConsumer,
+        store::{self, Db, IngestControl, StatusFilter},
+        test_support::{did, memory_pool},
+    };
+
+    const ALICE: &str = "did:plc:alice0000000000000000000";
+    const BOB: &str = "did:plc:bob00000000000000000000000";
+
+    // a Jetstream commit event creating `record` in `did`'s repo
+    fn commit(did: &str, collection: &str, rkey: &str, record: serde_json::Value) -> Message {
+        Message::text(
+            json!({
+                "did": did,
+                "time_us": 1725911162329308u64,
+                "kind": "commit",
+                "commit": {
+                    "rev": "3l3qo2vutsw2b",
+                    "operation": "create",
+                    "collection": collection,
+                    "rkey": rkey,
+                    "record": record,
+                    "cid": "bafyreie5737gdxlw5i64vzichcalba3z2v5n6icifvx5xytvske7mr3hpm",
+                },
+            })
+            .to_string(),
+        )
+    }
+
+    // the deletion of a record, after it was created by `commit`
+    fn delete(did: &str, collection: &str, rkey: &str) -> Message {
+        Message::text(
+            json!({
+                "did": did,
+                "time_us": 1725911162329309u64,
+                "kind": "commit",
+                "commit": {
+                    "rev": "3l3qo2vutsw2c",
+                    "operation": "delete",
+                    "collection": collection,
+                    "rkey": rkey,
+                },
+            })
+            .to_string(),
+        )
+    }
+
+    fn status(did: &str, rkey: &str, status: &str) -> Message {
+        commit(
+            did,
+            Status::NSID,
+            rkey,
+            json!({ "$type": Status::NSID, "status": status, "createdAt": Datetime::now() }),
+        )
+    }
+
+    fn status_uri(did: &str, rkey: &str) -> String {
+        format!("at://{did}/{}/{rkey}", Status::NSID)
+    }
+
+    async fn stores() -> (StatusStore, DeadLetterStore) {
+        let db = Db::from(memory_pool().await);
+        store::migrate(&db).await.expect("database migrates");
+        let status_store = StatusStore::new(db.clone());
+        let dead_letters = DeadLetterStore::new(db);
+        (status_store, dead_letters)
+    }
+
+    // runs `messages` through the ingester's consumers, as though they came from Jetstream
+    async fn ingest(
+        messages: Vec<Message>,
+        status_store: &StatusStore,
+        dead_letters: &DeadLetterStore,
+        toggles: &CollectionToggles,
+    ) {
+        ingest_registered(
+            messages,
+            status_store,
+            dead_letters,
+            toggles,
+            ConsumerRegistry::new(),
+        )
+        .await;
+    }
+
+    // as `ingest`, with `registered` consumers alongside the app's own
+    async fn ingest_registered(
+        messages: Vec<Message>,
+        status_store: &StatusStore,
+        dead_letters: &DeadLetterStore,
+        toggles: &CollectionToggles,
+        registered: ConsumerRegistry,
+    ) {
+        let mut config = AppConfig::for_tests();
+        config.ingest_batch = BatchConfig {
+            max_size: 1,
+            max_delay: Duration::from_millis(10),
+        };
+        let consumers = Consumers::new(
+            &config,
+            status_store.clone(),
+            dead_letters.clone(),
+            Arc::new(Metrics::new(Scope::Ingester).expect("metrics register")),
+            toggles,
+            registered,
+        );
+        let (tx, rx) = mpsc::channel(messages.len().max(1));
+        for message in messages {
+            tx.send(message).await.expect("channel is open");
+        }
+        drop(tx);
+        consume_events(rx, consumers, dead_letters.clone(), 4).await;
+    }
+
+    fn toggles() -> CollectionToggles {
+        CollectionToggles::new([Status::NSID, Pin::NSID, Reaction::NSID])
+    }
+
+    // statuses are inserted by the batcher in the background, so this waits (briefly) for `count`
+    // of them to land
+    async fn stored_statuses(store: &StatusStore, count: usize) -> Vec<StoreStatus> {
+        for _ in 0..100 {
+            let statuses = store
+                .fetch_n(&StatusFilter::new(), count + 1)
+                .await
+                .expect("statuses are fetched");
+            if statuses.len() >= count {
+                return statuses;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("fewer than {count} statuses were stored");
+    }
+
+    #[tokio::test]
+    async fn statuses_are_stored() {
+        let (status_store, dead_letters) = stores().await;
+        ingest(
+            vec![
+                status(ALICE, "3kaaaaaaaaaa2", "🦋"),
+                status(BOB, "3kaaaaaaaaab2", "🥳"),
+            ],
+            &status_store,
+            &dead_letters,
+            &toggles(),
+        )
+        .await;
+
+        let mut stored = stored_statuses(&status_store, 2)
+            .await
+            .into_iter()
+            .map(|status| (status.uri, status.author_did, status.status))
+            .collect::<Vec<_>>();
+        stored.sort();
+        assert_eq!(
+            stored,
+            vec![
+                (
+                    status_uri(ALICE, "3kaaaaaaaaaa2"),
+                    did(ALICE),
+                    "🦋".to_owned()
+                ),
+                (status_uri(BOB, "3kaaaaaaaaab2"), did(BOB), "🥳".to_owned()),
+            ]
+        );
+        assert_eq!(dead_letters.count().await.expect("dead letters count"), 0);
+    }
+
+    #[tokio::test]
+    async fn disallowed_statuses_are_ignored() {
+        let (status_store, dead_letters) = stores().await;
+        // statuses are stored in order, so once the allowed one is in, the other was dropped
+        ingest(
+            vec![
+                status(ALICE, "3kaaaaaaaaaa2", "not an emoji"),
+                status(BOB, "3kaaaaaaaaab2", "🥳"),
+            ],
+            &status_store,
+            &dead_letters,
+            &toggles(),
+        )
+        .await;
+
+        let stored = stored_statuses(&status_store, 1).await;
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].author_did, did(BOB));
+    }
+
+    #[tokio::test]
+    async fn reactions_to_statuses_are_counted() {
+        let (status_store, dead_letters) = stores().await;
+        let subject = status_uri(ALICE, "3kaaaaaaaaaa2");
+        ingest(
+            vec![
+                status(ALICE, "3kaaaaaaaaaa2", "🦋"),
+                commit(
+                    BOB,
+                    Reaction::NSID,
+                    "3kaaaaaaaaac2",
+                    json!({
+                        "$type": Reaction::NSID,
+                        "subject": subject,
+                        "emoji": "👍",
+                        "createdAt": Datetime::now(),
+                    }),
+                ),
+            ],
+            &status_store,
+            &dead_letters,
+            &toggles(),
+        )
+        .await;
+
+        let counts = status_store
+            .reaction_counts(&[subject.clone()])
+            .await
+            .expect("reactions are counted");
+        let counts = counts.get(&subject).expect("status has reactions");
+        assert_eq!(counts.len(), 1);
+        assert_eq!((counts[0].emoji.as_str(), counts[0].count), ("👍", 1));
+    }
+
+    #[tokio::test]
+    async fn repeated_reactions_replace_each_other() {
+        let (status_store, dead_letters) = stores().await;
+        let subject = status_uri(ALICE, "3kaaaaaaaaaa2");
+        let reaction = |rkey| {
+            commit(
+                BOB,
+                Reaction::NSID,
+                rkey,
+                json!({
+                    "$type": Reaction::NSID,
+                    "subject": subject,
+                    "emoji": "👍",
+                    "createdAt": Datetime::now(),
+                }),
+            )
+        };
+        ingest(
+            vec![
+                status(ALICE, "3kaaaaaaaaaa2", "🦋"),
+                reaction("3kaaaaaaaaac2"),
+                reaction("3kaaaaaaaaad2"),
+            ],
+            &status_store,
+            &dead_letters,
+            &toggles(),
+        )
+        .await;
+
+        assert_eq!(
+            dead_letters
+                .count()
+                .await
+                .expect("dead letters are counted"),
+            0
+        );
+        assert!(
+            status_store
+                .has_reacted(&did(BOB), &subject, "👍")
+                .await
+                .expect("reaction is looked up")
+        );
+    }
+
+    #[tokio::test]
+    async fn paused_collections_are_ignored() {
+        let (status_store, dead_letters) = stores().await;
+        let toggles = toggles();
+        toggles.set(Pin::NSID, false);
+        let subject = status_uri(ALICE, "3kaaaaaaaaaa2");
+        ingest(
+            vec![
+                status(ALICE, "3kaaaaaaaaaa2", "🦋"),
+                commit(
+                    ALICE,
+                    Pin::NSID,
+                    "self",
+                    json!({ "$type": Pin::NSID, "subject": subject, "createdAt": Datetime::now() }),
+                ),
+            ],
+            &status_store,
+            &dead_letters,
+            &toggles,
+        )
+        .await;
+
+        stored_statuses(&status_store, 1).await;
+        let pinned = status_store
+            .fetch_pinned(&did(ALICE))
+            .await
+            .expect("pin is fetched");
+        assert!(pinned.is_none());
+    }
+
+    #[tokio::test]
+    async fn pins_are_stored() {
+        let (status_store, dead_letters) = stores().await;
+        let subject = status_uri(ALICE, "3kaaaaaaaaaa2");
+        ingest(
+            vec![
+                status(ALICE, "3kaaaaaaaaaa2", "🦋"),
+                commit(
+                    ALICE,
+                    Pin::NSID,
+                    "self",
+                    json!({ "$type": Pin::NSID, "subject": subject, "createdAt": Datetime::now() }),
+                ),
+            ],
+            &status_store,
+            &dead_letters,
+            &toggles(),
+        )
+        .await;
+
+        stored_statuses(&status_store, 1).await;
+        let pinned = status_store
+            .fetch_pinned(&did(ALICE))
+            .await
+            .expect("pin is fetched")
+            .expect("status is pinned");
+        assert_eq!(pinned.uri, subject);
+    }
+
+    #[tokio::test]
+    async fn deleted_pins_are_unpinned() {
+        let (status_store, dead_letters) = stores().await;
+        let subject = status_uri(ALICE, "3kaaaaaaaaaa2");
+        ingest(
+            vec![
+                status(ALICE, "3kaaaaaaaaaa2", "🦋"),
+                commit(
+                    ALICE,
+                    Pin::NSID,
+                    "self",
+                    json!({ "$type": Pin::NSID, "subject": subject, "createdAt": Datetime::now() }),
+                ),
+                delete(ALICE, Pin::NSID, "self"),
+            ],
+            &status_store,
+            &dead_letters,
+            &toggles(),
+        )
+        .await;
 
-    Ok(())
+        let pinned = status_store
+            .fetch_pinned(&did(ALICE))
+            .await
+            .expect("pin is fetched");
+        assert!(pinned.is_none());
+        assert_eq!(
+            dead_letters
+                .count()
+                .await
+                .expect("dead letters are counted"),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn statuses_of_many_authors_are_spread_over_the_workers() {
+        let (status_store, dead_letters) = stores().await;
+        let authors = (0..8)
+            .map(|i| format!("did:plc:author{i:0>19}"))
+            .collect::<Vec<_>>();
+        assert!(
+            authors
+                .iter()
+                .map(|author| worker_for(Some(author), 4))
+                .collect::<std::collections::HashSet<_>>()
+                .len()
+                > 1
+        );
+        ingest(
+            authors
+                .iter()
+                .map(|author| status(author, "3kaaaaaaaaaa2", "🦋"))
+                .collect(),
+            &status_store,
+            &dead_letters,
+            &toggles(),
+        )
+        .await;
+
+        assert_eq!(stored_statuses(&status_store, 8).await.len(), 8);
+    }
+
+    // a status event, as delivered at `time_us`
+    fn status_at(did: &str, rkey: &str, emoji: &str, time_us: u64) -> Message {
+        let mut event: serde_json::Value =
+            serde_json::from_str(status(did, rkey, emoji).to_text().expect("text"))
+                .expect("event is JSON");
+        event["time_us"] = json!(time_us);
+        Message::text(event.to_string())
+    }
+
+    fn deduplicator(capacity: usize) -> Deduplicator {
+        Deduplicator::with_capacity(
+            Arc::new(Metrics::new(Scope::Ingester).expect("metrics register")),
+            NonZeroUsize::new(capacity).expect("capacity is positive"),
+        )
+    }
+
+    // whether `dedup` takes `message` for a re-delivery
+    fn is_duplicate(dedup: &Deduplicator, message: &Message) -> bool {
+        dedup.is_duplicate(&CommitEventKey::parse(message).expect("commit event"))
+    }
+
+    #[test]
+    fn re_delivered_events_are_duplicates() {
+        let dedup = deduplicator(100);
+
+        assert!(!is_duplicate(
+            &dedup,
+            &status_at(ALICE, "3kaaaaaaaaaa2", "🦋", 100)
+        ));
+        assert!(is_duplicate(
+            &dedup,
+            &status_at(ALICE, "3kaaaaaaaaaa2", "🦋", 100)
+        ));
+        // older events about the record are stale too
+        assert!(is_duplicate(
+            &dedup,
+            &status_at(ALICE, "3kaaaaaaaaaa2", "🦋", 50)
+        ));
+        // newer ones, and ones about other records, aren't
+        assert!(!is_duplicate(
+            &dedup,
+            &status_at(ALICE, "3kaaaaaaaaaa2", "🥳", 200)
+        ));
+        assert!(!is_duplicate(
+            &dedup,
+            &status_at(ALICE, "3kaaaaaaaaab2", "🦋", 100)
+        ));
+        assert!(!is_duplicate(
+            &dedup,
+            &status_at(BOB, "3kaaaaaaaaaa2", "🦋", 100)
+        ));
+    }
+
+    #[test]
+    fn the_least_recently_seen_records_are_forgotten() {
+        let dedup = deduplicator(2);
+        assert!(!is_duplicate(
+            &dedup,
+            &status_at(ALICE, "3kaaaaaaaaaa2", "🦋", 100)
+        ));
+        assert!(!is_duplicate(
+            &dedup,
+            &status_at(BOB, "3kaaaaaaaaaa2", "🦋", 100)
+        ));
+
+        // seeing Alice's record again keeps it around over Bob's
+        assert!(!is_duplicate(
+            &dedup,
+            &status_at(ALICE, "3kaaaaaaaaaa2", "🥳", 200)
+        ));
+        assert!(!is_duplicate(
+            &dedup,
+            &status_at(ALICE, "3kaaaaaaaaab2", "🦋", 100)
+        ));
+
+        assert!(is_duplicate(
+            &dedup,
+            &status_at(ALICE, "3kaaaaaaaaaa2", "🥳", 200)
+        ));
+        assert!(!is_duplicate(
+            &dedup,
+            &status_at(BOB, "3kaaaaaaaaaa2", "🦋", 100)
+        ));
+    }
+
+    #[tokio::test]
+    async fn cursor_follows_the_latest_event() {
+        let (tx, rx) = mpsc::channel(1);
+        let latest = Arc::new(AtomicU64::new(0));
+        let status = IngesterStatus::new();
+        let mut source = CursorTracking {
+            source: rx,
+            latest: Arc::clone(&latest),
+            status: status.clone(),
+        };
+
+        tx.send(status(ALICE, "3kaaaaaaaaaa2", "🦋"))
+            .await
+            .expect("channel is open");
+        source.next_message().await.expect("message is passed on");
+
+        assert_eq!(latest.load(Ordering::Relaxed), 1725911162329308);
+        let report = status.report();
+        assert_eq!(report.connection, ConnectionState::Connected);
+        assert!(report.last_event_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn pause_holds_the_cursor_until_resumed() {
+        let db = Db::from(memory_pool().await);
+        store::migrate(&db).await.expect("database migrates");
+        let control = IngestControlStore::new(db);
+        control.set_paused(true).await.expect("ingestion pauses");
+
+        let mut pause = PauseSwitch::spawn(control.clone())
+            .await
+            .expect("pause switch is read");
+        pause.paused().await;
+        pause.hold(42).await.expect("cursor is held");
+        // as a restarted ingester would find it
+        let restarted = PauseSwitch::spawn(control.clone())
+            .await
+            .expect("pause switch is read");
+        assert_eq!(restarted.resume_cursor, Some(42));
+
+        control.set_paused(false).await.expect("ingestion resumes");
+        pause.resumed().await.expect("cursor is let go of");
+        assert_eq!(
+            control.get().await.expect("pause switch is read"),
+            IngestControl::default()
+        );
+    }
+
+    const LIKE: &str = "com.example.like";
+
+    // keeps the commits it's handed, failing those to records keyed `bad`
+    #[derive(Debug, Default, Clone)]
+    struct LikeConsumer {
+        commits: Arc<Mutex<Vec<RecordCommit>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl RecordConsumer for LikeConsumer {
+        async fn consume(&self, commit: RecordCommit) -> anyhow::Result<()> {
+            if commit.rkey == "bad" {
+                anyhow::bail!("bad like");
+            }
+            self.commits.lock().expect("poisoned lock").push(commit);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn registered_consumers_are_handed_their_collections() {
+        let (status_store, dead_letters) = stores().await;
+        let likes = LikeConsumer::default();
+        let like = |rkey| commit(ALICE, LIKE, rkey, json!({ "$type": LIKE, "subject": "x" }));
+
+        ingest_registered(
+            vec![status(BOB, "1", "👍"), like("2"), like("bad")],
+            &status_store,
+            &dead_letters,
+            &toggles(),
+            ConsumerRegistry::new().register(LIKE, likes.clone()),
+        )
+        .await;
+
+        let commits = likes.commits.lock().expect("poisoned lock").clone();
+        assert_eq!(commits.len(), 1);
+        assert_eq!(commits[0].uri(), format!("at://{ALICE}/{LIKE}/2"));
+        assert_eq!(commits[0].operation, CommitOperation::Create);
+        assert!(commits[0].record.is_some());
+        // the app's own collections are still consumed by the app
+        assert_eq!(stored_statuses(&status_store, 1).await.len(), 1);
+        let failed = dead_letters
+            .fetch_n(10)
+            .await
+            .expect("dead letters are fetched");
+        assert_eq!(failed.len(), 1);
+        assert!(failed[0].payload.contains("\"rkey\":\"bad\""));
+    }
 }
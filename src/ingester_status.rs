@@ -0,0 +1,166 @@
+//! What the ingester is up to, for `/healthz` and the admin dashboard: whether it's running, its
+//! connection to the event stream, and how far behind the stream it is, so that ingestion stalling
+//! without an error doesn't go unnoticed.
+
+// only the ingester records anything
+#![cfg_attr(not(feature = "ingester"), allow(dead_code))]
+
+use std::sync::{Arc, Mutex};
+
+use atrium_api::types::string::Datetime;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Whether the ingester is running, as far as its supervisor knows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IngesterState {
+    /// Not run by this process, e.g. because it's deployed on its own.
+    Disabled,
+    Running,
+    /// Stopped (crashed, or its connection failed), waiting to be restarted.
+    Restarting,
+}
+
+/// The ingester's connection to Jetstream or the firehose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    Disconnected,
+    Connecting,
+    /// Events have come through since connecting.
+    Connected,
+    /// Disconnected on purpose, until ingestion is resumed.
+    Paused,
+}
+
+/// The ingester's status at one point in time.
+#[derive(Debug, Clone, Serialize)]
+pub struct IngesterReport {
+    pub state: IngesterState,
+    /// When the ingester entered `state`, if it ever left `Disabled`.
+    pub since: Option<Datetime>,
+    pub restarts: u64,
+    pub last_error: Option<String>,
+    pub connection: ConnectionState,
+    /// When the latest event was received.
+    pub last_event_at: Option<Datetime>,
+    /// How long ago the latest event happened, in seconds: how far behind the stream the
+    /// ingester is, which keeps growing if events stop coming through.
+    pub cursor_lag_secs: Option<f64>,
+}
+
+#[derive(Debug)]
+struct Tracked {
+    state: IngesterState,
+    since: Option<DateTime<Utc>>,
+    restarts: u64,
+    last_error: Option<String>,
+    connection: ConnectionState,
+    last_event_at: Option<DateTime<Utc>>,
+    // when the latest event with a known time happened, according to the stream
+    last_event_time: Option<DateTime<Utc>>,
+}
+
+/// The ingester's status, shared between the ingester recording it and whatever reports it.
+#[derive(Debug, Clone)]
+pub struct IngesterStatus {
+    tracked: Arc<Mutex<Tracked>>,
+}
+
+impl IngesterStatus {
+    pub fn new() -> Self {
+        Self {
+            tracked: Arc::new(Mutex::new(Tracked {
+                state: IngesterState::Disabled,
+                since: None,
+                restarts: 0,
+                last_error: None,
+                connection: ConnectionState::Disconnected,
+                last_event_at: None,
+                last_event_time: None,
+            })),
+        }
+    }
+
+    pub fn report(&self) -> IngesterReport {
+        let tracked = self.tracked.lock().expect("poisoned lock");
+        let datetime = |at: DateTime<Utc>| Datetime::new(at.fixed_offset());
+        IngesterReport {
+            state: tracked.state,
+            since: tracked.since.map(datetime),
+            restarts: tracked.restarts,
+            last_error: tracked.last_error.clone(),
+            connection: tracked.connection,
+            last_event_at: tracked.last_event_at.map(datetime),
+            cursor_lag_secs: tracked
+                .last_event_time
+                .map(|time| (Utc::now() - time).num_milliseconds().max(0) as f64 / 1000.0),
+        }
+    }
+
+    /// Records that the supervisor (re)started the ingester.
+    pub fn running(&self) {
+        let mut tracked = self.tracked.lock().expect("poisoned lock");
+        tracked.state = IngesterState::Running;
+        tracked.since = Some(Utc::now());
+    }
+
+    /// Records that the ingester stopped because of `error`, and is about to be restarted.
+    pub fn restarting(&self, error: String) {
+        let mut tracked = self.tracked.lock().expect("poisoned lock");
+        tracked.state = IngesterState::Restarting;
+        tracked.since = Some(Utc::now());
+        tracked.restarts += 1;
+        tracked.last_error = Some(error);
+        tracked.connection = ConnectionState::Disconnected;
+    }
+
+    pub fn set_connection(&self, connection: ConnectionState) {
+        self.tracked.lock().expect("poisoned lock").connection = connection;
+    }
+
+    /// Records receipt of an event, which happened at `event_time` if the stream says when.
+    pub fn event_received(&self, event_time: Option<DateTime<Utc>>) {
+        let mut tracked = self.tracked.lock().expect("poisoned lock");
+        tracked.connection = ConnectionState::Connected;
+        tracked.last_event_at = Some(Utc::now());
+        if event_time.is_some() {
+            tracked.last_event_time = event_time;
+        }
+    }
+}
+
+impl Default for IngesterStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_until_supervised() {
+        let report = IngesterStatus::new().report();
+        assert_eq!(report.state, IngesterState::Disabled);
+        assert_eq!(report.connection, ConnectionState::Disconnected);
+        assert_eq!(report.restarts, 0);
+        assert!(report.cursor_lag_secs.is_none());
+    }
+
+    #[test]
+    fn lag_is_measured_from_the_latest_event_time() {
+        let status = IngesterStatus::new();
+        status.event_received(Some(Utc::now() - chrono::Duration::seconds(90)));
+        // frames without a time don't reset it
+        status.event_received(None);
+
+        let report = status.report();
+        assert_eq!(report.connection, ConnectionState::Connected);
+        assert!(report.last_event_at.is_some());
+        let lag = report.cursor_lag_secs.expect("lag is known");
+        assert!((90.0..100.0).contains(&lag), "lag of {lag}s");
+    }
+}
@@ -1,3 +1,31 @@
-// @generated - This file is generated by esquema-codegen (forked from atrium-codegen). DO NOT EDIT.
-pub mod record;
-pub mod xyz;
+//! The app's record types, generated by `build.rs` from the lexicon documents in `lexicons/`: edit
+//! those, not the generated code.
+
+include!(concat!(env!("OUT_DIR"), "/lexicons.rs"));
+
+#[cfg(test)]
+mod tests {
+    use atrium_api::types::string::Datetime;
+
+    use super::xyz::statusphere::status;
+
+    #[test]
+    fn records_use_the_lexicon_field_names() {
+        let record = status::RecordData {
+            content_warning: None,
+            created_at: "2024-01-02T03:04:05Z"
+                .parse::<Datetime>()
+                .expect("valid datetime"),
+            image: None,
+            status: "👍".to_owned(),
+        };
+
+        let json = serde_json::to_value(&record).expect("serializable record");
+        assert_eq!(
+            json,
+            serde_json::json!({"createdAt": "2024-01-02T03:04:05Z", "status": "👍"})
+        );
+        let round_trip: status::RecordData = serde_json::from_value(json).expect("deserializable");
+        assert_eq!(round_trip, record);
+    }
+}
@@ -1,3 +0,0 @@
-// @generated - This file is generated by esquema-codegen (forked from atrium-codegen). DO NOT EDIT.
-//!Definitions for the `xyz` namespace.
-pub mod statusphere;
@@ -1,15 +0,0 @@
-// @generated - This file is generated by esquema-codegen (forked from atrium-codegen). DO NOT EDIT.
-//!Definitions for the `xyz.statusphere.status` namespace.
-use atrium_api::types::TryFromUnknown;
-#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
-#[serde(rename_all = "camelCase")]
-pub struct RecordData {
-    pub created_at: atrium_api::types::string::Datetime,
-    pub status: String,
-}
-pub type Record = atrium_api::types::Object<RecordData>;
-impl From<atrium_api::types::Unknown> for RecordData {
-    fn from(value: atrium_api::types::Unknown) -> Self {
-        Self::try_from_unknown(value).unwrap()
-    }
-}
@@ -0,0 +1,572 @@
+mod account;
+#[cfg(feature = "admin")]
+mod admin;
+mod api;
+mod app;
+mod assets;
+mod auth;
+mod avatar;
+mod backfill;
+mod config;
+mod cursor;
+mod dead_letter;
+mod dev_auth;
+mod envelope;
+mod error;
+mod export;
+mod feed_generator;
+#[cfg(feature = "ingester")]
+mod firehose;
+mod follows;
+mod handles;
+mod health;
+mod home;
+mod htmx;
+mod i18n;
+#[cfg(feature = "ingester")]
+mod ingester;
+mod ingester_status;
+mod lexicons;
+mod login;
+mod lookups;
+mod metrics;
+mod oauth;
+mod permalink;
+mod preferences;
+mod profile;
+mod reconcile;
+mod record_consumer;
+mod report;
+mod request_id;
+#[cfg(feature = "admin")]
+mod roles;
+mod rollup;
+mod security_headers;
+mod seed;
+mod smoke;
+mod status;
+mod store;
+mod supervisor;
+#[cfg(test)]
+mod test_support;
+mod throttle;
+mod tls;
+mod toggles;
+mod upstream;
+mod validate;
+mod viewer;
+mod views;
+
+use std::{env, net::SocketAddr, path::Path, sync::Arc};
+
+use app::{App, AppBuilder, Stores, WebSessionStore};
+use assets::Assets;
+use atrium_api::types::string::{Datetime, Did};
+use atrium_oauth::DefaultHttpClient;
+use avatar::{GeneratedAvatars, ProfileAvatars};
+use backfill::Backfill;
+use config::AppConfig;
+use follows::FollowCache;
+use handles::HandleResolver;
+use ingester_status::IngesterStatus;
+use lookups::LookupCache;
+use metrics::Metrics;
+use minijinja::Environment;
+use oauth::DidResolver;
+use serde::{Deserialize, Serialize};
+use store::{
+    DeadLetterStore, IngestControlStore, OAuthSessionStore, OAuthStateStore, ReportStore,
+    StatusStore,
+};
+use throttle::{LoginThrottle, PostGuard};
+use toggles::CollectionToggles;
+use tower_sessions::ExpiredDeletion;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+use upstream::CircuitBreaker;
+
+use error::Error;
+
+pub use record_consumer::{CommitOperation, ConsumerRegistry, RecordCommit, RecordConsumer};
+
+macro_rules! open_template {
+    ($state:ident, $name:expr) => {
+        $state
+            .template_env
+            .get_template($name)
+            // panic, this is an unrecoverable error
+            .expect(format!("missing {} template", $name).as_str())
+    };
+}
+pub(crate) use open_template;
+
+struct AppState {
+    template_env: Environment<'static>,
+    oauth_client: oauth::Client,
+    oauth_session_store: OAuthSessionStore,
+    oauth_state_store: OAuthStateStore,
+    status_store: StatusStore,
+    // written to by the ingester, reprocessed from the admin dashboard
+    #[cfg_attr(not(any(feature = "ingester", feature = "admin")), allow(dead_code))]
+    dead_letters: DeadLetterStore,
+    // read by the ingester, flipped from the admin dashboard
+    #[cfg_attr(not(any(feature = "ingester", feature = "admin")), allow(dead_code))]
+    ingest_control: IngestControlStore,
+    reports: ReportStore,
+    http_client: Arc<DefaultHttpClient>,
+    did_resolver: DidResolver,
+    handle_resolver: HandleResolver,
+    circuit_breaker: CircuitBreaker,
+    follow_cache: FollowCache,
+    lookup_cache: LookupCache,
+    login_throttle: LoginThrottle,
+    post_guard: PostGuard,
+    generated_avatars: GeneratedAvatars,
+    profile_avatars: Option<ProfileAvatars>,
+    metrics: Arc<Metrics>,
+    #[cfg_attr(not(any(feature = "ingester", feature = "admin")), allow(dead_code))]
+    collection_toggles: CollectionToggles,
+    ingester_status: IngesterStatus,
+    config: AppConfig,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClientSession {
+    did: Did,
+    // what's shown of the user, as of `refreshed_at` (absent from sessions predating them)
+    #[serde(default)]
+    handle: Option<String>,
+    #[serde(default)]
+    display_name: Option<String>,
+    #[serde(default)]
+    refreshed_at: Option<Datetime>,
+}
+
+// periodically prune expired web sessions (tower-sessions only ignores them on load) and
+// abandoned OAuth login states
+// (https://github.com/maxcountryman/tower-sessions-stores/tree/main/sqlx-store#sqlite-example)
+fn spawn_session_cleanup(
+    session_store: WebSessionStore,
+    oauth_state_store: OAuthStateStore,
+    interval: std::time::Duration,
+) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            // keep going on errors, unlike `continuously_delete_expired`
+            if let Err(e) = session_store.delete_expired().await {
+                error!("expired session cleanup failed: {e}");
+            }
+            match oauth_state_store.delete_expired().await {
+                Ok(count) => debug!("Deleted {count} expired OAuth states"),
+                Err(e) => error!("expired OAuth state cleanup failed: {e}"),
+            }
+        }
+    });
+}
+
+// compiled in, so the binary can be deployed on its own
+const TEMPLATES: &[(&str, &str)] = &[
+    ("layout", include_str!("../templates/layout.jinja")),
+    ("login", include_str!("../templates/login.jinja")),
+    (
+        "login_cancelled",
+        include_str!("../templates/login_cancelled.jinja"),
+    ),
+    ("home", include_str!("../templates/home.jinja")),
+    ("error", include_str!("../templates/error.jinja")),
+    ("error_404", include_str!("../templates/error_404.jinja")),
+    ("error_401", include_str!("../templates/error_401.jinja")),
+    ("error_422", include_str!("../templates/error_422.jinja")),
+    ("error_413", include_str!("../templates/error_413.jinja")),
+    ("error_5xx", include_str!("../templates/error_5xx.jinja")),
+    ("admin", include_str!("../templates/admin.jinja")),
+    ("feed", include_str!("../templates/feed.jinja")),
+    ("reveal", include_str!("../templates/reveal.jinja")),
+    ("history", include_str!("../templates/history.jinja")),
+    ("status", include_str!("../templates/status.jinja")),
+    (
+        "delete_account",
+        include_str!("../templates/delete_account.jinja"),
+    ),
+];
+
+// templates named `<name>.jinja` in `templates_dir` replace the compiled-in ones
+fn initialize_templates(
+    templates_dir: Option<&Path>,
+    assets: Arc<Assets>,
+) -> anyhow::Result<Environment<'static>> {
+    let mut template_env = Environment::new();
+    for (name, source) in TEMPLATES {
+        let override_path = templates_dir
+            .map(|dir| dir.join(format!("{name}.jinja")))
+            .filter(|path| path.is_file());
+        match override_path {
+            Some(path) => {
+                let source = std::fs::read_to_string(&path)
+                    .map_err(|e| anyhow::anyhow!("reading template {}: {e}", path.display()))?;
+                template_env
+                    .add_template_owned(*name, source)
+                    .map_err(|e| anyhow::anyhow!("invalid template {}: {e}", path.display()))?;
+                info!("Using template {}", path.display());
+            }
+            None => template_env
+                .add_template(name, source)
+                .expect("invalid compiled-in template"),
+        }
+    }
+    template_env.add_filter("relative_time", views::relative_time);
+    template_env.add_function("t", i18n::translate);
+    template_env.add_function("asset", move |path: &str| assets.url(path));
+    Ok(template_env)
+}
+
+// logging, panic reporting and TLS, shared by both binaries
+fn init_process() {
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer())
+        .with(EnvFilter::from_default_env())
+        .init();
+    error::install_panic_hook();
+
+    // needed for serving HTTPS, and for tungstenite
+    rustls::crypto::aws_lc_rs::default_provider()
+        .install_default()
+        .expect("failed to install default crypto provider");
+}
+
+/// Runs the web server, with the background jobs and (unless `EMBEDDED_INGESTER` is false) the
+/// ingester, handing commits to the collections of the `registered` consumers to them, or the
+/// one-off command named by the first argument.
+pub async fn run_web(
+    #[cfg_attr(not(feature = "ingester"), allow(unused_variables))] registered: ConsumerRegistry,
+) -> anyhow::Result<()> {
+    init_process();
+
+    let stores = Stores::from_env().await?;
+
+    let app_config = AppConfig::from_env()?;
+    if app_config.dev_fake_auth {
+        warn!("DEV_FAKE_AUTH is set: logins are unauthenticated and record writes aren't sent");
+    }
+
+    // one-off commands
+    if let Some(command) = env::args().nth(1) {
+        match command.as_str() {
+            "reprocess-dead-letters" => {
+                let summary =
+                    dead_letter::reprocess(&stores.dead_letters, &app_config, &stores.status_store)
+                        .await?;
+                println!("{} succeeded, {} failed", summary.succeeded, summary.failed);
+            }
+            // picked up by a running ingester within a few seconds, wherever it's deployed
+            "pause-ingester" => {
+                stores.ingest_control.set_paused(true).await?;
+                println!("ingestion paused");
+            }
+            "resume-ingester" => {
+                stores.ingest_control.set_paused(false).await?;
+                println!("ingestion resumed");
+            }
+            "export" => {
+                let count = export::export(
+                    &stores.status_store,
+                    &export::Args::parse(env::args().skip(2))?,
+                )
+                .await?;
+                eprintln!("exported {count} statuses");
+            }
+            "import" => {
+                let count = export::import(
+                    &stores.status_store,
+                    &export::Args::parse(env::args().skip(2))?,
+                )
+                .await?;
+                eprintln!("imported {count} statuses");
+            }
+            "seed" => {
+                let count = seed::seed(
+                    &stores.status_store,
+                    &stores.handle_cache,
+                    &app_config.status_options,
+                    &seed::Args::parse(env::args().skip(2))?,
+                )
+                .await?;
+                eprintln!("seeded {count} statuses");
+            }
+            "reconcile" => {
+                let summary = reconcile::Reconciler::new(
+                    &app_config,
+                    Arc::new(oauth::http_client()),
+                    stores.status_store.clone(),
+                    stores.oauth_session_store.clone(),
+                )
+                .run()
+                .await?;
+                println!(
+                    "{} inserted, {} deleted, {} failed",
+                    summary.inserted, summary.deleted, summary.failed
+                );
+            }
+            "smoke" => {
+                smoke::run(
+                    &smoke::SmokeConfig::from_env()?,
+                    Arc::new(oauth::http_client()),
+                    &stores.status_store,
+                    &app_config.status_options[0],
+                )
+                .await?;
+                println!("smoke test passed");
+            }
+            other => anyhow::bail!("unknown command '{other}'"),
+        }
+        return Ok(());
+    }
+
+    let session_store = stores.session_store.clone();
+    let App {
+        state: app_state,
+        router: app,
+    } = AppBuilder::new(app_config).stores(stores).build().await?;
+    let http_client = Arc::clone(&app_state.http_client);
+
+    // backfill historical statuses in the background, if configured
+    if let Some(source) = app_state.config.backfill.clone() {
+        let backfill = Backfill::new(&app_state);
+        tokio::spawn(async move {
+            if let Err(e) = backfill.run(source).await {
+                error!("backfill failed: {e}");
+            }
+        });
+    }
+
+    rollup::spawn_rollup_job(
+        app_state.status_store.clone(),
+        app_state.config.rollup_interval,
+    );
+    if let Some(interval) = app_state.config.reconcile_interval {
+        reconcile::spawn_reconcile_job(
+            reconcile::Reconciler::new(
+                &app_state.config,
+                Arc::clone(&http_client),
+                app_state.status_store.clone(),
+                app_state.oauth_session_store.clone(),
+            ),
+            interval,
+        );
+    }
+    spawn_session_cleanup(
+        session_store,
+        app_state.oauth_state_store.clone(),
+        app_state.config.session_cleanup_interval,
+    );
+
+    // fire up ingester, unless it's deployed on its own
+    #[cfg(feature = "ingester")]
+    if app_state.config.embedded_ingester {
+        ingester::ingester(
+            &app_state.config,
+            app_state.status_store.clone(),
+            app_state.dead_letters.clone(),
+            app_state.ingest_control.clone(),
+            Arc::clone(&app_state.metrics),
+            &app_state.collection_toggles,
+            registered,
+            app_state.ingester_status.clone(),
+        );
+        info!("Ingester started");
+    }
+
+    let addr = app_state.config.bind_addr;
+    let tls_config = app_state.config.tls.clone();
+
+    match tls_config {
+        Some(tls_config) => tls::serve(app, addr, &tls_config).await?,
+        None => {
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            info!("Server bound on {addr}");
+            // with the peer's address, for throttling
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs only the ingester, keeping the status store up to date (and handing commits to the
+/// collections of the `registered` consumers to them) until the process is stopped, so ingestion
+/// can be deployed and restarted independently of the web server.
+#[cfg(feature = "ingester")]
+pub async fn run_ingester(registered: ConsumerRegistry) -> anyhow::Result<()> {
+    use atrium_api::types::Collection;
+
+    use lexicons::xyz::statusphere::{Pin, Reaction, Status};
+
+    init_process();
+
+    let stores = Stores::from_env().await?;
+    let config = AppConfig::from_env()?;
+    // the time for posts to come back through Jetstream isn't measured here: the posts are made
+    // by the web server, so only it knows when
+    let metrics = Arc::new(Metrics::new(metrics::Scope::Ingester)?);
+    // nothing flips these, as the admin dashboard is in the web server
+    let toggles = CollectionToggles::new([Status::NSID, Pin::NSID, Reaction::NSID]);
+    ingester::ingester(
+        &config,
+        stores.status_store,
+        stores.dead_letters,
+        stores.ingest_control,
+        Arc::clone(&metrics),
+        &toggles,
+        registered,
+        IngesterStatus::new(),
+    );
+    info!("Ingester started");
+
+    #[cfg(feature = "metrics")]
+    if let Some(addr) = config.ingester_metrics_addr {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        info!("Metrics served on {addr}");
+        axum::serve(listener, metrics::router(metrics)).await?;
+        return Ok(());
+    }
+
+    // the ingester runs in spawned tasks, restarted by its supervisor when it stops
+    std::future::pending().await
+}
+
+#[cfg(test)]
+mod tests {
+    use minijinja::{Value, context};
+
+    use super::*;
+    use viewer::Viewer;
+
+    // the compiled-in templates, rendered as a handler would render them
+    fn render(name: &str, context: Value) -> String {
+        let template_env =
+            initialize_templates(None, Arc::new(Assets::new(None))).expect("valid templates");
+        template_env
+            .get_template(name)
+            .expect("template exists")
+            .render(context)
+            .expect("template renders")
+    }
+
+    fn status_options() -> Vec<&'static str> {
+        vec!["👍", "🦋", "🥳"]
+    }
+
+    #[test]
+    fn home_logged_out_empty_feed() {
+        insta::assert_snapshot!(render(
+            "home",
+            context! {
+                locale => "en",
+                theme => "light",
+                statuses => Vec::<Value>::new(),
+                feed => "all",
+                appended => false,
+                total_statuses => 0,
+                total_authors => 0,
+                status_options => status_options(),
+            }
+        ));
+    }
+
+    #[test]
+    fn home_logged_out_error() {
+        insta::assert_snapshot!(render(
+            "home",
+            context! {
+                locale => "en",
+                theme => "light",
+                error => "logged_out",
+                statuses => Vec::<Value>::new(),
+                feed => "all",
+                appended => false,
+                total_statuses => 3,
+                total_authors => 2,
+                status_options => status_options(),
+            }
+        ));
+    }
+
+    #[test]
+    fn home_logged_in() {
+        insta::assert_snapshot!(render(
+            "home",
+            context! {
+                locale => "en",
+                theme => "dark",
+                viewer => Viewer {
+                    name: "Alice <3".to_owned(),
+                    handle: "@alice.test".to_owned(),
+                },
+                statuses => Vec::<Value>::new(),
+                feed => "current",
+                appended => false,
+                user_status => "🦋",
+                total_statuses => 1,
+                total_authors => 1,
+                status_options => status_options(),
+                post_token => "0123456789abcdef0123456789abcdef",
+            }
+        ));
+    }
+
+    #[test]
+    fn login_form() {
+        insta::assert_snapshot!(render(
+            "login",
+            context! {
+                locale => "en",
+                theme => "light",
+            }
+        ));
+    }
+
+    #[test]
+    fn login_error() {
+        insta::assert_snapshot!(render(
+            "login",
+            context! {
+                locale => "fr",
+                theme => "light",
+                error => "Invalid DID",
+            }
+        ));
+    }
+
+    #[test]
+    fn error_not_found() {
+        insta::assert_snapshot!(render(
+            "error_404",
+            context! {
+                locale => "en",
+                theme => "light",
+                status_code => 404,
+                request_id => "0b7c5a9e-4c1e-4d3a-9f0e-6b2f1d8a7c34",
+            }
+        ));
+    }
+
+    #[test]
+    fn error_server() {
+        insta::assert_snapshot!(render(
+            "error_5xx",
+            context! {
+                locale => "en",
+                theme => "dark",
+                status_code => 503,
+                error_kind => "storage",
+                error_details => "storage: database is locked",
+            }
+        ));
+    }
+}
@@ -1,65 +1,254 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use atrium_api::{agent::SessionManager, types::string::Handle};
+use atrium_api::{
+    agent::{Agent, SessionManager},
+    types::string::{Did, Handle},
+};
+use atrium_common::store::Store;
 use atrium_oauth::CallbackParams;
 use axum::{
     Form,
     extract::{Query, State},
+    http::{HeaderValue, StatusCode, Uri, header::RETRY_AFTER},
     response::{Html, IntoResponse, Redirect, Response},
 };
 use minijinja::context;
 use serde::Deserialize;
 use tower_sessions::Session;
+use tracing::{info, warn};
 
-use crate::{AppState, ClientSession, error::Error, oauth::OAuthAuthorize, open_template};
+use crate::{
+    AppState, ClientSession,
+    backfill::Backfill,
+    error::Error,
+    i18n::Locale,
+    oauth::{AppSession, OAuthAuthorize},
+    open_template,
+    preferences::{self, Theme},
+    store::StatusFilter,
+    throttle::ClientIp,
+};
 
 fn render_login_form(
     state: Arc<AppState>,
+    locale: Locale,
+    theme: Theme,
     error: Option<&'static str>,
+    retry_after: Option<Duration>,
 ) -> Result<Html<String>, crate::Error> {
     let template = open_template!(state, "login");
 
-    let rendered = template.render(
-        error
-            .map(|e| context! { error => e })
-            .unwrap_or_else(|| context! {}),
-    )?;
+    let rendered = template.render(context! {
+        locale => locale,
+        theme => theme,
+        error => error,
+        // rounded up, so nobody's told to wait 0 minutes
+        retry_minutes => retry_after.map(|retry_after| retry_after.as_secs().div_ceil(60)),
+    })?;
 
     Ok(Html(rendered))
 }
 
-pub async fn login_form(State(state): State<Arc<AppState>>) -> Result<Html<String>, crate::Error> {
-    render_login_form(state, None)
+pub async fn login_form(
+    State(state): State<Arc<AppState>>,
+    locale: Locale,
+    session: Session,
+) -> Result<Html<String>, crate::Error> {
+    render_login_form(
+        state,
+        locale,
+        preferences::theme(&session).await?,
+        None,
+        None,
+    )
 }
 
+// handles are domain names, which can't be longer than this
+const MAX_HANDLE_LEN: usize = 253;
+// DIDs can't be longer than this, and no reasonable PDS URL is
+const MAX_IDENTIFIER_LEN: usize = 2048;
+
 #[derive(Deserialize, Debug)]
 pub struct LoginInput {
+    /// A handle, DID, or PDS URL.
     handle: String,
 }
 
+/// Who to log in as. Handles sometimes fail to resolve, so a DID or the URL of the user's PDS
+/// (whose authorization server then asks which account) can be used instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LoginIdentifier {
+    Handle(Handle),
+    Did(Did),
+    Pds(String),
+}
+
+impl LoginIdentifier {
+    // the error is a message for the login form
+    fn parse(input: &str) -> Result<Self, &'static str> {
+        let input = input.trim();
+        if input.len() > MAX_IDENTIFIER_LEN {
+            return Err("Handle is too long");
+        }
+        if input.starts_with("did:") {
+            let did = Did::new(input.to_owned()).map_err(|_| "Invalid DID")?;
+            // the methods atproto supports
+            return match did.method() {
+                "did:plc" | "did:web" => Ok(Self::Did(did)),
+                _ => Err("Unsupported DID method"),
+            };
+        }
+        if input.starts_with("https://") || input.starts_with("http://") {
+            let uri = input.parse::<Uri>().map_err(|_| "Invalid PDS URL")?;
+            let (Some(scheme), Some(authority)) = (uri.scheme_str(), uri.authority()) else {
+                return Err("Invalid PDS URL");
+            };
+            // plain HTTP is only for PDSes running locally, e.g. the atproto dev-env
+            let local = matches!(uri.host(), Some("localhost" | "127.0.0.1" | "[::1]"));
+            if (scheme == "http" && !local) || !matches!(uri.path(), "" | "/") {
+                return Err("Invalid PDS URL");
+            }
+            return Ok(Self::Pds(format!("{scheme}://{authority}")));
+        }
+        let handle = input.trim_start_matches('@');
+        if handle.len() > MAX_HANDLE_LEN {
+            return Err("Handle is too long");
+        }
+        Handle::new(handle.to_owned()).map(Self::Handle)
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            Self::Handle(handle) => handle.as_str(),
+            Self::Did(did) => did.as_str(),
+            Self::Pds(url) => url,
+        }
+    }
+}
+
 pub async fn accept_login_form(
     State(state): State<Arc<AppState>>,
+    locale: Locale,
+    ClientIp(client_ip): ClientIp,
+    session: Session,
     Form(input): Form<LoginInput>,
 ) -> Result<Response, crate::Error> {
-    // check handle validity
-    if let Err(error) = Handle::new(input.handle.clone()) {
-        return render_login_form(state, Some(error)).map(|form| form.into_response());
+    // every attempt costs a handle resolution and an authorization server round trip
+    if let Err(retry_after) = state.login_throttle.attempt(client_ip) {
+        let form = render_login_form(
+            state,
+            locale,
+            preferences::theme(&session).await?,
+            None,
+            Some(retry_after),
+        )?;
+        let retry_after = HeaderValue::from(retry_after.as_secs().max(1));
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            [(RETRY_AFTER, retry_after)],
+            form,
+        )
+            .into_response());
     }
 
+    // check the identifier's validity before it goes anywhere near the network
+    let identifier = match LoginIdentifier::parse(&input.handle) {
+        Ok(identifier) => identifier,
+        Err(error) => {
+            return render_login_form(
+                state,
+                locale,
+                preferences::theme(&session).await?,
+                Some(error),
+                None,
+            )
+            .map(|form| (StatusCode::UNPROCESSABLE_ENTITY, form).into_response());
+        }
+    };
+
+    // development mode logs straight in as whoever is asked for, so there's no handle to resolve
+    if state.config.dev_fake_auth {
+        let LoginIdentifier::Did(did) = identifier else {
+            return render_login_form(
+                state,
+                locale,
+                preferences::theme(&session).await?,
+                Some("Log in with a DID in development mode"),
+                None,
+            )
+            .map(|form| (StatusCode::UNPROCESSABLE_ENTITY, form).into_response());
+        };
+        warn!(
+            "DEV_FAKE_AUTH: logging in as {} unauthenticated",
+            did.as_str()
+        );
+        session.insert("sid", ClientSession::new(did)).await?;
+        return Ok(Redirect::to("/").into_response());
+    }
+
+    // the OAuth client resolves handles and DIDs to the PDS's authorization server itself, and
+    // treats URLs as the PDS to ask for its authorization server
     let redirect_url = state
         .oauth_client
-        .oauth_authorize(input.handle.as_str())
+        .oauth_authorize(identifier.as_str())
         .await?;
 
     Ok(Redirect::to(&redirect_url).into_response())
 }
 
+/// What the authorization server redirects back with: a code on success, or an error (RFC 6749
+/// section 4.1.2.1), e.g. if the user denied authorization.
+#[derive(Debug, Deserialize)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    iss: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
 pub async fn oauth_callback(
     State(state): State<Arc<AppState>>,
-    Query(params): Query<CallbackParams>,
+    Query(query): Query<CallbackQuery>,
+    locale: Locale,
     session: Session,
 ) -> Result<Response, Error> {
-    let (oauth_session, _oauth_state) = state.oauth_client.callback(params).await.unwrap();
+    if let Some(error) = query.error {
+        // the login is over, so its pending state won't be needed
+        if let Some(key) = &query.state {
+            if let Err(e) = state.oauth_state_store.del(key).await {
+                warn!("failed to delete OAuth state after a failed login: {e}");
+            }
+        }
+        info!(
+            "login failed at the authorization server: {error} ({})",
+            query.error_description.as_deref().unwrap_or_default()
+        );
+        let template = open_template!(state, "login_cancelled");
+        let rendered = template.render(context! {
+            locale => locale,
+            theme => preferences::theme(&session).await?,
+            denied => error == "access_denied",
+            description => query.error_description,
+        })?;
+        return Ok(Html(rendered).into_response());
+    }
+    let Some(code) = query.code else {
+        return Err(Error::InvalidQuery(
+            "OAuth callback without a code or an error".to_owned(),
+        ));
+    };
+
+    let params = CallbackParams {
+        code,
+        state: query.state,
+        iss: query.iss,
+    };
+    let (oauth_session, _oauth_state) = state
+        .oauth_client
+        .callback(params)
+        .await
+        .map_err(Error::Callback)?;
     let did = oauth_session.did().await;
     let Some(did) = did else {
         return Err(Error::MissingDid);
@@ -69,9 +258,29 @@ pub async fn oauth_callback(
     if client_session.is_some() {
         return Err(Error::SessionAlreadyExists);
     }
-    session
-        .insert("sid", ClientSession { did: did.clone() })
+    // remember who they are, so pages can show it without asking their PDS every time
+    let mut client_session = ClientSession::new(did.clone());
+    client_session
+        .refresh(&state, &Agent::new(AppSession::OAuth(oauth_session)))
         .await?;
+    session.insert("sid", client_session).await?;
+
+    // first login: import the statuses already in the user's repo in the background, so their
+    // history shows up without waiting for new posts to come through the ingester
+    let known_user = state
+        .status_store
+        .fetch_one(&StatusFilter::new().author(did.clone()).visible_to(&did, []))
+        .await?
+        .is_some();
+    if !known_user {
+        let backfill = Backfill::new(&state);
+        tokio::spawn(async move {
+            match backfill.backfill_repo(&did).await {
+                Ok(count) => info!("Imported {count} statuses for {}", did.as_str()),
+                Err(e) => warn!("failed to import statuses for {}: {e}", did.as_str()),
+            }
+        });
+    }
 
     Ok(Redirect::to("/").into_response())
 }
@@ -0,0 +1,96 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use atrium_api::types::string::Did;
+
+use crate::store::Status;
+
+// most users whose looked-up statuses are kept; past it, the longest-cached ones make way
+const MAX_CACHED_LOOKUPS: usize = 1024;
+
+#[derive(Debug)]
+struct CachedLookup {
+    statuses: Vec<Status>,
+    fetched_at: Instant,
+}
+
+/// Statuses of users we've never indexed a status from, as last fetched from their PDS when their
+/// history was viewed, so viewing it again doesn't go back to the PDS. Users without any are kept
+/// too. Kept for `ttl`.
+#[derive(Debug)]
+pub struct LookupCache {
+    ttl: Duration,
+    lookups: Mutex<HashMap<Did, CachedLookup>>,
+}
+
+impl LookupCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            lookups: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The statuses last fetched for `did`, unless they're unknown or stale.
+    pub fn get(&self, did: &Did) -> Option<Vec<Status>> {
+        self.lookups
+            .lock()
+            .expect("poisoned lock")
+            .get(did)
+            .filter(|cached| cached.fetched_at.elapsed() <= self.ttl)
+            .map(|cached| cached.statuses.clone())
+    }
+
+    pub fn insert(&self, did: Did, statuses: Vec<Status>) {
+        let now = Instant::now();
+        let mut lookups = self.lookups.lock().expect("poisoned lock");
+        if lookups.len() >= MAX_CACHED_LOOKUPS && !lookups.contains_key(&did) {
+            lookups.retain(|_, cached| now.duration_since(cached.fetched_at) <= self.ttl);
+            if lookups.len() >= MAX_CACHED_LOOKUPS {
+                let oldest = lookups
+                    .iter()
+                    .min_by_key(|(_, cached)| cached.fetched_at)
+                    .map(|(did, _)| did.clone());
+                if let Some(did) = oldest {
+                    lookups.remove(&did);
+                }
+            }
+        }
+        lookups.insert(
+            did,
+            CachedLookup {
+                statuses,
+                fetched_at: now,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::did;
+
+    const ALICE: &str = "did:plc:alice0000000000000000000";
+
+    #[test]
+    fn users_without_statuses_are_remembered_until_stale() {
+        let cache = LookupCache::new(Duration::from_secs(60));
+        assert!(cache.get(&did(ALICE)).is_none());
+
+        cache.insert(did(ALICE), vec![]);
+        assert!(
+            cache
+                .get(&did(ALICE))
+                .is_some_and(|statuses| statuses.is_empty())
+        );
+
+        let stale = LookupCache::new(Duration::ZERO);
+        stale.insert(did(ALICE), vec![]);
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(stale.get(&did(ALICE)).is_none());
+    }
+}
@@ -0,0 +1,248 @@
+//! Latencies of status posts and counts of re-delivered Jetstream events, served to Prometheus at
+//! `/metrics` with the `metrics` feature. Without it, [`Metrics`] records nothing.
+//!
+//! Each process only registers the metrics it can record (see [`Scope`]): the time for a post to
+//! come back through Jetstream is matched up in memory, so it's only measured with the ingester
+//! embedded in the web server.
+
+#[cfg(not(feature = "metrics"))]
+use std::convert::Infallible;
+use std::time::Instant;
+#[cfg(feature = "metrics")]
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+#[cfg(feature = "metrics")]
+use axum::{
+    Router,
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+#[cfg(feature = "metrics")]
+use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, Registry, TextEncoder};
+
+use crate::config::AppConfig;
+#[cfg(feature = "metrics")]
+use crate::{AppState, error::Error};
+
+// posts we haven't seen come back through Jetstream after this long are assumed lost and forgotten
+#[cfg(feature = "metrics")]
+const PENDING_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+// latency buckets in seconds; upstream round trips can be slow so these extend well past the
+// prometheus defaults
+#[cfg(feature = "metrics")]
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+/// Which parts of the app run in a process, and so which metrics it can record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(feature = "ingester"), allow(dead_code))]
+pub enum Scope {
+    /// The web server with the ingester embedded: everything.
+    All,
+    /// The web server alone: latencies of status posts to the PDS.
+    WebServer,
+    /// The `ingester` binary: re-delivered events.
+    Ingester,
+}
+
+impl Scope {
+    /// The web server's scope, depending on whether it runs the ingester itself.
+    #[cfg_attr(not(feature = "ingester"), allow(unused_variables))]
+    pub fn web_server(config: &AppConfig) -> Self {
+        #[cfg(feature = "ingester")]
+        if config.embedded_ingester {
+            return Self::All;
+        }
+        Self::WebServer
+    }
+
+    #[cfg(feature = "metrics")]
+    fn posts(self) -> bool {
+        self != Self::Ingester
+    }
+
+    #[cfg(feature = "metrics")]
+    fn ingests(self) -> bool {
+        self != Self::WebServer
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub struct Metrics {
+    registry: Registry,
+    /// Time from status form submission to PDS acknowledgment of the record write.
+    post_pds_latency: Option<Histogram>,
+    /// Time from PDS acknowledgment to receipt of the same record from Jetstream.
+    post_jetstream_latency: Option<Histogram>,
+    /// Jetstream events skipped as re-deliveries of events already ingested.
+    ingest_duplicates: Option<IntCounter>,
+    // records written by this instance awaiting their Jetstream event, keyed by URI
+    pending: Mutex<HashMap<String, Instant>>,
+}
+
+#[cfg(feature = "metrics")]
+impl Metrics {
+    /// The metrics a process of `scope` can record, registered for serving.
+    pub fn new(scope: Scope) -> Result<Self, prometheus::Error> {
+        let registry = Registry::new();
+        let post_pds_latency = scope
+            .posts()
+            .then(|| {
+                latency_histogram(
+                    &registry,
+                    "statusphere_post_pds_latency_seconds",
+                    "Time from status submission to PDS acknowledgment",
+                )
+            })
+            .transpose()?;
+        let post_jetstream_latency = (scope == Scope::All)
+            .then(|| {
+                latency_histogram(
+                    &registry,
+                    "statusphere_post_jetstream_latency_seconds",
+                    "Time from PDS acknowledgment to receipt of the record from Jetstream",
+                )
+            })
+            .transpose()?;
+        let ingest_duplicates = scope
+            .ingests()
+            .then(|| {
+                let counter = IntCounter::new(
+                    "statusphere_ingest_duplicates_total",
+                    "Jetstream events skipped as re-deliveries of events already ingested",
+                )?;
+                registry.register(Box::new(counter.clone()))?;
+                Ok::<_, prometheus::Error>(counter)
+            })
+            .transpose()?;
+
+        Ok(Self {
+            registry,
+            post_pds_latency,
+            post_jetstream_latency,
+            ingest_duplicates,
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Records a successful PDS write of the record at `uri`, submitted at `submitted_at`, and
+    /// starts waiting for the record to show up on Jetstream.
+    pub fn record_pds_write(&self, uri: impl Into<String>, submitted_at: Instant) {
+        let now = Instant::now();
+        if let Some(post_pds_latency) = &self.post_pds_latency {
+            post_pds_latency.observe(now.duration_since(submitted_at).as_secs_f64());
+        }
+
+        // with the ingester elsewhere, the receipt will never be seen here
+        if self.post_jetstream_latency.is_none() {
+            return;
+        }
+        let mut pending = self.pending.lock().expect("poisoned lock");
+        pending.retain(|_, written_at| now.duration_since(*written_at) < PENDING_TIMEOUT);
+        pending.insert(uri.into(), now);
+    }
+
+    /// Records receipt of the record at `uri` from Jetstream. Records that weren't written by
+    /// this process are ignored.
+    #[cfg_attr(not(feature = "ingester"), allow(dead_code))]
+    pub fn record_jetstream_receipt(&self, uri: &str) {
+        let Some(post_jetstream_latency) = &self.post_jetstream_latency else {
+            return;
+        };
+        let written_at = self.pending.lock().expect("poisoned lock").remove(uri);
+        if let Some(written_at) = written_at {
+            post_jetstream_latency.observe(written_at.elapsed().as_secs_f64());
+        }
+    }
+
+    /// Records a Jetstream event skipped as a re-delivery.
+    #[cfg_attr(not(feature = "ingester"), allow(dead_code))]
+    pub fn record_ingest_duplicate(&self) {
+        if let Some(ingest_duplicates) = &self.ingest_duplicates {
+            ingest_duplicates.inc();
+        }
+    }
+
+    pub fn encode(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn latency_histogram(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+) -> Result<Histogram, prometheus::Error> {
+    let histogram =
+        Histogram::with_opts(HistogramOpts::new(name, help).buckets(LATENCY_BUCKETS.to_vec()))?;
+    registry.register(Box::new(histogram.clone()))?;
+    Ok(histogram)
+}
+
+// the metrics themselves are for Prometheus to read, not for debug output
+#[cfg(feature = "metrics")]
+impl std::fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub async fn metrics(State(state): State<Arc<AppState>>) -> Result<Response, Error> {
+    respond(&state.metrics)
+}
+
+/// Serves only `/metrics`, for the `ingester` binary, which has no web server of its own.
+#[cfg(feature = "metrics")]
+pub fn router(metrics: Arc<Metrics>) -> Router {
+    Router::new()
+        .route(
+            "/metrics",
+            get(|State(metrics): State<Arc<Metrics>>| async move { respond(&metrics) }),
+        )
+        .with_state(metrics)
+}
+
+#[cfg(feature = "metrics")]
+fn respond(metrics: &Metrics) -> Result<Response, Error> {
+    let encoded = metrics.encode()?;
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            TextEncoder::new().format_type().to_owned(),
+        )],
+        encoded,
+    )
+        .into_response())
+}
+
+/// Stand-in for the Prometheus metrics when the `metrics` feature is off.
+#[cfg(not(feature = "metrics"))]
+#[derive(Debug)]
+pub struct Metrics;
+
+#[cfg(not(feature = "metrics"))]
+impl Metrics {
+    pub fn new(_scope: Scope) -> Result<Self, Infallible> {
+        Ok(Self)
+    }
+
+    pub fn record_pds_write(&self, _uri: impl Into<String>, _submitted_at: Instant) {}
+
+    #[cfg_attr(not(feature = "ingester"), allow(dead_code))]
+    pub fn record_jetstream_receipt(&self, _uri: &str) {}
+
+    #[cfg_attr(not(feature = "ingester"), allow(dead_code))]
+    pub fn record_ingest_duplicate(&self) {}
+}
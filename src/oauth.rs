@@ -1,8 +1,15 @@
-use std::sync::Arc;
+use std::{fmt::Debug, sync::Arc};
 
-use atrium_api::{agent::Agent, types::string::Did};
+use atrium_api::{
+    agent::{Agent, SessionManager},
+    types::string::Did,
+    xrpc::{
+        self, HttpClient, OutputDataOrBytes, XrpcClient, XrpcRequest,
+        http::{Request, Response},
+    },
+};
 use atrium_identity::{
-    did::{CommonDidResolver, CommonDidResolverConfig, DEFAULT_PLC_DIRECTORY_URL},
+    did::{CommonDidResolver, CommonDidResolverConfig},
     handle::{AtprotoHandleResolver, AtprotoHandleResolverConfig, DnsTxtResolver},
 };
 use atrium_oauth::{
@@ -10,12 +17,15 @@ use atrium_oauth::{
     OAuthClientConfig, OAuthResolverConfig, Scope,
 };
 use hickory_resolver::TokioResolver;
+use serde::{Serialize, de::DeserializeOwned};
 use tower_sessions::Session;
 use tracing::info;
 
 use crate::{
     AppState, ClientSession, Error,
+    dev_auth::FakeSession,
     store::{OAuthSessionStore, OAuthStateStore},
+    upstream::{pds_circuit, with_timeout},
 };
 
 pub struct HickoryDnsTxtResolver {
@@ -47,33 +57,47 @@ impl DnsTxtResolver for HickoryDnsTxtResolver {
 
 pub type DidResolver = CommonDidResolver<DefaultHttpClient>;
 
+pub type HandleDidResolver = AtprotoHandleResolver<HickoryDnsTxtResolver, DefaultHttpClient>;
+
 pub type Config = OAuthClientConfig<
     OAuthStateStore,
     OAuthSessionStore,
     AtprotoLocalhostClientMetadata,
     CommonDidResolver<DefaultHttpClient>,
-    AtprotoHandleResolver<HickoryDnsTxtResolver, DefaultHttpClient>,
+    HandleDidResolver,
 >;
 
 pub fn http_client() -> DefaultHttpClient {
     DefaultHttpClient::default()
 }
 
-pub fn did_resolver(http_client: Arc<DefaultHttpClient>) -> DidResolver {
+pub fn did_resolver(http_client: Arc<DefaultHttpClient>, plc_directory_url: &str) -> DidResolver {
     CommonDidResolver::new(CommonDidResolverConfig {
-        plc_directory_url: DEFAULT_PLC_DIRECTORY_URL.to_string(),
+        plc_directory_url: plc_directory_url.to_owned(),
         http_client: http_client,
     })
 }
 
+pub fn handle_resolver(http_client: Arc<DefaultHttpClient>) -> Result<HandleDidResolver, Error> {
+    Ok(AtprotoHandleResolver::new(AtprotoHandleResolverConfig {
+        dns_txt_resolver: HickoryDnsTxtResolver::new()?,
+        http_client,
+    }))
+}
+
+/// OAuth client configuration for a server listening on `port`, resolving `did:plc` DIDs with the
+/// PLC directory at `plc_directory_url`.
 pub fn config(
     http_client: Arc<DefaultHttpClient>,
     oauth_session_store: OAuthSessionStore,
     oauth_state_store: OAuthStateStore,
+    port: u16,
+    plc_directory_url: &str,
 ) -> Result<Config, Error> {
     let config = OAuthClientConfig {
         client_metadata: AtprotoLocalhostClientMetadata {
-            redirect_uris: Some(vec![String::from("http://127.0.0.1:8081/oauth/callback")]),
+            // localhost clients must redirect to a loopback address, but the port can be any
+            redirect_uris: Some(vec![format!("http://127.0.0.1:{port}/oauth/callback")]),
             scopes: Some(vec![
                 Scope::Known(KnownScope::Atproto),
                 Scope::Known(KnownScope::TransitionGeneric),
@@ -81,11 +105,8 @@ pub fn config(
         },
         keys: None,
         resolver: OAuthResolverConfig {
-            did_resolver: did_resolver(Arc::clone(&http_client)),
-            handle_resolver: AtprotoHandleResolver::new(AtprotoHandleResolverConfig {
-                dns_txt_resolver: HickoryDnsTxtResolver::new()?,
-                http_client: Arc::clone(&http_client),
-            }),
+            did_resolver: did_resolver(Arc::clone(&http_client), plc_directory_url),
+            handle_resolver: handle_resolver(Arc::clone(&http_client))?,
             authorization_server_metadata: Default::default(),
             protected_resource_metadata: Default::default(),
         },
@@ -101,30 +122,37 @@ pub type Client = OAuthClient<
     OAuthStateStore,
     OAuthSessionStore,
     CommonDidResolver<DefaultHttpClient>,
-    AtprotoHandleResolver<HickoryDnsTxtResolver, DefaultHttpClient>,
+    HandleDidResolver,
 >;
 
 pub fn client(
     http_client: Arc<DefaultHttpClient>,
     oauth_session_store: OAuthSessionStore,
     oauth_state_store: OAuthStateStore,
+    port: u16,
+    plc_directory_url: &str,
 ) -> Result<Client, Error> {
-    Ok(
-        OAuthClient::new(config(http_client, oauth_session_store, oauth_state_store)?)
-            .map_err(Error::OAuthClientCreation)?,
-    )
+    Ok(OAuthClient::new(config(
+        http_client,
+        oauth_session_store,
+        oauth_state_store,
+        port,
+        plc_directory_url,
+    )?)
+    .map_err(Error::OAuthClientCreation)?)
 }
 
 pub trait OAuthAuthorize {
-    async fn oauth_authorize(&self, handle: &str) -> Result<String, Error>;
+    async fn oauth_authorize(&self, identifier: &str) -> Result<String, Error>;
 }
 
 impl OAuthAuthorize for Client {
-    /// Initiates authorization of a handle. Returns the URL to visit for OAuth authorization.
-    async fn oauth_authorize(&self, handle: &str) -> Result<String, Error> {
+    /// Initiates authorization of a handle, DID, or (for users to pick an account there) PDS URL.
+    /// Returns the URL to visit for OAuth authorization.
+    async fn oauth_authorize(&self, identifier: &str) -> Result<String, Error> {
         let url = self
             .authorize(
-                handle,
+                identifier,
                 AuthorizeOptions {
                     scopes: vec![
                         Scope::Known(KnownScope::Atproto),
@@ -142,32 +170,108 @@ impl OAuthAuthorize for Client {
 pub type OAuthSession = atrium_oauth::OAuthSession<
     DefaultHttpClient,
     CommonDidResolver<DefaultHttpClient>,
-    AtprotoHandleResolver<HickoryDnsTxtResolver, DefaultHttpClient>,
+    HandleDidResolver,
     OAuthSessionStore,
 >;
 
-pub type ATProtoAgent = Agent<OAuthSession>;
+/// A logged-in user's connection to their PDS: their OAuth session, or in development mode
+/// (`DEV_FAKE_AUTH`), a stand-in that never leaves the process.
+pub enum AppSession {
+    OAuth(OAuthSession),
+    Fake(FakeSession),
+}
+
+impl HttpClient for AppSession {
+    async fn send_http(
+        &self,
+        request: Request<Vec<u8>>,
+    ) -> core::result::Result<Response<Vec<u8>>, Box<dyn std::error::Error + Send + Sync + 'static>>
+    {
+        match self {
+            Self::OAuth(session) => session.send_http(request).await,
+            Self::Fake(session) => session.send_http(request).await,
+        }
+    }
+}
+
+impl XrpcClient for AppSession {
+    fn base_uri(&self) -> String {
+        match self {
+            Self::OAuth(session) => session.base_uri(),
+            Self::Fake(session) => session.base_uri(),
+        }
+    }
+
+    // delegated whole, as OAuth sessions add DPoP proofs and refresh tokens as part of sending
+    async fn send_xrpc<P, I, O, E>(
+        &self,
+        request: &XrpcRequest<P, I>,
+    ) -> core::result::Result<OutputDataOrBytes<O>, xrpc::Error<E>>
+    where
+        P: Serialize + Send + Sync,
+        I: Serialize + Send + Sync,
+        O: DeserializeOwned + Send + Sync,
+        E: DeserializeOwned + Send + Sync + Debug,
+    {
+        match self {
+            Self::OAuth(session) => session.send_xrpc(request).await,
+            Self::Fake(session) => session.send_xrpc(request).await,
+        }
+    }
+}
+
+impl SessionManager for AppSession {
+    async fn did(&self) -> Option<Did> {
+        match self {
+            Self::OAuth(session) => session.did().await,
+            Self::Fake(session) => Some(session.did().clone()),
+        }
+    }
+}
+
+pub type ATProtoAgent = Agent<AppSession>;
 
 pub async fn session_agent(
     state: &AppState,
     session: &Session,
 ) -> Result<Option<ATProtoAgent>, Error> {
     let client_session: Option<ClientSession> = session.get("sid").await?;
+    if state.config.dev_fake_auth {
+        return Ok(client_session.map(|cs| Agent::new(AppSession::Fake(FakeSession::new(cs.did)))));
+    }
     let oauth_session = match client_session {
-        Some(cs) => match state.oauth_client.restore(&cs.did).await {
-            Ok(session) => {
-                let agent = Agent::new(session);
-                info!("Restored session agent for user: {:?}", agent.did().await);
-                Some(agent)
+        // restoring resolves the user's DID and may refresh their tokens with their PDS
+        Some(cs) => {
+            let restored = state
+                .circuit_breaker
+                .call(&pds_circuit(&cs.did), async {
+                    match with_timeout(
+                        state.config.upstream_timeout,
+                        "session restore",
+                        state.oauth_client.restore(&cs.did),
+                    )
+                    .await?
+                    {
+                        Ok(session) => Ok(Some(session)),
+                        // ideally we'd want to inspect the SessionRegistry error to make sure
+                        // it's a 'not found' error, but that type isn't visible
+                        Err(e @ atrium_oauth::Error::SessionRegistry(_)) => {
+                            info!("No oauth session found for user {}: {e}", cs.did.as_str());
+                            Ok(None)
+                        }
+                        Err(e) => Err(Error::Restore(e)),
+                    }
+                })
+                .await?;
+            match restored {
+                Some(session) => {
+                    let agent = Agent::new(AppSession::OAuth(session));
+                    info!("Restored session agent for user: {:?}", agent.did().await);
+                    Some(agent)
+                }
+                None => None,
             }
-            // ideally we'd want to inspect the SessionRegistry error to make sure it's a
-            // 'not found' error, but that type isn't visible
-            Err(e @ atrium_oauth::Error::SessionRegistry(_)) => {
-                info!("No oauth session found for user {}: {e}", cs.did.as_str());
-                None
-            }
-            Err(e) => return Err(Error::Restore(e)),
-        },
+        }
         None => {
             info!("No user session found");
             None
@@ -179,3 +283,133 @@ pub async fn session_agent(
 pub async fn agent_did(agent: &ATProtoAgent) -> Did {
     agent.did().await.expect("agent should always have Did")
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{method, path},
+    };
+
+    use super::*;
+    use crate::{
+        store::Db,
+        test_support::{did_document, memory_pool, mock_did_document},
+    };
+
+    const ALICE: &str = "did:plc:alice0000000000000000000";
+
+    // a client resolving DIDs with `plc`
+    async fn client(plc: &MockServer) -> Client {
+        let db = Db::from(memory_pool().await);
+        crate::store::migrate(&db).await.expect("database migrates");
+        let session_store = OAuthSessionStore::new(db.clone());
+        let state_store = OAuthStateStore::new(db);
+        super::client(
+            Arc::new(http_client()),
+            session_store,
+            state_store,
+            8080,
+            &plc.uri(),
+        )
+        .expect("OAuth client builds")
+    }
+
+    // has `server` act as Alice's PDS and its own authorization server
+    async fn mock_pds(server: &MockServer) {
+        let issuer = server.uri();
+        Mock::given(method("GET"))
+            .and(path("/.well-known/oauth-protected-resource"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "resource": issuer,
+                "authorization_servers": [issuer],
+                "scopes_supported": [],
+                "bearer_methods_supported": ["header"],
+            })))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/.well-known/oauth-authorization-server"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "issuer": issuer,
+                "authorization_endpoint": format!("{issuer}/oauth/authorize"),
+                "token_endpoint": format!("{issuer}/oauth/token"),
+                "pushed_authorization_request_endpoint": format!("{issuer}/oauth/par"),
+                "require_pushed_authorization_requests": true,
+                "response_types_supported": ["code"],
+                "grant_types_supported": ["authorization_code", "refresh_token"],
+                "code_challenge_methods_supported": ["S256"],
+                "token_endpoint_auth_methods_supported": ["none", "private_key_jwt"],
+                "token_endpoint_auth_signing_alg_values_supported": ["ES256"],
+                "dpop_signing_alg_values_supported": ["ES256"],
+                "scopes_supported": ["atproto", "transition:generic"],
+                "authorization_response_iss_parameter_supported": true,
+                "client_id_metadata_document_supported": true,
+            })))
+            .mount(server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/oauth/par"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+                "request_uri": "urn:ietf:params:oauth:request_uri:req-0123456789",
+                "expires_in": 299,
+            })))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn authorization_server_is_discovered_from_the_pds() {
+        let server = MockServer::start().await;
+        mock_did_document(
+            &server,
+            ALICE,
+            did_document(ALICE, Some("alice.test"), &server.uri()),
+        )
+        .await;
+        mock_pds(&server).await;
+
+        let url = client(&server)
+            .await
+            .oauth_authorize(ALICE)
+            .await
+            .expect("authorization starts");
+        assert!(url.starts_with(&format!("{}/oauth/authorize?", server.uri())));
+        // the pushed request is referred to rather than repeated in the URL
+        assert!(url.contains("request_uri="));
+        assert!(url.contains("req-0123456789"));
+    }
+
+    #[tokio::test]
+    async fn pds_without_oauth_metadata_fails() {
+        let server = MockServer::start().await;
+        mock_did_document(
+            &server,
+            ALICE,
+            did_document(ALICE, Some("alice.test"), &server.uri()),
+        )
+        .await;
+        Mock::given(method("GET"))
+            .and(path("/.well-known/oauth-protected-resource"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let result = client(&server).await.oauth_authorize(ALICE).await;
+        assert!(matches!(result, Err(Error::Authorize(_))));
+    }
+
+    #[tokio::test]
+    async fn unknown_did_fails() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/{ALICE}")))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let result = client(&server).await.oauth_authorize(ALICE).await;
+        assert!(matches!(result, Err(Error::Authorize(_))));
+    }
+}
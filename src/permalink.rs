@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use atrium_api::types::{
+    Collection,
+    string::{Cid, Did, RecordKey},
+};
+use axum::{
+    extract::{Path, State},
+    http::header::{CACHE_CONTROL, CONTENT_TYPE},
+    response::{Html, IntoResponse, Response},
+};
+use minijinja::context;
+use tower_sessions::Session;
+
+use crate::{
+    AppState,
+    auth::OptionalAuth,
+    avatar::avatar_url,
+    backfill::{fetch_blob, fetch_repo_status},
+    error::Error,
+    home::visibility_filter,
+    lexicons::xyz::statusphere::Status,
+    oauth::agent_did,
+    open_template, preferences,
+    store::Visibility,
+    upstream::with_timeout,
+    viewer,
+    views::{bsky_post_url, display_dates, image_url},
+};
+
+/// Canonical page for a single status. Statuses we haven't indexed (e.g. posted while the
+/// ingester was down) are fetched from the author's PDS instead.
+pub async fn show_status(
+    State(state): State<Arc<AppState>>,
+    Path((did, rkey)): Path<(String, String)>,
+    OptionalAuth(maybe_agent): OptionalAuth,
+    session: Session,
+) -> Result<Response, Error> {
+    let did = Did::new(did).map_err(Error::InvalidDid)?;
+    let not_found = || Error::StatusNotFound(format!("{}/{rkey}", did.as_str()));
+    let record_key = RecordKey::new(rkey.clone()).map_err(|_| not_found())?;
+
+    let timezone = preferences::timezone(&session).await?;
+    let user_did = match &maybe_agent {
+        Some(agent) => Some(agent_did(agent).await),
+        None => None,
+    };
+    let filter =
+        visibility_filter(state.as_ref(), maybe_agent.as_deref(), user_did.as_ref()).await?;
+
+    // followers-only statuses share the permalink scheme, under their `private:` URI
+    let mut status = None;
+    for uri in [
+        format!("at://{}/{}/{rkey}", did.as_str(), Status::NSID),
+        format!("private:{}/{rkey}", did.as_str()),
+    ] {
+        status = state
+            .status_store
+            .fetch_by_uri(&uri)
+            .await?
+            .filter(|status| filter.is_visible(status));
+        if status.is_some() {
+            break;
+        }
+    }
+    if status.is_none() && state.config.did_filter.allows(did.as_str()) {
+        status = with_timeout(
+            state.config.upstream_timeout,
+            "PDS status fetch",
+            fetch_repo_status(
+                Arc::clone(&state.http_client),
+                &state.did_resolver,
+                &did,
+                record_key,
+                state.config.max_clock_skew,
+            ),
+        )
+        .await??
+        .filter(|status| state.config.is_allowed_status(&status.status));
+    }
+    let Some(status) = status else {
+        return Err(not_found());
+    };
+
+    let crosspost_url = state
+        .status_store
+        .fetch_crosspost(&status.uri)
+        .await?
+        .and_then(|post_uri| bsky_post_url(&post_uri));
+
+    let template = open_template!(state, "status");
+    let rendered = template.render(context! {
+        theme => preferences::theme(&session).await?,
+        viewer => viewer::current(state.as_ref(), &session, maybe_agent.as_deref()).await?,
+        permalink => format!("/status/{}/{rkey}", did.as_str()),
+        handle => state.handle_resolver.lookup(&did).await?,
+        profile_path => did.as_str(),
+        avatar => avatar_url(&did),
+        status => status.status,
+        content_warning => status.content_warning,
+        followers_only => status.visibility == Visibility::Followers,
+        crosspost_url => crosspost_url,
+        image_url => image_url(&status.uri, status.image.as_ref()),
+        record_uri => status.uri.starts_with("at://").then(|| status.uri.clone()),
+        dates => display_dates(
+            state.config.date_policy,
+            state.config.backdate_threshold,
+            &status.created_at,
+            &status.indexed_at,
+            timezone,
+        ),
+    })?;
+
+    Ok(Html(rendered).into_response())
+}
+
+/// The image attached to a status, fetched from its author's PDS. Only images of statuses we've
+/// indexed are served, so this can't be used to fetch arbitrary blobs.
+pub async fn status_image(
+    State(state): State<Arc<AppState>>,
+    Path((did, rkey)): Path<(String, String)>,
+) -> Result<Response, Error> {
+    let did = Did::new(did).map_err(Error::InvalidDid)?;
+    // only public statuses have images, as followers-only ones have no record
+    let uri = format!("at://{}/{}/{rkey}", did.as_str(), Status::NSID);
+    let not_found = || Error::StatusNotFound(uri.clone());
+
+    let Some(image) = state
+        .status_store
+        .fetch_by_uri(&uri)
+        .await?
+        .and_then(|status| status.image)
+    else {
+        return Err(not_found());
+    };
+    let cid = image.cid.parse::<Cid>().map_err(|_| not_found())?;
+    let blob = with_timeout(
+        state.config.upstream_timeout,
+        "PDS blob fetch",
+        fetch_blob(
+            Arc::clone(&state.http_client),
+            &state.did_resolver,
+            &did,
+            cid,
+        ),
+    )
+    .await??
+    .ok_or_else(not_found)?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, image.mime_type),
+            // the image only changes if the status is edited
+            (CACHE_CONTROL, "public, max-age=3600".to_owned()),
+        ],
+        blob,
+    )
+        .into_response())
+}
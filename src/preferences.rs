@@ -0,0 +1,78 @@
+use std::str::FromStr;
+
+use axum::{
+    Form,
+    http::{HeaderMap, StatusCode, Uri, header::REFERER},
+    response::{IntoResponse, Redirect, Response},
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tower_sessions::Session;
+
+use crate::error::Error;
+
+// session key of the viewer's IANA timezone name
+const TIMEZONE_KEY: &str = "timezone";
+// session key of the viewer's color theme
+const THEME_KEY: &str = "theme";
+
+/// The viewer's timezone, if their browser has reported it.
+pub async fn timezone(session: &Session) -> Result<Option<Tz>, Error> {
+    let timezone: Option<String> = session.get(TIMEZONE_KEY).await?;
+    // a zone removed from the tz database shouldn't break the page, just fall back
+    Ok(timezone.and_then(|timezone| Tz::from_str(&timezone).ok()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimezoneInput {
+    timezone: String,
+}
+
+/// Stores the viewer's timezone, as detected by the layout's script.
+pub async fn set_timezone(
+    session: Session,
+    Form(input): Form<TimezoneInput>,
+) -> Result<Response, Error> {
+    let timezone = Tz::from_str(&input.timezone)
+        .map_err(|_| Error::InvalidQuery(format!("unknown timezone '{}'", input.timezone)))?;
+    session.insert(TIMEZONE_KEY, timezone.name()).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Color theme of the site, selecting which stylesheets the layout loads.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Light,
+    Dark,
+}
+
+/// The viewer's chosen theme, light unless they've picked otherwise.
+pub async fn theme(session: &Session) -> Result<Theme, Error> {
+    Ok(session.get(THEME_KEY).await?.unwrap_or_default())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ThemeInput {
+    theme: Theme,
+}
+
+/// Target of the layout's theme toggle: stores the theme and sends the viewer back to the page
+/// they were on.
+pub async fn set_theme(
+    session: Session,
+    headers: HeaderMap,
+    Form(input): Form<ThemeInput>,
+) -> Result<Redirect, Error> {
+    session.insert(THEME_KEY, input.theme).await?;
+    // only keep the referer's path, so this can't be used to redirect off-site
+    let back = headers
+        .get(REFERER)
+        .and_then(|referer| referer.to_str().ok())
+        .and_then(|referer| referer.parse::<Uri>().ok())
+        .and_then(|uri| uri.path_and_query().map(|path| path.as_str().to_owned()))
+        .filter(|path| path.starts_with('/') && !path.starts_with("//"))
+        .unwrap_or_else(|| "/".to_owned());
+    Ok(Redirect::to(&back))
+}
@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use atrium_api::types::string::Did;
+use axum::{
+    extract::{Path, Query, State},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use minijinja::context;
+use serde::{Deserialize, Serialize};
+use tower_sessions::Session;
+
+use crate::{
+    AppState,
+    auth::OptionalAuth,
+    avatar::avatar_url,
+    backfill::fetch_recent_repo_statuses,
+    error::Error,
+    home::visibility_filter,
+    oauth::agent_did,
+    open_template, preferences,
+    store::{Status, Visibility},
+    upstream::with_timeout,
+    viewer,
+    views::{DisplayDates, display_dates, permalink},
+};
+
+// statuses per page of a user's history
+const HISTORY_PAGE_SIZE: usize = 25;
+
+// a page of a user's most recent statuses straight from their repo, limited to what the ingester
+// would accept
+async fn fetch_pds_statuses(state: &AppState, did: &Did) -> Result<Vec<Status>, Error> {
+    if !state.config.did_filter.allows(did.as_str()) {
+        return Ok(vec![]);
+    }
+    Ok(with_timeout(
+        state.config.upstream_timeout,
+        "PDS statuses fetch",
+        fetch_recent_repo_statuses(
+            Arc::clone(&state.http_client),
+            &state.did_resolver,
+            did,
+            HISTORY_PAGE_SIZE as u8,
+            state.config.max_clock_skew,
+        ),
+    )
+    .await??
+    .into_iter()
+    .filter(|status| state.config.is_allowed_status(&status.status))
+    .collect())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// Opaque cursor from the previous page's "older" link.
+    cursor: Option<String>,
+}
+
+/// Lists every status a user has posted, newest first, a page at a time.
+pub async fn history(
+    State(state): State<Arc<AppState>>,
+    Path(handle): Path<String>,
+    Query(HistoryQuery { cursor }): Query<HistoryQuery>,
+    OptionalAuth(maybe_agent): OptionalAuth,
+    session: Session,
+) -> Result<Response, Error> {
+    let did = with_timeout(
+        state.config.upstream_timeout,
+        "handle resolution",
+        state.handle_resolver.resolve_did(&handle),
+    )
+    .await??;
+    let after = cursor
+        .as_deref()
+        .map(|cursor| state.config.cursor_codec.decode(cursor))
+        .transpose()?;
+
+    let timezone = preferences::timezone(&session).await?;
+    let user_did = match &maybe_agent {
+        Some(agent) => Some(agent_did(agent).await),
+        None => None,
+    };
+    let filter = visibility_filter(state.as_ref(), maybe_agent.as_deref(), user_did.as_ref())
+        .await?
+        .author(did.clone());
+
+    // fetch one extra status to find out whether there's another page
+    let mut statuses = state
+        .status_store
+        .fetch_page(&filter, after.as_ref(), HISTORY_PAGE_SIZE + 1)
+        .await?;
+    // users we've never indexed a status from are looked up on their PDS, once per
+    // `lookup_cache_ttl`
+    let mut from_pds = false;
+    if statuses.is_empty() && after.is_none() {
+        let cached = state.lookup_cache.get(&did);
+        let looked_up = cached.is_none();
+        let pds_statuses = match cached {
+            Some(statuses) => statuses,
+            None => {
+                let statuses = fetch_pds_statuses(state.as_ref(), &did).await?;
+                state.lookup_cache.insert(did.clone(), statuses.clone());
+                statuses
+            }
+        };
+        // stored when fetched, not each time they're shown from the cache
+        if state.config.lookup_backfill && looked_up {
+            state.status_store.insert_many(pds_statuses).await?;
+            statuses = state
+                .status_store
+                .fetch_page(&filter, None, HISTORY_PAGE_SIZE + 1)
+                .await?;
+        } else {
+            // not stored, so there's no cursor to page through them with
+            from_pds = true;
+            statuses = pds_statuses;
+            statuses.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            statuses.truncate(HISTORY_PAGE_SIZE);
+        }
+    }
+    let pinned = state.status_store.fetch_pinned(&did).await?;
+    let own_profile = user_did.as_ref() == Some(&did);
+
+    let next_cursor = if statuses.len() > HISTORY_PAGE_SIZE {
+        statuses.truncate(HISTORY_PAGE_SIZE);
+        statuses
+            .last()
+            .map(|status| state.config.cursor_codec.encode(&status.cursor()))
+    } else {
+        None
+    };
+
+    #[derive(Serialize)]
+    struct HistoryView {
+        status: String,
+        permalink: Option<String>,
+        // `at://` URI of the record; followers-only statuses only exist on this site
+        record_uri: Option<String>,
+        followers_only: bool,
+        content_warning: Option<String>,
+        #[serde(flatten)]
+        dates: DisplayDates,
+    }
+
+    let history_views = statuses
+        .into_iter()
+        .map(|status| HistoryView {
+            permalink: permalink(&status.uri),
+            record_uri: status.uri.starts_with("at://").then(|| status.uri.clone()),
+            followers_only: status.visibility == Visibility::Followers,
+            content_warning: status.content_warning,
+            status: status.status,
+            dates: display_dates(
+                state.config.date_policy,
+                state.config.backdate_threshold,
+                &status.created_at,
+                &status.indexed_at,
+                timezone,
+            ),
+        })
+        .collect::<Vec<_>>();
+
+    let template = open_template!(state, "history");
+    let rendered = template.render(context! {
+        theme => preferences::theme(&session).await?,
+        viewer => viewer::current(state.as_ref(), &session, maybe_agent.as_deref()).await?,
+        handle => state.handle_resolver.lookup(&did).await?,
+        profile_path => handle,
+        avatar => avatar_url(&did),
+        statuses => history_views,
+        next_cursor => next_cursor,
+        first_page => after.is_none(),
+        from_pds => from_pds,
+        pinned_status => pinned.map(|status| status.status),
+        own_profile => own_profile,
+    })?;
+
+    Ok(Html(rendered).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LookupQuery {
+    handle: String,
+}
+
+/// Target of the "look up a user" form: sends the visitor to that user's history.
+pub async fn lookup(Query(LookupQuery { handle }): Query<LookupQuery>) -> Result<Redirect, Error> {
+    let handle = handle.trim().trim_start_matches('@');
+    // handles and DIDs never need escaping in a path
+    if handle.is_empty()
+        || !handle
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".-_:%".contains(c))
+    {
+        return Err(Error::UnknownHandle(handle.to_owned()));
+    }
+    Ok(Redirect::to(&format!("/profile/{handle}/history")))
+}
@@ -0,0 +1,140 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use atrium_api::types::string::Did;
+use atrium_oauth::DefaultHttpClient;
+use tracing::{error, info, warn};
+
+use crate::{
+    backfill::fetch_repo_statuses,
+    config::{AppConfig, DidFilter, is_allowed_status},
+    error::Error,
+    oauth::{self, DidResolver},
+    store::{OAuthSessionStore, StatusStore},
+};
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReconcileSummary {
+    /// Statuses found in a repo but missing from the store.
+    pub inserted: usize,
+    /// Stored statuses whose records no longer exist.
+    pub deleted: u64,
+    /// Repos that couldn't be reconciled.
+    pub failed: usize,
+}
+
+/// Repairs drift between logged-in users' repos and the store, e.g. from events missed while
+/// the ingester was down.
+pub struct Reconciler {
+    pub http_client: Arc<DefaultHttpClient>,
+    pub did_resolver: DidResolver,
+    pub status_store: StatusStore,
+    pub session_store: OAuthSessionStore,
+    pub status_options: Vec<String>,
+    pub did_filter: DidFilter,
+    pub max_clock_skew: Duration,
+}
+
+impl Reconciler {
+    /// Reconciler of the repos of users with sessions in `session_store`, as `config` says which
+    /// statuses are accepted.
+    pub fn new(
+        config: &AppConfig,
+        http_client: Arc<DefaultHttpClient>,
+        status_store: StatusStore,
+        session_store: OAuthSessionStore,
+    ) -> Self {
+        Self {
+            did_resolver: oauth::did_resolver(Arc::clone(&http_client), &config.plc_directory_url),
+            http_client,
+            status_store,
+            session_store,
+            status_options: config.status_options.clone(),
+            did_filter: config.did_filter.clone(),
+            max_clock_skew: config.max_clock_skew,
+        }
+    }
+
+    /// Reconciles a single repo, returning how many statuses were inserted and deleted.
+    pub async fn reconcile_repo(&self, did: &Did) -> Result<(usize, u64), Error> {
+        if !self.did_filter.allows(did.as_str()) {
+            return Ok((0, 0));
+        }
+        let repo_statuses = fetch_repo_statuses(
+            Arc::clone(&self.http_client),
+            &self.did_resolver,
+            did,
+            self.max_clock_skew,
+        )
+        .await?;
+        let stored = self
+            .status_store
+            .public_uris(did)
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let in_repo = repo_statuses
+            .iter()
+            .map(|status| status.uri.clone())
+            .collect::<HashSet<_>>();
+
+        // only insert what's missing, so existing statuses keep their indexing time
+        let missing = repo_statuses
+            .into_iter()
+            .filter(|status| !stored.contains(&status.uri))
+            .filter(|status| is_allowed_status(&self.status_options, &status.status))
+            .collect::<Vec<_>>();
+        let inserted = missing.len();
+        self.status_store.insert_many(missing).await?;
+
+        let removed = stored
+            .into_iter()
+            .filter(|uri| !in_repo.contains(uri))
+            .collect::<Vec<_>>();
+        let deleted = self.status_store.delete_many(&removed).await?;
+
+        Ok((inserted, deleted))
+    }
+
+    /// Reconciles the repos of every user with a stored OAuth session.
+    pub async fn run(&self) -> Result<ReconcileSummary, Error> {
+        let mut summary = ReconcileSummary::default();
+        for key in self.session_store.keys().await? {
+            let did = match Did::new(key) {
+                Ok(did) => did,
+                Err(e) => {
+                    warn!("skipping OAuth session with invalid DID: {e}");
+                    continue;
+                }
+            };
+            // one unreachable PDS shouldn't stop the others from being reconciled
+            match self.reconcile_repo(&did).await {
+                Ok((inserted, deleted)) => {
+                    summary.inserted += inserted;
+                    summary.deleted += deleted;
+                }
+                Err(e) => {
+                    error!("reconciling {} failed: {e}", did.as_str());
+                    summary.failed += 1;
+                }
+            }
+        }
+        Ok(summary)
+    }
+}
+
+/// Spawns a task that periodically reconciles logged-in users' repos with the store.
+pub fn spawn_reconcile_job(reconciler: Reconciler, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match reconciler.run().await {
+                Ok(summary) => info!(
+                    "Reconciled repos: {} inserted, {} deleted, {} failed",
+                    summary.inserted, summary.deleted, summary.failed
+                ),
+                Err(e) => error!("repo reconciliation failed: {e}"),
+            }
+        }
+    });
+}
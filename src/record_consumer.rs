@@ -0,0 +1,116 @@
+//! Consumers of collections beyond the app's own, registered by applications built on it: the
+//! ingester hands them commits to their collections' records alongside ingesting statuses.
+
+// only the ingester hands out commits
+#![cfg_attr(not(feature = "ingester"), allow(dead_code))]
+
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+use async_trait::async_trait;
+use atrium_api::types::{
+    Collection, Unknown,
+    string::{Did, Nsid},
+};
+
+use crate::lexicons::xyz::statusphere::{Pin, Reaction, Status};
+
+/// What a commit did to a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitOperation {
+    Create,
+    Update,
+    Delete,
+}
+
+impl CommitOperation {
+    /// Parses a Jetstream `operation` or firehose `action`.
+    pub fn parse(operation: &str) -> Option<Self> {
+        match operation {
+            "create" => Some(Self::Create),
+            "update" => Some(Self::Update),
+            "delete" => Some(Self::Delete),
+            _ => None,
+        }
+    }
+}
+
+/// A commit to a record of a registered collection, from Jetstream or the firehose.
+#[derive(Debug, Clone)]
+pub struct RecordCommit {
+    pub did: Did,
+    pub collection: String,
+    pub rkey: String,
+    pub operation: CommitOperation,
+    /// The record as created or updated; `None` for deletes. Typically converted to a lexicon type
+    /// with [`atrium_api::types::TryFromUnknown`].
+    pub record: Option<Unknown>,
+    pub cid: Option<String>,
+}
+
+impl RecordCommit {
+    pub fn uri(&self) -> String {
+        format!(
+            "at://{}/{}/{}",
+            self.did.as_str(),
+            self.collection,
+            self.rkey
+        )
+    }
+}
+
+/// Handles the commits to one collection's records. A commit it fails to handle is dead-lettered.
+#[async_trait]
+pub trait RecordConsumer: Send + Sync + 'static {
+    async fn consume(&self, commit: RecordCommit) -> anyhow::Result<()>;
+}
+
+/// The consumers of collections beyond the app's own, by collection (NSID). Their collections are
+/// ingested in addition to the configured `INGEST_COLLECTIONS`.
+#[derive(Clone, Default)]
+pub struct ConsumerRegistry {
+    consumers: BTreeMap<String, Arc<dyn RecordConsumer>>,
+}
+
+impl ConsumerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `consumer` for the records of `collection`. Panics if `collection` isn't a valid
+    /// NSID, is one of the app's own collections, or already has a consumer.
+    pub fn register(mut self, collection: &str, consumer: impl RecordConsumer) -> Self {
+        if let Err(e) = Nsid::new(collection.to_owned()) {
+            panic!("can't register a consumer for '{collection}': {e}");
+        }
+        if [Status::NSID, Pin::NSID, Reaction::NSID].contains(&collection) {
+            panic!("can't register a consumer for '{collection}': the app consumes it itself");
+        }
+        if self
+            .consumers
+            .insert(collection.to_owned(), Arc::new(consumer))
+            .is_some()
+        {
+            panic!("can't register a consumer for '{collection}': it already has one");
+        }
+        self
+    }
+
+    /// The collections with a registered consumer.
+    pub fn collections(&self) -> impl Iterator<Item = &str> {
+        self.consumers.keys().map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.consumers.is_empty()
+    }
+
+    pub fn get(&self, collection: &str) -> Option<&Arc<dyn RecordConsumer>> {
+        self.consumers.get(collection)
+    }
+}
+
+impl fmt::Debug for ConsumerRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.consumers.keys()).finish()
+    }
+}
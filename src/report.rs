@@ -0,0 +1,237 @@
+use std::{sync::Arc, time::Duration};
+
+use atrium_api::{
+    com::atproto::{moderation::create_report, repo::strong_ref},
+    types::{Union, string::Cid},
+};
+use axum::{
+    Form,
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{
+    AppState,
+    auth::RequireAuth,
+    error::Error,
+    oauth::{ATProtoAgent, agent_did},
+    store::Visibility,
+    upstream::with_timeout,
+};
+
+// as long as a report's reason can be, per `com.atproto.moderation.createReport`
+const MAX_REASON_CHARS: usize = 2000;
+
+/// Why a status is reported, as picked in the report form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasonType {
+    Spam,
+    Violation,
+    Misleading,
+    Sexual,
+    Rude,
+    Other,
+}
+
+impl ReasonType {
+    /// The `com.atproto.moderation.defs#reasonType` token for the reason.
+    pub fn token(self) -> &'static str {
+        match self {
+            Self::Spam => "com.atproto.moderation.defs#reasonSpam",
+            Self::Violation => "com.atproto.moderation.defs#reasonViolation",
+            Self::Misleading => "com.atproto.moderation.defs#reasonMisleading",
+            Self::Sexual => "com.atproto.moderation.defs#reasonSexual",
+            Self::Rude => "com.atproto.moderation.defs#reasonRude",
+            Self::Other => "com.atproto.moderation.defs#reasonOther",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReportInput {
+    subject: String,
+    reason_type: ReasonType,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+// files a report of the record `uri` (at version `cid`) with the moderation service the user's
+// PDS forwards reports to, returning the ID it was given
+async fn file_report(
+    agent: &ATProtoAgent,
+    timeout: Duration,
+    uri: &str,
+    cid: Cid,
+    reason_type: ReasonType,
+    reason: Option<&str>,
+) -> Result<i64, Error> {
+    let input_data = create_report::InputData {
+        mod_tool: None,
+        reason: reason.map(str::to_owned),
+        reason_type: reason_type.token().to_owned(),
+        subject: Union::Refs(
+            create_report::InputSubjectRefs::ComAtprotoRepoStrongRefMain(Box::new(
+                strong_ref::MainData {
+                    cid,
+                    uri: uri.to_owned(),
+                }
+                .into(),
+            )),
+        ),
+    };
+    // not retried: a report that went through but whose response was lost would be filed twice
+    let report = with_timeout(
+        timeout,
+        "report create",
+        agent
+            .api
+            .com
+            .atproto
+            .moderation
+            .create_report(input_data.into()),
+    )
+    .await??;
+    Ok(report.data.id)
+}
+
+/// Reports a status, both to moderators here and (for public statuses) to the moderation service
+/// the user's PDS forwards reports to. Reporting a status again does nothing.
+pub async fn report_status(
+    State(state): State<Arc<AppState>>,
+    RequireAuth(agent): RequireAuth,
+    Form(input): Form<ReportInput>,
+) -> Result<Response, Error> {
+    let reason = input
+        .reason
+        .as_deref()
+        .map(str::trim)
+        .filter(|reason| !reason.is_empty());
+    if reason.is_some_and(|reason| reason.chars().count() > MAX_REASON_CHARS) {
+        return Err(Error::InvalidInput(format!(
+            "report reasons can be at most {MAX_REASON_CHARS} characters"
+        )));
+    }
+    let Some(status) = state.status_store.fetch_by_uri(&input.subject).await? else {
+        return Err(Error::StatusNotFound(input.subject));
+    };
+
+    let did = agent_did(&agent).await;
+    if state.reports.has_reported(&status.uri, &did).await? {
+        return Ok(Redirect::to("/").into_response());
+    }
+
+    // reports are of a specific version of a record, so only public statuses whose CID is known
+    // can be filed with the moderation service; the rest are only reviewed here
+    let cid = match status.visibility {
+        Visibility::Public => status.cid.as_deref().and_then(|cid| cid.parse().ok()),
+        Visibility::Followers => None,
+    };
+    let filed_id = match cid {
+        Some(cid) => Some(
+            file_report(
+                &agent,
+                state.config.upstream_timeout,
+                &status.uri,
+                cid,
+                input.reason_type,
+                reason,
+            )
+            .await?,
+        ),
+        None => None,
+    };
+
+    state
+        .reports
+        .insert(
+            &status.uri,
+            &did,
+            input.reason_type.token(),
+            reason,
+            filed_id,
+        )
+        .await?;
+    info!(
+        "{} reported {} ({:?})",
+        did.as_str(),
+        status.uri,
+        input.reason_type
+    );
+
+    Ok(Redirect::to("/").into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use crate::test_support::{TestApp, did};
+
+    const ALICE: &str = "did:plc:alice0000000000000000000";
+    const BOB: &str = "did:plc:bob00000000000000000000000";
+
+    #[tokio::test]
+    async fn reports_are_filed_and_kept_for_moderators() {
+        let app = TestApp::new().await;
+        let public = app.seed_status(&did(BOB), "3kaaaaaaaaaa2", "🦋").await;
+        let private = app
+            .seed_followers_status(&did(BOB), "3kaaaaaaaaab2", "🦋")
+            .await;
+        let cookie = app.login(&did(ALICE)).await;
+
+        for (subject, form) in [
+            (&public, "reason_type=spam&reason=buy+now"),
+            // reporting again doesn't add another report
+            (&public, "reason_type=rude"),
+            (&private, "reason_type=other"),
+        ] {
+            let response = app
+                .post_form(
+                    "/report",
+                    &format!("subject={subject}&{form}"),
+                    Some(&cookie),
+                )
+                .await;
+            assert_eq!(response.status, StatusCode::SEE_OTHER);
+        }
+
+        let reports = app
+            .state
+            .reports
+            .fetch_open(10)
+            .await
+            .expect("reports are fetched");
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].subject, public);
+        assert_eq!(reports[0].reporter_did, ALICE);
+        assert_eq!(
+            reports[0].reason_type,
+            "com.atproto.moderation.defs#reasonSpam"
+        );
+        assert_eq!(reports[0].reason.as_deref(), Some("buy now"));
+        assert!(reports[0].filed_id.is_some());
+        // followers-only statuses never leave the site, so neither do reports of them
+        assert_eq!(reports[1].subject, private);
+        assert_eq!(reports[1].filed_id, None);
+    }
+
+    #[tokio::test]
+    async fn unknown_statuses_cant_be_reported() {
+        let app = TestApp::new().await;
+        let cookie = app.login(&did(ALICE)).await;
+
+        let response = app
+            .post_form(
+                "/report",
+                &format!(
+                    "subject=at://{BOB}/xyz.statusphere.status/3kaaaaaaaaaa2&reason_type=spam"
+                ),
+                Some(&cookie),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+}
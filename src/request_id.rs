@@ -0,0 +1,26 @@
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderName},
+};
+use tracing::{Span, info_span};
+
+/// Header carrying the ID of a request, set by the client or generated on arrival.
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// The ID assigned to a request, if it has one.
+pub fn request_id(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Span wrapping the handling of `request`, so its log lines can be told apart from those of
+/// concurrent requests.
+pub fn request_span(request: &Request) -> Span {
+    info_span!(
+        "request",
+        method = %request.method(),
+        path = request.uri().path(),
+        request_id = request_id(request.headers()).unwrap_or_default(),
+    )
+}
@@ -0,0 +1,119 @@
+use std::{collections::HashMap, marker::PhantomData, sync::Arc};
+
+use atrium_api::types::string::Did;
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::request::Parts,
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+use tower_sessions::Session;
+
+use crate::{AppState, ClientSession, config::env_var_or_default, error::Error};
+
+/// Access level of a user. Roles are ordered, so each role has all the permissions of the roles
+/// below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Any logged-in user.
+    Viewer,
+    /// Can act on reported or abusive content.
+    Moderator,
+    /// Full control of the instance.
+    Owner,
+}
+
+/// Mapping of DIDs to elevated roles, from `MODERATOR_DIDS` and `OWNER_DIDS` (or its alias
+/// `ADMIN_DIDS`). DIDs not present are [`Role::Viewer`]s.
+#[derive(Debug, Clone, Default)]
+pub struct RoleMap {
+    roles: HashMap<String, Role>,
+}
+
+impl RoleMap {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut roles = HashMap::new();
+        // owners last so they win if a DID is listed twice
+        for (key, role) in [
+            ("MODERATOR_DIDS", Role::Moderator),
+            ("ADMIN_DIDS", Role::Owner),
+            ("OWNER_DIDS", Role::Owner),
+        ] {
+            for did in env_var_or_default(key, "")?
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|did| !did.is_empty())
+            {
+                roles.insert(did.to_owned(), role);
+            }
+        }
+        Ok(Self { roles })
+    }
+
+    pub fn role(&self, did: &Did) -> Role {
+        self.roles
+            .get(did.as_str())
+            .copied()
+            .unwrap_or(Role::Viewer)
+    }
+}
+
+/// Marker for the minimum role a route requires.
+pub trait RoleRequirement {
+    const ROLE: Role;
+}
+
+pub struct Moderator;
+impl RoleRequirement for Moderator {
+    const ROLE: Role = Role::Moderator;
+}
+
+pub struct Owner;
+impl RoleRequirement for Owner {
+    const ROLE: Role = Role::Owner;
+}
+
+/// Extractor that only succeeds if the logged-in user has at least role `R`. Rejects with 401
+/// if nobody is logged in and 403 if the user's role is insufficient.
+pub struct Authorized<R: RoleRequirement> {
+    pub did: Did,
+    pub role: Role,
+    _requirement: PhantomData<R>,
+}
+
+impl<R: RoleRequirement> FromRequestParts<Arc<AppState>> for Authorized<R> {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request_parts(parts, state)
+            .await
+            .map_err(|_| Error::Unauthorized)?;
+        let Some(ClientSession { did, .. }) = session.get("sid").await? else {
+            return Err(Error::Unauthorized);
+        };
+
+        let role = state.config.roles.role(&did);
+        if role < R::ROLE {
+            return Err(Error::Forbidden);
+        }
+        Ok(Self {
+            did,
+            role,
+            _requirement: PhantomData,
+        })
+    }
+}
+
+/// Extractor for admin and maintenance routes: only owners (`OWNER_DIDS` or `ADMIN_DIDS`) get
+/// through.
+pub type AdminOnly = Authorized<Owner>;
+
+/// Middleware guarding every route of a router with [`AdminOnly`], for the error middleware to
+/// render its rejections as the themed 401/403 page.
+pub async fn admin_only(_admin: AdminOnly, request: Request, next: Next) -> Response {
+    next.run(request).await
+}
@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+use tracing::{debug, error};
+
+use crate::store::StatusStore;
+
+/// Spawns a task that periodically folds new statuses into the hourly rollup tables.
+pub fn spawn_rollup_job(status_store: StatusStore, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match status_store.rollup().await {
+                Ok(count) => debug!("Rolled up {count} statuses"),
+                Err(e) => error!("status rollup failed: {e}"),
+            }
+        }
+    });
+}
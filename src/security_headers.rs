@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{
+        HeaderValue,
+        header::{
+            CONTENT_SECURITY_POLICY, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY,
+            X_CONTENT_TYPE_OPTIONS,
+        },
+    },
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{AppState, config::env_var_or_default};
+
+// scripts and styles are served from /assets (plus htmx from unpkg), so nothing inline is allowed
+const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; \
+    script-src 'self' https://unpkg.com; \
+    style-src 'self'; \
+    img-src 'self' data: https://cdn.bsky.app; \
+    connect-src 'self'; \
+    object-src 'none'; \
+    base-uri 'self'";
+
+/// Security headers added to every response, unless a handler already set them.
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders {
+    /// `Content-Security-Policy`, including the `frame-ancestors` directive; `None` leaves it out.
+    pub content_security_policy: Option<HeaderValue>,
+    /// `Referrer-Policy`; `None` leaves it out.
+    pub referrer_policy: Option<HeaderValue>,
+    /// `Strict-Transport-Security`, only sent on requests served over TLS or that a trusted proxy
+    /// says arrived over HTTPS; `None` leaves it out.
+    pub strict_transport_security: Option<HeaderValue>,
+}
+
+impl SecurityHeaders {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut policy =
+            env_var_or_default("CONTENT_SECURITY_POLICY", DEFAULT_CONTENT_SECURITY_POLICY)?
+                .trim()
+                .trim_end_matches(';')
+                .to_owned();
+        let frame_ancestors = env_var_or_default("FRAME_ANCESTORS", "'none'")?;
+        if !frame_ancestors.is_empty() {
+            if !policy.is_empty() {
+                policy.push_str("; ");
+            }
+            policy.push_str(&format!("frame-ancestors {frame_ancestors}"));
+        }
+        let hsts_max_age: u64 = env_var_or_default("HSTS_MAX_AGE_SECS", "31536000")?.parse()?;
+        Ok(Self {
+            content_security_policy: header_value("CONTENT_SECURITY_POLICY", &policy)?,
+            referrer_policy: header_value(
+                "REFERRER_POLICY",
+                &env_var_or_default("REFERRER_POLICY", "strict-origin-when-cross-origin")?,
+            )?,
+            strict_transport_security: match hsts_max_age {
+                0 => None,
+                max_age => Some(HeaderValue::from_str(&format!(
+                    "max-age={max_age}; includeSubDomains"
+                ))?),
+            },
+        })
+    }
+}
+
+// empty values disable the header
+fn header_value(key: &'static str, value: &str) -> anyhow::Result<Option<HeaderValue>> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    HeaderValue::from_str(value)
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("invalid {key} '{value}': {e}"))
+}
+
+/// Adds the configured security headers to responses. HSTS is only sent over HTTPS: when we serve
+/// it ourselves, or, with `TRUST_PROXY` set, a TLS-terminating proxy reports it as the original
+/// scheme via `X-Forwarded-Proto`. Without a proxy, clients could set that header themselves.
+pub async fn security_headers(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let https = state.config.tls.is_some()
+        || (state.config.trust_proxy
+            && request
+                .headers()
+                .get("X-Forwarded-Proto")
+                .is_some_and(|proto| proto.as_bytes().eq_ignore_ascii_case(b"https")));
+    let config = &state.config.security_headers;
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers
+        .entry(X_CONTENT_TYPE_OPTIONS)
+        .or_insert(HeaderValue::from_static("nosniff"));
+    for (name, value) in [
+        (
+            CONTENT_SECURITY_POLICY,
+            config.content_security_policy.as_ref(),
+        ),
+        (REFERRER_POLICY, config.referrer_policy.as_ref()),
+        (
+            STRICT_TRANSPORT_SECURITY,
+            config.strict_transport_security.as_ref().filter(|_| https),
+        ),
+    ] {
+        if let Some(value) = value {
+            headers.entry(name).or_insert_with(|| value.clone());
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{HeaderMap, Request, header::STRICT_TRANSPORT_SECURITY},
+    };
+
+    use crate::{config::AppConfig, test_support::TestApp};
+
+    // headers of a response to a request a proxy says arrived over HTTPS
+    async fn forwarded_https_headers(trust_proxy: bool) -> HeaderMap {
+        let mut config = AppConfig::for_tests();
+        config.trust_proxy = trust_proxy;
+        let app = TestApp::with_config(config).await;
+        let request = Request::get("/healthz")
+            .header("X-Forwarded-Proto", "https")
+            .body(Body::empty())
+            .expect("valid request");
+        app.send(request).await.headers
+    }
+
+    #[tokio::test]
+    async fn hsts_follows_forwarded_proto_only_from_a_trusted_proxy() {
+        let trusted = forwarded_https_headers(true).await;
+        assert!(trusted.contains_key(STRICT_TRANSPORT_SECURITY));
+
+        let untrusted = forwarded_https_headers(false).await;
+        assert!(!untrusted.contains_key(STRICT_TRANSPORT_SECURITY));
+    }
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use atrium_api::types::{
+    Collection,
+    string::{Datetime, Did},
+};
+use chrono::{DateTime, Utc};
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+use crate::{
+    lexicons::xyz::statusphere::Status as StatusRecord,
+    store::{HandleCache, Status, StatusStore, Visibility},
+};
+
+// statuses inserted per transaction
+const SEED_BATCH_SIZE: usize = 1000;
+
+// alphabet of base32-sortable, the encoding of TIDs and of `did:plc` identifiers
+const BASE32_SORTABLE: &[u8] = b"234567abcdefghijklmnopqrstuvwxyz";
+
+// shown in place of a few statuses, so the reveal button shows up too
+const CONTENT_WARNINGS: [&str; 3] = ["spoilers", "food", "politics"];
+
+/// What to seed, parsed from `[--authors N] [--days N] [--seed N] COUNT`.
+#[derive(Debug, Clone)]
+pub struct Args {
+    /// Number of statuses.
+    pub count: usize,
+    /// Number of made-up users the statuses are spread across.
+    pub authors: usize,
+    /// Statuses are dated up to this far in the past.
+    pub period: Duration,
+    /// Seed of the random choices, to generate the same statuses again.
+    pub seed: Option<u64>,
+}
+
+impl Args {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut count = None;
+        let mut authors = 20;
+        let mut days = 7;
+        let mut seed = None;
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| anyhow::anyhow!("{name} requires a value"))
+            };
+            match arg.as_str() {
+                "--authors" => authors = value("--authors")?.parse()?,
+                "--days" => days = value("--days")?.parse()?,
+                "--seed" => seed = Some(value("--seed")?.parse()?),
+                _ if count.is_none() => count = Some(arg.parse()?),
+                _ => anyhow::bail!("unexpected argument '{arg}'"),
+            }
+        }
+        if authors == 0 {
+            anyhow::bail!("--authors must be at least 1");
+        }
+        Ok(Self {
+            count: count.ok_or_else(|| anyhow::anyhow!("how many statuses to seed is required"))?,
+            authors,
+            period: Duration::from_secs(days * 24 * 60 * 60),
+            seed,
+        })
+    }
+}
+
+// `len` random base32-sortable characters
+fn base32_sortable(rng: &mut impl Rng, len: usize) -> String {
+    (0..len)
+        .map(|_| *BASE32_SORTABLE.choose(rng).expect("alphabet isn't empty") as char)
+        .collect()
+}
+
+// the TID for `timestamp`, as record keys are made
+fn tid(timestamp: DateTime<Utc>, clock_id: u64) -> String {
+    let value = ((timestamp.timestamp_micros() as u64) << 10) | (clock_id & 0x3ff);
+    (0..13)
+        .rev()
+        .map(|i| BASE32_SORTABLE[((value >> (i * 5)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+/// Stores `args.count` made-up public statuses by made-up users, picked from `status_options`
+/// and dated over the last `args.period`, for development and load testing. The users' handles
+/// are cached so pages show them without resolving anything. Returns how many were stored.
+pub async fn seed(
+    status_store: &StatusStore,
+    handle_cache: &HandleCache,
+    status_options: &[String],
+    args: &Args,
+) -> anyhow::Result<usize> {
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let mut authors = Vec::with_capacity(args.authors);
+    for i in 1..=args.authors {
+        let did = Did::new(format!("did:plc:{}", base32_sortable(&mut rng, 24)))
+            .map_err(|e| anyhow::anyhow!("invalid made-up DID: {e}"))?;
+        handle_cache
+            .set(&did, Some(&format!("seed-user-{i}.test")))
+            .await?;
+        authors.push(did);
+    }
+
+    let now = Utc::now();
+    let period_millis = i64::try_from(args.period.as_millis())?.max(1);
+    let mut count = 0;
+    let mut batch = Vec::with_capacity(SEED_BATCH_SIZE);
+    for _ in 0..args.count {
+        let author = authors
+            .choose(&mut rng)
+            .expect("there's at least one author");
+        let status = status_options
+            .choose(&mut rng)
+            .ok_or_else(|| anyhow::anyhow!("no status options to pick from"))?;
+        let created_at = now - chrono::Duration::milliseconds(rng.gen_range(0..period_millis));
+        // as though the status took a few seconds to come through Jetstream
+        let indexed_at = created_at + chrono::Duration::milliseconds(rng.gen_range(200..5000));
+        let content_warning = rng
+            .gen_bool(0.05)
+            .then(|| CONTENT_WARNINGS.choose(&mut rng).map(|cw| (*cw).to_owned()))
+            .flatten();
+        batch.push(Status {
+            uri: format!(
+                "at://{}/{}/{}",
+                author.as_str(),
+                StatusRecord::NSID,
+                tid(created_at, rng.gen_range(0..1024))
+            ),
+            author_did: author.clone(),
+            status: status.clone(),
+            created_at: Datetime::new(created_at.fixed_offset()),
+            indexed_at: Datetime::new(indexed_at.min(now).fixed_offset()),
+            raw_created_at: None,
+            visibility: Visibility::Public,
+            content_warning,
+            cid: None,
+            image: None,
+        });
+        if batch.len() == SEED_BATCH_SIZE {
+            count += batch.len();
+            status_store.insert_many(std::mem::take(&mut batch)).await?;
+        }
+    }
+    count += batch.len();
+    status_store.insert_many(batch).await?;
+    Ok(count)
+}
@@ -0,0 +1,203 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use atrium_api::{
+    client::AtpServiceClient,
+    com::atproto::{repo, server::create_session},
+    types::{
+        Collection,
+        string::{Datetime, RecordKey},
+    },
+    xrpc::{
+        HttpClient, XrpcClient,
+        http::{Request, Response},
+        types::AuthorizationToken,
+    },
+};
+use atrium_oauth::DefaultHttpClient;
+use tracing::{info, warn};
+
+use crate::{
+    backfill::ServiceClient,
+    config::{env_var_or_default, env_var_required},
+    lexicons::{
+        self,
+        xyz::statusphere::{self, Status},
+    },
+    store::{StatusFilter, StatusStore},
+};
+
+// how often to check the store for the posted status
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Test account and limits for the smoke test.
+#[derive(Debug, Clone)]
+pub struct SmokeConfig {
+    /// PDS hosting the test account (e.g. `http://localhost:2583` in the atproto dev-env).
+    pub pds_url: String,
+    /// Handle or DID of the test account.
+    pub identifier: String,
+    /// Password (or app password) of the test account.
+    pub password: String,
+    /// How long to wait for the status to come back through the ingester.
+    pub timeout: Duration,
+}
+
+impl SmokeConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            pds_url: env_var_or_default("SMOKE_PDS_URL", "http://localhost:2583")?,
+            identifier: env_var_required("SMOKE_IDENTIFIER")?,
+            password: env_var_required("SMOKE_PASSWORD")?,
+            timeout: Duration::from_secs(env_var_or_default("SMOKE_TIMEOUT_SECS", "60")?.parse()?),
+        })
+    }
+}
+
+// service client that authenticates with a password session's access token
+struct SessionClient {
+    inner: ServiceClient,
+    access_jwt: String,
+}
+
+impl HttpClient for SessionClient {
+    async fn send_http(
+        &self,
+        request: Request<Vec<u8>>,
+    ) -> core::result::Result<Response<Vec<u8>>, Box<dyn std::error::Error + Send + Sync + 'static>>
+    {
+        self.inner.send_http(request).await
+    }
+}
+
+impl XrpcClient for SessionClient {
+    fn base_uri(&self) -> String {
+        self.inner.base_uri()
+    }
+
+    async fn authorization_token(&self, _is_refresh: bool) -> Option<AuthorizationToken> {
+        Some(AuthorizationToken::Bearer(self.access_jwt.clone()))
+    }
+}
+
+/// Logs in as a test account, posts a status to its repo, and waits for the status to reach the
+/// store (via whichever ingester is writing to it) and show up in the feed. The test status is
+/// deleted from the repo afterwards.
+///
+/// Meant as a canary for operators after upgrades: run it against a live deployment's database
+/// with a test account on the same network the ingester follows.
+pub async fn run(
+    config: &SmokeConfig,
+    http_client: Arc<DefaultHttpClient>,
+    status_store: &StatusStore,
+    status: &str,
+) -> anyhow::Result<()> {
+    let client = AtpServiceClient::new(ServiceClient::new(
+        Arc::clone(&http_client),
+        config.pds_url.as_str(),
+    ));
+    let session = client
+        .service
+        .com
+        .atproto
+        .server
+        .create_session(
+            create_session::InputData {
+                allow_takendown: None,
+                auth_factor_token: None,
+                identifier: config.identifier.clone(),
+                password: config.password.clone(),
+            }
+            .into(),
+        )
+        .await?;
+    let did = session.data.did.clone();
+    info!("Logged in as {}", did.as_str());
+
+    let client = AtpServiceClient::new(SessionClient {
+        inner: ServiceClient::new(http_client, config.pds_url.as_str()),
+        access_jwt: session.data.access_jwt.clone(),
+    });
+    let record = client
+        .service
+        .com
+        .atproto
+        .repo
+        .create_record(
+            repo::create_record::InputData {
+                collection: Status::NSID
+                    .parse()
+                    .expect("NSID is generated, should never fail to parse"),
+                record: lexicons::record::KnownRecord::from(statusphere::status::RecordData {
+                    content_warning: None,
+                    created_at: Datetime::now(),
+                    image: None,
+                    status: status.to_owned(),
+                })
+                .into(),
+                repo: did.clone().into(),
+                rkey: None,
+                swap_commit: None,
+                validate: None,
+            }
+            .into(),
+        )
+        .await?;
+    let uri = record.data.uri.clone();
+    info!("Posted {uri}");
+
+    let result = wait_for_status(config, status_store, &uri).await;
+
+    // clean up regardless of the outcome, so repeated runs don't litter the test account
+    let rkey = uri.rsplit('/').next().unwrap_or_default().to_owned();
+    if let Err(e) = client
+        .service
+        .com
+        .atproto
+        .repo
+        .delete_record(
+            repo::delete_record::InputData {
+                collection: Status::NSID
+                    .parse()
+                    .expect("NSID is generated, should never fail to parse"),
+                repo: did.into(),
+                rkey: RecordKey::new(rkey).map_err(|e| anyhow::anyhow!("{e}"))?,
+                swap_commit: None,
+                swap_record: None,
+            }
+            .into(),
+        )
+        .await
+    {
+        warn!("failed to delete smoke test status {uri}: {e}");
+    }
+
+    result
+}
+
+async fn wait_for_status(
+    config: &SmokeConfig,
+    status_store: &StatusStore,
+    uri: &str,
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+    loop {
+        if status_store.fetch_by_uri(uri).await?.is_some() {
+            break;
+        }
+        if started.elapsed() > config.timeout {
+            anyhow::bail!("{uri} wasn't ingested within {}s", config.timeout.as_secs());
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    info!("Ingested {uri} after {:?}", started.elapsed());
+
+    // the same query the home page uses for logged-out visitors
+    let feed = status_store.fetch_n(&StatusFilter::new(), 10).await?;
+    if !feed.iter().any(|status| status.uri == uri) {
+        anyhow::bail!("{uri} was ingested but isn't in the feed");
+    }
+    Ok(())
+}
@@ -1,89 +1,819 @@
-use std::sync::Arc;
+use std::{
+    fmt::Debug,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use atrium_api::{
+    app::bsky,
     com::atproto,
     types::{
-        Collection,
-        string::{Datetime, RecordKey, Tid},
+        BlobRef, Collection,
+        string::{Cid, Datetime, Did, RecordKey, Tid},
+    },
+    xrpc::{
+        self,
+        error::{XrpcError, XrpcErrorKind},
     },
 };
 use axum::{
     Form,
-    extract::State,
+    extract::{FromRequest, Multipart, Request, State},
+    http::header::CONTENT_TYPE,
     response::{IntoResponse, Redirect, Response},
 };
 use serde::Deserialize;
 use tower_sessions::Session;
+use tracing::warn;
 
 use crate::{
     AppState,
+    auth::RequireAuth,
     error::Error,
+    home::{FeedMode, render_feed},
+    htmx::HxRequest,
+    i18n::Locale,
     lexicons::{
         self,
-        xyz::statusphere::{self, Status},
+        xyz::statusphere::{self, Pin, Reaction, Status},
     },
-    oauth::{agent_did, session_agent},
+    oauth::{ATProtoAgent, agent_did},
+    store::{MAX_CONTENT_WARNING_CHARS, StatusImage, Visibility, sanitize_content_warning},
+    throttle::PostClaim,
+    upstream::{with_retries, with_timeout},
+    validate,
 };
 
+// a fresh TID record key
+fn new_rkey() -> RecordKey {
+    let tid = Tid::now(
+        0.try_into()
+            .expect("unexpected clock ID conversion failure"),
+    );
+    RecordKey::new(tid.to_string()).expect("unexpected record key failure")
+}
+
+// attempts at a write before giving up on the repo settling down
+const MAX_SWAP_ATTEMPTS: u32 = 3;
+
+// write errors meaning the repo moved on from the commit passed as `swap_commit`
+trait SwapConflict {
+    fn is_invalid_swap(&self) -> bool;
+}
+
+impl SwapConflict for atproto::repo::create_record::Error {
+    fn is_invalid_swap(&self) -> bool {
+        matches!(self, Self::InvalidSwap(_))
+    }
+}
+
+impl SwapConflict for atproto::repo::put_record::Error {
+    fn is_invalid_swap(&self) -> bool {
+        matches!(self, Self::InvalidSwap(_))
+    }
+}
+
+impl SwapConflict for atproto::repo::delete_record::Error {
+    fn is_invalid_swap(&self) -> bool {
+        matches!(self, Self::InvalidSwap(_))
+    }
+}
+
+// writes to `did`'s repo with `write`, which is given the repo's latest commit to pass as
+// `swap_commit`: a write racing another client's then fails rather than clobbering it, and is
+// retried on top of the newer commit
+async fn write_record<T, E, F, Fut>(
+    agent: &ATProtoAgent,
+    timeout: Duration,
+    did: &Did,
+    what: &'static str,
+    mut write: F,
+) -> Result<T, Error>
+where
+    E: Debug + SwapConflict,
+    F: FnMut(Cid) -> Fut,
+    Fut: Future<Output = Result<T, xrpc::Error<E>>>,
+    Error: From<xrpc::Error<E>>,
+{
+    let mut attempt = 1;
+    loop {
+        let latest_commit = with_timeout(
+            timeout,
+            "latest commit fetch",
+            with_retries("latest commit fetch", || {
+                agent.api.com.atproto.sync.get_latest_commit(
+                    atproto::sync::get_latest_commit::ParametersData { did: did.clone() }.into(),
+                )
+            }),
+        )
+        .await??;
+        let written = with_timeout(
+            timeout,
+            what,
+            with_retries(what, || write(latest_commit.data.cid.clone())),
+        )
+        .await?;
+        match written {
+            Err(xrpc::Error::XrpcResponse(XrpcError {
+                error: Some(XrpcErrorKind::Custom(e)),
+                ..
+            })) if attempt < MAX_SWAP_ATTEMPTS && e.is_invalid_swap() => {
+                warn!(
+                    "{what} raced another write to {}'s repo (attempt {attempt}/{MAX_SWAP_ATTEMPTS}), retrying",
+                    did.as_str()
+                );
+                attempt += 1;
+            }
+            written => return Ok(written?),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct LoginInput {
     status: String,
+    #[serde(default)]
+    visibility: Visibility,
+    #[serde(default)]
+    content_warning: Option<String>,
+    /// Whether to also announce the status in a Bluesky post (public statuses only).
+    #[serde(default)]
+    crosspost: bool,
+    /// Token of the rendered form, telling resubmissions of it apart from new posts.
+    #[serde(default)]
+    post_token: Option<String>,
+}
+
+/// An image attached to a posted status, as uploaded in the status form.
+#[derive(Debug)]
+pub struct ImageUpload {
+    mime_type: String,
+    bytes: Vec<u8>,
+}
+
+/// The status form, which is sent as `multipart/form-data` when it has an image to upload and
+/// URL-encoded otherwise.
+#[derive(Debug)]
+pub struct StatusForm {
+    input: LoginInput,
+    image: Option<ImageUpload>,
+}
+
+impl FromRequest<Arc<AppState>> for StatusForm {
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let is_multipart = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("multipart/form-data"));
+        if !is_multipart {
+            let Form(input) = Form::<LoginInput>::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            return Ok(Self { input, image: None });
+        }
+
+        let mut multipart = Multipart::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let mut fields = vec![];
+        let mut image = None;
+        while let Some(field) = multipart
+            .next_field()
+            .await
+            .map_err(IntoResponse::into_response)?
+        {
+            let Some(name) = field.name().map(str::to_owned) else {
+                continue;
+            };
+            if name == "image" {
+                let mime_type = field.content_type().unwrap_or_default().to_owned();
+                let bytes = field.bytes().await.map_err(IntoResponse::into_response)?;
+                // browsers send an empty file when none was picked
+                if !bytes.is_empty() {
+                    image = Some(ImageUpload {
+                        mime_type,
+                        bytes: bytes.to_vec(),
+                    });
+                }
+            } else {
+                let value = field.text().await.map_err(IntoResponse::into_response)?;
+                fields.push((name, value));
+            }
+        }
+        // the rest of the form is read just as it would be if it were URL-encoded
+        let input = serde_urlencoded::to_string(&fields)
+            .ok()
+            .and_then(|encoded| serde_urlencoded::from_str::<LoginInput>(&encoded).ok())
+            .ok_or_else(|| {
+                Error::InvalidInput("the status form is missing fields".to_owned()).into_response()
+            })?;
+        Ok(Self { input, image })
+    }
+}
+
+// uploads an image to the user's PDS, returning the blob ref to put in their status record
+async fn upload_image(
+    agent: &ATProtoAgent,
+    timeout: Duration,
+    image: ImageUpload,
+) -> Result<BlobRef, Error> {
+    let blob = with_timeout(
+        timeout,
+        "blob upload",
+        with_retries("blob upload", || {
+            agent.api.com.atproto.repo.upload_blob(image.bytes.clone())
+        }),
+    )
+    .await??;
+    Ok(blob.data.blob)
+}
+
+// rejects content warnings that would otherwise be mangled by `sanitize_content_warning`
+fn check_content_warning(content_warning: Option<&str>) -> Result<(), Error> {
+    let Some(content_warning) = content_warning.map(str::trim) else {
+        return Ok(());
+    };
+    if content_warning.chars().count() > MAX_CONTENT_WARNING_CHARS {
+        return Err(Error::InvalidInput(format!(
+            "content warnings can be at most {MAX_CONTENT_WARNING_CHARS} characters"
+        )));
+    }
+    if content_warning.chars().any(char::is_control) {
+        return Err(Error::InvalidInput(
+            "content warnings can't contain control characters".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+// announces a status in a Bluesky post, returning the post's URI
+async fn crosspost(
+    agent: &ATProtoAgent,
+    timeout: Duration,
+    did: &Did,
+    status: &str,
+    content_warning: Option<&str>,
+) -> Result<String, Error> {
+    // don't give away a status the user put behind a content warning
+    let text = match content_warning {
+        Some(warning) => format!("current status (content warning: {warning})"),
+        None => format!("current status: {status}"),
+    };
+    let input_data = atproto::repo::create_record::InputData {
+        collection: bsky::feed::Post::NSID
+            .parse()
+            .expect("NSID is generated, should never fail to parse"),
+        record: atrium_api::record::KnownRecord::from(bsky::feed::post::RecordData {
+            created_at: Datetime::now(),
+            embed: None,
+            entities: None,
+            facets: None,
+            labels: None,
+            langs: None,
+            reply: None,
+            tags: None,
+            text,
+        })
+        .into(),
+        repo: did.clone().into(),
+        // chosen here rather than by the PDS, so a retried create can't make a duplicate
+        rkey: Some(new_rkey()),
+        swap_commit: None,
+        validate: None,
+    };
+    let record = write_record(agent, timeout, did, "record create", |swap_commit| {
+        agent.api.com.atproto.repo.create_record(
+            atproto::repo::create_record::InputData {
+                swap_commit: Some(swap_commit),
+                ..input_data.clone()
+            }
+            .into(),
+        )
+    })
+    .await?;
+    Ok(record.data.uri)
 }
 
 #[axum::debug_handler]
 pub async fn post_status(
     State(state): State<Arc<AppState>>,
+    locale: Locale,
+    hx_request: HxRequest,
+    RequireAuth(agent): RequireAuth,
     session: Session,
-    Form(input): Form<LoginInput>,
+    StatusForm { input, image }: StatusForm,
 ) -> Result<Response, Error> {
-    let Some(agent) = session_agent(state.as_ref(), &session).await? else {
-        return Ok(Redirect::to("/?error=logged_out").into_response());
-    };
+    let submitted_at = Instant::now();
+
+    if !state.config.is_allowed_status(&input.status) {
+        return Err(Error::InvalidStatus(input.status));
+    }
+    check_content_warning(input.content_warning.as_deref())?;
+    if let Some(image) = &image {
+        // blobs are public once uploaded, so they can't go with a followers-only status
+        if input.visibility != Visibility::Public {
+            return Err(Error::InvalidInput(
+                "only public statuses can have an image".to_owned(),
+            ));
+        }
+        validate::image(&image.mime_type, image.bytes.len())?;
+    }
 
     let did = agent_did(&agent).await;
-    let rkey = Tid::now(
-        0.try_into()
-            .expect("unexpected clock ID conversion failure"),
-    )
-    .to_string();
+    // a double submission replaces the status it duplicates, rather than posting it twice
+    let PostClaim { rkey, duplicate } = state.post_guard.claim(
+        &did,
+        input.post_token.as_deref(),
+        &input.status,
+        input.visibility,
+        Tid::now(
+            0.try_into()
+                .expect("unexpected clock ID conversion failure"),
+        )
+        .to_string(),
+    );
 
+    // a blob the record never ends up referencing is garbage collected by the PDS
+    let image = match image {
+        Some(image) => Some(upload_image(&agent, state.config.upstream_timeout, image).await?),
+        None => None,
+    };
     let status_record_data = statusphere::status::RecordData {
+        content_warning: sanitize_content_warning(input.content_warning),
         created_at: Datetime::now(),
+        image,
         status: input.status,
     };
+    validate::status_record(
+        &status_record_data,
+        &state.config.status_options,
+        state.config.max_clock_skew,
+    )?;
 
-    let input_data = atproto::repo::create_record::InputData {
-        collection: Status::NSID
+    let (uri, cid) = match input.visibility {
+        Visibility::Public => {
+            // a put rather than a create, so a duplicate replaces the record it duplicates
+            let input_data = atproto::repo::put_record::InputData {
+                collection: Status::NSID
+                    .parse()
+                    .expect("NSID is generated, should never fail to parse"),
+                record: lexicons::record::KnownRecord::from(status_record_data.clone()).into(),
+                repo: did.clone().into(),
+                rkey: RecordKey::new(rkey.clone()).expect("unexpected record key failure"),
+                swap_commit: None,
+                swap_record: None,
+                validate: Some(true),
+            };
+
+            // add to the repo
+            let record = write_record(
+                &agent,
+                state.config.upstream_timeout,
+                &did,
+                "record put",
+                |swap_commit| {
+                    agent.api.com.atproto.repo.put_record(
+                        atproto::repo::put_record::InputData {
+                            swap_commit: Some(swap_commit),
+                            ..input_data.clone()
+                        }
+                        .into(),
+                    )
+                },
+            )
+            .await?;
+            state
+                .metrics
+                .record_pds_write(record.data.uri.clone(), submitted_at);
+            (record.data.uri, Some(record.data.cid.as_ref().to_string()))
+        }
+        // followers-only statuses never leave the appview, so they have neither an at:// URI nor
+        // a CID
+        Visibility::Followers => (format!("private:{}/{rkey}", did.as_str()), None),
+    };
+
+    // followers-only statuses stay on this site, so they're never crossposted, and a duplicate's
+    // status already was if it was going to be
+    let crosspost_uri = if input.crosspost && input.visibility == Visibility::Public && !duplicate {
+        match crosspost(
+            &agent,
+            state.config.upstream_timeout,
+            &did,
+            &status_record_data.status,
+            status_record_data.content_warning.as_deref(),
+        )
+        .await
+        {
+            Ok(post_uri) => Some(post_uri),
+            // the status itself was posted, so don't fail the whole request
+            Err(e) => {
+                warn!("failed to crosspost {uri}: {e}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // also go aheard and add to the DB so the user sees their update immediately
+    state
+        .status_store
+        .insert(crate::store::Status {
+            uri: uri.clone(),
+            author_did: did,
+            status: status_record_data.status,
+            created_at: status_record_data.created_at,
+            indexed_at: Datetime::now(),
+            raw_created_at: None,
+            visibility: input.visibility,
+            content_warning: status_record_data.content_warning,
+            cid,
+            image: status_record_data
+                .image
+                .as_ref()
+                .and_then(StatusImage::from_blob),
+        })
+        .await?;
+    if let Some(post_uri) = crosspost_uri {
+        state.status_store.set_crosspost(&uri, post_uri).await?;
+    }
+
+    // htmx posts swap the refreshed feed in place of a reload
+    if hx_request.0 {
+        return render_feed(
+            state.as_ref(),
+            Some(agent.as_ref()),
+            &session,
+            locale,
+            FeedMode::All,
+            None,
+        )
+        .await;
+    }
+    Ok(Redirect::to("/").into_response())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PinInput {
+    uri: String,
+}
+
+pub async fn pin_status(
+    State(state): State<Arc<AppState>>,
+    RequireAuth(agent): RequireAuth,
+    Form(input): Form<PinInput>,
+) -> Result<Response, Error> {
+    let did = agent_did(&agent).await;
+    // users can only pin their own statuses
+    if !input
+        .uri
+        .starts_with(&format!("at://{}/{}/", did.as_str(), Status::NSID))
+    {
+        return Err(Error::InvalidPin(input.uri));
+    }
+
+    let pin_record_data = statusphere::pin::RecordData {
+        created_at: Datetime::now(),
+        subject: input.uri,
+    };
+
+    let input_data = atproto::repo::put_record::InputData {
+        collection: Pin::NSID
             .parse()
             .expect("NSID is generated, should never fail to parse"),
-        record: lexicons::record::KnownRecord::from(status_record_data.clone()).into(),
+        record: lexicons::record::KnownRecord::from(pin_record_data.clone()).into(),
         repo: did.clone().into(),
-        rkey: Some(RecordKey::new(rkey.to_owned()).expect("unexpected record key failure")),
+        // pins are a singleton record
+        rkey: RecordKey::new("self".to_owned()).expect("unexpected record key failure"),
         swap_commit: None,
+        swap_record: None,
         validate: None,
     };
-    // TOOD: validate input data
-
-    // add to the repo
-    let record = agent
-        .api
-        .com
-        .atproto
-        .repo
-        .create_record(input_data.into())
+
+    write_record(
+        &agent,
+        state.config.upstream_timeout,
+        &did,
+        "record put",
+        |swap_commit| {
+            agent.api.com.atproto.repo.put_record(
+                atproto::repo::put_record::InputData {
+                    swap_commit: Some(swap_commit),
+                    ..input_data.clone()
+                }
+                .into(),
+            )
+        },
+    )
+    .await?;
+
+    state
+        .status_store
+        .pin(&did, pin_record_data.subject, &pin_record_data.created_at)
         .await?;
 
-    // also go aheard and add to the DB so the user sees their update immediately
+    Ok(Redirect::to(&profile_path(&did)).into_response())
+}
+
+/// Deletes the user's pin record, so their profile no longer shows a pinned status.
+pub async fn unpin_status(
+    State(state): State<Arc<AppState>>,
+    RequireAuth(agent): RequireAuth,
+) -> Result<Response, Error> {
+    let did = agent_did(&agent).await;
+
+    let input_data = atproto::repo::delete_record::InputData {
+        collection: Pin::NSID
+            .parse()
+            .expect("NSID is generated, should never fail to parse"),
+        repo: did.clone().into(),
+        rkey: RecordKey::new("self".to_owned()).expect("unexpected record key failure"),
+        swap_commit: None,
+        swap_record: None,
+    };
+
+    write_record(
+        &agent,
+        state.config.upstream_timeout,
+        &did,
+        "record deletion",
+        |swap_commit| {
+            agent.api.com.atproto.repo.delete_record(
+                atproto::repo::delete_record::InputData {
+                    swap_commit: Some(swap_commit),
+                    ..input_data.clone()
+                }
+                .into(),
+            )
+        },
+    )
+    .await?;
+
+    state.status_store.unpin(&did).await?;
+
+    Ok(Redirect::to(&profile_path(&did)).into_response())
+}
+
+// where `did`'s pinned status is shown
+fn profile_path(did: &Did) -> String {
+    format!("/profile/{}/history", did.as_str())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReactInput {
+    subject: String,
+    emoji: String,
+}
+
+pub async fn react(
+    State(state): State<Arc<AppState>>,
+    RequireAuth(agent): RequireAuth,
+    Form(input): Form<ReactInput>,
+) -> Result<Response, Error> {
+    if !state.config.is_allowed_reaction(&input.emoji) {
+        return Err(Error::InvalidReaction(input.emoji));
+    }
+    // only public statuses can be reacted to, since reactions are public records
+    if state
+        .status_store
+        .fetch_by_uri(&input.subject)
+        .await?
+        .is_none_or(|status| status.visibility != Visibility::Public)
+    {
+        return Err(Error::StatusNotFound(input.subject));
+    }
+
+    let did = agent_did(&agent).await;
+    // each user reacts with each emoji once
+    if state
+        .status_store
+        .has_reacted(&did, &input.subject, &input.emoji)
+        .await?
+    {
+        return Ok(Redirect::to("/").into_response());
+    }
+    let reaction_record_data = statusphere::reaction::RecordData {
+        created_at: Datetime::now(),
+        emoji: input.emoji,
+        subject: input.subject,
+    };
+
+    let input_data = atproto::repo::create_record::InputData {
+        collection: Reaction::NSID
+            .parse()
+            .expect("NSID is generated, should never fail to parse"),
+        record: lexicons::record::KnownRecord::from(reaction_record_data.clone()).into(),
+        repo: did.clone().into(),
+        // chosen here rather than by the PDS, so a retried create can't make a duplicate
+        rkey: Some(new_rkey()),
+        swap_commit: None,
+        validate: None,
+    };
+
+    let record = write_record(
+        &agent,
+        state.config.upstream_timeout,
+        &did,
+        "record create",
+        |swap_commit| {
+            agent.api.com.atproto.repo.create_record(
+                atproto::repo::create_record::InputData {
+                    swap_commit: Some(swap_commit),
+                    ..input_data.clone()
+                }
+                .into(),
+            )
+        },
+    )
+    .await?;
+
+    // store it right away too, so the count updates before the ingester catches up
     state
         .status_store
-        .insert(crate::store::Status {
-            uri: record.data.uri,
+        .react(crate::store::Reaction {
+            uri: record.data.uri.clone(),
             author_did: did,
-            status: status_record_data.status,
-            created_at: status_record_data.created_at,
-            indexed_at: Datetime::now(),
+            subject: reaction_record_data.subject,
+            emoji: reaction_record_data.emoji,
+            created_at: reaction_record_data.created_at,
         })
         .await?;
 
     Ok(Redirect::to("/").into_response())
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::{
+        config::AppConfig,
+        store::StatusFilter,
+        test_support::{TestApp, did},
+    };
+
+    const ALICE: &str = "did:plc:alice0000000000000000000";
+    // enough of a PNG for the fake PDS, which doesn't look inside
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n not really the rest of a PNG";
+
+    #[tokio::test]
+    async fn quick_second_post_replaces_first() {
+        let app = TestApp::new().await;
+        let alice = did(ALICE);
+        let cookie = app.login(&alice).await;
+
+        // 🦋, then 🥳 straight after
+        for status in ["%F0%9F%A6%8B", "%F0%9F%A5%B3"] {
+            let response = app
+                .post_form("/status", &format!("status={status}"), Some(&cookie))
+                .await;
+            assert_eq!(response.status, StatusCode::SEE_OTHER);
+        }
+
+        let stored = app
+            .state
+            .status_store
+            .fetch_n(&StatusFilter::new().author(alice), 10)
+            .await
+            .expect("statuses are fetched");
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].status, "🥳");
+    }
+
+    #[tokio::test]
+    async fn resubmitted_form_replaces_status() {
+        let mut config = AppConfig::for_tests();
+        config.post_cooldown = Duration::ZERO;
+        let app = TestApp::with_config(config).await;
+        let alice = did(ALICE);
+        let cookie = app.login(&alice).await;
+
+        for token in ["form-1", "form-1", "form-2"] {
+            let response = app
+                .post_form(
+                    "/status",
+                    &format!("status=%F0%9F%A6%8B&post_token={token}"),
+                    Some(&cookie),
+                )
+                .await;
+            assert_eq!(response.status, StatusCode::SEE_OTHER);
+        }
+
+        // the resubmission replaced the first post, the other form's post didn't
+        let stored = app
+            .state
+            .status_store
+            .fetch_n(&StatusFilter::new().author(alice), 10)
+            .await
+            .expect("statuses are fetched");
+        assert_eq!(stored.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn posted_image_is_uploaded_and_shown_in_feed() {
+        let app = TestApp::new().await;
+        let alice = did(ALICE);
+        let cookie = app.login(&alice).await;
+
+        let response = app
+            .post_multipart(
+                "/status",
+                &[("status", "🦋")],
+                Some(("image/png", PNG)),
+                Some(&cookie),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::SEE_OTHER);
+
+        let stored = app
+            .state
+            .status_store
+            .fetch_one(&StatusFilter::new().author(alice))
+            .await
+            .expect("statuses are fetched")
+            .expect("status is stored");
+        // as the PDS (here the fake session) answered the upload
+        let image = stored.image.expect("status has an image");
+        assert_eq!(image.mime_type, "image/png");
+        let response = app.get("/", Some(&cookie)).await;
+        assert!(response.body.contains(&format!(
+            "/status/{ALICE}/{}/image",
+            stored.uri.rsplit('/').next().expect("URI has an rkey")
+        )));
+    }
+
+    #[tokio::test]
+    async fn images_are_only_for_public_statuses() {
+        let app = TestApp::new().await;
+        let alice = did(ALICE);
+        let cookie = app.login(&alice).await;
+
+        let response = app
+            .post_multipart(
+                "/status",
+                &[("status", "🦋"), ("visibility", "followers")],
+                Some(("image/png", PNG)),
+                Some(&cookie),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(
+            app.state
+                .status_store
+                .fetch_one(
+                    &StatusFilter::new()
+                        .author(alice.clone())
+                        .visible_to(&alice, [])
+                )
+                .await
+                .expect("statuses are fetched")
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn images_must_be_of_an_accepted_type() {
+        let app = TestApp::new().await;
+        let cookie = app.login(&did(ALICE)).await;
+
+        let response = app
+            .post_multipart(
+                "/status",
+                &[("status", "🦋")],
+                Some(("image/svg+xml", PNG)),
+                Some(&cookie),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn multipart_form_without_a_status_is_rejected() {
+        let app = TestApp::new().await;
+        let alice = did(ALICE);
+        let cookie = app.login(&alice).await;
+
+        let response = app
+            .post_multipart(
+                "/status",
+                &[("visibility", "public")],
+                Some(("image/png", PNG)),
+                Some(&cookie),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(
+            app.state
+                .status_store
+                .fetch_one(&StatusFilter::new().author(alice))
+                .await
+                .expect("statuses are fetched")
+                .is_none()
+        );
+    }
+}
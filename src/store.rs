@@ -1,23 +1,38 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr, time::Duration};
 
-use atrium_api::types::string::{Datetime, Did};
+use atrium_api::types::{
+    BlobRef, TypedBlobRef,
+    string::{Datetime, Did},
+};
 use atrium_common::store::Store;
 use atrium_oauth::store::{
     session::{Session, SessionStore},
     state::{InternalStateData, StateStore},
 };
+use futures::{Stream, TryStreamExt, stream};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tower_sessions_sqlx_store::sqlx::{self, FromRow, SqlitePool};
+#[cfg(feature = "mysql")]
+use tower_sessions_sqlx_store::sqlx::MySqlPool;
+#[cfg(feature = "postgres")]
+use tower_sessions_sqlx_store::sqlx::PgPool;
+use tower_sessions_sqlx_store::sqlx::{
+    self, FromRow, Row, SqlitePool,
+    migrate::{MigrateError, Migrator},
+};
+use tracing::instrument;
+
+use crate::{cursor::FeedCursor, envelope::EnvelopeCipher, validate::IMAGE_ACCEPT};
+
+#[cfg(feature = "redis")]
+pub mod redis;
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error(
-        "invalid table name '{0}': table names should start with an alphabetic character, \
-        followed by alphanumeric and underscore characters"
-    )]
-    InvalidTableName(String),
     #[error("migration: {0}")]
     MigrationFailed(sqlx::Error),
+    #[error("migration: {0}")]
+    MigrateFailed(MigrateError),
     #[error("insert: {0}")]
     InsertFailed(sqlx::Error),
     #[error("select: {0}")]
@@ -28,10 +43,238 @@ pub enum Error {
     DeleteAllFailed(sqlx::Error),
     #[error("invalid did: {0}")]
     InvalidDid(&'static str),
+    #[error("invalid datetime: {0}")]
+    InvalidDatetime(chrono::ParseError),
+    #[error("invalid visibility '{0}'")]
+    InvalidVisibility(String),
+    #[cfg(feature = "ingester")]
+    #[error("insert batcher closed")]
+    BatcherClosed,
     #[error("deserialization: {0}")]
     Deserialization(serde_json::Error),
     #[error("serialization: {0}")]
     Serialization(serde_json::Error),
+    #[error("encryption: {0}")]
+    Encryption(crate::envelope::Error),
+    #[cfg(feature = "redis")]
+    #[error("redis: {0}")]
+    Redis(::redis::RedisError),
+}
+
+/// The database everything is stored in, chosen at runtime by the `DATABASE_URL` scheme among
+/// those compiled in (SQLite always, MySQL and Postgres with their features).
+#[derive(Debug, Clone)]
+pub enum Db {
+    Sqlite(SqlitePool),
+    /// MySQL or MariaDB, for hosts that don't offer anything else.
+    #[cfg(feature = "mysql")]
+    MySql(MySqlPool),
+    #[cfg(feature = "postgres")]
+    Postgres(PgPool),
+}
+
+impl From<SqlitePool> for Db {
+    fn from(pool: SqlitePool) -> Self {
+        Db::Sqlite(pool)
+    }
+}
+
+#[cfg(feature = "mysql")]
+impl From<MySqlPool> for Db {
+    fn from(pool: MySqlPool) -> Self {
+        Db::MySql(pool)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl From<PgPool> for Db {
+    fn from(pool: PgPool) -> Self {
+        Db::Postgres(pool)
+    }
+}
+
+// evaluates `$body` with `$pool` bound to the database's pool, whichever kind it is, so queries
+// written in the SQL every database understands (see `Db::sql`) are only written once
+macro_rules! with_pool {
+    ($db:expr, $pool:ident => $body:expr) => {
+        match $db {
+            Db::Sqlite($pool) => $body,
+            #[cfg(feature = "mysql")]
+            Db::MySql($pool) => $body,
+            #[cfg(feature = "postgres")]
+            Db::Postgres($pool) => $body,
+        }
+    };
+}
+
+impl Db {
+    // `query`, written with `?` placeholders and backtick-quoted identifiers (which SQLite and
+    // MySQL both understand), in the database's own dialect
+    fn sql(&self, query: impl Into<String>) -> String {
+        let query = query.into();
+        match self {
+            Db::Sqlite(_) => query,
+            #[cfg(feature = "mysql")]
+            Db::MySql(_) => query,
+            #[cfg(feature = "postgres")]
+            Db::Postgres(_) => {
+                let mut placeholders = 0;
+                let mut rewritten = String::with_capacity(query.len());
+                for c in query.chars() {
+                    match c {
+                        '?' => {
+                            placeholders += 1;
+                            rewritten.push_str(&format!("${placeholders}"));
+                        }
+                        '`' => rewritten.push('"'),
+                        c => rewritten.push(c),
+                    }
+                }
+                rewritten
+            }
+        }
+    }
+
+    // the clause of an insert that updates the row with the same `key` instead, if there is one;
+    // `assignments` refer to the values being inserted as `excluded.column`, as in SQLite
+    fn on_conflict(&self, key: &str, assignments: &str) -> String {
+        match self {
+            Db::Sqlite(_) => format!("on conflict({key}) do update set {assignments}"),
+            #[cfg(feature = "postgres")]
+            Db::Postgres(_) => format!("on conflict({key}) do update set {assignments}"),
+            #[cfg(feature = "mysql")]
+            Db::MySql(_) => format!(
+                "on duplicate key update {}",
+                mysql_inserted_values(assignments)
+            ),
+        }
+    }
+
+    // the column numbering statuses in insertion order
+    fn sequence_column(&self) -> &'static str {
+        match self {
+            Db::Sqlite(_) => "rowid",
+            #[cfg(feature = "mysql")]
+            Db::MySql(_) => "seq",
+            #[cfg(feature = "postgres")]
+            Db::Postgres(_) => "seq",
+        }
+    }
+
+    // whether rows become visible in sequence order, so everything up to the highest sequence
+    // number seen is there: SQLite has a single writer, but MySQL and Postgres hand sequence
+    // numbers out before transactions commit, possibly in a different order
+    fn commits_in_sequence_order(&self) -> bool {
+        matches!(self, Db::Sqlite(_))
+    }
+
+    // the type to `cast` an aggregate to for it to come back as an `i64`
+    fn integer_type(&self) -> &'static str {
+        match self {
+            Db::Sqlite(_) => "integer",
+            #[cfg(feature = "mysql")]
+            Db::MySql(_) => "signed",
+            #[cfg(feature = "postgres")]
+            Db::Postgres(_) => "bigint",
+        }
+    }
+}
+
+// rewrites `excluded.column` as `values(column)`, which (unlike an alias of the inserted row)
+// MariaDB understands too
+#[cfg(feature = "mysql")]
+fn mysql_inserted_values(assignments: &str) -> String {
+    let mut rewritten = String::with_capacity(assignments.len());
+    let mut rest = assignments;
+    while let Some(start) = rest.find("excluded.") {
+        rewritten.push_str(&rest[..start]);
+        rest = &rest[start + "excluded.".len()..];
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        rewritten.push_str(&format!("values({})", &rest[..end]));
+        rest = &rest[end..];
+    }
+    rewritten.push_str(rest);
+    rewritten
+}
+
+// the schema, as the migrations in `migrations/` for each database
+static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("migrations/sqlite");
+#[cfg(feature = "mysql")]
+static MYSQL_MIGRATOR: Migrator = sqlx::migrate!("migrations/mysql");
+#[cfg(feature = "postgres")]
+static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("migrations/postgres");
+
+// the statuses table, which the tables of what relates to statuses are named after
+const STATUS_TABLE: &str = "status";
+
+// columns added to tables before the schema was versioned, which databases last opened back then
+// may be missing: (table, column, definition)
+const UNVERSIONED_COLUMNS: [(&str, &str, &str); 6] = [
+    ("status", "raw_created_at", "text"),
+    ("status", "visibility", "text not null default 'public'"),
+    ("status", "content_warning", "text"),
+    ("status", "cid", "text"),
+    ("oauth_session", "created_at", "text"),
+    ("oauth_state", "created_at", "text"),
+];
+
+/// Brings the database's schema up to date, running whichever migrations it hasn't had yet.
+pub async fn migrate(db: &Db) -> Result<(), Error> {
+    match db {
+        Db::Sqlite(pool) => {
+            upgrade_unversioned(pool).await?;
+            SQLITE_MIGRATOR.run(pool).await
+        }
+        #[cfg(feature = "mysql")]
+        Db::MySql(pool) => MYSQL_MIGRATOR.run(pool).await,
+        #[cfg(feature = "postgres")]
+        Db::Postgres(pool) => POSTGRES_MIGRATOR.run(pool).await,
+    }
+    .map_err(Error::MigrateFailed)
+}
+
+// the initial migration only creates what's missing, so a database from before migrations were
+// versioned first gets the columns its tables might lack (other databases were only supported after)
+async fn upgrade_unversioned(pool: &SqlitePool) -> Result<(), Error> {
+    let (versioned,): (i64,) = sqlx::query_as(
+        r#"
+        select count(*) from sqlite_master where type = 'table' and name = '_sqlx_migrations'
+        "#,
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(Error::MigrationFailed)?;
+    if versioned > 0 {
+        return Ok(());
+    }
+
+    for (table, column, definition) in UNVERSIONED_COLUMNS {
+        // a table that doesn't exist yet is created whole by the initial migration
+        let (tables, columns): (i64, i64) = sqlx::query_as(
+            r#"
+            select
+                (select count(*) from sqlite_master where type = 'table' and name = ?),
+                (select count(*) from pragma_table_info(?) where name = ?)
+            "#,
+        )
+        .bind(table)
+        .bind(table)
+        .bind(column)
+        .fetch_one(pool)
+        .await
+        .map_err(Error::MigrationFailed)?;
+        if tables > 0 && columns == 0 {
+            sqlx::query(&format!(
+                "alter table {table} add column {column} {definition}"
+            ))
+            .execute(pool)
+            .await
+            .map_err(Error::MigrationFailed)?;
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone)]
@@ -41,6 +284,138 @@ pub struct Status {
     pub status: String,
     pub created_at: Datetime,
     pub indexed_at: Datetime,
+    /// Client-provided `created_at`, if it was clamped by [`Status::clamp_created_at`].
+    pub raw_created_at: Option<Datetime>,
+    pub visibility: Visibility,
+    /// Label to show in place of the status until the viewer reveals it.
+    pub content_warning: Option<String>,
+    /// CID of the record, as last written; unknown for statuses stored before it was kept, and
+    /// followers-only statuses have no record.
+    pub cid: Option<String>,
+    /// Image attached to the status, if any.
+    pub image: Option<StatusImage>,
+}
+
+/// An image blob attached to a status, as served by its author's PDS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusImage {
+    pub cid: String,
+    pub mime_type: String,
+}
+
+impl StatusImage {
+    /// The image a record's blob ref points at, unless it isn't of a type the lexicon accepts
+    /// (records from other apps aren't validated, and these are served from our own origin).
+    pub fn from_blob(blob: &BlobRef) -> Option<Self> {
+        let image = match blob {
+            BlobRef::Typed(TypedBlobRef::Blob(blob)) => Self {
+                cid: blob.r#ref.0.to_string(),
+                mime_type: blob.mime_type.clone(),
+            },
+            BlobRef::Untyped(blob) => Self {
+                cid: blob.cid.clone(),
+                mime_type: blob.mime_type.clone(),
+            },
+        };
+        IMAGE_ACCEPT
+            .contains(&image.mime_type.as_str())
+            .then_some(image)
+    }
+}
+
+/// A Bluesky post crossposting a status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Crosspost {
+    pub post_uri: String,
+    /// Position of the crossposted status in the feed.
+    pub cursor: FeedCursor,
+}
+
+/// Longest content warning label we'll store, in characters.
+pub const MAX_CONTENT_WARNING_CHARS: usize = 64;
+
+/// Normalizes a client-provided content warning: strips control characters, trims and truncates
+/// it, and drops it entirely if nothing is left.
+pub fn sanitize_content_warning(content_warning: Option<String>) -> Option<String> {
+    let content_warning = content_warning?
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>();
+    let content_warning = content_warning
+        .trim()
+        .chars()
+        .take(MAX_CONTENT_WARNING_CHARS)
+        .collect::<String>();
+    let content_warning = content_warning.trim_end();
+    (!content_warning.is_empty()).then(|| content_warning.to_owned())
+}
+
+/// Who can see a status. Public statuses are records in the author's repo; followers-only
+/// statuses are never written to the repo, and only exist in this appview's database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    #[default]
+    Public,
+    Followers,
+}
+
+impl Visibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Followers => "followers",
+        }
+    }
+}
+
+impl FromStr for Visibility {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "public" => Ok(Visibility::Public),
+            "followers" => Ok(Visibility::Followers),
+            other => Err(Error::InvalidVisibility(other.to_owned())),
+        }
+    }
+}
+
+impl Status {
+    /// Clamps `created_at` to `indexed_at` if it's further than `max_skew` in the future (e.g.
+    /// due to client clock skew, or deliberate attempts to pin a status to the top of
+    /// date-sorted views). The original value is kept in `raw_created_at`.
+    pub fn clamp_created_at(mut self, max_skew: Duration) -> Self {
+        let max_skew = chrono::Duration::from_std(max_skew).unwrap_or(chrono::Duration::MAX);
+        let limit = self
+            .indexed_at
+            .as_ref()
+            .checked_add_signed(max_skew)
+            .unwrap_or(*self.indexed_at.as_ref());
+        if self.created_at.as_ref() > &limit {
+            let raw = std::mem::replace(&mut self.created_at, self.indexed_at.clone());
+            self.raw_created_at = Some(raw);
+        }
+        self
+    }
+
+    /// The status as read from its author's repo after the fact rather than as it was posted:
+    /// clamped as by [`Self::clamp_created_at`], and indexed as of when it was created.
+    pub fn into_historical(self, max_skew: Duration) -> Self {
+        let mut status = self.clamp_created_at(max_skew);
+        if status.created_at.as_ref() < status.indexed_at.as_ref() {
+            status.indexed_at = status.created_at.clone();
+        }
+        status
+    }
+
+    /// Cursor pointing just past this status in a feed.
+    pub fn cursor(&self) -> FeedCursor {
+        FeedCursor {
+            indexed_at: self.indexed_at.clone(),
+            uri: self.uri.clone(),
+        }
+    }
 }
 
 // sqlx FromRow derive doesn't play nice with re-exported sqlx from tower_sessions_sqlx_store,
@@ -51,6 +426,8 @@ where
     &'a str: sqlx::ColumnIndex<R>,
     String: sqlx::decode::Decode<'a, R::Database>,
     String: sqlx::types::Type<R::Database>,
+    Option<String>: sqlx::decode::Decode<'a, R::Database>,
+    Option<String>: sqlx::types::Type<R::Database>,
 {
     fn from_row(row: &'a R) -> Result<Self, sqlx::Error> {
         let uri: String = row.try_get("uri")?;
@@ -58,6 +435,12 @@ where
         let status: String = row.try_get("status")?;
         let created_at: String = row.try_get("created_at")?;
         let indexed_at: String = row.try_get("indexed_at")?;
+        let raw_created_at: Option<String> = row.try_get("raw_created_at")?;
+        let visibility: String = row.try_get("visibility")?;
+        let content_warning: Option<String> = row.try_get("content_warning")?;
+        let cid: Option<String> = row.try_get("cid")?;
+        let image_cid: Option<String> = row.try_get("image_cid")?;
+        let image_mime_type: Option<String> = row.try_get("image_mime_type")?;
         Ok(Status {
             uri,
             author_did: Did::new(author_did)
@@ -67,222 +450,1936 @@ where
                 .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
             indexed_at: Datetime::from_str(indexed_at.as_str())
                 .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+            raw_created_at: raw_created_at
+                .map(|dt| Datetime::from_str(dt.as_str()))
+                .transpose()
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+            visibility: Visibility::from_str(&visibility)
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+            content_warning,
+            cid,
+            image: image_cid
+                .zip(image_mime_type)
+                .map(|(cid, mime_type)| StatusImage { cid, mime_type }),
         })
     }
 }
 
+/// Composable filter for status queries. Every condition is bound as a query parameter.
+#[derive(Debug, Clone, Default)]
+pub struct StatusFilter {
+    author: Option<Did>,
+    status: Option<String>,
+    // authors whose followers-only statuses may be returned; empty means public statuses only
+    audience: Vec<Did>,
+    latest_per_author: bool,
+}
+
+impl StatusFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only statuses posted by `author`.
+    pub fn author(mut self, author: Did) -> Self {
+        self.author = Some(author);
+        self
+    }
+
+    /// Only statuses with this exact status (emoji).
+    pub fn status(mut self, status: impl Into<String>) -> Self {
+        self.status = Some(status.into());
+        self
+    }
+
+    /// Only each author's most recent matching status (their current status).
+    pub fn latest_per_author(mut self) -> Self {
+        self.latest_per_author = true;
+        self
+    }
+
+    /// Also include followers-only statuses from `viewer` and the authors in `followed` (who
+    /// the caller has checked `viewer` follows). Without this, only public statuses match.
+    pub fn visible_to(mut self, viewer: &Did, followed: impl IntoIterator<Item = Did>) -> Self {
+        self.audience = std::iter::once(viewer.clone()).chain(followed).collect();
+        self
+    }
+
+    /// Whether `status` is visible to this filter's audience, as with [`visible_to`]: it's
+    /// public, or it's by one of the audience. For statuses fetched without the filter, e.g. with
+    /// [`StatusStore::fetch_by_uri`].
+    ///
+    /// [`visible_to`]: Self::visible_to
+    pub fn is_visible(&self, status: &Status) -> bool {
+        status.visibility == Visibility::Public || self.audience.contains(&status.author_did)
+    }
+
+    // SQL conditions (to be joined with `and`) and their parameters, in order
+    fn conditions(&self) -> (Vec<String>, Vec<String>) {
+        let mut conditions = vec![];
+        let mut params = vec![];
+        if self.audience.is_empty() {
+            conditions.push("visibility = 'public'".to_owned());
+        } else {
+            let placeholders = vec!["?"; self.audience.len()].join(", ");
+            conditions.push(format!(
+                "(visibility = 'public' or author_did in ({placeholders}))"
+            ));
+            params.extend(self.audience.iter().map(|did| did.as_str().to_owned()));
+        }
+        if let Some(author) = &self.author {
+            conditions.push("author_did = ?".to_owned());
+            params.push(author.as_str().to_owned());
+        }
+        if let Some(status) = &self.status {
+            conditions.push("status = ?".to_owned());
+            params.push(status.clone());
+        }
+        (conditions, params)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct StatusStore {
-    pool: SqlitePool,
-    table_name: String,
+    db: Db,
 }
 
 impl StatusStore {
-    pub fn new(pool: SqlitePool, table_name: impl AsRef<str>) -> Result<Self, Error> {
-        let table_name = table_name.as_ref();
-        if !is_valid_table_name(table_name) {
-            return Err(Error::InvalidTableName(table_name.to_owned()));
+    pub fn new(db: impl Into<Db>) -> Self {
+        StatusStore { db: db.into() }
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn insert(&self, status: Status) -> Result<(), Error> {
+        let query = self.insert_query();
+        with_pool!(&self.db, pool => {
+            sqlx::query(&query)
+                .bind(status.uri)
+                .bind(status.author_did.as_str())
+                .bind(status.status)
+                .bind(status.created_at.as_str())
+                .bind(status.indexed_at.as_str())
+                .bind(status.raw_created_at.as_ref().map(|dt| dt.as_str()))
+                .bind(status.visibility.as_str())
+                .bind(status.content_warning)
+                .bind(status.cid)
+                .bind(status.image.as_ref().map(|image| image.cid.clone()))
+                .bind(status.image.map(|image| image.mime_type))
+                .execute(pool)
+                .await
+                .map_err(Error::InsertFailed)?;
+        });
+        Ok(())
+    }
+
+    /// Inserts (or updates) many statuses in a single transaction.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn insert_many(&self, statuses: Vec<Status>) -> Result<(), Error> {
+        if statuses.is_empty() {
+            return Ok(());
+        }
+        let query = self.insert_query();
+        with_pool!(&self.db, pool => {
+            let mut tx = pool.begin().await.map_err(Error::InsertFailed)?;
+            for status in statuses {
+                sqlx::query(&query)
+                    .bind(status.uri)
+                    .bind(status.author_did.as_str())
+                    .bind(status.status)
+                    .bind(status.created_at.as_str())
+                    .bind(status.indexed_at.as_str())
+                    .bind(status.raw_created_at.as_ref().map(|dt| dt.as_str()))
+                    .bind(status.visibility.as_str())
+                    .bind(status.content_warning)
+                    .bind(status.cid)
+                    .bind(status.image.as_ref().map(|image| image.cid.clone()))
+                    .bind(status.image.map(|image| image.mime_type))
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(Error::InsertFailed)?;
+            }
+            tx.commit().await.map_err(Error::InsertFailed)?;
+        });
+        Ok(())
+    }
+
+    /// Deletes the statuses with the given URIs in a single transaction, returning how many
+    /// were deleted.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn delete_many(&self, uris: &[String]) -> Result<u64, Error> {
+        if uris.is_empty() {
+            return Ok(0);
         }
-        Ok(StatusStore {
-            pool,
-            table_name: table_name.to_owned(),
+        let query = self.db.sql(format!(
+            r#"
+            delete from {table_name} where uri = ?
+            "#,
+            table_name = STATUS_TABLE
+        ));
+        with_pool!(&self.db, pool => {
+            let mut tx = pool.begin().await.map_err(Error::DeleteFailed)?;
+            let mut deleted = 0;
+            for uri in uris {
+                deleted += sqlx::query(&query)
+                    .bind(uri)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(Error::DeleteFailed)?
+                    .rows_affected();
+            }
+            tx.commit().await.map_err(Error::DeleteFailed)?;
+            Ok(deleted)
         })
     }
 
-    pub async fn migrate(&self) -> Result<(), Error> {
-        let query = format!(
+    /// Deletes everything stored about `did`'s activity (their statuses, with the reactions to
+    /// them and their crossposts, their own reactions and pin, and their hourly rollup counts) in
+    /// a single transaction, returning how many statuses were deleted.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn delete_author(&self, did: &Did) -> Result<u64, Error> {
+        // what refers to their statuses goes first, while the statuses can still be found
+        let queries = [
             r#"
-            create table if not exists {table_name}
-            (
-                uri text primary key,
-                author_did text not null,
-                status text not null,
-                created_at text not null,
-                indexed_at text not null
-            )
+            delete from {table_name}_crosspost
+            where subject in (select uri from {table_name} where author_did = ?)
             "#,
-            table_name = self.table_name
-        );
-        sqlx::query(&query)
-            .execute(&self.pool)
-            .await
-            .map_err(Error::MigrationFailed)?;
-        Ok(())
+            r#"
+            delete from {table_name}_reaction
+            where subject in (select uri from {table_name} where author_did = ?)
+            "#,
+            r#"
+            delete from {table_name}_reaction where author_did = ?
+            "#,
+            r#"
+            delete from {table_name}_pin where author_did = ?
+            "#,
+            r#"
+            delete from {table_name}_hourly_author where author_did = ?
+            "#,
+        ]
+        .map(|query| self.db.sql(query.replace("{table_name}", STATUS_TABLE)));
+        let query = self.db.sql(format!(
+            r#"
+            delete from {table_name} where author_did = ?
+            "#,
+            table_name = STATUS_TABLE
+        ));
+        with_pool!(&self.db, pool => {
+            let mut tx = pool.begin().await.map_err(Error::DeleteFailed)?;
+            for query in &queries {
+                sqlx::query(query)
+                    .bind(did.as_str())
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(Error::DeleteFailed)?;
+            }
+            let deleted = sqlx::query(&query)
+                .bind(did.as_str())
+                .execute(&mut *tx)
+                .await
+                .map_err(Error::DeleteFailed)?
+                .rows_affected();
+            tx.commit().await.map_err(Error::DeleteFailed)?;
+            Ok(deleted)
+        })
     }
 
-    pub async fn insert(&self, status: Status) -> Result<(), Error> {
-        let query = format!(
+    fn insert_query(&self) -> String {
+        self.db.sql(format!(
             r#"
             insert into {table_name}
-                (uri, author_did, status, created_at, indexed_at)
+                (uri, author_did, status, created_at, indexed_at, raw_created_at, visibility,
+                    content_warning, cid, image_cid, image_mime_type)
                 values
-                (?, ?, ?, ?, ?)
-            on conflict(uri) do update set
+                (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            {on_conflict}
+            "#,
+            table_name = STATUS_TABLE,
+            on_conflict = self.db.on_conflict(
+                "uri",
+                r#"
                 author_did = excluded.author_did,
                 status = excluded.status,
                 created_at = excluded.created_at,
-                indexed_at = excluded.indexed_at
+                indexed_at = excluded.indexed_at,
+                raw_created_at = excluded.raw_created_at,
+                visibility = excluded.visibility,
+                content_warning = excluded.content_warning,
+                cid = excluded.cid,
+                image_cid = excluded.image_cid,
+                image_mime_type = excluded.image_mime_type
+                "#
+            ),
+        ))
+    }
+
+    // all statuses queries go through here, so filters are always bound parameters
+    async fn fetch(
+        &self,
+        filter: &StatusFilter,
+        after: Option<&FeedCursor>,
+        count: usize,
+    ) -> Result<Vec<Status>, Error> {
+        let (mut conditions, mut params) = filter.conditions();
+        let mut source = STATUS_TABLE.to_owned();
+        if filter.latest_per_author {
+            // rank each author's matching statuses, and keep only the newest; the cursor applies
+            // to the result, not the ranking, so pages don't resurface older statuses
+            let where_clause = where_clause(&std::mem::take(&mut conditions));
+            source = format!(
+                r#"
+                (
+                    select {table_name}.*, row_number() over (
+                        partition by author_did order by indexed_at desc, uri desc
+                    ) as author_rank
+                    from {table_name}
+                    {where_clause}
+                ) as ranked
+                "#,
+                table_name = STATUS_TABLE,
+            );
+            conditions.push("author_rank = 1".to_owned());
+        }
+        if let Some(after) = after {
+            conditions.push("(indexed_at < ? or (indexed_at = ? and uri < ?))".to_owned());
+            params.extend([
+                after.indexed_at.as_str().to_owned(),
+                after.indexed_at.as_str().to_owned(),
+                after.uri.clone(),
+            ]);
+        }
+        let where_clause = where_clause(&conditions);
+        let query = self.db.sql(format!(
+            r#"
+            select uri, author_did, status, created_at, indexed_at, raw_created_at, visibility,
+                content_warning, cid, image_cid, image_mime_type
+            from {source}
+            {where_clause}
+            order by indexed_at desc, uri desc
+            limit ?
             "#,
-            table_name = self.table_name
-        );
-        sqlx::query(&query)
-            .bind(status.uri)
-            .bind(status.author_did.as_str())
-            .bind(status.status)
-            .bind(status.created_at.as_str())
-            .bind(status.indexed_at.as_str())
-            .execute(&self.pool)
-            .await
-            .map_err(Error::InsertFailed)?;
+        ));
+        with_pool!(&self.db, pool => {
+            let mut query = sqlx::query_as(&query);
+            for param in params {
+                query = query.bind(param);
+            }
+            query
+                .bind(count as i64)
+                .fetch_all(pool)
+                .await
+                .map_err(Error::SelectFailed)
+        })
+    }
+
+    /// Streams every status (of any visibility) in insertion order, fetching them from the
+    /// database a page at a time.
+    pub fn stream_all(&self) -> impl Stream<Item = Result<Status, Error>> + '_ {
+        stream::try_unfold(Some(0), move |after| async move {
+            let Some(after) = after else {
+                return Ok(None);
+            };
+            let query = self.db.sql(format!(
+                r#"
+                select {sequence} as seq, uri, author_did, status, created_at, indexed_at,
+                    raw_created_at, visibility, content_warning, cid, image_cid,
+                    image_mime_type
+                from {table_name}
+                where {sequence} > ?
+                order by {sequence} asc
+                limit ?
+                "#,
+                table_name = STATUS_TABLE,
+                sequence = self.db.sequence_column(),
+            ));
+            let (statuses, next) = with_pool!(&self.db, pool => {
+                let rows = sqlx::query(&query)
+                    .bind(after)
+                    .bind(STREAM_PAGE_SIZE)
+                    .fetch_all(pool)
+                    .await
+                    .map_err(Error::SelectFailed)?;
+                let next = match rows.last() {
+                    Some(row) if rows.len() as i64 == STREAM_PAGE_SIZE => Some(
+                        row.try_get::<i64, _>("seq")
+                            .map_err(Error::SelectFailed)?,
+                    ),
+                    _ => None,
+                };
+                let statuses = rows
+                    .iter()
+                    .map(|row| Status::from_row(row).map_err(Error::SelectFailed))
+                    .collect::<Vec<_>>();
+                (statuses, next)
+            });
+            Ok(Some((stream::iter(statuses), next)))
+        })
+        .try_flatten()
+    }
+
+    /// Authors with at least one followers-only status.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn followers_only_authors(&self) -> Result<Vec<Did>, Error> {
+        let query = self.db.sql(format!(
+            r#"
+            select distinct author_did from {table_name} where visibility = 'followers'
+            "#,
+            table_name = STATUS_TABLE
+        ));
+        let data: Vec<(String,)> = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .fetch_all(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+        data.into_iter()
+            .map(|(did,)| Did::new(did).map_err(Error::InvalidDid))
+            .collect()
+    }
+
+    /// URIs of an author's public statuses, i.e. those that should exist in their repo.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn public_uris(&self, author: &Did) -> Result<Vec<String>, Error> {
+        let query = self.db.sql(format!(
+            r#"
+            select uri from {table_name} where author_did = ? and visibility = 'public'
+            "#,
+            table_name = STATUS_TABLE
+        ));
+        let data: Vec<(String,)> = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .bind(author.as_str())
+                .fetch_all(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+        Ok(data.into_iter().map(|(uri,)| uri).collect())
+    }
+
+    /// The status at `uri`, of any visibility, looked up by primary key.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn fetch_by_uri(&self, uri: &str) -> Result<Option<Status>, Error> {
+        let query = self.db.sql(format!(
+            r#"
+            select uri, author_did, status, created_at, indexed_at, raw_created_at, visibility,
+                content_warning, cid, image_cid, image_mime_type
+            from {table_name}
+            where uri = ?
+            "#,
+            table_name = STATUS_TABLE,
+        ));
+        with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .bind(uri)
+                .fetch_optional(pool)
+                .await
+                .map_err(Error::SelectFailed)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn fetch_n(&self, filter: &StatusFilter, count: usize) -> Result<Vec<Status>, Error> {
+        self.fetch(filter, None, count).await
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn fetch_one(&self, filter: &StatusFilter) -> Result<Option<Status>, Error> {
+        let mut results = self.fetch(filter, None, 1).await?;
+        Ok(results.pop())
+    }
+
+    /// Up to `count` public statuses strictly after `after` in `(indexed_at, uri)` order, oldest
+    /// first, for clients catching up on what they haven't seen: the last one's cursor is where
+    /// the next page starts, even if it ends partway through statuses indexed at the same time.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn fetch_since(
+        &self,
+        after: &FeedCursor,
+        count: usize,
+    ) -> Result<Vec<Status>, Error> {
+        let (mut conditions, mut params) = StatusFilter::new().conditions();
+        conditions.push("(indexed_at > ? or (indexed_at = ? and uri > ?))".to_owned());
+        params.extend([
+            after.indexed_at.as_str().to_owned(),
+            after.indexed_at.as_str().to_owned(),
+            after.uri.clone(),
+        ]);
+        let query = self.db.sql(format!(
+            r#"
+            select uri, author_did, status, created_at, indexed_at, raw_created_at, visibility,
+                content_warning, cid, image_cid, image_mime_type
+            from {table_name}
+            {where_clause}
+            order by indexed_at asc, uri asc
+            limit ?
+            "#,
+            table_name = STATUS_TABLE,
+            where_clause = where_clause(&conditions),
+        ));
+        with_pool!(&self.db, pool => {
+            let mut query = sqlx::query_as(&query);
+            for param in params {
+                query = query.bind(param);
+            }
+            query
+                .bind(count as i64)
+                .fetch_all(pool)
+                .await
+                .map_err(Error::SelectFailed)
+        })
+    }
+
+    /// Summary of the statuses matching `filter` (ignoring `latest_per_author`), plus the
+    /// reactions and pins, that changes whenever a page of them could render differently.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn feed_version(&self, filter: &StatusFilter) -> Result<FeedVersion, Error> {
+        let (conditions, params) = filter.conditions();
+        let query = self.db.sql(format!(
+            r#"
+            select max(indexed_at), count(*),
+                (select count(*) from {table_name}_reaction),
+                (select max(created_at) from {table_name}_pin)
+            from {table_name}
+            {where_clause}
+            "#,
+            table_name = STATUS_TABLE,
+            where_clause = where_clause(&conditions),
+        ));
+        let (latest_indexed_at, statuses, reactions, latest_pin) = with_pool!(&self.db, pool => {
+            let mut query = sqlx::query_as(&query);
+            for param in params {
+                query = query.bind(param);
+            }
+            query.fetch_one(pool).await.map_err(Error::SelectFailed)?
+        });
+        Ok(FeedVersion {
+            latest_indexed_at,
+            statuses,
+            reactions,
+            latest_pin,
+        })
+    }
+
+    /// Fetches a page of up to `count` statuses ordered by `(indexed_at, uri)` descending,
+    /// starting strictly after `after` (keyset pagination).
+    #[instrument(level = "debug", skip_all)]
+    pub async fn fetch_page(
+        &self,
+        filter: &StatusFilter,
+        after: Option<&FeedCursor>,
+        count: usize,
+    ) -> Result<Vec<Status>, Error> {
+        self.fetch(filter, after, count).await
+    }
+
+    /// Pins the status at `subject` for `author`, replacing any previously pinned status.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn pin(
+        &self,
+        author: &Did,
+        subject: impl AsRef<str>,
+        created_at: &Datetime,
+    ) -> Result<(), Error> {
+        let query = self.db.sql(format!(
+            r#"
+            insert into {table_name}_pin
+                (author_did, subject, created_at)
+                values
+                (?, ?, ?)
+            {on_conflict}
+            "#,
+            table_name = STATUS_TABLE,
+            on_conflict = self.db.on_conflict(
+                "author_did",
+                "subject = excluded.subject, created_at = excluded.created_at"
+            ),
+        ));
+        with_pool!(&self.db, pool => {
+            sqlx::query(&query)
+                .bind(author.as_str())
+                .bind(subject.as_ref())
+                .bind(created_at.as_str())
+                .execute(pool)
+                .await
+                .map_err(Error::InsertFailed)?;
+        });
         Ok(())
     }
 
-    async fn fetch(&self, author: Option<Did>, count: usize) -> Result<Vec<Status>, Error> {
-        let where_clause = author
-            .map(|did| format!("where author_did = \"{}\"", did.as_str()))
-            .unwrap_or(String::new());
-        let query = format!(
+    /// Removes whichever status `author` has pinned, if any.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn unpin(&self, author: &Did) -> Result<(), Error> {
+        let query = self.db.sql(format!(
+            "delete from {table_name}_pin where author_did = ?",
+            table_name = STATUS_TABLE,
+        ));
+        with_pool!(&self.db, pool => {
+            sqlx::query(&query)
+                .bind(author.as_str())
+                .execute(pool)
+                .await
+                .map_err(Error::DeleteFailed)?;
+        });
+        Ok(())
+    }
+
+    /// Inserts (or updates) a reaction, replacing any other reaction by its author to the same
+    /// status with the same emoji.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn react(&self, reaction: Reaction) -> Result<(), Error> {
+        let replace_query = self.db.sql(format!(
+            r#"
+            delete from {table_name}_reaction
+            where author_did = ? and subject = ? and emoji = ? and uri <> ?
+            "#,
+            table_name = STATUS_TABLE,
+        ));
+        let query = self.db.sql(format!(
             r#"
-            select uri, author_did, status, created_at, indexed_at
-            from "{table_name}"
+            insert into {table_name}_reaction
+                (uri, author_did, subject, emoji, created_at)
+                values
+                (?, ?, ?, ?, ?)
+            {on_conflict}
+            "#,
+            table_name = STATUS_TABLE,
+            on_conflict = self.db.on_conflict(
+                "uri",
+                r#"
+                subject = excluded.subject,
+                emoji = excluded.emoji,
+                created_at = excluded.created_at
+                "#
+            ),
+        ));
+        with_pool!(&self.db, pool => {
+            let mut tx = pool.begin().await.map_err(Error::InsertFailed)?;
+            sqlx::query(&replace_query)
+                .bind(reaction.author_did.as_str())
+                .bind(&reaction.subject)
+                .bind(&reaction.emoji)
+                .bind(&reaction.uri)
+                .execute(&mut *tx)
+                .await
+                .map_err(Error::DeleteFailed)?;
+            sqlx::query(&query)
+                .bind(reaction.uri)
+                .bind(reaction.author_did.as_str())
+                .bind(reaction.subject)
+                .bind(reaction.emoji)
+                .bind(reaction.created_at.as_str())
+                .execute(&mut *tx)
+                .await
+                .map_err(Error::InsertFailed)?;
+            tx.commit().await.map_err(Error::InsertFailed)?;
+        });
+        Ok(())
+    }
+
+    /// Whether `author` has reacted to the status at `subject` with `emoji`.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn has_reacted(
+        &self,
+        author: &Did,
+        subject: &str,
+        emoji: &str,
+    ) -> Result<bool, Error> {
+        let query = self.db.sql(format!(
+            r#"
+            select count(*) from {table_name}_reaction
+            where author_did = ? and subject = ? and emoji = ?
+            "#,
+            table_name = STATUS_TABLE,
+        ));
+        let (count,): (i64,) = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .bind(author.as_str())
+                .bind(subject)
+                .bind(emoji)
+                .fetch_one(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+        Ok(count > 0)
+    }
+
+    /// Reaction counts for each of `subjects` that has any, most common first. Each user counts
+    /// once per emoji, however many reaction records they've created.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn reaction_counts(
+        &self,
+        subjects: &[String],
+    ) -> Result<HashMap<String, Vec<ReactionCount>>, Error> {
+        if subjects.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let query = self.db.sql(format!(
+            r#"
+            select subject, emoji, count(distinct author_did) as reactions
+            from {table_name}_reaction
+            where subject in ({placeholders})
+            group by subject, emoji
+            order by reactions desc, emoji
+            "#,
+            table_name = STATUS_TABLE,
+            placeholders = vec!["?"; subjects.len()].join(", ")
+        ));
+        let data = with_pool!(&self.db, pool => {
+            let mut query = sqlx::query_as::<_, (String, String, i64)>(&query);
+            for subject in subjects {
+                query = query.bind(subject);
+            }
+            query.fetch_all(pool).await.map_err(Error::SelectFailed)?
+        });
+
+        let mut counts = HashMap::<String, Vec<ReactionCount>>::new();
+        for (subject, emoji, count) in data {
+            counts
+                .entry(subject)
+                .or_default()
+                .push(ReactionCount { emoji, count });
+        }
+        Ok(counts)
+    }
+
+    /// Records that `post_uri` is a Bluesky post crossposting the status at `subject`.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn set_crosspost(
+        &self,
+        subject: impl AsRef<str>,
+        post_uri: impl AsRef<str>,
+    ) -> Result<(), Error> {
+        let query = self.db.sql(format!(
+            r#"
+            insert into {table_name}_crosspost
+                (subject, post_uri)
+                values
+                (?, ?)
+            {on_conflict}
+            "#,
+            table_name = STATUS_TABLE,
+            on_conflict = self
+                .db
+                .on_conflict("subject", "post_uri = excluded.post_uri"),
+        ));
+        with_pool!(&self.db, pool => {
+            sqlx::query(&query)
+                .bind(subject.as_ref())
+                .bind(post_uri.as_ref())
+                .execute(pool)
+                .await
+                .map_err(Error::InsertFailed)?;
+        });
+        Ok(())
+    }
+
+    /// URI of the Bluesky post crossposting the status at `subject`, if any.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn fetch_crosspost(&self, subject: impl AsRef<str>) -> Result<Option<String>, Error> {
+        let query = self.db.sql(format!(
+            r#"
+            select post_uri from {table_name}_crosspost where subject = ?
+            "#,
+            table_name = STATUS_TABLE
+        ));
+        let data: Option<(String,)> = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .bind(subject.as_ref())
+                .fetch_optional(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+        Ok(data.map(|(post_uri,)| post_uri))
+    }
+
+    /// Fetches a page of up to `count` crossposts of public statuses, ordered like the statuses
+    /// by `(indexed_at, uri)` descending, starting strictly after `after`.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn fetch_crossposts(
+        &self,
+        after: Option<&FeedCursor>,
+        count: usize,
+    ) -> Result<Vec<Crosspost>, Error> {
+        let mut conditions = vec![format!("{STATUS_TABLE}.visibility = 'public'")];
+        let mut params = vec![];
+        if let Some(after) = after {
+            conditions.push(format!(
+                "({STATUS_TABLE}.indexed_at < ? or ({STATUS_TABLE}.indexed_at = ? and {STATUS_TABLE}.uri < ?))"
+            ));
+            params.extend([
+                after.indexed_at.as_str().to_owned(),
+                after.indexed_at.as_str().to_owned(),
+                after.uri.clone(),
+            ]);
+        }
+        let query = self.db.sql(format!(
+            r#"
+            select {table_name}.indexed_at, {table_name}.uri, {table_name}_crosspost.post_uri
+            from {table_name}_crosspost
+            join {table_name} on {table_name}.uri = {table_name}_crosspost.subject
             {where_clause}
-            order by indexed_at desc
-            limit {count}
+            order by {table_name}.indexed_at desc, {table_name}.uri desc
+            limit ?
+            "#,
+            table_name = STATUS_TABLE,
+            where_clause = where_clause(&conditions),
+        ));
+        let data: Vec<(String, String, String)> = with_pool!(&self.db, pool => {
+            let mut query = sqlx::query_as(&query);
+            for param in params {
+                query = query.bind(param);
+            }
+            query
+                .bind(count as i64)
+                .fetch_all(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+
+        data.into_iter()
+            .map(|(indexed_at, uri, post_uri)| {
+                Ok(Crosspost {
+                    cursor: FeedCursor {
+                        indexed_at: Datetime::from_str(&indexed_at)
+                            .map_err(Error::InvalidDatetime)?,
+                        uri,
+                    },
+                    post_uri,
+                })
+            })
+            .collect()
+    }
+
+    /// Folds statuses inserted since the last rollup into the hourly rollup tables, returning
+    /// the number of statuses rolled up.
+    ///
+    /// Only new rows are counted; updates to already-rolled-up statuses aren't reflected. On
+    /// MySQL and Postgres, statuses are only rolled up by the run after the one that first saw
+    /// them, by which time any statuses numbered before them have committed too.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn rollup(&self) -> Result<u64, Error> {
+        let state_query = self.db.sql(format!(
+            "select last_rowid, pending_rowid from {table_name}_rollup_state where id = 0",
+            table_name = STATUS_TABLE
+        ));
+        let max_query = self.db.sql(format!(
+            "select coalesce(max({sequence}), 0) from {table_name}",
+            table_name = STATUS_TABLE,
+            sequence = self.db.sequence_column(),
+        ));
+        let count_query = self.db.sql(format!(
+            "select count(*) from {table_name} where {sequence} > ? and {sequence} <= ?",
+            table_name = STATUS_TABLE,
+            sequence = self.db.sequence_column(),
+        ));
+        // hours are the first 13 characters of the RFC 3339 timestamp: YYYY-MM-DDTHH
+        let rollup_queries = [("hourly_author", "author_did"), ("hourly_emoji", "status")].map(
+            |(rollup_table, column)| {
+                self.db.sql(format!(
+                    r#"
+                    insert into {table_name}_{rollup_table} (hour, {column}, posts)
+                        select substr(indexed_at, 1, 13), {column}, count(*)
+                        from {table_name}
+                        where {sequence} > ? and {sequence} <= ? and visibility = 'public'
+                        group by 1, 2
+                    {on_conflict}
+                    "#,
+                    table_name = STATUS_TABLE,
+                    sequence = self.db.sequence_column(),
+                    // Postgres can't tell the row already there from the inserted one without the
+                    // table name
+                    on_conflict = self.db.on_conflict(
+                        &format!("hour, {column}"),
+                        &format!("posts = {STATUS_TABLE}_{rollup_table}.posts + excluded.posts")
+                    ),
+                ))
+            },
+        );
+        let set_state_query = self.db.sql(format!(
+            r#"
+            insert into {table_name}_rollup_state (id, last_rowid, pending_rowid) values (0, ?, ?)
+            {on_conflict}
+            "#,
+            table_name = STATUS_TABLE,
+            on_conflict = self.db.on_conflict(
+                "id",
+                "last_rowid = excluded.last_rowid, pending_rowid = excluded.pending_rowid"
+            ),
+        ));
+        let in_order = self.db.commits_in_sequence_order();
+
+        with_pool!(&self.db, pool => {
+            let mut tx = pool.begin().await.map_err(Error::InsertFailed)?;
+
+            let state: Option<(i64, i64)> = sqlx::query_as(&state_query)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(Error::SelectFailed)?;
+            let (last_rowid, pending_rowid) = state.unwrap_or((0, 0));
+
+            let (max_rowid,): (i64,) = sqlx::query_as(&max_query)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(Error::SelectFailed)?;
+            // statuses numbered up to the previous run's maximum have all committed by now,
+            // unless their transaction took longer than the rollup interval
+            let up_to = if in_order {
+                max_rowid
+            } else {
+                pending_rowid.max(last_rowid)
+            };
+            if up_to == last_rowid && max_rowid == pending_rowid {
+                return Ok(0);
+            }
+
+            let (count,): (i64,) = sqlx::query_as(&count_query)
+                .bind(last_rowid)
+                .bind(up_to)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(Error::SelectFailed)?;
+            if count > 0 {
+                for query in &rollup_queries {
+                    sqlx::query(query)
+                        .bind(last_rowid)
+                        .bind(up_to)
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(Error::InsertFailed)?;
+                }
+            }
+
+            sqlx::query(&set_state_query)
+                .bind(up_to)
+                .bind(max_rowid)
+                .execute(&mut *tx)
+                .await
+                .map_err(Error::InsertFailed)?;
+
+            tx.commit().await.map_err(Error::InsertFailed)?;
+            Ok(count as u64)
+        })
+    }
+
+    /// Total public statuses and distinct authors of them, from the rollup tables (so as of the
+    /// last rollup).
+    #[instrument(level = "debug", skip_all)]
+    pub async fn totals(&self) -> Result<Totals, Error> {
+        let query = self.db.sql(format!(
+            r#"
+            select cast(coalesce(sum(posts), 0) as {integer}), count(distinct author_did)
+            from {table_name}_hourly_author
+            "#,
+            table_name = STATUS_TABLE,
+            integer = self.db.integer_type(),
+        ));
+        let (statuses, authors): (i64, i64) = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .fetch_one(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+        Ok(Totals { statuses, authors })
+    }
+
+    /// Public statuses indexed per day (as `YYYY-MM-DD`) over the most recent `days` days with
+    /// any, newest first, from the rollup tables.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn daily_counts(&self, days: usize) -> Result<Vec<(String, i64)>, Error> {
+        let query = self.db.sql(format!(
+            r#"
+            select substr(hour, 1, 10) as day, cast(sum(posts) as {integer})
+            from {table_name}_hourly_author
+            group by day
+            order by day desc
+            limit ?
+            "#,
+            table_name = STATUS_TABLE,
+            integer = self.db.integer_type(),
+        ));
+        with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .bind(days as i64)
+                .fetch_all(pool)
+                .await
+                .map_err(Error::SelectFailed)
+        })
+    }
+
+    /// Public statuses per emoji, most-posted first, from the rollup tables.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn emoji_counts(&self) -> Result<Vec<EmojiCount>, Error> {
+        let query = self.db.sql(format!(
+            r#"
+            select status, cast(sum(posts) as {integer}) as total
+            from {table_name}_hourly_emoji
+            group by status
+            order by total desc, status asc
+            "#,
+            table_name = STATUS_TABLE,
+            integer = self.db.integer_type(),
+        ));
+        let data: Vec<(String, i64)> = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .fetch_all(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+        Ok(data
+            .into_iter()
+            .map(|(status, posts)| EmojiCount { status, posts })
+            .collect())
+    }
+
+    /// Fetches up to `count` hours of public activity between the `from` and `to` hours
+    /// (inclusive, as `YYYY-MM-DDTHH` prefixes) in ascending order, starting strictly after the
+    /// `after` hour, from the rollup tables. Hours without any posts are omitted.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn hourly_stats(
+        &self,
+        from: &str,
+        to: &str,
+        after: Option<&str>,
+        count: usize,
+    ) -> Result<Vec<HourlyStats>, Error> {
+        let query = self.db.sql(format!(
+            r#"
+            select hour, cast(sum(posts) as {integer}), count(*)
+            from {table_name}_hourly_author
+            where hour >= ? and hour <= ? and hour > ?
+            group by hour
+            order by hour asc
+            limit ?
+            "#,
+            table_name = STATUS_TABLE,
+            integer = self.db.integer_type(),
+        ));
+        let data: Vec<(String, i64, i64)> = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .bind(from)
+                .bind(to)
+                .bind(after.unwrap_or(""))
+                .bind(count as i64)
+                .fetch_all(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+        let (Some((first, ..)), Some((last, ..))) = (data.first(), data.last()) else {
+            return Ok(vec![]);
+        };
+
+        // top emojis of every hour in the page, most-posted first (ties broken by emoji so pages
+        // are stable)
+        let query = self.db.sql(format!(
+            r#"
+            select hour, status, posts
+            from (
+                select hour, status, posts,
+                    row_number() over (
+                        partition by hour order by posts desc, status asc
+                    ) as emoji_rank
+                from {table_name}_hourly_emoji
+                where hour >= ? and hour <= ?
+            ) as ranked
+            where emoji_rank <= ?
+            order by hour asc, emoji_rank asc
+            "#,
+            table_name = STATUS_TABLE,
+        ));
+        let emojis: Vec<(String, String, i64)> = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .bind(first)
+                .bind(last)
+                .bind(TOP_EMOJIS_PER_HOUR)
+                .fetch_all(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+
+        let mut stats = data
+            .into_iter()
+            .map(|(hour, posts, unique_authors)| HourlyStats {
+                hour,
+                posts,
+                unique_authors,
+                top_emojis: vec![],
+            })
+            .collect::<Vec<_>>();
+        for (hour, status, posts) in emojis {
+            if let Ok(i) = stats.binary_search_by(|stats| stats.hour.as_str().cmp(&hour)) {
+                stats[i].top_emojis.push(EmojiCount { status, posts });
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Fetches the status pinned by `author`, if any (and if we've seen the pinned status).
+    #[instrument(level = "debug", skip_all)]
+    pub async fn fetch_pinned(&self, author: &Did) -> Result<Option<Status>, Error> {
+        let query = self.db.sql(format!(
+            r#"
+            select s.uri, s.author_did, s.status, s.created_at, s.indexed_at, s.raw_created_at,
+                s.visibility, s.content_warning, s.cid, s.image_cid,
+                s.image_mime_type
+            from {table_name} s
+            join {table_name}_pin p on p.subject = s.uri
+            where p.author_did = ? and s.author_did = p.author_did
+            "#,
+            table_name = STATUS_TABLE,
+        ));
+        with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .bind(author.as_str())
+                .fetch_optional(pool)
+                .await
+                .map_err(Error::SelectFailed)
+        })
+    }
+}
+
+/// A cached DID-to-handle resolution.
+#[derive(Debug, Clone)]
+pub struct CachedHandle {
+    /// Resolved handle, or `None` if the DID document has no handle.
+    pub handle: Option<String>,
+    pub resolved_at: Datetime,
+}
+
+/// Persistent cache of DID-to-handle resolutions.
+#[derive(Debug, Clone)]
+pub struct HandleCache {
+    db: Db,
+}
+
+impl HandleCache {
+    pub fn new(db: impl Into<Db>) -> Self {
+        Self { db: db.into() }
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get(&self, did: &Did) -> Result<Option<CachedHandle>, Error> {
+        let query = self.db.sql(
+            r#"
+            select handle, resolved_at from handle_cache where `key` = ?
             "#,
-            table_name = self.table_name,
         );
-        let data: Vec<Status> = sqlx::query_as(&query)
-            .fetch_all(&self.pool)
-            .await
-            .map_err(Error::SelectFailed)?;
+        let data: Option<(Option<String>, String)> = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .bind(did.as_str())
+                .fetch_optional(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+
+        data.map(|(handle, resolved_at)| {
+            Ok(CachedHandle {
+                handle,
+                resolved_at: Datetime::from_str(&resolved_at).map_err(Error::InvalidDatetime)?,
+            })
+        })
+        .transpose()
+    }
+
+    /// Cached resolutions of whichever of `dids` are cached, in one query.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_many(&self, dids: &[Did]) -> Result<HashMap<Did, CachedHandle>, Error> {
+        if dids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let query = self.db.sql(format!(
+            r#"
+            select `key`, handle, resolved_at from handle_cache where `key` in ({placeholders})
+            "#,
+            placeholders = vec!["?"; dids.len()].join(", ")
+        ));
+        let data = with_pool!(&self.db, pool => {
+            let mut query = sqlx::query_as::<_, (String, Option<String>, String)>(&query);
+            for did in dids {
+                query = query.bind(did.as_str());
+            }
+            query.fetch_all(pool).await.map_err(Error::SelectFailed)?
+        });
+
+        data.into_iter()
+            .map(|(did, handle, resolved_at)| {
+                Ok((
+                    Did::new(did).map_err(Error::InvalidDid)?,
+                    CachedHandle {
+                        handle,
+                        resolved_at: Datetime::from_str(&resolved_at)
+                            .map_err(Error::InvalidDatetime)?,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    /// Most recently resolved DID with `handle`, if any.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_did(&self, handle: &str) -> Result<Option<(Did, CachedHandle)>, Error> {
+        let query = self.db.sql(
+            r#"
+            select `key`, resolved_at from handle_cache where handle = ?
+            order by resolved_at desc limit 1
+            "#,
+        );
+        let data: Option<(String, String)> = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .bind(handle)
+                .fetch_optional(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
 
-        Ok(data)
+        data.map(|(did, resolved_at)| {
+            Ok((
+                Did::new(did).map_err(Error::InvalidDid)?,
+                CachedHandle {
+                    handle: Some(handle.to_owned()),
+                    resolved_at: Datetime::from_str(&resolved_at)
+                        .map_err(Error::InvalidDatetime)?,
+                },
+            ))
+        })
+        .transpose()
     }
 
-    pub async fn fetch_n(&self, author: Option<Did>, count: usize) -> Result<Vec<Status>, Error> {
-        self.fetch(author, count).await
+    #[instrument(level = "debug", skip_all)]
+    pub async fn set(&self, did: &Did, handle: Option<&str>) -> Result<(), Error> {
+        let query = self.db.sql(format!(
+            r#"
+            insert into handle_cache (`key`, handle, resolved_at) values (?, ?, ?)
+            {on_conflict}
+            "#,
+            on_conflict = self.db.on_conflict(
+                "`key`",
+                "handle = excluded.handle, resolved_at = excluded.resolved_at"
+            ),
+        ));
+        with_pool!(&self.db, pool => {
+            sqlx::query(&query)
+                .bind(did.as_str())
+                .bind(handle)
+                .bind(Datetime::now().as_str())
+                .execute(pool)
+                .await
+                .map_err(Error::InsertFailed)?;
+        });
+        Ok(())
     }
+}
 
-    pub async fn fetch_one(&self, author: Option<Did>) -> Result<Option<Status>, Error> {
-        let mut results = self.fetch(author, 1).await?;
-        Ok(results.pop())
+/// Cheap stand-in for the contents of a feed, see [`StatusStore::feed_version`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedVersion {
+    pub latest_indexed_at: Option<String>,
+    pub statuses: i64,
+    pub reactions: i64,
+    pub latest_pin: Option<String>,
+}
+
+/// All-time public activity.
+#[derive(Debug, Clone, Copy)]
+pub struct Totals {
+    pub statuses: i64,
+    pub authors: i64,
+}
+
+// how many statuses `StatusStore::stream_all` fetches per query
+const STREAM_PAGE_SIZE: i64 = 500;
+
+// how many of each hour's most-posted emojis `StatusStore::hourly_stats` reports
+const TOP_EMOJIS_PER_HOUR: i64 = 5;
+
+/// Aggregate public activity during one hour.
+#[derive(Debug, Clone)]
+pub struct HourlyStats {
+    /// The hour, as an RFC 3339 prefix (`YYYY-MM-DDTHH`).
+    pub hour: String,
+    pub posts: i64,
+    pub unique_authors: i64,
+    /// Most-posted emojis, most popular first.
+    pub top_emojis: Vec<EmojiCount>,
+}
+
+/// A reaction to a status.
+#[derive(Debug, Clone)]
+pub struct Reaction {
+    pub uri: String,
+    pub author_did: Did,
+    /// URI of the status reacted to.
+    pub subject: String,
+    pub emoji: String,
+    pub created_at: Datetime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReactionCount {
+    pub emoji: String,
+    /// Number of users who reacted with `emoji`.
+    pub count: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct EmojiCount {
+    pub status: String,
+    pub posts: i64,
+}
+
+/// An ingest message that failed to process.
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub id: i64,
+    /// Raw message, in Jetstream JSON event format.
+    pub payload: String,
+    pub error: String,
+    pub failed_at: String,
+    pub attempts: i64,
+}
+
+/// Store for ingest messages that failed to process, so they can be inspected and reprocessed.
+#[derive(Debug, Clone)]
+pub struct DeadLetterStore {
+    db: Db,
+}
+
+impl DeadLetterStore {
+    pub fn new(db: impl Into<Db>) -> Self {
+        Self { db: db.into() }
+    }
+
+    #[cfg(feature = "ingester")]
+    #[instrument(level = "debug", skip_all)]
+    pub async fn insert(
+        &self,
+        payload: impl AsRef<str>,
+        error: impl ToString,
+    ) -> Result<(), Error> {
+        let query = self.db.sql(
+            r#"
+            insert into dead_letter (payload, error, failed_at) values (?, ?, ?)
+            "#,
+        );
+        with_pool!(&self.db, pool => {
+            sqlx::query(&query)
+                .bind(payload.as_ref())
+                .bind(error.to_string())
+                .bind(Datetime::now().as_str())
+                .execute(pool)
+                .await
+                .map_err(Error::InsertFailed)?;
+        });
+        Ok(())
+    }
+
+    /// Fetches up to `count` dead letters, oldest first.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn fetch_n(&self, count: usize) -> Result<Vec<DeadLetter>, Error> {
+        let query = self.db.sql(
+            r#"
+            select id, payload, error, failed_at, attempts
+            from dead_letter
+            order by id asc
+            limit ?
+            "#,
+        );
+        let data: Vec<(i64, String, String, String, i64)> = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .bind(count as i64)
+                .fetch_all(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+
+        Ok(data
+            .into_iter()
+            .map(|(id, payload, error, failed_at, attempts)| DeadLetter {
+                id,
+                payload,
+                error,
+                failed_at,
+                attempts,
+            })
+            .collect())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn count(&self) -> Result<i64, Error> {
+        let query = self.db.sql("select count(*) from dead_letter");
+        let (count,): (i64,) = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .fetch_one(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+        Ok(count)
+    }
+
+    /// Records another failed processing attempt.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn record_failure(&self, id: i64, error: impl ToString) -> Result<(), Error> {
+        let query = self.db.sql(
+            r#"
+            update dead_letter
+            set error = ?, failed_at = ?, attempts = attempts + 1
+            where id = ?
+            "#,
+        );
+        with_pool!(&self.db, pool => {
+            sqlx::query(&query)
+                .bind(error.to_string())
+                .bind(Datetime::now().as_str())
+                .bind(id)
+                .execute(pool)
+                .await
+                .map_err(Error::InsertFailed)?;
+        });
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn delete(&self, id: i64) -> Result<(), Error> {
+        let query = self.db.sql("delete from dead_letter where id = ?");
+        with_pool!(&self.db, pool => {
+            sqlx::query(&query)
+                .bind(id)
+                .execute(pool)
+                .await
+                .map_err(Error::DeleteFailed)?;
+        });
+        Ok(())
     }
 }
 
-fn is_valid_table_name(name: &str) -> bool {
-    if name.is_empty() {
-        return false;
+/// The ingester's pause switch, as last set from the admin dashboard or the command line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestControl {
+    pub paused: bool,
+    /// Where in the event stream the ingester stopped when it paused (a Jetstream `time_us` or a
+    /// firehose sequence number), to pick up from when it resumes.
+    #[cfg_attr(not(feature = "ingester"), allow(dead_code))]
+    pub resume_cursor: Option<i64>,
+}
+
+/// Store for the ingester's pause switch. It's kept in the database rather than in memory so it
+/// reaches an ingester deployed on its own, and holds across restarts.
+#[derive(Debug, Clone)]
+pub struct IngestControlStore {
+    db: Db,
+}
+
+impl IngestControlStore {
+    pub fn new(db: impl Into<Db>) -> Self {
+        Self { db: db.into() }
+    }
+
+    #[cfg_attr(not(any(feature = "ingester", feature = "admin")), allow(dead_code))]
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get(&self) -> Result<IngestControl, Error> {
+        let query = self
+            .db
+            .sql("select paused, resume_cursor from ingest_control where id = 0");
+        let data: Option<(bool, Option<i64>)> = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .fetch_optional(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+        Ok(data
+            .map(|(paused, resume_cursor)| IngestControl {
+                paused,
+                resume_cursor,
+            })
+            .unwrap_or_default())
     }
 
-    let mut chars = name.chars();
-    let first = chars.next().expect("expected non-empty");
-    first.is_ascii_alphabetic() && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    #[instrument(level = "debug", skip_all)]
+    pub async fn set_paused(&self, paused: bool) -> Result<(), Error> {
+        let query = self.db.sql(format!(
+            r#"
+            insert into ingest_control (id, paused) values (0, ?)
+            {on_conflict}
+            "#,
+            on_conflict = self.db.on_conflict("id", "paused = excluded.paused"),
+        ));
+        with_pool!(&self.db, pool => {
+            sqlx::query(&query)
+                .bind(paused)
+                .execute(pool)
+                .await
+                .map_err(Error::InsertFailed)?;
+        });
+        Ok(())
+    }
+
+    /// Records where the ingester stopped, or with `None`, that it's caught up again.
+    #[cfg(feature = "ingester")]
+    #[instrument(level = "debug", skip_all)]
+    pub async fn set_resume_cursor(&self, resume_cursor: Option<i64>) -> Result<(), Error> {
+        let query = self.db.sql(format!(
+            r#"
+            insert into ingest_control (id, resume_cursor) values (0, ?)
+            {on_conflict}
+            "#,
+            on_conflict = self
+                .db
+                .on_conflict("id", "resume_cursor = excluded.resume_cursor"),
+        ));
+        with_pool!(&self.db, pool => {
+            sqlx::query(&query)
+                .bind(resume_cursor)
+                .execute(pool)
+                .await
+                .map_err(Error::InsertFailed)?;
+        });
+        Ok(())
+    }
+}
+
+/// A user's report of a status, for moderators to review.
+#[cfg_attr(not(feature = "admin"), allow(dead_code))]
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub id: i64,
+    /// URI of the reported status.
+    pub subject: String,
+    pub reporter_did: String,
+    /// Why it was reported, as a `com.atproto.moderation.defs#reasonType` token.
+    pub reason_type: String,
+    pub reason: Option<String>,
+    pub created_at: String,
+    /// The ID the moderation service gave the report, if it was filed with one.
+    pub filed_id: Option<i64>,
+}
+
+/// Store for users' reports of statuses, open until a moderator resolves them.
+#[derive(Debug, Clone)]
+pub struct ReportStore {
+    db: Db,
+}
+
+impl ReportStore {
+    pub fn new(db: impl Into<Db>) -> Self {
+        Self { db: db.into() }
+    }
+
+    /// Records `reporter`'s report of `subject`, replacing any earlier one of theirs.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn insert(
+        &self,
+        subject: &str,
+        reporter: &Did,
+        reason_type: &str,
+        reason: Option<&str>,
+        filed_id: Option<i64>,
+    ) -> Result<(), Error> {
+        let query = self.db.sql(format!(
+            r#"
+            insert into report (subject, reporter_did, reason_type, reason, created_at, filed_id)
+            values (?, ?, ?, ?, ?, ?)
+            {on_conflict}
+            "#,
+            on_conflict = self.db.on_conflict(
+                "subject, reporter_did",
+                "reason_type = excluded.reason_type, reason = excluded.reason, \
+                 filed_id = excluded.filed_id",
+            ),
+        ));
+        with_pool!(&self.db, pool => {
+            sqlx::query(&query)
+                .bind(subject)
+                .bind(reporter.as_str())
+                .bind(reason_type)
+                .bind(reason)
+                .bind(Datetime::now().as_str())
+                .bind(filed_id)
+                .execute(pool)
+                .await
+                .map_err(Error::InsertFailed)?;
+        });
+        Ok(())
+    }
+
+    /// Whether `reporter` has already reported `subject`.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn has_reported(&self, subject: &str, reporter: &Did) -> Result<bool, Error> {
+        let query = self
+            .db
+            .sql("select count(*) from report where subject = ? and reporter_did = ?");
+        let (count,): (i64,) = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .bind(subject)
+                .bind(reporter.as_str())
+                .fetch_one(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+        Ok(count > 0)
+    }
+
+    /// Fetches up to `count` open reports, oldest first.
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    #[instrument(level = "debug", skip_all)]
+    pub async fn fetch_open(&self, count: usize) -> Result<Vec<Report>, Error> {
+        let query = self.db.sql(
+            r#"
+            select id, subject, reporter_did, reason_type, reason, created_at, filed_id
+            from report
+            where resolved_at is null
+            order by id asc
+            limit ?
+            "#,
+        );
+        #[allow(clippy::type_complexity)]
+        let data: Vec<(
+            i64,
+            String,
+            String,
+            String,
+            Option<String>,
+            String,
+            Option<i64>,
+        )> = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .bind(count as i64)
+                .fetch_all(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+
+        Ok(data
+            .into_iter()
+            .map(
+                |(id, subject, reporter_did, reason_type, reason, created_at, filed_id)| Report {
+                    id,
+                    subject,
+                    reporter_did,
+                    reason_type,
+                    reason,
+                    created_at,
+                    filed_id,
+                },
+            )
+            .collect())
+    }
+
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    #[instrument(level = "debug", skip_all)]
+    pub async fn count_open(&self) -> Result<i64, Error> {
+        let query = self
+            .db
+            .sql("select count(*) from report where resolved_at is null");
+        let (count,): (i64,) = with_pool!(&self.db, pool => {
+            sqlx::query_as(&query)
+                .fetch_one(pool)
+                .await
+                .map_err(Error::SelectFailed)?
+        });
+        Ok(count)
+    }
+
+    /// Closes report `id`, as dealt with by `moderator`.
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    #[instrument(level = "debug", skip_all)]
+    pub async fn resolve(&self, id: i64, moderator: &Did) -> Result<(), Error> {
+        let query = self.db.sql(
+            r#"
+            update report
+            set resolved_at = ?, resolved_by = ?
+            where id = ? and resolved_at is null
+            "#,
+        );
+        with_pool!(&self.db, pool => {
+            sqlx::query(&query)
+                .bind(Datetime::now().as_str())
+                .bind(moderator.as_str())
+                .bind(id)
+                .execute(pool)
+                .await
+                .map_err(Error::InsertFailed)?;
+        });
+        Ok(())
+    }
+}
+
+// joins SQL conditions into a `where` clause (empty if there are none)
+fn where_clause(conditions: &[String]) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("where {}", conditions.join(" and "))
+    }
+}
+
+/// Where OAuth sessions and states are kept.
+#[derive(Clone)]
+enum OAuthBackend {
+    Database(Db),
+    /// Shared between instances, for multi-instance deployments.
+    #[cfg(feature = "redis")]
+    Redis(redis::RedisBackend),
 }
 
 // OAuthSessionStore and OAuthStateStore are very similar, so we use a macro to help
 macro_rules! oauth_store {
     ($struct_name:ident, $table_name:expr, $key_ty:ty, $value_name:expr, $value_ty:ty) => {
+        #[derive(Clone)]
         pub struct $struct_name {
-            pool: SqlitePool,
+            backend: OAuthBackend,
+            // entries older than this are treated as missing; `None` keeps them forever
+            ttl: Option<Duration>,
+            // encrypts values at rest; `None` stores them as plaintext JSON
+            cipher: Option<EnvelopeCipher>,
         }
 
         impl $struct_name {
-            pub fn new(pool: SqlitePool) -> Self {
-                Self { pool }
+            pub fn new(db: impl Into<Db>) -> Self {
+                Self {
+                    backend: OAuthBackend::Database(db.into()),
+                    ttl: None,
+                    cipher: None,
+                }
+            }
+
+            /// Keeps entries in Redis (under keys prefixed by the table name) instead of the database.
+            #[cfg(feature = "redis")]
+            pub fn redis(backend: redis::RedisBackend) -> Self {
+                Self {
+                    backend: OAuthBackend::Redis(backend),
+                    ttl: None,
+                    cipher: None,
+                }
+            }
+
+            /// Expires entries `ttl` after they were last set.
+            pub fn with_ttl(mut self, ttl: Duration) -> Self {
+                self.ttl = Some(ttl);
+                self
+            }
+
+            /// Encrypts values with `cipher` before storing them. Plaintext values stored before
+            /// encryption was enabled can still be read, and are encrypted when next set.
+            pub fn with_encryption(mut self, cipher: EnvelopeCipher) -> Self {
+                self.cipher = Some(cipher);
+                self
+            }
+
+            // serializes a value for storage, sealing it if encryption is enabled
+            fn encode_value(&self, key: &str, value: &$value_ty) -> Result<String, Error> {
+                let json = serde_json::to_string(value).map_err(Error::Serialization)?;
+                match &self.cipher {
+                    // bind the ciphertext to its key, so values can't be swapped between rows
+                    Some(cipher) => cipher
+                        .seal(&json, &format!("{}:{key}", $table_name))
+                        .map_err(Error::Encryption),
+                    None => Ok(json),
+                }
+            }
+
+            fn decode_value(&self, key: &str, stored: &str) -> Result<$value_ty, Error> {
+                let json = if EnvelopeCipher::is_sealed(stored) {
+                    self.cipher
+                        .as_ref()
+                        .ok_or(Error::Encryption(crate::envelope::Error::Decrypt))?
+                        .open(stored, &format!("{}:{key}", $table_name))
+                        .map_err(Error::Encryption)?
+                } else {
+                    stored.to_owned()
+                };
+                serde_json::from_str(&json).map_err(Error::Deserialization)
+            }
+
+            // entries set before this time have expired
+            fn expiry_cutoff(&self) -> Option<Datetime> {
+                let ttl = chrono::Duration::from_std(self.ttl?).unwrap_or(chrono::Duration::MAX);
+                let cutoff = chrono::Utc::now()
+                    .checked_sub_signed(ttl)
+                    .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC);
+                Some(Datetime::new(cutoff.fixed_offset()))
             }
 
-            pub async fn migrate(&self) -> Result<(), Error> {
-                let query = format!(
+            /// Deletes expired entries (rows from before `created_at` was tracked count as
+            /// expired), returning how many were deleted. Does nothing without a TTL.
+            #[instrument(level = "debug", skip_all, fields(table = $table_name))]
+            pub async fn delete_expired(&self) -> Result<u64, Error> {
+                let db = match &self.backend {
+                    OAuthBackend::Database(db) => db,
+                    // Redis expires keys itself
+                    #[cfg(feature = "redis")]
+                    OAuthBackend::Redis(_) => return Ok(0),
+                };
+                let Some(cutoff) = self.expiry_cutoff() else {
+                    return Ok(0);
+                };
+                let query = db.sql(format!(
                     r#"
-                    create table if not exists {table_name}
-                    (
-                        key text primary key,
-                        {value_name} text not null
-                    )
+                    delete from {table_name} where created_at is null or created_at < ?
                     "#,
-                    table_name = $table_name,
-                    value_name = $value_name
-                );
-                sqlx::query(&query)
-                    .execute(&self.pool)
-                    .await
-                    .map_err(Error::MigrationFailed)?;
-                Ok(())
+                    table_name = $table_name
+                ));
+                with_pool!(db, pool => {
+                    let result = sqlx::query(&query)
+                        .bind(cutoff.as_str())
+                        .execute(pool)
+                        .await
+                        .map_err(Error::DeleteFailed)?;
+                    Ok(result.rows_affected())
+                })
+            }
+
+            /// Keys of all unexpired entries.
+            #[instrument(level = "debug", skip_all, fields(table = $table_name))]
+            pub async fn keys(&self) -> Result<Vec<String>, Error> {
+                let db = match &self.backend {
+                    OAuthBackend::Database(db) => db,
+                    #[cfg(feature = "redis")]
+                    OAuthBackend::Redis(redis) => return redis.keys($table_name).await,
+                };
+                let query = db.sql(format!(
+                    r#"
+                    select `key` from {table_name} where ? is null or created_at >= ?
+                    "#,
+                    table_name = $table_name
+                ));
+                let cutoff = self.expiry_cutoff();
+                let cutoff = cutoff.as_ref().map(|dt| dt.as_str());
+                let data: Vec<(String,)> = with_pool!(db, pool => {
+                    sqlx::query_as(&query)
+                        .bind(cutoff)
+                        .bind(cutoff)
+                        .fetch_all(pool)
+                        .await
+                        .map_err(Error::SelectFailed)?
+                });
+                Ok(data.into_iter().map(|(key,)| key).collect())
             }
         }
 
         impl Store<$key_ty, $value_ty> for $struct_name {
             type Error = Error;
 
+            #[instrument(level = "debug", skip_all, fields(table = $table_name))]
             async fn get(&self, key: &$key_ty) -> Result<Option<$value_ty>, Self::Error> {
-                let query = format!(
+                let db = match &self.backend {
+                    OAuthBackend::Database(db) => db,
+                    #[cfg(feature = "redis")]
+                    OAuthBackend::Redis(redis) => {
+                        return redis
+                            .get($table_name, key.as_str())
+                            .await?
+                            .map(|value| self.decode_value(key.as_str(), &value))
+                            .transpose();
+                    }
+                };
+                let query = db.sql(format!(
                     r#"
-                    select key, {value_name}
+                    select `key`, {value_name}
                     from {table_name}
-                    where key = ?
+                    where `key` = ? and (? is null or created_at >= ?)
                     "#,
                     value_name = $value_name,
                     table_name = $table_name
-                );
-                let data: Option<(String, String)> = sqlx::query_as(&query)
-                    .bind(key.as_str())
-                    .fetch_optional(&self.pool)
-                    .await
-                    .map_err(Error::SelectFailed)?;
+                ));
+                let cutoff = self.expiry_cutoff();
+                let cutoff = cutoff.as_ref().map(|dt| dt.as_str());
+                let data: Option<(String, String)> = with_pool!(db, pool => {
+                    sqlx::query_as(&query)
+                        .bind(key.as_str())
+                        .bind(cutoff)
+                        .bind(cutoff)
+                        .fetch_optional(pool)
+                        .await
+                        .map_err(Error::SelectFailed)?
+                });
 
-                Ok(data
-                    .map(|(_, value)| serde_json::from_str(&value).map_err(Error::Deserialization))
-                    .transpose()?)
+                data.map(|(_, value)| self.decode_value(key.as_str(), &value))
+                    .transpose()
             }
 
+            #[instrument(level = "debug", skip_all, fields(table = $table_name))]
             async fn set(&self, key: $key_ty, value: $value_ty) -> Result<(), Self::Error> {
-                let query = format!(
+                let value = self.encode_value(key.as_str(), &value)?;
+                let db = match &self.backend {
+                    OAuthBackend::Database(db) => db,
+                    #[cfg(feature = "redis")]
+                    OAuthBackend::Redis(redis) => {
+                        return redis.set($table_name, key.as_str(), value, self.ttl).await;
+                    }
+                };
+                let query = db.sql(format!(
                     r#"
                     insert into {table_name}
-                        (key, {value_name})
+                        (`key`, {value_name}, created_at)
                         values
-                        (?, ?)
-                    on conflict(key) do update set
-                        {value_name} = excluded.{value_name}
+                        (?, ?, ?)
+                    {on_conflict}
                     "#,
                     table_name = $table_name,
-                    value_name = $value_name
-                );
-                sqlx::query(&query)
-                    .bind(key.as_str())
-                    .bind(serde_json::to_string(&value).map_err(Error::Serialization)?)
-                    .execute(&self.pool)
-                    .await
-                    .map_err(Error::InsertFailed)?;
+                    value_name = $value_name,
+                    on_conflict = db.on_conflict(
+                        "`key`",
+                        &format!(
+                            "{value_name} = excluded.{value_name}, created_at = excluded.created_at",
+                            value_name = $value_name
+                        )
+                    ),
+                ));
+                with_pool!(db, pool => {
+                    sqlx::query(&query)
+                        .bind(key.as_str())
+                        .bind(value)
+                        .bind(Datetime::now().as_str())
+                        .execute(pool)
+                        .await
+                        .map_err(Error::InsertFailed)?;
+                });
                 Ok(())
             }
 
+            #[instrument(level = "debug", skip_all, fields(table = $table_name))]
             async fn del(&self, key: &$key_ty) -> Result<(), Self::Error> {
-                let query = format!(
+                let db = match &self.backend {
+                    OAuthBackend::Database(db) => db,
+                    #[cfg(feature = "redis")]
+                    OAuthBackend::Redis(redis) => {
+                        return redis.del($table_name, key.as_str()).await;
+                    }
+                };
+                let query = db.sql(format!(
                     r#"
-                    delete from {table_name} where key = ?
+                    delete from {table_name} where `key` = ?
                     "#,
                     table_name = $table_name
-                );
-                sqlx::query(&query)
-                    .bind(key.as_str())
-                    .execute(&self.pool)
-                    .await
-                    .map_err(Error::DeleteFailed)?;
+                ));
+                with_pool!(db, pool => {
+                    sqlx::query(&query)
+                        .bind(key.as_str())
+                        .execute(pool)
+                        .await
+                        .map_err(Error::DeleteFailed)?;
+                });
                 Ok(())
             }
 
+            #[instrument(level = "debug", skip_all, fields(table = $table_name))]
             async fn clear(&self) -> Result<(), Self::Error> {
-                let query = format!(
+                let db = match &self.backend {
+                    OAuthBackend::Database(db) => db,
+                    #[cfg(feature = "redis")]
+                    OAuthBackend::Redis(redis) => return redis.clear($table_name).await,
+                };
+                let query = db.sql(format!(
                     r#"
                     delete from {table_name}
                     "#,
                     table_name = $table_name
-                );
-                sqlx::query(&query)
-                    .execute(&self.pool)
-                    .await
-                    .map_err(Error::DeleteAllFailed)?;
+                ));
+                with_pool!(db, pool => {
+                    sqlx::query(&query)
+                        .execute(pool)
+                        .await
+                        .map_err(Error::DeleteAllFailed)?;
+                });
                 Ok(())
             }
         }
@@ -300,3 +2397,95 @@ oauth_store!(
     InternalStateData
 );
 impl StateStore for OAuthStateStore {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{did, memory_pool};
+
+    const ALICE: &str = "did:plc:alice0000000000000000000";
+    const BOB: &str = "did:plc:bob00000000000000000000000";
+
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn postgres_queries_use_numbered_placeholders_and_double_quotes() {
+        let db =
+            Db::from(PgPool::connect_lazy("postgres://localhost/statusphere").expect("valid URL"));
+
+        assert_eq!(
+            db.sql("select `key` from t where a = ? and b in (?, ?)"),
+            r#"select "key" from t where a = $1 and b in ($2, $3)"#
+        );
+    }
+
+    #[tokio::test]
+    async fn sqlite_queries_are_unchanged() {
+        let db = Db::from(memory_pool().await);
+
+        assert_eq!(
+            db.sql("select `key` from t where a = ?"),
+            "select `key` from t where a = ?"
+        );
+    }
+
+    #[cfg(feature = "mysql")]
+    #[test]
+    fn mysql_upserts_refer_to_inserted_values() {
+        assert_eq!(
+            mysql_inserted_values("status = excluded.status, image_cid = excluded.image_cid"),
+            "status = values(status), image_cid = values(image_cid)"
+        );
+        // columns not of the inserted row are left alone
+        assert_eq!(
+            mysql_inserted_values("count = count + excluded.count"),
+            "count = count + values(count)"
+        );
+        assert_eq!(mysql_inserted_values("seen = 1"), "seen = 1");
+    }
+
+    #[test]
+    fn unfiltered_statuses_are_public_only() {
+        assert_eq!(
+            StatusFilter::new().conditions(),
+            (vec!["visibility = 'public'".to_owned()], vec![])
+        );
+    }
+
+    #[test]
+    fn audience_sees_their_followers_only_statuses() {
+        let filter = StatusFilter::new().visible_to(&did(ALICE), [did(BOB)]);
+
+        assert_eq!(
+            filter.conditions(),
+            (
+                vec!["(visibility = 'public' or author_did in (?, ?))".to_owned()],
+                vec![ALICE.to_owned(), BOB.to_owned()]
+            )
+        );
+    }
+
+    #[test]
+    fn author_and_status_filters_are_bound_in_order() {
+        let filter = StatusFilter::new().author(did(ALICE)).status("🦋");
+
+        assert_eq!(
+            filter.conditions(),
+            (
+                vec![
+                    "visibility = 'public'".to_owned(),
+                    "author_did = ?".to_owned(),
+                    "status = ?".to_owned(),
+                ],
+                vec![ALICE.to_owned(), "🦋".to_owned()]
+            )
+        );
+    }
+
+    #[test]
+    fn latest_per_author_adds_no_conditions() {
+        assert_eq!(
+            StatusFilter::new().latest_per_author().conditions(),
+            StatusFilter::new().conditions()
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use redis::{AsyncCommands, aio::ConnectionManager};
+
+use super::Error;
+
+/// Redis connection backing the OAuth stores, for deployments with several instances that can't
+/// share a SQLite database. Values are stored under `{table_name}:{key}`.
+#[derive(Clone)]
+pub struct RedisBackend {
+    connection: ConnectionManager,
+}
+
+impl RedisBackend {
+    pub async fn connect(url: &str) -> Result<Self, Error> {
+        let client = redis::Client::open(url).map_err(Error::Redis)?;
+        let connection = ConnectionManager::new(client).await.map_err(Error::Redis)?;
+        Ok(Self { connection })
+    }
+
+    pub(super) async fn get(&self, table_name: &str, key: &str) -> Result<Option<String>, Error> {
+        self.connection
+            .clone()
+            .get(format!("{table_name}:{key}"))
+            .await
+            .map_err(Error::Redis)
+    }
+
+    pub(super) async fn set(
+        &self,
+        table_name: &str,
+        key: &str,
+        value: String,
+        ttl: Option<Duration>,
+    ) -> Result<(), Error> {
+        let key = format!("{table_name}:{key}");
+        let mut connection = self.connection.clone();
+        match ttl {
+            // expiry has second granularity, and zero isn't allowed
+            Some(ttl) => connection.set_ex(key, value, ttl.as_secs().max(1)).await,
+            None => connection.set(key, value).await,
+        }
+        .map_err(Error::Redis)
+    }
+
+    pub(super) async fn del(&self, table_name: &str, key: &str) -> Result<(), Error> {
+        self.connection
+            .clone()
+            .del(format!("{table_name}:{key}"))
+            .await
+            .map_err(Error::Redis)
+    }
+
+    /// Keys (without the table name prefix) stored for `table_name`.
+    pub(super) async fn keys(&self, table_name: &str) -> Result<Vec<String>, Error> {
+        let prefix = format!("{table_name}:");
+        Ok(self
+            .scan(table_name)
+            .await?
+            .into_iter()
+            .filter_map(|key| key.strip_prefix(&prefix).map(str::to_owned))
+            .collect())
+    }
+
+    pub(super) async fn clear(&self, table_name: &str) -> Result<(), Error> {
+        let keys = self.scan(table_name).await?;
+        if keys.is_empty() {
+            return Ok(());
+        }
+        self.connection
+            .clone()
+            .del(keys)
+            .await
+            .map_err(Error::Redis)
+    }
+
+    // full keys stored for `table_name`
+    async fn scan(&self, table_name: &str) -> Result<Vec<String>, Error> {
+        let mut connection = self.connection.clone();
+        let mut keys = vec![];
+        let mut iter = connection
+            .scan_match::<_, String>(format!("{table_name}:*"))
+            .await
+            .map_err(Error::Redis)?;
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        Ok(keys)
+    }
+}
@@ -0,0 +1,109 @@
+//! Keeps the ingester running: restarts it with backoff whenever it stops, recording as much in its
+//! [`IngesterStatus`].
+
+// only the ingester is supervised
+#![cfg_attr(not(feature = "ingester"), allow(dead_code))]
+
+use std::time::{Duration, Instant};
+
+use tracing::{error, info};
+
+use crate::ingester_status::IngesterStatus;
+
+const MIN_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Runs the ingester started by `start` in the background, starting it again (after a backoff)
+/// whenever it stops, whether by returning or by panicking.
+pub fn spawn_supervisor<F, Fut>(status: IngesterStatus, start: F)
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), crate::error::Error>> + Send + 'static,
+{
+    tokio::spawn(supervise(
+        status,
+        MIN_RESTART_BACKOFF,
+        MAX_RESTART_BACKOFF,
+        start,
+    ));
+}
+
+async fn supervise<F, Fut>(
+    status: IngesterStatus,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    mut start: F,
+) where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), crate::error::Error>> + Send + 'static,
+{
+    let mut backoff = min_backoff;
+    loop {
+        status.running();
+        let started_at = Instant::now();
+        // in a task of its own, so a panic stops only this run
+        let error = match tokio::spawn(start()).await {
+            Ok(Ok(())) => "ingester stopped".to_owned(),
+            Ok(Err(e)) => format!("ingester failed: {e}"),
+            Err(e) => format!("ingester crashed: {e}"),
+        };
+        // a run that lasted a while wasn't part of a crash loop
+        if started_at.elapsed() >= max_backoff {
+            backoff = min_backoff;
+        }
+        error!("{error}, restarting in {backoff:?}");
+        status.restarting(error);
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(max_backoff);
+        info!("Restarting ingester");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    };
+
+    use super::*;
+    use crate::ingester_status::IngesterState;
+
+    #[tokio::test]
+    async fn restarts_after_failures_and_panics() {
+        let status = IngesterStatus::new();
+        let runs = Arc::new(AtomicU32::new(0));
+        let task = tokio::spawn(supervise(
+            status.clone(),
+            Duration::from_millis(1),
+            Duration::from_millis(5),
+            {
+                let runs = Arc::clone(&runs);
+                move || {
+                    let run = runs.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        match run {
+                            0 => Err(crate::error::Error::MissingDid),
+                            1 => panic!("ingester bug"),
+                            // then keeps running
+                            _ => std::future::pending().await,
+                        }
+                    }
+                }
+            },
+        ));
+
+        while runs.load(Ordering::SeqCst) < 3 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        let report = status.report();
+        assert_eq!(report.state, IngesterState::Running);
+        assert_eq!(report.restarts, 2);
+        assert!(
+            report
+                .last_error
+                .is_some_and(|error| error.starts_with("ingester crashed"))
+        );
+        task.abort();
+    }
+}
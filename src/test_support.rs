@@ -0,0 +1,498 @@
+//! The app put together by [`AppBuilder`], as `main` puts it together, but against an in-memory
+//! database and in development mode (`DEV_FAKE_AUTH`), so logged-in users get a [`FakeSession`]
+//! rather than an OAuth session and nothing leaves the process. For end-to-end tests of the routes.
+//!
+//! Also canned responses for the identity services and PDSes the app talks to, served by a
+//! [`MockServer`] standing in for them.
+//!
+//! [`FakeSession`]: crate::dev_auth::FakeSession
+
+use std::sync::Arc;
+
+use atrium_api::types::{
+    Collection,
+    string::{Datetime, Did},
+};
+use axum::{
+    Router,
+    body::Body,
+    http::{
+        HeaderMap, Request, StatusCode,
+        header::{CONTENT_TYPE, COOKIE, LOCATION, SET_COOKIE},
+    },
+};
+use serde_json::json;
+use tower::ServiceExt;
+use tower_sessions_sqlx_store::sqlx::{SqlitePool, sqlite::SqlitePoolOptions};
+use wiremock::{
+    Mock, MockServer, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::{
+    AppState,
+    app::{App, AppBuilder, Stores},
+    config::AppConfig,
+    lexicons::xyz::statusphere::Status,
+    store::{self, HandleCache, Visibility},
+};
+
+// CID of the records behind seeded public statuses; nothing ever looks it up
+const SEED_CID: &str = "bafyreie5737gdxlw5i64vzichcalba3z2v5n6icifvx5xytvske7mr3hpm";
+
+/// The app, ready to take requests.
+pub struct TestApp {
+    router: Router,
+    pub state: Arc<AppState>,
+    handle_cache: HandleCache,
+}
+
+/// What the app answered, with the body read.
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+impl TestResponse {
+    /// The session cookie set by the response (as sent back in a `Cookie` header), if any.
+    pub fn session_cookie(&self) -> Option<String> {
+        self.headers
+            .get(SET_COOKIE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(str::to_owned)
+    }
+
+    pub fn location(&self) -> Option<&str> {
+        self.headers
+            .get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+    }
+}
+
+// every connection to `sqlite::memory:` opens a database of its own, so the pool keeps exactly one
+// connection open for the app's lifetime
+pub async fn memory_pool() -> SqlitePool {
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .expect("in-memory database connects")
+}
+
+impl TestApp {
+    /// The app with the default configuration, and nothing stored yet.
+    pub async fn new() -> Self {
+        Self::with_config(AppConfig::for_tests()).await
+    }
+
+    /// The app with `config` (in development mode, whatever it says), and nothing stored yet.
+    pub async fn with_config(mut config: AppConfig) -> Self {
+        config.dev_fake_auth = true;
+        // pages fall back to generated avatars rather than looking profiles up
+        config.avatar_appview_url = None;
+        let stores = Stores::sqlite(memory_pool().await)
+            .await
+            .expect("in-memory stores open");
+        // seed handles with `seed_handle`, or they're looked up for real in the background
+        let handle_cache = stores.handle_cache.clone();
+        let App { state, router } = AppBuilder::new(config)
+            .stores(stores)
+            .build()
+            .await
+            .expect("app builds");
+        Self {
+            router,
+            state,
+            handle_cache,
+        }
+    }
+
+    /// Caches `did`'s handle, so pages show it without resolving it.
+    pub async fn seed_handle(&self, did: &Did, handle: &str) {
+        self.handle_cache
+            .set(did, Some(handle))
+            .await
+            .expect("handle is cached");
+    }
+
+    /// Stores a public status by `did` (as though ingested), returning its URI.
+    pub async fn seed_status(&self, did: &Did, rkey: &str, status: &str) -> String {
+        let uri = format!("at://{}/{}/{rkey}", did.as_str(), Status::NSID);
+        self.insert_status(did, &uri, status, Visibility::Public, Some(SEED_CID))
+            .await;
+        uri
+    }
+
+    /// Stores a followers-only status by `did` (as though posted here), returning its URI.
+    pub async fn seed_followers_status(&self, did: &Did, rkey: &str, status: &str) -> String {
+        let uri = format!("private:{}/{rkey}", did.as_str());
+        self.insert_status(did, &uri, status, Visibility::Followers, None)
+            .await;
+        uri
+    }
+
+    async fn insert_status(
+        &self,
+        did: &Did,
+        uri: &str,
+        status: &str,
+        visibility: Visibility,
+        cid: Option<&str>,
+    ) {
+        self.state
+            .status_store
+            .insert(store::Status {
+                uri: uri.to_owned(),
+                author_did: did.clone(),
+                status: status.to_owned(),
+                created_at: Datetime::now(),
+                indexed_at: Datetime::now(),
+                raw_created_at: None,
+                visibility,
+                content_warning: None,
+                cid: cid.map(str::to_owned),
+                image: None,
+            })
+            .await
+            .expect("status is stored");
+    }
+
+    /// Sends `request` through the whole app, middleware and all.
+    pub async fn send(&self, request: Request<Body>) -> TestResponse {
+        let response = self
+            .router
+            .clone()
+            .oneshot(request)
+            .await
+            .expect("router is infallible");
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body is readable");
+        TestResponse {
+            status,
+            headers,
+            body: String::from_utf8(body.to_vec()).expect("body is UTF-8"),
+        }
+    }
+
+    pub async fn get(&self, uri: &str, cookie: Option<&str>) -> TestResponse {
+        let mut request = Request::get(uri);
+        if let Some(cookie) = cookie {
+            request = request.header(COOKIE, cookie);
+        }
+        self.send(request.body(Body::empty()).expect("valid request"))
+            .await
+    }
+
+    /// Posts `form`, which is already URL-encoded.
+    pub async fn post_form(&self, uri: &str, form: &str, cookie: Option<&str>) -> TestResponse {
+        let mut request =
+            Request::post(uri).header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+        if let Some(cookie) = cookie {
+            request = request.header(COOKIE, cookie);
+        }
+        self.send(
+            request
+                .body(Body::from(form.to_owned()))
+                .expect("valid request"),
+        )
+        .await
+    }
+
+    /// Posts `fields` and, if there is one, `image` (its MIME type and bytes) as
+    /// `multipart/form-data`, as a form with a file input is.
+    pub async fn post_multipart(
+        &self,
+        uri: &str,
+        fields: &[(&str, &str)],
+        image: Option<(&str, &[u8])>,
+        cookie: Option<&str>,
+    ) -> TestResponse {
+        const BOUNDARY: &str = "statusphere-test-boundary";
+        let mut body = vec![];
+        for (name, value) in fields {
+            body.extend_from_slice(
+                format!(
+                    "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+                )
+                .as_bytes(),
+            );
+        }
+        if let Some((mime_type, bytes)) = image {
+            body.extend_from_slice(
+                format!(
+                    "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"image\"; filename=\"image\"\r\nContent-Type: {mime_type}\r\n\r\n"
+                )
+                .as_bytes(),
+            );
+            body.extend_from_slice(bytes);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{BOUNDARY}--\r\n").as_bytes());
+
+        let mut request = Request::post(uri).header(
+            CONTENT_TYPE,
+            format!("multipart/form-data; boundary={BOUNDARY}"),
+        );
+        if let Some(cookie) = cookie {
+            request = request.header(COOKIE, cookie);
+        }
+        self.send(request.body(Body::from(body)).expect("valid request"))
+            .await
+    }
+
+    /// Logs in as `did` through the login form, returning the session cookie.
+    pub async fn login(&self, did: &Did) -> String {
+        let response = self
+            .post_form("/login", &format!("handle={}", did.as_str()), None)
+            .await;
+        assert_eq!(response.status, StatusCode::SEE_OTHER);
+        response
+            .session_cookie()
+            .expect("login sets a session cookie")
+    }
+}
+
+pub fn did(did: &str) -> Did {
+    Did::new(did.to_owned()).expect("valid DID")
+}
+
+/// A DID document for `did`, claiming `handle` (if there is one) and hosted on the PDS at `pds`.
+pub fn did_document(did: &str, handle: Option<&str>, pds: &str) -> serde_json::Value {
+    let mut document = json!({
+        "@context": ["https://www.w3.org/ns/did/v1"],
+        "id": did,
+        "service": [{
+            "id": "#atproto_pds",
+            "type": "AtprotoPersonalDataServer",
+            "serviceEndpoint": pds,
+        }],
+    });
+    if let Some(handle) = handle {
+        document["alsoKnownAs"] = json!([format!("at://{handle}")]);
+    }
+    document
+}
+
+/// Serves `document` from `plc`, a mock PLC directory, as the DID document of `did`.
+pub async fn mock_did_document(plc: &MockServer, did: &str, document: serde_json::Value) {
+    Mock::given(method("GET"))
+        .and(path(format!("/{did}")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(document))
+        .mount(plc)
+        .await;
+}
+
+/// An XRPC error response, as PDSes send them.
+pub fn xrpc_error(status: u16, error: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_json(json!({ "error": error, "message": error }))
+}
+
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "did:plc:alice0000000000000000000";
+    const BOB: &str = "did:plc:bob00000000000000000000000";
+
+    #[tokio::test]
+    async fn home_shows_seeded_statuses() {
+        let app = TestApp::new().await;
+        let (alice, bob) = (did(ALICE), did(BOB));
+        app.seed_handle(&alice, "alice.test").await;
+        app.seed_handle(&bob, "bob.test").await;
+        app.seed_status(&alice, "3kaaaaaaaaaa2", "🦋").await;
+        app.seed_status(&bob, "3kaaaaaaaaab2", "🥳").await;
+
+        let response = app.get("/", None).await;
+        assert_eq!(response.status, StatusCode::OK);
+        for shown in ["@alice.test", "🦋", "@bob.test", "🥳"] {
+            assert!(response.body.contains(shown), "{shown} missing from home");
+        }
+        // nobody's logged in, so there's no one to log out
+        assert!(!response.body.contains(r#"action="/logout""#));
+    }
+
+    #[tokio::test]
+    async fn permalink_shows_status() {
+        let app = TestApp::new().await;
+        let alice = did(ALICE);
+        app.seed_status(&alice, "3kaaaaaaaaaa2", "🦋").await;
+
+        let response = app
+            .get(&format!("/status/{ALICE}/3kaaaaaaaaaa2"), None)
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(response.body.contains("🦋"));
+    }
+
+    #[tokio::test]
+    async fn login_form_renders() {
+        let app = TestApp::new().await;
+        let response = app.get("/login", None).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(response.body.contains(r#"name="handle""#));
+    }
+
+    #[tokio::test]
+    async fn login_rejects_unsupported_did() {
+        let app = TestApp::new().await;
+        let response = app
+            .post_form(
+                "/login",
+                "handle=did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK",
+                None,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.session_cookie(), None);
+    }
+
+    #[tokio::test]
+    async fn login_shows_who_is_logged_in() {
+        let app = TestApp::new().await;
+        let alice = did(ALICE);
+        app.seed_handle(&alice, "alice.test").await;
+
+        let cookie = app.login(&alice).await;
+        let response = app.get("/", Some(&cookie)).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(response.body.contains("Logged in as @alice.test"));
+    }
+
+    #[tokio::test]
+    async fn posting_status_requires_login() {
+        let app = TestApp::new().await;
+        let response = app.post_form("/status", "status=%F0%9F%A6%8B", None).await;
+        assert_eq!(response.status, StatusCode::SEE_OTHER);
+        assert_eq!(response.location(), Some("/login"));
+    }
+
+    #[tokio::test]
+    async fn posted_status_is_in_feed() {
+        let app = TestApp::new().await;
+        let alice = did(ALICE);
+        app.seed_handle(&alice, "alice.test").await;
+        let cookie = app.login(&alice).await;
+
+        let response = app
+            .post_form("/status", "status=%F0%9F%A6%8B", Some(&cookie))
+            .await;
+        assert_eq!(response.status, StatusCode::SEE_OTHER);
+        assert_eq!(response.location(), Some("/"));
+
+        let stored = app
+            .state
+            .status_store
+            .fetch_one(&store::StatusFilter::new().author(alice))
+            .await
+            .expect("statuses are fetched")
+            .expect("status is stored");
+        assert_eq!(stored.status, "🦋");
+        // as the PDS (here the fake session) answered the write
+        assert!(stored.cid.is_some());
+        let response = app.get("/", Some(&cookie)).await;
+        assert!(response.body.contains("🦋"));
+    }
+
+    #[tokio::test]
+    async fn posting_unknown_status_is_rejected() {
+        let app = TestApp::new().await;
+        let cookie = app.login(&did(ALICE)).await;
+        let response = app
+            .post_form("/status", "status=not-an-emoji", Some(&cookie))
+            .await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn api_statuses_since() {
+        let app = TestApp::new().await;
+        let alice = did(ALICE);
+        let before = Datetime::now();
+        let uri = app.seed_status(&alice, "3kaaaaaaaaaa2", "🦋").await;
+
+        let response = app
+            .get(
+                &format!(
+                    "/api/v2/statuses?since={}",
+                    before.as_str().replace('+', "%2B")
+                ),
+                None,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        let page: serde_json::Value = serde_json::from_str(&response.body).expect("JSON body");
+        assert_eq!(page["statuses"][0]["uri"], uri);
+
+        // polling again from where that left off finds nothing new
+        let next_since = page["page"]["nextSince"]
+            .as_str()
+            .expect("nextSince is set");
+        let response = app
+            .get(
+                &format!("/api/v2/statuses?since={}", next_since.replace('+', "%2B")),
+                None,
+            )
+            .await;
+        let page: serde_json::Value = serde_json::from_str(&response.body).expect("JSON body");
+        assert_eq!(page["statuses"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn api_statuses_since_pages_through_statuses_indexed_together() {
+        let app = TestApp::new().await;
+        let alice = did(ALICE);
+        let before = Datetime::now();
+        let indexed_at = Datetime::now();
+        let mut uris = vec![];
+        for rkey in ["3kaaaaaaaaaa2", "3kaaaaaaaaaa3", "3kaaaaaaaaaa4"] {
+            let uri = format!("at://{}/{}/{rkey}", alice.as_str(), Status::NSID);
+            app.state
+                .status_store
+                .insert(store::Status {
+                    uri: uri.clone(),
+                    author_did: alice.clone(),
+                    status: "🦋".to_owned(),
+                    created_at: indexed_at.clone(),
+                    indexed_at: indexed_at.clone(),
+                    raw_created_at: None,
+                    visibility: Visibility::Public,
+                    content_warning: None,
+                    cid: Some(SEED_CID.to_owned()),
+                    image: None,
+                })
+                .await
+                .expect("status is stored");
+            uris.push(uri);
+        }
+
+        let mut since = before.as_str().replace('+', "%2B");
+        let mut seen = vec![];
+        for _ in 0..2 {
+            let response = app
+                .get(&format!("/api/v2/statuses?since={since}&limit=2"), None)
+                .await;
+            assert_eq!(response.status, StatusCode::OK);
+            let page: serde_json::Value = serde_json::from_str(&response.body).expect("JSON body");
+            seen.extend(
+                page["statuses"]
+                    .as_array()
+                    .expect("statuses is an array")
+                    .iter()
+                    .map(|status| status["uri"].as_str().expect("uri is set").to_owned()),
+            );
+            since = page["page"]["nextSince"]
+                .as_str()
+                .expect("nextSince is set")
+                .to_owned();
+        }
+        // the first page ends partway through the tie; the second picks up the rest
+        assert_eq!(seen, uris);
+    }
+}
@@ -0,0 +1,490 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use atrium_api::types::string::Did;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+};
+use tracing::{debug, warn};
+
+use crate::{AppState, store::Visibility};
+
+// once this many clients are tracked, those with nothing left to remember are forgotten
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// Address of the client making a request: the peer, or with `TRUST_PROXY` set, the address the
+/// outermost of the `TRUSTED_PROXY_HOPS` proxies added to `X-Forwarded-For`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+// the address `hops` entries from the end of an `X-Forwarded-For` header: each proxy appends the
+// address it was connected from, so entries further left are whatever the client sent
+fn forwarded_client(header: &str, hops: usize) -> Option<IpAddr> {
+    let entries = header.split(',').map(str::trim).collect::<Vec<_>>();
+    // fewer entries than proxies means the proxies are misconfigured; the leftmost entry is then
+    // still one of theirs
+    let index = entries.len().saturating_sub(hops.max(1));
+    entries.get(index)?.parse().ok()
+}
+
+impl FromRequestParts<Arc<AppState>> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let forwarded = state
+            .config
+            .trust_proxy
+            .then(|| {
+                parts
+                    .headers
+                    .get("X-Forwarded-For")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| forwarded_client(value, state.config.trusted_proxy_hops))
+            })
+            .flatten();
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        // only missing if the server was started without connect info
+        Ok(Self(
+            forwarded
+                .or(peer)
+                .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        ))
+    }
+}
+
+#[derive(Debug)]
+struct Attempts {
+    window_start: Instant,
+    count: u32,
+    locked_until: Option<Instant>,
+}
+
+/// Limits how often each client can attempt to log in: more than `max_attempts` in `window`
+/// locks them out for `lockout`.
+#[derive(Debug)]
+pub struct LoginThrottle {
+    max_attempts: u32,
+    window: Duration,
+    lockout: Duration,
+    clients: Mutex<HashMap<IpAddr, Attempts>>,
+}
+
+impl LoginThrottle {
+    pub fn new(max_attempts: u32, window: Duration, lockout: Duration) -> Self {
+        Self {
+            max_attempts,
+            window,
+            lockout,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a login attempt by `client`, or returns how long until they may try again if
+    /// they're locked out.
+    pub fn attempt(&self, client: IpAddr) -> Result<(), Duration> {
+        self.attempt_at(client, Instant::now())
+    }
+
+    fn attempt_at(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut clients = self.clients.lock().expect("poisoned lock");
+        if clients.len() >= PRUNE_THRESHOLD {
+            clients.retain(|_, attempts| !self.is_stale(attempts, now));
+        }
+
+        let attempts = clients.entry(client).or_insert(Attempts {
+            window_start: now,
+            count: 0,
+            locked_until: None,
+        });
+        if let Some(locked_until) = attempts.locked_until {
+            if now < locked_until {
+                return Err(locked_until - now);
+            }
+            attempts.locked_until = None;
+            attempts.count = 0;
+        }
+        if now.duration_since(attempts.window_start) > self.window {
+            attempts.window_start = now;
+            attempts.count = 0;
+        }
+
+        attempts.count += 1;
+        if attempts.count > self.max_attempts {
+            warn!("{client} made too many login attempts, locking them out");
+            attempts.locked_until = Some(now + self.lockout);
+            return Err(self.lockout);
+        }
+        Ok(())
+    }
+
+    // whether forgetting `attempts` changes nothing
+    fn is_stale(&self, attempts: &Attempts, now: Instant) -> bool {
+        attempts
+            .locked_until
+            .is_none_or(|locked_until| locked_until <= now)
+            && now.duration_since(attempts.window_start) > self.window
+    }
+}
+
+/// Token identifying one rendering of the status form, so resubmissions of it can be told apart
+/// from new posts.
+pub fn post_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+// a user's latest status post
+#[derive(Debug)]
+struct RecentPost {
+    rkey: String,
+    token: Option<String>,
+    status: String,
+    visibility: Visibility,
+    posted_at: Instant,
+}
+
+/// Record key a status post is written under.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PostClaim {
+    pub rkey: String,
+    /// Whether the post duplicates the user's previous one, whose record it replaces.
+    pub duplicate: bool,
+}
+
+/// Remembers each user's latest status post, so double submissions of the status form replace
+/// the status they posted rather than adding another: any post within `cooldown` of the previous
+/// one, and resubmissions of the same form (same token and status) within `window`.
+#[derive(Debug)]
+pub struct PostGuard {
+    cooldown: Duration,
+    window: Duration,
+    posts: Mutex<HashMap<String, RecentPost>>,
+}
+
+impl PostGuard {
+    pub fn new(cooldown: Duration, window: Duration) -> Self {
+        Self {
+            cooldown,
+            window,
+            posts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Claims the record key `did`'s post of `status` is written under: that of their previous
+    /// post if this one duplicates it, otherwise `rkey`. Claims are made before writing, so
+    /// concurrent duplicates agree on the record key.
+    pub fn claim(
+        &self,
+        did: &Did,
+        token: Option<&str>,
+        status: &str,
+        visibility: Visibility,
+        rkey: String,
+    ) -> PostClaim {
+        self.claim_at(did, token, status, visibility, rkey, Instant::now())
+    }
+
+    fn claim_at(
+        &self,
+        did: &Did,
+        token: Option<&str>,
+        status: &str,
+        visibility: Visibility,
+        rkey: String,
+        now: Instant,
+    ) -> PostClaim {
+        let mut posts = self.posts.lock().expect("poisoned lock");
+        if posts.len() >= PRUNE_THRESHOLD {
+            let forget_after = self.cooldown.max(self.window);
+            posts.retain(|_, post| now.duration_since(post.posted_at) <= forget_after);
+        }
+
+        if let Some(post) = posts.get_mut(did.as_str()) {
+            let elapsed = now.duration_since(post.posted_at);
+            let resubmitted = token.is_some()
+                && post.token.as_deref() == token
+                && post.status == status
+                && elapsed <= self.window;
+            // a followers-only status can't replace a public record, or the other way round
+            if post.visibility == visibility && (elapsed <= self.cooldown || resubmitted) {
+                debug!(
+                    "{}'s post duplicates their previous one, replacing it",
+                    did.as_str()
+                );
+                post.token = token.map(str::to_owned);
+                post.status = status.to_owned();
+                post.posted_at = now;
+                return PostClaim {
+                    rkey: post.rkey.clone(),
+                    duplicate: true,
+                };
+            }
+        }
+
+        posts.insert(
+            did.as_str().to_owned(),
+            RecentPost {
+                rkey: rkey.clone(),
+                token: token.map(str::to_owned),
+                status: status.to_owned(),
+                visibility,
+                posted_at: now,
+            },
+        );
+        PostClaim {
+            rkey,
+            duplicate: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarded_client_is_taken_from_the_trusted_end() {
+        let header = "203.0.113.7, 198.51.100.2, 10.0.0.1";
+
+        // spoofed entries on the left are ignored
+        assert_eq!(
+            forwarded_client(header, 1),
+            Some(IpAddr::from([10, 0, 0, 1]))
+        );
+        assert_eq!(
+            forwarded_client(header, 2),
+            Some(IpAddr::from([198, 51, 100, 2]))
+        );
+        assert_eq!(
+            forwarded_client(header, 5),
+            Some(IpAddr::from([203, 0, 113, 7]))
+        );
+        assert_eq!(forwarded_client("not-an-address", 1), None);
+    }
+
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+
+    fn login_throttle() -> LoginThrottle {
+        LoginThrottle::new(2, Duration::from_secs(60), Duration::from_secs(300))
+    }
+
+    #[test]
+    fn too_many_login_attempts_lock_the_client_out() {
+        let throttle = login_throttle();
+        let start = Instant::now();
+
+        assert_eq!(throttle.attempt_at(CLIENT, start), Ok(()));
+        assert_eq!(throttle.attempt_at(CLIENT, start), Ok(()));
+        assert_eq!(
+            throttle.attempt_at(CLIENT, start),
+            Err(Duration::from_secs(300))
+        );
+        // other clients aren't affected
+        assert_eq!(
+            throttle.attempt_at(IpAddr::V4(Ipv4Addr::LOCALHOST), start),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn locked_out_clients_are_told_how_long_is_left() {
+        let throttle = login_throttle();
+        let start = Instant::now();
+        for _ in 0..3 {
+            let _ = throttle.attempt_at(CLIENT, start);
+        }
+
+        assert_eq!(
+            throttle.attempt_at(CLIENT, start + Duration::from_secs(100)),
+            Err(Duration::from_secs(200))
+        );
+    }
+
+    #[test]
+    fn lockout_ends_after_its_duration() {
+        let throttle = login_throttle();
+        let start = Instant::now();
+        for _ in 0..3 {
+            let _ = throttle.attempt_at(CLIENT, start);
+        }
+
+        let unlocked = start + Duration::from_secs(300);
+        assert_eq!(throttle.attempt_at(CLIENT, unlocked), Ok(()));
+        // with a fresh count of attempts
+        assert_eq!(throttle.attempt_at(CLIENT, unlocked), Ok(()));
+        assert!(throttle.attempt_at(CLIENT, unlocked).is_err());
+    }
+
+    #[test]
+    fn attempts_are_counted_per_window() {
+        let throttle = login_throttle();
+        let start = Instant::now();
+
+        assert_eq!(throttle.attempt_at(CLIENT, start), Ok(()));
+        assert_eq!(throttle.attempt_at(CLIENT, start), Ok(()));
+        // the window has passed, so the count starts over
+        let next_window = start + Duration::from_secs(61);
+        assert_eq!(throttle.attempt_at(CLIENT, next_window), Ok(()));
+        assert_eq!(throttle.attempt_at(CLIENT, next_window), Ok(()));
+        assert!(throttle.attempt_at(CLIENT, next_window).is_err());
+    }
+
+    fn post_guard() -> PostGuard {
+        PostGuard::new(Duration::from_secs(2), Duration::from_secs(60))
+    }
+
+    fn alice() -> Did {
+        Did::new("did:plc:alice0000000000000000000".to_owned()).expect("valid DID")
+    }
+
+    // `guard`'s claim at `at` for alice's post of 🦋 from the form rendered with `token`
+    fn claim(
+        guard: &PostGuard,
+        token: &str,
+        visibility: Visibility,
+        rkey: &str,
+        at: Instant,
+    ) -> PostClaim {
+        guard.claim_at(&alice(), Some(token), "🦋", visibility, rkey.to_owned(), at)
+    }
+
+    fn replaced(rkey: &str) -> PostClaim {
+        PostClaim {
+            rkey: rkey.to_owned(),
+            duplicate: true,
+        }
+    }
+
+    fn fresh(rkey: &str) -> PostClaim {
+        PostClaim {
+            rkey: rkey.to_owned(),
+            duplicate: false,
+        }
+    }
+
+    #[test]
+    fn posts_of_another_visibility_are_never_duplicates() {
+        let guard = post_guard();
+        let start = Instant::now();
+
+        assert_eq!(
+            claim(&guard, "form-1", Visibility::Public, "a", start),
+            fresh("a")
+        );
+        // within the cooldown, and even a resubmission of the same form
+        assert_eq!(
+            claim(&guard, "form-1", Visibility::Followers, "b", start),
+            fresh("b")
+        );
+    }
+
+    #[test]
+    fn only_resubmissions_of_the_same_form_are_duplicates_after_the_cooldown() {
+        let guard = post_guard();
+        let start = Instant::now();
+        assert_eq!(
+            claim(&guard, "form-1", Visibility::Public, "a", start),
+            fresh("a")
+        );
+
+        let later = start + Duration::from_secs(10);
+        assert_eq!(
+            claim(&guard, "form-2", Visibility::Public, "b", later),
+            fresh("b")
+        );
+        assert_eq!(
+            claim(
+                &guard,
+                "form-2",
+                Visibility::Public,
+                "c",
+                later + Duration::from_secs(10)
+            ),
+            replaced("b")
+        );
+    }
+
+    #[test]
+    fn any_post_within_the_cooldown_is_a_duplicate() {
+        let guard = post_guard();
+        let start = Instant::now();
+        assert_eq!(
+            claim(&guard, "form-1", Visibility::Public, "a", start),
+            fresh("a")
+        );
+
+        assert_eq!(
+            claim(
+                &guard,
+                "form-2",
+                Visibility::Public,
+                "b",
+                start + Duration::from_secs(2)
+            ),
+            replaced("a")
+        );
+        // the cooldown runs from the latest post
+        assert_eq!(
+            claim(
+                &guard,
+                "form-3",
+                Visibility::Public,
+                "c",
+                start + Duration::from_secs(4)
+            ),
+            replaced("a")
+        );
+        assert_eq!(
+            claim(
+                &guard,
+                "form-4",
+                Visibility::Public,
+                "d",
+                start + Duration::from_secs(7)
+            ),
+            fresh("d")
+        );
+    }
+
+    #[test]
+    fn resubmissions_are_only_duplicates_within_the_window() {
+        let guard = post_guard();
+        let start = Instant::now();
+        assert_eq!(
+            claim(&guard, "form-1", Visibility::Public, "a", start),
+            fresh("a")
+        );
+
+        assert_eq!(
+            claim(
+                &guard,
+                "form-1",
+                Visibility::Public,
+                "b",
+                start + Duration::from_secs(60)
+            ),
+            replaced("a")
+        );
+        assert_eq!(
+            claim(
+                &guard,
+                "form-1",
+                Visibility::Public,
+                "c",
+                start + Duration::from_secs(121)
+            ),
+            fresh("c")
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use axum::{
+    Router,
+    http::{HeaderMap, StatusCode, Uri, header::HOST, uri::Authority},
+    response::{IntoResponse, Redirect, Response},
+    routing::any,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use tracing::{error, info};
+
+use crate::config::env_var_or_default;
+
+/// Certificate and key for serving HTTPS directly, for deployments without a TLS-terminating
+/// reverse proxy.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM certificate chain.
+    pub cert_path: PathBuf,
+    /// PEM private key.
+    pub key_path: PathBuf,
+    /// Where to listen for plain HTTP requests to redirect to HTTPS, if anywhere.
+    pub redirect_addr: Option<SocketAddr>,
+}
+
+impl TlsConfig {
+    /// HTTPS settings, if `TLS_CERT_PATH` and `TLS_KEY_PATH` are set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let cert_path = env_var_or_default("TLS_CERT_PATH", "")?;
+        let key_path = env_var_or_default("TLS_KEY_PATH", "")?;
+        match (cert_path.as_str(), key_path.as_str()) {
+            ("", "") => return Ok(None),
+            ("", _) | (_, "") => {
+                anyhow::bail!("TLS_CERT_PATH and TLS_KEY_PATH must be set together")
+            }
+            _ => {}
+        }
+        let redirect_addr = match env_var_or_default("TLS_REDIRECT_ADDR", "0.0.0.0:80")?.as_str() {
+            "" => None,
+            addr => Some(
+                addr.parse()
+                    .map_err(|e| anyhow::anyhow!("invalid TLS_REDIRECT_ADDR '{addr}': {e}"))?,
+            ),
+        };
+        Ok(Some(Self {
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+            redirect_addr,
+        }))
+    }
+}
+
+/// Serves `app` over HTTPS on `addr`, and redirects plain HTTP requests to it if configured to.
+/// Relies on the process-wide rustls crypto provider having been installed.
+pub async fn serve(app: Router, addr: SocketAddr, config: &TlsConfig) -> anyhow::Result<()> {
+    let rustls_config = RustlsConfig::from_pem_file(&config.cert_path, &config.key_path)
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "loading TLS certificate {} and key {}: {e}",
+                config.cert_path.display(),
+                config.key_path.display()
+            )
+        })?;
+
+    if let Some(redirect_addr) = config.redirect_addr {
+        let https_port = addr.port();
+        let redirect_app =
+            Router::new().fallback(any(move |headers: HeaderMap, uri: Uri| async move {
+                redirect_to_https(&headers, &uri, https_port)
+            }));
+        let listener = tokio::net::TcpListener::bind(redirect_addr).await?;
+        info!("Redirecting HTTP on {redirect_addr} to HTTPS");
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, redirect_app).await {
+                error!("HTTP redirect server failed: {e}");
+            }
+        });
+    }
+
+    info!("Server bound on {addr} (HTTPS)");
+    axum_server::bind_rustls(addr, rustls_config)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await?;
+    Ok(())
+}
+
+// the same host and path, over HTTPS on `https_port`
+fn redirect_to_https(headers: &HeaderMap, uri: &Uri, https_port: u16) -> Response {
+    let Some(host) = headers
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok())
+    else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "HTTPS required, and the request has no valid Host header",
+        )
+            .into_response();
+    };
+    let port = match https_port {
+        443 => String::new(),
+        port => format!(":{port}"),
+    };
+    let path_and_query = uri.path_and_query().map_or("/", |pq| pq.as_str());
+    Redirect::permanent(&format!("https://{}{port}{path_and_query}", host.host())).into_response()
+}
@@ -0,0 +1,57 @@
+//! Runtime switches for ingesting each collection, flipped from the admin dashboard.
+
+// the ingester watches the switches and the admin dashboard flips them, so without both of those
+// features some of this goes unused
+#![cfg_attr(not(all(feature = "ingester", feature = "admin")), allow(dead_code))]
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use tokio::sync::watch;
+
+/// Runtime switches for ingesting each collection, e.g. to pause one that's misbehaving. Records
+/// of a paused collection are dropped, not queued for later.
+#[derive(Debug, Clone)]
+pub struct CollectionToggles {
+    switches: Arc<BTreeMap<&'static str, watch::Sender<bool>>>,
+}
+
+impl CollectionToggles {
+    /// Toggles for `collections` (NSIDs), all initially enabled.
+    pub fn new(collections: impl IntoIterator<Item = &'static str>) -> Self {
+        Self {
+            switches: Arc::new(
+                collections
+                    .into_iter()
+                    .map(|collection| (collection, watch::Sender::new(true)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Watches whether `collection` is enabled. Panics if `collection` isn't toggleable.
+    pub fn subscribe(&self, collection: &str) -> watch::Receiver<bool> {
+        self.switches
+            .get(collection)
+            .unwrap_or_else(|| panic!("no toggle for collection {collection}"))
+            .subscribe()
+    }
+
+    /// Enables or disables ingestion of `collection`, returning `false` if it isn't toggleable.
+    pub fn set(&self, collection: &str, enabled: bool) -> bool {
+        match self.switches.get(collection) {
+            Some(switch) => {
+                switch.send_replace(enabled);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Every toggleable collection and whether it's enabled.
+    pub fn states(&self) -> Vec<(&'static str, bool)> {
+        self.switches
+            .iter()
+            .map(|(collection, switch)| (*collection, *switch.borrow()))
+            .collect()
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use atrium_api::{
+    types::string::Did,
+    xrpc::{self, error::XrpcError},
+};
+use rand::Rng;
+// tokio's, so tests can move time on instead of waiting out cooldowns
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::error::Error;
+
+// attempts made at an XRPC call before a transient failure is returned
+const MAX_ATTEMPTS: u32 = 3;
+// backoff before the first retry, doubled for each one after
+const BASE_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Runs a request to another service (PLC directory, PDS, ...), giving up with
+/// [`Error::UpstreamTimeout`] if it takes longer than `timeout`. The request's own result is
+/// passed through, so fallible requests are usually followed by `??`.
+pub async fn with_timeout<F: Future>(
+    timeout: Duration,
+    what: &'static str,
+    request: F,
+) -> Result<F::Output, Error> {
+    tokio::time::timeout(timeout, request)
+        .await
+        .map_err(|_| Error::UpstreamTimeout(what))
+}
+
+// failures that may well not happen again: the PDS erroring or being unreachable
+pub(crate) fn is_transient<E>(error: &xrpc::Error<E>) -> bool {
+    match error {
+        xrpc::Error::HttpClient(_) => true,
+        xrpc::Error::XrpcResponse(XrpcError { status, .. }) => status.is_server_error(),
+        _ => false,
+    }
+}
+
+/// Makes an XRPC call, retrying transient failures with jittered exponential backoff. Only
+/// use this for calls that are safe to repeat, since a failed response doesn't prove the
+/// request had no effect.
+pub async fn with_retries<T, E, F, Fut>(
+    what: &'static str,
+    mut call: F,
+) -> Result<T, xrpc::Error<E>>
+where
+    E: Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, xrpc::Error<E>>>,
+{
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Err(e) if attempt < MAX_ATTEMPTS && is_transient(&e) => {
+                let backoff = BASE_BACKOFF * 2u32.pow(attempt - 1);
+                // full jitter, so clients that failed together don't retry together
+                let delay = rand::thread_rng().gen_range(Duration::ZERO..=backoff);
+                warn!("{what} failed (attempt {attempt}/{MAX_ATTEMPTS}), retrying: {e:?}");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Name of the circuit for DID resolution, shared by every DID since it's one PLC directory.
+pub const IDENTITY_CIRCUIT: &str = "identity";
+
+/// Circuit for calls to `did`'s PDS made on their behalf.
+pub fn pds_circuit(did: &Did) -> String {
+    format!("pds:{}", did.as_str())
+}
+
+#[derive(Debug, Default)]
+struct Circuit {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    // when the call let through after the cooldown started, while it's outstanding
+    probe_started: Option<Instant>,
+}
+
+/// Fails calls to an upstream fast once it has failed `threshold` times in a row, for
+/// `cooldown`, so pages can fall back instead of waiting on timeouts. After the cooldown, the
+/// next call is let through as a probe while the others keep failing fast: success closes the
+/// circuit, failure re-opens it. A probe that never finishes (say, because the page requesting it
+/// was abandoned) is given up on after another cooldown.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    circuits: Arc<Mutex<HashMap<String, Circuit>>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            circuits: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // whether a call to `upstream` may go ahead, marking it as the probe if the circuit's
+    // cooldown is over
+    fn admit(&self, upstream: &str) -> bool {
+        let now = Instant::now();
+        let mut circuits = self.circuits.lock().expect("poisoned lock");
+        let Some(circuit) = circuits.get_mut(upstream) else {
+            return true;
+        };
+        match circuit.open_until {
+            None => true,
+            Some(open_until) if now < open_until => false,
+            Some(_) => {
+                let probing = circuit
+                    .probe_started
+                    .is_some_and(|started| now < started + self.cooldown);
+                if !probing {
+                    circuit.probe_started = Some(now);
+                }
+                !probing
+            }
+        }
+    }
+
+    /// Runs `request` against `upstream`, unless its circuit is open, in which case this fails
+    /// with [`Error::UpstreamUnavailable`] without running it.
+    pub async fn call<T>(
+        &self,
+        upstream: &str,
+        request: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        if !self.admit(upstream) {
+            return Err(Error::UpstreamUnavailable(upstream.to_owned()));
+        }
+        let result = request.await;
+        // only failures that say something about the upstream's health count
+        self.record(
+            upstream,
+            result.as_ref().is_err_and(|e| e.is_upstream_outage()),
+        );
+        result
+    }
+
+    fn record(&self, upstream: &str, failed: bool) {
+        let mut circuits = self.circuits.lock().expect("poisoned lock");
+        if !failed {
+            if circuits
+                .remove(upstream)
+                .is_some_and(|c| c.open_until.is_some())
+            {
+                info!("{upstream} recovered, closing its circuit");
+            }
+            return;
+        }
+        let circuit = circuits.entry(upstream.to_owned()).or_default();
+        circuit.consecutive_failures += 1;
+        circuit.probe_started = None;
+        if circuit.consecutive_failures >= self.threshold {
+            warn!(
+                "{upstream} failed {} times in a row, failing calls to it for {:?}",
+                circuit.consecutive_failures, self.cooldown
+            );
+            circuit.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPSTREAM: &str = "pds:did:plc:alice0000000000000000000";
+
+    async fn fail(breaker: &CircuitBreaker) -> Result<(), Error> {
+        breaker
+            .call(UPSTREAM, async {
+                Err(Error::UpstreamTimeout("test request"))
+            })
+            .await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn only_one_probe_is_let_through_after_the_cooldown() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(30));
+        assert!(matches!(
+            fail(&breaker).await,
+            Err(Error::UpstreamTimeout(_))
+        ));
+        assert!(matches!(
+            fail(&breaker).await,
+            Err(Error::UpstreamUnavailable(_))
+        ));
+
+        tokio::time::advance(Duration::from_secs(29)).await;
+        assert!(
+            !breaker.admit(UPSTREAM),
+            "the circuit is open until the cooldown is over"
+        );
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(breaker.admit(UPSTREAM), "the probe goes ahead");
+        assert!(!breaker.admit(UPSTREAM), "calls during the probe fail fast");
+
+        breaker.record(UPSTREAM, false);
+        assert!(
+            breaker.admit(UPSTREAM),
+            "a successful probe closes the circuit"
+        );
+        assert!(breaker.admit(UPSTREAM));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_failed_probe_reopens_the_circuit() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(30));
+        let _ = fail(&breaker).await;
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert!(matches!(
+            fail(&breaker).await,
+            Err(Error::UpstreamTimeout(_))
+        ));
+        assert!(matches!(
+            fail(&breaker).await,
+            Err(Error::UpstreamUnavailable(_))
+        ));
+    }
+}
@@ -0,0 +1,214 @@
+//! Checks of records against their lexicons, made before they're written to a PDS so invalid
+//! records are turned away with a 422 page rather than a PDS error.
+
+use std::time::Duration;
+
+use atrium_api::types::{BlobRef, TypedBlobRef, string::Datetime};
+use thiserror::Error;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{config::is_allowed_status, lexicons::xyz::statusphere::status};
+
+// limits of `xyz.statusphere.status`, as in lexicons/status.json; lengths are in UTF-8 bytes
+const STATUS_MIN_LENGTH: usize = 1;
+const STATUS_MAX_LENGTH: usize = 32;
+const STATUS_MAX_GRAPHEMES: usize = 1;
+const CONTENT_WARNING_MAX_LENGTH: usize = 640;
+const CONTENT_WARNING_MAX_GRAPHEMES: usize = 64;
+// limits of its `image` blob
+pub const IMAGE_ACCEPT: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+pub const IMAGE_MAX_SIZE: usize = 1_000_000;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum Error {
+    #[error("{field} must be at least {min} bytes")]
+    TooShort { field: &'static str, min: usize },
+    #[error("{field} can be at most {max} bytes")]
+    TooLong { field: &'static str, max: usize },
+    #[error("{field} can be at most {max} characters")]
+    TooManyGraphemes { field: &'static str, max: usize },
+    #[error("'{0}' isn't one of the status options")]
+    UnknownStatus(String),
+    #[error("createdAt {0} is in the future")]
+    CreatedInFuture(String),
+    #[error("images can't be of type '{0}'")]
+    UnsupportedImageType(String),
+    #[error("images can be at most {max} bytes")]
+    ImageTooLarge { max: usize },
+}
+
+// a string property's `minLength`, `maxLength` and `maxGraphemes`
+fn check_length(
+    field: &'static str,
+    value: &str,
+    min: usize,
+    max: usize,
+    max_graphemes: usize,
+) -> Result<(), Error> {
+    if value.len() < min {
+        return Err(Error::TooShort { field, min });
+    }
+    if value.len() > max {
+        return Err(Error::TooLong { field, max });
+    }
+    if value.graphemes(true).count() > max_graphemes {
+        return Err(Error::TooManyGraphemes {
+            field,
+            max: max_graphemes,
+        });
+    }
+    Ok(())
+}
+
+/// Checks an image of `mime_type`, `size` bytes long, against the limits of a status's `image`.
+pub fn image(mime_type: &str, size: usize) -> Result<(), Error> {
+    if !IMAGE_ACCEPT.contains(&mime_type) {
+        return Err(Error::UnsupportedImageType(mime_type.to_owned()));
+    }
+    if size > IMAGE_MAX_SIZE {
+        return Err(Error::ImageTooLarge {
+            max: IMAGE_MAX_SIZE,
+        });
+    }
+    Ok(())
+}
+
+/// Checks a status record against the `xyz.statusphere.status` lexicon, and beyond it that the
+/// status is one of `status_options` and that it isn't dated more than `max_clock_skew` ahead.
+pub fn status_record(
+    record: &status::RecordData,
+    status_options: &[String],
+    max_clock_skew: Duration,
+) -> Result<(), Error> {
+    check_length(
+        "status",
+        &record.status,
+        STATUS_MIN_LENGTH,
+        STATUS_MAX_LENGTH,
+        STATUS_MAX_GRAPHEMES,
+    )?;
+    if !is_allowed_status(status_options, &record.status) {
+        return Err(Error::UnknownStatus(record.status.clone()));
+    }
+    if let Some(content_warning) = &record.content_warning {
+        check_length(
+            "contentWarning",
+            content_warning,
+            0,
+            CONTENT_WARNING_MAX_LENGTH,
+            CONTENT_WARNING_MAX_GRAPHEMES,
+        )?;
+    }
+    match &record.image {
+        Some(BlobRef::Typed(TypedBlobRef::Blob(blob))) => image(&blob.mime_type, blob.size)?,
+        // legacy blob refs don't say how big they are
+        Some(BlobRef::Untyped(blob)) => image(&blob.mime_type, 0)?,
+        None => {}
+    }
+    let max_skew = chrono::Duration::from_std(max_clock_skew).unwrap_or(chrono::Duration::MAX);
+    let too_far_ahead = Datetime::now()
+        .as_ref()
+        .checked_add_signed(max_skew)
+        .is_some_and(|limit| record.created_at.as_ref() > &limit);
+    if too_far_ahead {
+        return Err(Error::CreatedInFuture(
+            record.created_at.as_str().to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SKEW: Duration = Duration::from_secs(300);
+
+    fn options() -> Vec<String> {
+        vec!["🦋".to_owned(), "👍🏽".to_owned()]
+    }
+
+    fn record(status: &str) -> status::RecordData {
+        status::RecordData {
+            content_warning: None,
+            created_at: Datetime::now(),
+            image: None,
+            status: status.to_owned(),
+        }
+    }
+
+    #[test]
+    fn accepts_status_option() {
+        assert_eq!(status_record(&record("🦋"), &options(), SKEW), Ok(()));
+        // one grapheme, however many code points
+        assert_eq!(status_record(&record("👍🏽"), &options(), SKEW), Ok(()));
+    }
+
+    #[test]
+    fn rejects_empty_status() {
+        assert_eq!(
+            status_record(&record(""), &options(), SKEW),
+            Err(Error::TooShort {
+                field: "status",
+                min: 1
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_several_graphemes() {
+        let options = vec!["🦋🦋".to_owned()];
+        assert_eq!(
+            status_record(&record("🦋🦋"), &options, SKEW),
+            Err(Error::TooManyGraphemes {
+                field: "status",
+                max: 1
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_status() {
+        assert_eq!(
+            status_record(&record("🥳"), &options(), SKEW),
+            Err(Error::UnknownStatus("🥳".to_owned()))
+        );
+    }
+
+    #[test]
+    fn rejects_long_content_warning() {
+        let mut record = record("🦋");
+        record.content_warning = Some("a".repeat(65));
+        assert_eq!(
+            status_record(&record, &options(), SKEW),
+            Err(Error::TooManyGraphemes {
+                field: "contentWarning",
+                max: 64
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_future_created_at() {
+        let mut record = record("🦋");
+        record.created_at =
+            Datetime::new((chrono::Utc::now() + chrono::Duration::hours(1)).fixed_offset());
+        assert!(matches!(
+            status_record(&record, &options(), SKEW),
+            Err(Error::CreatedInFuture(_))
+        ));
+    }
+
+    #[test]
+    fn checks_images_against_the_lexicon() {
+        assert_eq!(image("image/png", 1_000), Ok(()));
+        assert_eq!(
+            image("image/svg+xml", 1_000),
+            Err(Error::UnsupportedImageType("image/svg+xml".to_owned()))
+        );
+        assert_eq!(
+            image("image/jpeg", 2_000_000),
+            Err(Error::ImageTooLarge { max: 1_000_000 })
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use atrium_api::{
+    app::bsky::actor::profile,
+    com::atproto::repo::get_record,
+    types::{
+        TryFromUnknown,
+        string::{Datetime, Did, Nsid, RecordKey},
+    },
+    xrpc::{
+        self,
+        error::{XrpcError, XrpcErrorKind},
+    },
+};
+use chrono::TimeDelta;
+use serde::Serialize;
+use tower_sessions::Session;
+use tracing::warn;
+
+use crate::{
+    AppState, ClientSession,
+    error::Error,
+    handles::display_handle,
+    oauth::ATProtoAgent,
+    upstream::{pds_circuit, with_retries, with_timeout},
+};
+
+// how long the handle and display name kept in a client session are shown before being refreshed
+const REFRESH_INTERVAL: TimeDelta = TimeDelta::hours(1);
+
+/// Who's logged in, as shown on pages.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Viewer {
+    /// Display name, or the handle for users without one.
+    pub name: String,
+    /// `@handle`, or the DID while the handle's unknown.
+    pub handle: String,
+}
+
+impl ClientSession {
+    /// A session for `did`, whose handle and display name are yet to be looked up.
+    pub fn new(did: Did) -> Self {
+        Self {
+            did,
+            handle: None,
+            display_name: None,
+            refreshed_at: None,
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        // handles are resolved in the background, so one missing may have turned up since
+        self.handle.is_none()
+            || self.refreshed_at.as_ref().is_none_or(|refreshed_at| {
+                Datetime::now()
+                    .as_ref()
+                    .signed_duration_since(refreshed_at.as_ref())
+                    > REFRESH_INTERVAL
+            })
+    }
+
+    /// Updates the handle (from the handle cache) and display name (from the user's profile on
+    /// their PDS). Either is left as it was if it can't be looked up right now.
+    pub async fn refresh(&mut self, state: &AppState, agent: &ATProtoAgent) -> Result<(), Error> {
+        if let Some(handle) = state.handle_resolver.handle(&self.did).await? {
+            self.handle = Some(handle);
+        }
+        match fetch_display_name(state, agent, &self.did).await {
+            Ok(display_name) => self.display_name = display_name,
+            Err(e) => warn!("keeping {}'s display name: {e}", self.did.as_str()),
+        }
+        self.refreshed_at = Some(Datetime::now());
+        Ok(())
+    }
+
+    /// How the user is shown, from what's remembered of them.
+    pub fn viewer(&self) -> Viewer {
+        let handle = display_handle(&self.did, self.handle.as_deref());
+        Viewer {
+            name: self
+                .display_name
+                .clone()
+                .filter(|name| !name.trim().is_empty())
+                .unwrap_or_else(|| handle.clone()),
+            handle,
+        }
+    }
+}
+
+/// Who's logged in with `agent`, refreshing what their session remembers of them if it's stale.
+pub async fn current(
+    state: &AppState,
+    session: &Session,
+    agent: Option<&ATProtoAgent>,
+) -> Result<Option<Viewer>, Error> {
+    let Some(agent) = agent else {
+        return Ok(None);
+    };
+    let Some(mut client_session) = session.get::<ClientSession>("sid").await? else {
+        return Ok(None);
+    };
+    if client_session.is_stale() {
+        client_session.refresh(state, agent).await?;
+        session.insert("sid", &client_session).await?;
+    }
+    Ok(Some(client_session.viewer()))
+}
+
+// the display name on the user's profile, if they have a profile with one
+async fn fetch_display_name(
+    state: &AppState,
+    agent: &ATProtoAgent,
+    did: &Did,
+) -> Result<Option<String>, Error> {
+    let parameters = get_record::ParametersData {
+        cid: None,
+        collection: Nsid::new("app.bsky.actor.profile".to_owned())
+            .expect("unexpected Nsid failure"),
+        repo: did.clone().into(),
+        rkey: RecordKey::new("self".to_owned()).expect("unexpected record key failure"),
+    };
+    state
+        .circuit_breaker
+        .call(&pds_circuit(did), async {
+            let fetched = with_timeout(
+                state.config.upstream_timeout,
+                "profile fetch",
+                with_retries("profile fetch", || {
+                    agent
+                        .api
+                        .com
+                        .atproto
+                        .repo
+                        .get_record(parameters.clone().into())
+                }),
+            )
+            .await?;
+            let output = match fetched {
+                Ok(output) => output,
+                Err(xrpc::Error::XrpcResponse(XrpcError {
+                    error: Some(XrpcErrorKind::Custom(get_record::Error::RecordNotFound(_))),
+                    ..
+                })) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            let profile = profile::RecordData::try_from_unknown(output.data.value)
+                .map_err(Error::ProfileParse)?;
+            Ok(profile.display_name)
+        })
+        .await
+}
@@ -0,0 +1,318 @@
+use std::{str::FromStr, time::Duration};
+
+use atrium_api::types::{Collection, string::Datetime};
+use chrono::{Local, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+use crate::{
+    i18n::{self, gettext},
+    lexicons::xyz::statusphere::Status,
+    store::StatusImage,
+};
+
+/// Which timestamp(s) of a status to show in the UI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatePolicy {
+    /// The earlier of the creation and indexing times.
+    Earliest,
+    /// When the appview first saw the status.
+    Indexed,
+    /// The client-provided creation time.
+    Created,
+    /// The creation time, flagged as backdated if it's much earlier than the indexing time.
+    Both,
+}
+
+impl FromStr for DatePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "earliest" => Ok(Self::Earliest),
+            "indexed" => Ok(Self::Indexed),
+            "created" => Ok(Self::Created),
+            "both" => Ok(Self::Both),
+            other => Err(anyhow::anyhow!(
+                "invalid date policy '{other}': expected one of 'earliest', 'indexed', 'created', \
+                'both'"
+            )),
+        }
+    }
+}
+
+// indexed_at is never in the future, so this also keeps skewed client clocks from displaying
+// future dates (even for rows ingested before created_at clamping)
+fn choose_date<'a>(created_at: &'a Datetime, indexed_at: &'a Datetime) -> &'a Datetime {
+    if created_at < indexed_at {
+        created_at
+    } else {
+        indexed_at
+    }
+}
+
+/// Date of `dt` in `tz`, or in the server's local timezone if the viewer's isn't known.
+pub fn display_date(dt: &Datetime, tz: Option<Tz>) -> String {
+    let utc = dt.as_ref().with_timezone(&Utc);
+    match tz {
+        Some(tz) => utc.with_timezone(&tz).date_naive().to_string(),
+        None => utc.with_timezone(&Local).date_naive().to_string(),
+    }
+}
+
+/// Minijinja filter rendering an RFC 3339 timestamp relative to now, e.g. "2 hours ago", in the
+/// template's locale.
+pub fn relative_time(
+    state: &minijinja::State,
+    timestamp: String,
+) -> Result<String, minijinja::Error> {
+    let dt = Datetime::from_str(&timestamp).map_err(|e| {
+        minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("invalid timestamp '{timestamp}': {e}"),
+        )
+    })?;
+    Ok(relative_to(
+        &dt,
+        &Datetime::now(),
+        &i18n::template_locale(state),
+    ))
+}
+
+fn relative_to(dt: &Datetime, now: &Datetime, locale: &str) -> String {
+    let seconds = now
+        .as_ref()
+        .signed_duration_since(dt.as_ref())
+        .num_seconds();
+    // slightly future timestamps (skewed clocks) read as "just now" too
+    if seconds < 60 {
+        return gettext(locale, "just now").to_owned();
+    }
+    let (count, singular, plural) = match seconds {
+        ..3_600 => (seconds / 60, "{count} minute ago", "{count} minutes ago"),
+        ..86_400 => (seconds / 3_600, "{count} hour ago", "{count} hours ago"),
+        ..604_800 => (seconds / 86_400, "{count} day ago", "{count} days ago"),
+        ..2_592_000 => (seconds / 604_800, "{count} week ago", "{count} weeks ago"),
+        ..31_536_000 => (
+            seconds / 2_592_000,
+            "{count} month ago",
+            "{count} months ago",
+        ),
+        _ => (
+            seconds / 31_536_000,
+            "{count} year ago",
+            "{count} years ago",
+        ),
+    };
+    gettext(locale, if count == 1 { singular } else { plural })
+        .replace("{count}", &count.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DisplayDates {
+    /// The date to show for the status.
+    pub date: String,
+    /// The same moment as `date`, as an RFC 3339 timestamp (for relative rendering).
+    pub timestamp: String,
+    /// Whether the status claims to have been created well before we saw it.
+    pub backdated: bool,
+    /// When we saw the status, if it's backdated.
+    pub indexed_date: Option<String>,
+}
+
+/// Computes the dates to display for a status under `policy`. Statuses created more than
+/// `backdate_threshold` before they were indexed are considered backdated (only reported under
+/// [`DatePolicy::Both`]).
+pub fn display_dates(
+    policy: DatePolicy,
+    backdate_threshold: Duration,
+    created_at: &Datetime,
+    indexed_at: &Datetime,
+    tz: Option<Tz>,
+) -> DisplayDates {
+    let shown = match policy {
+        DatePolicy::Earliest => choose_date(created_at, indexed_at),
+        DatePolicy::Indexed => indexed_at,
+        DatePolicy::Created | DatePolicy::Both => created_at,
+    };
+    let backdated = policy == DatePolicy::Both
+        && indexed_at
+            .as_ref()
+            .signed_duration_since(created_at.as_ref())
+            .to_std()
+            .is_ok_and(|lag| lag > backdate_threshold);
+    DisplayDates {
+        date: display_date(shown, tz),
+        timestamp: shown.as_str().to_owned(),
+        backdated,
+        indexed_date: backdated.then(|| display_date(indexed_at, tz)),
+    }
+}
+
+/// Path of a status' permalink page (`/status/{did}/{rkey}`), for both public (`at://`) and
+/// followers-only (`private:`) URIs.
+pub fn permalink(uri: &str) -> Option<String> {
+    let (did, rkey) = match uri.strip_prefix("at://") {
+        Some(path) => {
+            let (did, rest) = path.split_once('/')?;
+            (did, rest.strip_prefix(Status::NSID)?.strip_prefix('/')?)
+        }
+        None => uri.strip_prefix("private:")?.split_once('/')?,
+    };
+    (!rkey.is_empty() && !rkey.contains('/')).then(|| format!("/status/{did}/{rkey}"))
+}
+
+/// URL of the image attached to the status at `uri`, if it has one; served through
+/// [`crate::permalink::status_image`].
+pub fn image_url(uri: &str, image: Option<&StatusImage>) -> Option<String> {
+    image?;
+    Some(format!("{}/image", permalink(uri)?))
+}
+
+/// bsky.app URL of an `app.bsky.feed.post` record.
+pub fn bsky_post_url(uri: &str) -> Option<String> {
+    let (did, rkey) = uri
+        .strip_prefix("at://")?
+        .split_once("/app.bsky.feed.post/")?;
+    Some(format!("https://bsky.app/profile/{did}/post/{rkey}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn dt(s: &str) -> Datetime {
+        Datetime::from_str(s).expect("valid datetime")
+    }
+
+    #[test]
+    fn earliest_picks_earlier_timestamp() {
+        let created = dt("2025-01-01T12:00:00Z");
+        let indexed = dt("2025-01-05T12:00:00Z");
+        let dates = display_dates(DatePolicy::Earliest, HOUR, &created, &indexed, None);
+        assert_eq!(dates.date, display_date(&created, None));
+        assert!(!dates.backdated);
+
+        let dates = display_dates(DatePolicy::Earliest, HOUR, &indexed, &created, None);
+        assert_eq!(dates.date, display_date(&created, None));
+    }
+
+    #[test]
+    fn indexed_and_created_policies() {
+        let created = dt("2025-01-01T12:00:00Z");
+        let indexed = dt("2025-01-05T12:00:00Z");
+        let dates = display_dates(DatePolicy::Indexed, HOUR, &created, &indexed, None);
+        assert_eq!(dates.date, display_date(&indexed, None));
+        let dates = display_dates(DatePolicy::Created, HOUR, &created, &indexed, None);
+        assert_eq!(dates.date, display_date(&created, None));
+        assert!(!dates.backdated);
+    }
+
+    #[test]
+    fn both_flags_backdated_statuses() {
+        let created = dt("2025-01-01T12:00:00Z");
+        let indexed = dt("2025-01-05T12:00:00Z");
+        let dates = display_dates(DatePolicy::Both, HOUR, &created, &indexed, None);
+        assert_eq!(
+            dates,
+            DisplayDates {
+                date: display_date(&created, None),
+                timestamp: created.as_str().to_owned(),
+                backdated: true,
+                indexed_date: Some(display_date(&indexed, None)),
+            }
+        );
+    }
+
+    #[test]
+    fn both_tolerates_small_lag() {
+        let created = dt("2025-01-01T12:00:00Z");
+        let indexed = dt("2025-01-01T12:30:00Z");
+        let dates = display_dates(DatePolicy::Both, HOUR, &created, &indexed, None);
+        assert!(!dates.backdated);
+        assert_eq!(dates.indexed_date, None);
+
+        // future-dated statuses aren't backdated
+        let dates = display_dates(DatePolicy::Both, HOUR, &indexed, &created, None);
+        assert!(!dates.backdated);
+    }
+
+    #[test]
+    fn dates_follow_viewer_timezone() {
+        let late_evening_utc = dt("2025-01-01T23:30:00Z");
+        assert_eq!(display_date(&late_evening_utc, Some(Tz::UTC)), "2025-01-01");
+        assert_eq!(
+            display_date(&late_evening_utc, Some(Tz::Europe__Berlin)),
+            "2025-01-02"
+        );
+    }
+
+    #[test]
+    fn relative_times() {
+        let now = dt("2025-01-10T12:00:00Z");
+        assert_eq!(
+            relative_to(&dt("2025-01-10T11:59:30Z"), &now, "en"),
+            "just now"
+        );
+        assert_eq!(
+            relative_to(&dt("2025-01-10T12:00:30Z"), &now, "en"),
+            "just now"
+        );
+        assert_eq!(
+            relative_to(&dt("2025-01-10T11:59:00Z"), &now, "en"),
+            "1 minute ago"
+        );
+        assert_eq!(
+            relative_to(&dt("2025-01-10T10:00:00Z"), &now, "en"),
+            "2 hours ago"
+        );
+        assert_eq!(
+            relative_to(&dt("2025-01-07T12:00:00Z"), &now, "en"),
+            "3 days ago"
+        );
+        assert_eq!(
+            relative_to(&dt("2024-01-10T12:00:00Z"), &now, "en"),
+            "1 year ago"
+        );
+        assert_eq!(
+            relative_to(&dt("2025-01-10T10:00:00Z"), &now, "fr"),
+            "il y a 2 heures"
+        );
+    }
+
+    #[test]
+    fn permalinks() {
+        assert_eq!(
+            permalink("at://did:plc:abc/xyz.statusphere.status/3lb2c").as_deref(),
+            Some("/status/did:plc:abc/3lb2c")
+        );
+        assert_eq!(
+            permalink("private:did:plc:abc/3lb2c").as_deref(),
+            Some("/status/did:plc:abc/3lb2c")
+        );
+        assert_eq!(permalink("at://did:plc:abc/xyz.statusphere.pin/self"), None);
+        assert_eq!(permalink("at://did:plc:abc/xyz.statusphere.status/"), None);
+        assert_eq!(
+            bsky_post_url("at://did:plc:abc/app.bsky.feed.post/3lb2c").as_deref(),
+            Some("https://bsky.app/profile/did:plc:abc/post/3lb2c")
+        );
+        let image = StatusImage {
+            cid: "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku".to_owned(),
+            mime_type: "image/png".to_owned(),
+        };
+        assert_eq!(
+            image_url(
+                "at://did:plc:abc/xyz.statusphere.status/3lb2c",
+                Some(&image)
+            )
+            .as_deref(),
+            Some("/status/did:plc:abc/3lb2c/image")
+        );
+        assert_eq!(
+            image_url("at://did:plc:abc/xyz.statusphere.status/3lb2c", None),
+            None
+        );
+    }
+}